@@ -0,0 +1,13 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/media_controller.proto");
+        let descriptors = protox::compile(["proto/media_controller.proto"], ["proto"])
+            .expect("proto/media_controller.proto doesn't compile");
+        tonic_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("couldn't generate the gRPC service");
+    }
+}
@@ -0,0 +1,95 @@
+# Deutsche Antworttexte der Steuer-Endpunkte.
+
+playing = Wiedergabe läuft
+playing-no-player = Wiedergabe läuft (kein externer Player)
+paused = pausiert
+stopped = gestoppt
+raised = { $player } in den Vordergrund geholt
+quit-player = { $player } zum Beenden aufgefordert
+next-track = zum nächsten Titel gesprungen
+previous-track = zum vorherigen Titel gesprungen
+seeked = { $direction ->
+    [forward] { $seconds } s vorgespult
+   *[backward] { $seconds } s zurückgespult
+}
+seeked-to = zu { $position } gesprungen
+shuffle-set = Zufallswiedergabe { $state ->
+    [on] an
+   *[off] aus
+}
+loop-set = Wiederholung auf { $mode } gesetzt
+system-volume = Systemlautstärke { $delta }
+system-muted = Systemlautstärke stummgeschaltet
+system-unmuted = Stummschaltung aufgehoben
+
+plan-playback = würde { $player } { $verb ->
+    [play] abspielen
+    [pause] pausieren
+    [stop] stoppen
+   *[toggle] umschalten
+} und unseren Publisher aktualisieren
+plan-publisher-only = würde nur unseren Publisher { $verb ->
+    [play] abspielen
+    [pause] pausieren
+    [stop] stoppen
+   *[toggle] umschalten
+} (kein externer Player)
+plan-next = würde bei { $player } zum nächsten Titel springen
+plan-previous = würde bei { $player } zum vorherigen Titel springen
+plan-raise = würde { $player } in den Vordergrund holen
+plan-quit = würde { $player } zum Beenden auffordern
+plan-seek = { $direction ->
+    [forward] würde { $player } um { $seconds } s vorspulen
+   *[backward] würde { $player } um { $seconds } s zurückspulen
+}
+plan-seek-to = würde { $player } zu { $position } springen
+plan-shuffle = würde Zufallswiedergabe auf { $player } { $state ->
+    [on] einschalten
+   *[off] ausschalten
+}
+plan-loop = würde Wiederholung auf { $player } auf { $mode } setzen
+plan-volume = würde die Systemlautstärke um { $delta } ändern
+plan-mute = { $mode ->
+    [mute] würde die Systemlautstärke stummschalten
+    [unmute] würde die Stummschaltung aufheben
+   *[toggle] würde die Stummschaltung umschalten
+}
+plan-wait = würde bis zu { $seconds }s auf einen Player warten
+plan-launch = würde { $command } starten und bis zu { $seconds }s auf einen Player warten
+plan-ignored = würde nichts tun (kein externer Player)
+
+no-player = kein externer Player gefunden
+no-player-ignored = nichts zu tun (kein externer Player)
+locked = Lautstärke und Spulen sind gesperrt
+cannot-control = Player lässt sich nicht steuern
+cannot-next = Player kann nicht zum nächsten Titel springen
+cannot-previous = Player kann nicht zum vorherigen Titel springen
+cannot-seek = Player kann nicht spulen
+cannot-raise = Player kann nicht in den Vordergrund geholt werden
+cannot-quit = Player lässt sich nicht aus der Ferne beenden
+cannot-shuffle = Player kennt keine Zufallswiedergabe
+cannot-loop = Player kennt keine Wiederholung
+query-failed = Player konnte nicht abgefragt werden: { $error }
+status-failed = Status konnte nicht gelesen werden
+player-call-failed = Aufruf am Player fehlgeschlagen: { $error }
+seek-failed = Spulen fehlgeschlagen: { $error }
+shuffle-failed = Zufallswiedergabe konnte nicht geändert werden: { $error }
+loop-failed = Wiederholung konnte nicht geändert werden: { $error }
+volume-failed = Lautstärke konnte nicht geändert werden: { $error }
+mute-failed = Stummschaltung konnte nicht geändert werden: { $error }
+
+announce-track = { $title } von { $artist }
+announce-title = { $title }
+announce-nothing = es wird nichts abgespielt
+spoken-playing = { $player } spielt { $track }, { $progress }
+spoken-paused = { $player } ist pausiert bei { $track }, { $progress }
+spoken-stopped = { $player } ist gestoppt
+spoken-nothing = Es wird nichts abgespielt
+spoken-some-player = Ein Player
+spoken-some-track = einem unbekannten Titel
+spoken-just-started = gerade begonnen
+spoken-seconds-in = seit { $seconds } Sekunden
+spoken-one-minute-in = seit einer Minute
+spoken-minutes-in = seit { $minutes } Minuten
+tts-exited = Sprachausgabe wurde mit { $status } beendet
+tts-launch-failed = Sprachausgabe konnte nicht gestartet werden: { $error }
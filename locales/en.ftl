@@ -0,0 +1,96 @@
+# Human-readable responses of the control endpoints.
+# Message ids are stable; only the text is translated.
+
+playing = playing
+playing-no-player = playing (no external player)
+paused = paused
+stopped = stopped
+raised = brought { $player } to the front
+quit-player = asked { $player } to quit
+next-track = skipped to next track
+previous-track = skipped to previous track
+seeked = { $direction ->
+    [forward] seeked forward { $seconds }s
+   *[backward] seeked backward { $seconds }s
+}
+seeked-to = seeked to { $position }
+shuffle-set = shuffle { $state ->
+    [on] on
+   *[off] off
+}
+loop-set = loop set to { $mode }
+system-volume = system volume { $delta }
+system-muted = system volume muted
+system-unmuted = system volume unmuted
+
+plan-playback = would { $verb ->
+    [play] play
+    [pause] pause
+    [stop] stop
+   *[toggle] toggle
+} { $player } and update our publisher
+plan-publisher-only = would { $verb ->
+    [play] play
+    [pause] pause
+    [stop] stop
+   *[toggle] toggle
+} our publisher only (no external player)
+plan-next = would skip { $player } to the next track
+plan-previous = would skip { $player } to the previous track
+plan-raise = would bring { $player } to the front
+plan-quit = would ask { $player } to quit
+plan-seek = { $direction ->
+    [forward] would seek { $player } forward { $seconds }s
+   *[backward] would seek { $player } backward { $seconds }s
+}
+plan-seek-to = would seek { $player } to { $position }
+plan-shuffle = would turn shuffle { $state ->
+    [on] on
+   *[off] off
+} on { $player }
+plan-loop = would set loop on { $player } to { $mode }
+plan-volume = would change system volume { $delta }
+plan-mute = would { $mode ->
+    [mute] mute
+    [unmute] unmute
+   *[toggle] toggle mute on
+} the system volume
+plan-wait = would wait up to { $seconds }s for a player
+plan-launch = would run { $command } and wait up to { $seconds }s for a player
+plan-ignored = would do nothing (no external player)
+
+no-player = no external player found
+no-player-ignored = nothing to do (no external player)
+locked = volume and seeking are locked
+cannot-control = player cannot be controlled
+cannot-next = player cannot go to the next track
+cannot-previous = player cannot go to the previous track
+cannot-seek = player cannot seek
+cannot-raise = player cannot be brought to the front
+cannot-quit = player cannot be quit remotely
+cannot-shuffle = player cannot shuffle
+cannot-loop = player cannot loop
+query-failed = couldn't query player: { $error }
+status-failed = couldn't read status
+player-call-failed = player call failed: { $error }
+seek-failed = couldn't seek: { $error }
+shuffle-failed = couldn't change shuffle: { $error }
+loop-failed = couldn't change loop: { $error }
+volume-failed = couldn't change volume: { $error }
+mute-failed = couldn't change mute: { $error }
+
+announce-track = { $title } by { $artist }
+announce-title = { $title }
+announce-nothing = nothing is playing
+spoken-playing = { $player } is playing { $track }, { $progress }
+spoken-paused = { $player } is paused on { $track }, { $progress }
+spoken-stopped = { $player } is stopped
+spoken-nothing = Nothing is playing
+spoken-some-player = A player
+spoken-some-track = an unknown track
+spoken-just-started = just started
+spoken-seconds-in = { $seconds } seconds in
+spoken-one-minute-in = 1 minute in
+spoken-minutes-in = { $minutes } minutes in
+tts-exited = speech command exited with { $status }
+tts-launch-failed = failed to launch speech command: { $error }
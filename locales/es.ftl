@@ -0,0 +1,95 @@
+# Textos de respuesta de los endpoints de control en español.
+
+playing = reproduciendo
+playing-no-player = reproduciendo (sin reproductor externo)
+paused = en pausa
+stopped = detenido
+raised = { $player } traído al frente
+quit-player = se pidió a { $player } que se cierre
+next-track = saltado a la siguiente pista
+previous-track = saltado a la pista anterior
+seeked = { $direction ->
+    [forward] adelantado { $seconds } s
+   *[backward] retrocedido { $seconds } s
+}
+seeked-to = saltado a { $position }
+shuffle-set = aleatorio { $state ->
+    [on] activado
+   *[off] desactivado
+}
+loop-set = repetición en { $mode }
+system-volume = volumen del sistema { $delta }
+system-muted = volumen del sistema silenciado
+system-unmuted = volumen del sistema reactivado
+
+plan-playback = { $verb ->
+    [play] reproduciría
+    [pause] pausaría
+    [stop] detendría
+   *[toggle] alternaría
+} { $player } y actualizaría nuestro publicador
+plan-publisher-only = { $verb ->
+    [play] reproduciría
+    [pause] pausaría
+    [stop] detendría
+   *[toggle] alternaría
+} solo nuestro publicador (sin reproductor externo)
+plan-next = saltaría { $player } a la siguiente pista
+plan-previous = saltaría { $player } a la pista anterior
+plan-raise = traería { $player } al frente
+plan-quit = pediría a { $player } que se cierre
+plan-seek = { $direction ->
+    [forward] adelantaría { $player } { $seconds } s
+   *[backward] retrocedería { $player } { $seconds } s
+}
+plan-seek-to = saltaría { $player } a { $position }
+plan-shuffle = { $state ->
+    [on] activaría
+   *[off] desactivaría
+} el aleatorio en { $player }
+plan-loop = pondría la repetición de { $player } en { $mode }
+plan-volume = cambiaría el volumen del sistema { $delta }
+plan-mute = { $mode ->
+    [mute] silenciaría el volumen del sistema
+    [unmute] reactivaría el volumen del sistema
+   *[toggle] alternaría el silencio del sistema
+}
+plan-wait = esperaría hasta { $seconds }s a un reproductor
+plan-launch = ejecutaría { $command } y esperaría hasta { $seconds }s a un reproductor
+plan-ignored = no haría nada (sin reproductor externo)
+
+no-player = no se encontró ningún reproductor externo
+no-player-ignored = nada que hacer (sin reproductor externo)
+locked = el volumen y la búsqueda están bloqueados
+cannot-control = el reproductor no se puede controlar
+cannot-next = el reproductor no puede pasar a la siguiente pista
+cannot-previous = el reproductor no puede volver a la pista anterior
+cannot-seek = el reproductor no permite buscar
+cannot-raise = el reproductor no se puede traer al frente
+cannot-quit = el reproductor no se puede cerrar a distancia
+cannot-shuffle = el reproductor no permite el modo aleatorio
+cannot-loop = el reproductor no permite repetir
+query-failed = no se pudo consultar el reproductor: { $error }
+status-failed = no se pudo leer el estado
+player-call-failed = falló la llamada al reproductor: { $error }
+seek-failed = no se pudo saltar: { $error }
+shuffle-failed = no se pudo cambiar el aleatorio: { $error }
+loop-failed = no se pudo cambiar la repetición: { $error }
+volume-failed = no se pudo cambiar el volumen: { $error }
+mute-failed = no se pudo cambiar el silencio: { $error }
+
+announce-track = { $title } de { $artist }
+announce-title = { $title }
+announce-nothing = no se está reproduciendo nada
+spoken-playing = { $player } está reproduciendo { $track }, { $progress }
+spoken-paused = { $player } está en pausa en { $track }, { $progress }
+spoken-stopped = { $player } está detenido
+spoken-nothing = No se está reproduciendo nada
+spoken-some-player = Un reproductor
+spoken-some-track = una pista desconocida
+spoken-just-started = recién empezada
+spoken-seconds-in = a los { $seconds } segundos
+spoken-one-minute-in = al minuto
+spoken-minutes-in = a los { $minutes } minutos
+tts-exited = el comando de voz terminó con { $status }
+tts-launch-failed = no se pudo iniciar el comando de voz: { $error }
@@ -0,0 +1,712 @@
+//! Controller commands shared by the HTTP handlers and background tasks.
+//!
+//! Each [`Action`] does the same work as its HTTP endpoint: update our own
+//! MPRIS publisher where relevant and forward the command to the controlled
+//! external player (or the system mixer for volume).
+
+use crate::config;
+use crate::demo::{self, DemoPlayer};
+use crate::error::{AppError, MutexExt};
+use crate::i18n::Message;
+use crate::no_player::{self, Policy};
+use crate::player::{find_player, find_player_by_selector, seek_step_for};
+use crate::remote::{self, RemotePlayer};
+use crate::template::format_duration;
+use crate::{alerts, metrics, timings, volume, AppState};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use mpris::{LoopStatus, PlaybackStatus, Player};
+use serde::{Deserialize, Serialize};
+use souvlaki::MediaPlayback;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Percentage points `volume_up` and `volume_down` change the volume by,
+/// from `MEDIA_CONTROL_VOLUME_STEP` (default 5)
+fn volume_step() -> i32 {
+    config::var("MEDIA_CONTROL_VOLUME_STEP")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|step: &i32| *step > 0)
+        .unwrap_or(5)
+}
+
+/// A command that can be run against the controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Previous,
+    SeekForward,
+    SeekBackward,
+    /// Move by this many milliseconds, backwards if negative
+    #[serde(skip)]
+    SeekBy(i64),
+    /// Jump to this many milliseconds into the track
+    #[serde(skip)]
+    SeekTo(u64),
+    /// Turn shuffle on or off, or flip it if None
+    #[serde(skip)]
+    SetShuffle(Option<bool>),
+    /// Set the loop mode, or step through None, Playlist and Track if None
+    #[serde(skip)]
+    SetLoop(Option<LoopStatus>),
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Unmute,
+    ToggleMute,
+    /// Bring the player's window to the front
+    Raise,
+    /// Ask the player to exit
+    Quit,
+}
+
+impl Action {
+    /// Parse an endpoint-style name such as `seek_forward`
+    pub fn from_name(name: &str) -> Option<Action> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+
+    /// Name used for metrics, e.g. `seek_forward`
+    pub fn name(self) -> String {
+        match self {
+            Action::SeekBy(_) | Action::SeekTo(_) => "seek".to_string(),
+            Action::SetShuffle(_) => "shuffle".to_string(),
+            Action::SetLoop(_) => "loop".to_string(),
+            _ => serde_json::to_value(self)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_else(|| format!("{self:?}")),
+        }
+    }
+
+    /// Whether `POST /lock` blocks this action: anything touching the volume
+    /// or the playback position
+    pub fn frozen_by_lock(self) -> bool {
+        !self.targets_player()
+            || matches!(
+                self,
+                Action::SeekForward | Action::SeekBackward | Action::SeekBy(_) | Action::SeekTo(_)
+            )
+    }
+
+    /// Whether this is play, pause, toggle or stop, which also update our
+    /// own publisher
+    pub fn is_playback(self) -> bool {
+        matches!(
+            self,
+            Action::Play | Action::Pause | Action::Toggle | Action::Stop
+        )
+    }
+
+    /// Whether this action is sent to a player (as opposed to the system mixer)
+    pub fn targets_player(self) -> bool {
+        !matches!(
+            self,
+            Action::VolumeUp
+                | Action::VolumeDown
+                | Action::Mute
+                | Action::Unmute
+                | Action::ToggleMute
+        )
+    }
+}
+
+/// Which player an action is sent to
+#[derive(Clone, Debug)]
+pub enum Target {
+    /// Whatever `find_player()` picks
+    Auto,
+    /// A specific player, by bus name, identity substring or alias
+    Player(String),
+}
+
+impl Target {
+    /// Whether this target is the demo player, when running with `--demo`
+    pub fn is_demo(&self, state: &AppState) -> bool {
+        state.demo.is_some()
+            && match self {
+                Target::Auto => true,
+                Target::Player(selector) => DemoPlayer::matches(selector),
+            }
+    }
+
+    /// The remote player this target names, unless an MPRIS player matches too
+    pub fn remote(&self) -> Option<Arc<dyn RemotePlayer>> {
+        match self {
+            Target::Auto => None,
+            Target::Player(selector) => {
+                remote::find(selector).filter(|_| find_player_by_selector(selector).is_none())
+            }
+        }
+    }
+
+    pub fn find(&self, state: &AppState) -> Option<Rc<Player>> {
+        match self {
+            Target::Auto => find_player(&state.pinned),
+            Target::Player(selector) => find_player_by_selector(selector),
+        }
+    }
+}
+
+/// Why an action couldn't be carried out
+#[derive(Debug)]
+pub enum ActionError {
+    /// There is no external player to send the command to
+    NoPlayer,
+    /// The player doesn't support this command
+    Unsupported(Message),
+    /// Talking to the player or mixer failed
+    Failed(Message),
+    /// Volume and seeking are locked with `POST /lock`
+    Locked,
+}
+
+impl ActionError {
+    /// Status code matching what the endpoints have always returned
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ActionError::NoPlayer => StatusCode::NOT_FOUND,
+            ActionError::Unsupported(_) => StatusCode::BAD_REQUEST,
+            ActionError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ActionError::Locked => StatusCode::LOCKED,
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        match self {
+            ActionError::NoPlayer => Message::new("no-player"),
+            ActionError::Locked => Message::new("locked"),
+            ActionError::Unsupported(msg) | ActionError::Failed(msg) => msg.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.message().fmt(f)
+    }
+}
+
+/// Turn an action result into the endpoint's response, in the first of
+/// `languages` we have a translation for
+pub fn respond(result: Result<Message, ActionError>, languages: &[String]) -> HttpResponse {
+    match result {
+        Ok(msg) => HttpResponse::Ok().body(msg.localize(languages)),
+        Err(e) => AppError::new(e.status(), e.message().localize(languages)).error_response(),
+    }
+}
+
+/// Run `action` on the auto-selected player
+pub fn run(state: &AppState, action: Action) -> Result<Message, ActionError> {
+    run_on(state, &Target::Auto, action)
+}
+
+/// Run `action` on `target`, returning the human-readable outcome
+pub fn run_on(state: &AppState, target: &Target, action: Action) -> Result<Message, ActionError> {
+    if action.frozen_by_lock() && state.lock.is_locked() {
+        return Err(ActionError::Locked);
+    }
+    let targets_player = action.targets_player();
+    if let Some(demo) = state
+        .demo
+        .as_ref()
+        .filter(|_| targets_player && target.is_demo(state))
+    {
+        let result = demo.run(state, action);
+        log_outcome(action, Some(demo::IDENTITY), &result);
+        metrics::record_command(Some(demo::IDENTITY), action, result.is_ok());
+        return result;
+    }
+    if let Some(remote) = target.remote().filter(|_| targets_player) {
+        let result = timings::time("remote", || remote::run(&*remote, action));
+        log_outcome(action, Some(remote.identity()), &result);
+        metrics::record_command(Some(remote.identity()), action, result.is_ok());
+        alerts::record_command(action, &result);
+        return result;
+    }
+    let (player, policy) = timings::time("selection", || {
+        let mut player = if targets_player {
+            target.find(state)
+        } else {
+            None
+        };
+        let policy = if targets_player && player.is_none() {
+            no_player::policy_for(action)
+        } else {
+            None
+        };
+        if matches!(policy, Some(Policy::Wait | Policy::Launch)) {
+            if policy == Some(Policy::Launch) {
+                no_player::launch();
+            }
+            player = no_player::wait_for(state, target, no_player::wait_time());
+        }
+        (player, policy)
+    });
+    let p = player.as_deref();
+    let publisher_only = policy == Some(Policy::Publisher);
+
+    let result = timings::time("dbus", || match action {
+        _ if targets_player
+            && p.is_none()
+            && !publisher_only
+            && (policy.is_some() || matches!(target, Target::Player(_))) =>
+        {
+            Err(ActionError::NoPlayer)
+        }
+        _ if publisher_only && !action.is_playback() => Ok(Message::new("no-player-ignored")),
+        Action::Play => play(state, p),
+        Action::Pause => pause(state, p),
+        Action::Toggle => toggle(state, p),
+        Action::Stop => stop(state, p),
+        Action::Next => next_track(p),
+        Action::Previous => prev_track(p),
+        Action::SeekForward => seek_forward(p),
+        Action::SeekBackward => seek_backward(p),
+        Action::SeekBy(ms) => seek_by(p, ms),
+        Action::SeekTo(ms) => seek_to(p, ms),
+        Action::SetShuffle(shuffle) => set_shuffle(p, shuffle),
+        Action::SetLoop(status) => set_loop(p, status),
+        Action::VolumeUp => change_volume(volume_step()),
+        Action::VolumeDown => change_volume(-volume_step()),
+        Action::Mute => set_mute(Some(true)),
+        Action::Unmute => set_mute(Some(false)),
+        Action::ToggleMute => set_mute(None),
+        Action::Raise => raise(p),
+        Action::Quit => quit(p),
+    });
+    log_outcome(action, p.map(Player::identity), &result);
+    if targets_player {
+        metrics::record_command(p.map(Player::identity), action, result.is_ok());
+    }
+    alerts::record_command(action, &result);
+    result
+}
+
+/// Log what a command did, and on which player
+fn log_outcome(action: Action, player: Option<&str>, result: &Result<Message, ActionError>) {
+    match result {
+        Ok(message) => info!(action = %action.name(), player, "{message}"),
+        Err(e) => warn!(action = %action.name(), player, "Command failed: {e}"),
+    }
+}
+
+/// Work out what `action` would do without touching any player or the mixer.
+///
+/// Runs the same player selection and capability checks as [`run_on`], so the
+/// result (or error) matches what a real call would produce up to the point of
+/// execution.
+pub fn plan(state: &AppState, target: &Target, action: Action) -> Result<Message, ActionError> {
+    let describe = |p: &Player| format!("{} ({})", p.identity(), p.bus_name());
+    let check = |ok: Result<bool, mpris::DBusError>, unsupported: &'static str| match ok {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ActionError::Unsupported(Message::new(unsupported))),
+        Err(e) => Err(ActionError::Failed(
+            Message::new("query-failed").arg("error", e),
+        )),
+    };
+
+    if action.frozen_by_lock() && state.lock.is_locked() {
+        return Err(ActionError::Locked);
+    }
+    if let Some(demo) = state
+        .demo
+        .as_ref()
+        .filter(|_| action.targets_player() && target.is_demo(state))
+    {
+        return demo.plan(action);
+    }
+    if let Some(remote) = target.remote().filter(|_| action.targets_player()) {
+        return remote::plan(&*remote, action);
+    }
+    if let Some(policy) = action
+        .targets_player()
+        .then(|| no_player::policy_for(action))
+        .flatten()
+        .filter(|_| target.find(state).is_none())
+    {
+        let seconds = no_player::wait_time().as_secs();
+        match policy {
+            Policy::Fail => return Err(ActionError::NoPlayer),
+            Policy::Wait => return Ok(Message::new("plan-wait").arg("seconds", seconds)),
+            Policy::Launch => {
+                return Ok(Message::new("plan-launch")
+                    .arg("command", no_player::launch_command().unwrap_or_default())
+                    .arg("seconds", seconds))
+            }
+            Policy::Publisher if !action.is_playback() => return Ok(Message::new("plan-ignored")),
+            Policy::Publisher => {}
+        }
+    }
+    match action {
+        Action::Play | Action::Pause | Action::Toggle | Action::Stop => {
+            let verb = match action {
+                Action::Play => "play",
+                Action::Pause => "pause",
+                Action::Stop => "stop",
+                _ => "toggle",
+            };
+            match target.find(state) {
+                Some(p) => {
+                    check(p.can_control(), "cannot-control")?;
+                    Ok(Message::new("plan-playback")
+                        .arg("verb", verb)
+                        .arg("player", describe(&p)))
+                }
+                None if matches!(target, Target::Auto)
+                    || no_player::policy_for(action) == Some(Policy::Publisher) =>
+                {
+                    Ok(Message::new("plan-publisher-only").arg("verb", verb))
+                }
+                None => Err(ActionError::NoPlayer),
+            }
+        }
+        Action::Next | Action::Previous => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            if action == Action::Next {
+                check(p.can_go_next(), "cannot-next")?;
+                Ok(Message::new("plan-next").arg("player", describe(&p)))
+            } else {
+                check(p.can_go_previous(), "cannot-previous")?;
+                Ok(Message::new("plan-previous").arg("player", describe(&p)))
+            }
+        }
+        Action::SeekForward | Action::SeekBackward => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            check(p.can_seek(), "cannot-seek")?;
+            let direction = if action == Action::SeekForward {
+                "forward"
+            } else {
+                "backward"
+            };
+            let step = seek_step_for(p.identity());
+            Ok(Message::new("plan-seek")
+                .arg("player", describe(&p))
+                .arg("direction", direction)
+                .arg("seconds", step.as_secs()))
+        }
+        Action::SeekBy(ms) => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            check(p.can_seek(), "cannot-seek")?;
+            Ok(Message::new("plan-seek")
+                .arg("player", describe(&p))
+                .arg("direction", if ms < 0 { "backward" } else { "forward" })
+                .arg("seconds", ms.unsigned_abs() as f64 / 1000.0))
+        }
+        Action::SeekTo(ms) => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            check(p.can_seek(), "cannot-seek")?;
+            Ok(Message::new("plan-seek-to")
+                .arg("player", describe(&p))
+                .arg("position", format_duration(Duration::from_millis(ms))))
+        }
+        Action::SetShuffle(shuffle) => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            Ok(Message::new("plan-shuffle")
+                .arg("player", describe(&p))
+                .arg("state", on_off(shuffle_target(&p, shuffle)?)))
+        }
+        Action::SetLoop(status) => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            Ok(Message::new("plan-loop")
+                .arg("player", describe(&p))
+                .arg("mode", format!("{:?}", loop_target(&p, status)?)))
+        }
+        Action::VolumeUp => {
+            Ok(Message::new("plan-volume").arg("delta", format!("{:+}%", volume_step())))
+        }
+        Action::VolumeDown => {
+            Ok(Message::new("plan-volume").arg("delta", format!("{:+}%", -volume_step())))
+        }
+        Action::Mute => Ok(Message::new("plan-mute").arg("mode", "mute")),
+        Action::Unmute => Ok(Message::new("plan-mute").arg("mode", "unmute")),
+        Action::ToggleMute => Ok(Message::new("plan-mute").arg("mode", "toggle")),
+        Action::Raise => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            check(p.can_raise(), "cannot-raise")?;
+            Ok(Message::new("plan-raise").arg("player", describe(&p)))
+        }
+        Action::Quit => {
+            let p = target.find(state).ok_or(ActionError::NoPlayer)?;
+            check(p.can_quit(), "cannot-quit")?;
+            Ok(Message::new("plan-quit").arg("player", describe(&p)))
+        }
+    }
+}
+
+/// Record `playback` as our own state and publish it
+pub fn set_our_playback(state: &AppState, playback: MediaPlayback) {
+    timings::time("publisher", || {
+        let mut ctrls = state.controls.locked();
+        let mut pb = state.copy_playback.locked();
+        *pb = playback;
+        // The publisher isn't registered in demo mode
+        if state.demo.is_none() {
+            if let Err(e) = ctrls.set_playback(pb.clone()) {
+                error!("Couldn't update our publisher: {e:?}");
+            }
+        }
+    })
+}
+
+/// Update *your* MPRIS state and tell the active player to play
+fn play(state: &AppState, player: Option<&Player>) -> Result<Message, ActionError> {
+    // 1) Update your own publisher state
+    set_our_playback(state, MediaPlayback::Playing { progress: None });
+    // 2) Tell any other active player to play
+    if let Some(p) = player {
+        let _ = p.play();
+    }
+    Ok(Message::new("playing"))
+}
+
+/// Same pattern for pause
+fn pause(state: &AppState, player: Option<&Player>) -> Result<Message, ActionError> {
+    set_our_playback(state, MediaPlayback::Paused { progress: None });
+    if let Some(p) = player {
+        let _ = p.pause();
+    }
+    Ok(Message::new("paused"))
+}
+
+/// Same pattern for stop
+fn stop(state: &AppState, player: Option<&Player>) -> Result<Message, ActionError> {
+    set_our_playback(state, MediaPlayback::Stopped);
+    if let Some(p) = player {
+        let _ = p.stop();
+    }
+    Ok(Message::new("stopped"))
+}
+
+/// If the external player is playing, pause it; otherwise play it.
+/// Also update your own MPRIS service to match.
+fn toggle(state: &AppState, player: Option<&Player>) -> Result<Message, ActionError> {
+    // 1) Use the first real player
+    let Some(player) = player else {
+        // no external player found → just play
+        set_our_playback(state, MediaPlayback::Playing { progress: None });
+        return Ok(Message::new("playing-no-player"));
+    };
+    // 2) Query its status
+    match player.get_playback_status() {
+        Ok(PlaybackStatus::Playing) => {
+            let _ = player.pause();
+            set_our_playback(state, MediaPlayback::Paused { progress: None });
+            Ok(Message::new("paused"))
+        }
+        Ok(_) => {
+            let _ = player.play();
+            set_our_playback(state, MediaPlayback::Playing { progress: None });
+            Ok(Message::new("playing"))
+        }
+        Err(e) => {
+            error!("Failed to get playback status: {e}");
+            Err(ActionError::Failed(Message::new("status-failed")))
+        }
+    }
+}
+
+/// Bring the player's window to the foreground
+fn raise(player: Option<&Player>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    if !p.can_raise().unwrap_or(false) {
+        return Err(ActionError::Unsupported(Message::new("cannot-raise")));
+    }
+    if let Err(e) = p.raise() {
+        return Err(ActionError::Failed(
+            Message::new("player-call-failed").arg("error", e),
+        ));
+    }
+    Ok(Message::new("raised").arg("player", p.identity()))
+}
+
+/// Ask the player to shut down
+fn quit(player: Option<&Player>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    if !p.can_quit().unwrap_or(false) {
+        return Err(ActionError::Unsupported(Message::new("cannot-quit")));
+    }
+    if let Err(e) = p.quit() {
+        return Err(ActionError::Failed(
+            Message::new("player-call-failed").arg("error", e),
+        ));
+    }
+    Ok(Message::new("quit-player").arg("player", p.identity()))
+}
+
+/// Skip to next track
+fn next_track(player: Option<&Player>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    let _ = p.next();
+    Ok(Message::new("next-track"))
+}
+
+/// Skip to previous track
+fn prev_track(player: Option<&Player>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    let _ = p.previous();
+    Ok(Message::new("previous-track"))
+}
+
+/// Move forward within the current track by the player's seek step
+fn seek_forward(player: Option<&Player>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    if !p.can_seek().unwrap_or(false) {
+        return Err(ActionError::Unsupported(Message::new("cannot-seek")));
+    }
+    let step = seek_step_for(p.identity());
+    let _ = p.seek_forwards(&step);
+    Ok(Message::new("seeked")
+        .arg("direction", "forward")
+        .arg("seconds", step.as_secs()))
+}
+
+/// Move back within the current track by the player's seek step
+fn seek_backward(player: Option<&Player>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    if !p.can_seek().unwrap_or(false) {
+        return Err(ActionError::Unsupported(Message::new("cannot-seek")));
+    }
+    let step = seek_step_for(p.identity());
+    let _ = p.seek_backwards(&step);
+    Ok(Message::new("seeked")
+        .arg("direction", "backward")
+        .arg("seconds", step.as_secs()))
+}
+
+/// Move within the current track by `ms` milliseconds
+fn seek_by(player: Option<&Player>, ms: i64) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    if !p.can_seek().unwrap_or(false) {
+        return Err(ActionError::Unsupported(Message::new("cannot-seek")));
+    }
+    let _ = p.seek(ms.saturating_mul(1000));
+    Ok(Message::new("seeked")
+        .arg("direction", if ms < 0 { "backward" } else { "forward" })
+        .arg("seconds", ms.unsigned_abs() as f64 / 1000.0))
+}
+
+/// Jump to `ms` milliseconds into the current track
+fn seek_to(player: Option<&Player>, ms: u64) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    if !p.can_seek().unwrap_or(false) {
+        return Err(ActionError::Unsupported(Message::new("cannot-seek")));
+    }
+    let position = Duration::from_millis(ms);
+    if let Err(e) = set_position(p, position) {
+        return Err(ActionError::Failed(
+            Message::new("seek-failed").arg("error", e),
+        ));
+    }
+    Ok(Message::new("seeked-to").arg("position", format_duration(position)))
+}
+
+/// Jump a player to `position` in its current track
+pub(crate) fn set_position(player: &Player, position: Duration) -> Result<(), mpris::DBusError> {
+    // SetPosition needs the track id; without one, seek by the difference
+    match player.get_metadata().ok().and_then(|m| m.track_id()) {
+        Some(id) => player.set_position(id, &position),
+        None => player.get_position().and_then(|current| {
+            player.seek(position.as_micros() as i64 - current.as_micros() as i64)
+        }),
+    }
+}
+
+/// `on` or `off`, for messages
+pub fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// The loop mode that follows `status` when stepping through them
+pub fn next_loop_status(status: LoopStatus) -> LoopStatus {
+    match status {
+        LoopStatus::None => LoopStatus::Playlist,
+        LoopStatus::Playlist => LoopStatus::Track,
+        LoopStatus::Track => LoopStatus::None,
+    }
+}
+
+/// Shuffle state `wanted` leads to, flipping the current one if None
+fn shuffle_target(p: &Player, wanted: Option<bool>) -> Result<bool, ActionError> {
+    match p.checked_get_shuffle() {
+        Ok(Some(current)) => Ok(wanted.unwrap_or(!current)),
+        Ok(None) => Err(ActionError::Unsupported(Message::new("cannot-shuffle"))),
+        Err(e) => Err(ActionError::Failed(
+            Message::new("query-failed").arg("error", e),
+        )),
+    }
+}
+
+/// Loop mode `wanted` leads to, stepping on from the current one if None
+fn loop_target(p: &Player, wanted: Option<LoopStatus>) -> Result<LoopStatus, ActionError> {
+    match p.checked_get_loop_status() {
+        Ok(Some(current)) => Ok(wanted.unwrap_or_else(|| next_loop_status(current))),
+        Ok(None) => Err(ActionError::Unsupported(Message::new("cannot-loop"))),
+        Err(e) => Err(ActionError::Failed(
+            Message::new("query-failed").arg("error", e),
+        )),
+    }
+}
+
+/// Turn shuffle on or off
+fn set_shuffle(player: Option<&Player>, wanted: Option<bool>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    let shuffle = shuffle_target(p, wanted)?;
+    if let Err(e) = p.set_shuffle(shuffle) {
+        return Err(ActionError::Failed(
+            Message::new("shuffle-failed").arg("error", e),
+        ));
+    }
+    Ok(Message::new("shuffle-set").arg("state", on_off(shuffle)))
+}
+
+/// Change the loop mode
+fn set_loop(player: Option<&Player>, wanted: Option<LoopStatus>) -> Result<Message, ActionError> {
+    let p = player.ok_or(ActionError::NoPlayer)?;
+    let status = loop_target(p, wanted)?;
+    if let Err(e) = p.set_loop_status(status) {
+        return Err(ActionError::Failed(
+            Message::new("loop-failed").arg("error", e),
+        ));
+    }
+    Ok(Message::new("loop-set").arg("mode", format!("{status:?}")))
+}
+
+/// Nudge the default sink's volume by `delta` percentage points
+fn change_volume(delta: i32) -> Result<Message, ActionError> {
+    match timings::time("audio", || volume::backend().adjust_volume(delta)) {
+        Ok(_) => Ok(Message::new("system-volume").arg("delta", format!("{delta:+}%"))),
+        Err(e) => Err(ActionError::Failed(
+            Message::new("volume-failed").arg("error", e),
+        )),
+    }
+}
+
+/// Mute or unmute the default sink, or flip its mute flag for `None`
+fn set_mute(muted: Option<bool>) -> Result<Message, ActionError> {
+    let backend = volume::backend();
+    let result = timings::time("audio", || match muted {
+        Some(muted) => backend.set_mute(muted).map(|()| muted),
+        None => backend.toggle_mute(),
+    });
+    match result {
+        Ok(true) => Ok(Message::new("system-muted")),
+        Ok(false) => Ok(Message::new("system-unmuted")),
+        Err(e) => Err(ActionError::Failed(
+            Message::new("mute-failed").arg("error", e),
+        )),
+    }
+}
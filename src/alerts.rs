@@ -0,0 +1,213 @@
+//! Alerts for a service that has stopped working.
+//!
+//! Enabled with `MEDIA_CONTROL_ALERTS=1`. An alert fires when
+//!
+//! - `command_failures`: `MEDIA_CONTROL_ALERT_COMMAND_FAILURES` (default 3)
+//!   commands in a row failed or found no player,
+//! - `session_bus`: the D-Bus session bus couldn't be reached, or
+//! - `audio_backend`: the volume backend kept returning errors,
+//!
+//! the last two checked every 30 seconds and only once the problem has
+//! lasted `MEDIA_CONTROL_ALERT_AFTER_MINUTES` (default 5). It clears with a
+//! second notice once things work again. Each change is logged, passed to
+//! `MEDIA_CONTROL_ALERT_COMMAND` (run with `sh -c`, with
+//! `MEDIA_CONTROL_ALERT`, `MEDIA_CONTROL_ALERT_STATE` set to `firing` or
+//! `resolved`, and `MEDIA_CONTROL_ALERT_MESSAGE`), e.g. a `curl` to a chat
+//! webhook, and emailed when email is set up.
+
+use crate::actions::{Action, ActionError};
+use crate::error::MutexExt;
+use crate::i18n::Message;
+use crate::{config, registry, volume, AppState};
+use actix_web::web;
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// How often the session bus and the audio backend are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Default for `MEDIA_CONTROL_ALERT_COMMAND_FAILURES`
+const DEFAULT_COMMAND_FAILURES: u32 = 3;
+/// Default for `MEDIA_CONTROL_ALERT_AFTER_MINUTES`
+const DEFAULT_AFTER_MINUTES: f64 = 5.0;
+
+/// Whether `MEDIA_CONTROL_ALERTS` is set
+fn enabled() -> bool {
+    config::var("MEDIA_CONTROL_ALERTS")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+fn command_failure_threshold() -> u32 {
+    config::var("MEDIA_CONTROL_ALERT_COMMAND_FAILURES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_COMMAND_FAILURES)
+}
+
+fn grace_period() -> Duration {
+    let minutes = config::var("MEDIA_CONTROL_ALERT_AFTER_MINUTES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_AFTER_MINUTES);
+    Duration::try_from_secs_f64(minutes * 60.0).unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Alerts {
+    // Commands failed in a row
+    command_failures: u32,
+    // Since when each check has been failing
+    failing_since: HashMap<&'static str, Instant>,
+    // Alerts currently firing
+    firing: Vec<&'static str>,
+}
+
+fn alerts() -> &'static Mutex<Alerts> {
+    static ALERTS: OnceLock<Mutex<Alerts>> = OnceLock::new();
+    ALERTS.get_or_init(Mutex::default)
+}
+
+/// Fire or resolve `alert`, notifying only when that changes anything
+fn set(alert: &'static str, failing: bool, message: String) {
+    {
+        let mut a = alerts().locked();
+        let firing = a.firing.contains(&alert);
+        if failing == firing {
+            return;
+        }
+        if failing {
+            a.firing.push(alert);
+        } else {
+            a.firing.retain(|f| *f != alert);
+        }
+    }
+    notify(alert, if failing { "firing" } else { "resolved" }, message);
+}
+
+/// Tell everyone listening that `alert` changed `state`
+fn notify(alert: &'static str, state: &'static str, message: String) {
+    if state == "firing" {
+        warn!("Alert {alert} firing: {message}");
+    } else {
+        info!("Alert {alert} resolved: {message}");
+    }
+    if let Ok(cmd) = config::var("MEDIA_CONTROL_ALERT_COMMAND") {
+        let spawned = Command::new("sh")
+            .args(["-c", &cmd])
+            .env("MEDIA_CONTROL_ALERT", alert)
+            .env("MEDIA_CONTROL_ALERT_STATE", state)
+            .env("MEDIA_CONTROL_ALERT_MESSAGE", &message)
+            .spawn();
+        match spawned {
+            // Reap it whenever it exits so it doesn't linger as a zombie
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(e) => error!("Couldn't run MEDIA_CONTROL_ALERT_COMMAND: {e}"),
+        }
+    }
+    #[cfg(feature = "email")]
+    crate::email::notify(
+        if state == "firing" {
+            &crate::email::ALERT_FIRING
+        } else {
+            &crate::email::ALERT_RESOLVED
+        },
+        [("alert", alert.to_string()), ("message", message)].into(),
+    );
+}
+
+/// Count an action's outcome towards `command_failures`
+pub fn record_command(action: Action, result: &Result<Message, ActionError>) {
+    if !enabled() {
+        return;
+    }
+    let error = match result {
+        Ok(_) => None,
+        Err(e @ (ActionError::Failed(_) | ActionError::NoPlayer)) => Some(e.to_string()),
+        // The client's mistake, not the service's
+        Err(_) => return,
+    };
+    let failures = {
+        let mut a = alerts().locked();
+        a.command_failures = if error.is_some() {
+            a.command_failures + 1
+        } else {
+            0
+        };
+        a.command_failures
+    };
+    match error {
+        Some(e) if failures >= command_failure_threshold() => set(
+            "command_failures",
+            true,
+            format!(
+                "{failures} commands in a row failed, the last was {}: {e}",
+                action.name()
+            ),
+        ),
+        Some(_) => {}
+        None => set(
+            "command_failures",
+            false,
+            format!("{} worked again", action.name()),
+        ),
+    }
+}
+
+/// Fire `alert` once `check` has failed for the grace period, and resolve
+/// it as soon as it passes
+fn watch(alert: &'static str, check: Result<(), String>, recovered: &str) {
+    let failing_for = {
+        let mut a = alerts().locked();
+        match &check {
+            Ok(()) => {
+                a.failing_since.remove(alert);
+                None
+            }
+            Err(_) => Some(
+                a.failing_since
+                    .entry(alert)
+                    .or_insert_with(Instant::now)
+                    .elapsed(),
+            ),
+        }
+    };
+    match (check, failing_for) {
+        (Err(e), Some(elapsed)) if elapsed >= grace_period() => set(
+            alert,
+            true,
+            format!("failing for {} minutes: {e}", elapsed.as_secs() / 60),
+        ),
+        (Ok(()), _) => set(alert, false, recovered.to_string()),
+        _ => {}
+    }
+}
+
+/// Start checking the session bus and the audio backend if alerts are enabled
+pub fn spawn_monitor(state: web::Data<AppState>) {
+    if !enabled() || state.demo.is_some() {
+        return;
+    }
+    info!("Alerts enabled");
+    thread::spawn(|| loop {
+        thread::sleep(CHECK_INTERVAL);
+        watch(
+            "session_bus",
+            registry::players().map(|_| ()),
+            "the session bus is reachable again",
+        );
+        let backend = volume::backend();
+        let recovered = format!("the {} volume backend works again", backend.name());
+        watch(
+            "audio_backend",
+            backend.get_volume().map(|_| ()),
+            &recovered,
+        );
+    });
+}
@@ -0,0 +1,304 @@
+//! PulseAudio/PipeWire helpers built on `pactl`, plus BlueZ via `bluetoothctl`.
+
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Run `pactl` and return its stdout
+pub fn pactl(args: &[&str]) -> Result<String, String> {
+    run("pactl", args)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let out = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to launch {program}: {e}"))?;
+    if out.status.success() {
+        Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+    } else {
+        Err(format!(
+            "{program} exited with {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))
+    }
+}
+
+/// Default sink volume as a percentage (average of its channels)
+pub fn get_sink_volume() -> Result<u32, String> {
+    let out = pactl(&["get-sink-volume", "@DEFAULT_SINK@"])?;
+    parse_volume_percent(&out).ok_or_else(|| format!("couldn't parse pactl output: {out:?}"))
+}
+
+/// Set the default sink volume to an absolute percentage
+pub fn set_sink_volume(percent: u32) -> Result<(), String> {
+    pactl(&["set-sink-volume", "@DEFAULT_SINK@", &format!("{percent}%")]).map(|_| ())
+}
+
+/// Whether the default sink is muted
+pub fn get_sink_mute() -> Result<bool, String> {
+    // `Mute: yes` or `Mute: no`
+    let out = pactl(&["get-sink-mute", "@DEFAULT_SINK@"])?;
+    match out.split_whitespace().last() {
+        Some("yes") => Ok(true),
+        Some("no") => Ok(false),
+        _ => Err(format!("couldn't parse pactl output: {out:?}")),
+    }
+}
+
+/// PulseAudio's raw value for 100%
+const VOLUME_NORM: f64 = 65536.0;
+
+/// Default sink volume as an unrounded percentage, from the raw channel values
+pub fn get_sink_volume_exact() -> Result<f64, String> {
+    let out = pactl(&["get-sink-volume", "@DEFAULT_SINK@"])?;
+    // The raw value is the number right before each `/`
+    let words: Vec<&str> = out.split_whitespace().collect();
+    let raw: Vec<f64> = words
+        .windows(2)
+        .filter(|w| w[1] == "/")
+        .filter_map(|w| w[0].parse::<u32>().ok())
+        .map(f64::from)
+        .collect();
+    if raw.is_empty() {
+        return Err(format!("couldn't parse pactl output: {out:?}"));
+    }
+    Ok(raw.iter().sum::<f64>() / raw.len() as f64 * 100.0 / VOLUME_NORM)
+}
+
+/// Set the default sink volume to a fractional percentage
+pub fn set_sink_volume_exact(percent: f64) -> Result<(), String> {
+    let raw = (percent.max(0.0) * VOLUME_NORM / 100.0).round() as u32;
+    pactl(&["set-sink-volume", "@DEFAULT_SINK@", &raw.to_string()]).map(|_| ())
+}
+
+/// Mute or unmute the default sink
+pub fn set_sink_mute(muted: bool) -> Result<(), String> {
+    let flag = if muted { "1" } else { "0" };
+    pactl(&["set-sink-mute", "@DEFAULT_SINK@", flag]).map(|_| ())
+}
+
+/// Average the `NN%` channel values in pactl's volume output, e.g.
+/// `Volume: front-left: 42598 /  65% / -11.23 dB,   front-right: ...`
+fn parse_volume_percent(out: &str) -> Option<u32> {
+    let percents: Vec<u32> = out
+        .split_whitespace()
+        .filter_map(|w| w.strip_suffix('%'))
+        .filter_map(|n| n.parse().ok())
+        .collect();
+    if percents.is_empty() {
+        return None;
+    }
+    Some(percents.iter().sum::<u32>() / percents.len() as u32)
+}
+
+/// One stream playing into a sink
+pub struct SinkInput {
+    pub index: u32,
+    pub properties: Vec<(String, String)>,
+    // Percent, averaged over channels
+    pub volume: Option<u32>,
+    pub muted: Option<bool>,
+}
+
+impl SinkInput {
+    /// Look up a `Properties:` entry such as `application.name`
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Parse `pactl list sink-inputs` into index, volume, mute and properties
+pub fn list_sink_inputs() -> Result<Vec<SinkInput>, String> {
+    let out = pactl(&["list", "sink-inputs"])?;
+    let mut inputs: Vec<SinkInput> = Vec::new();
+    for line in out.lines() {
+        let trimmed = line.trim();
+        if let Some(index) = trimmed.strip_prefix("Sink Input #") {
+            if let Ok(index) = index.parse() {
+                inputs.push(SinkInput {
+                    index,
+                    properties: Vec::new(),
+                    volume: None,
+                    muted: None,
+                });
+            }
+        } else if let (Some(current), Some(volume)) =
+            (inputs.last_mut(), trimmed.strip_prefix("Volume:"))
+        {
+            current.volume = parse_volume_percent(volume);
+        } else if let (Some(current), Some(mute)) =
+            (inputs.last_mut(), trimmed.strip_prefix("Mute:"))
+        {
+            current.muted = Some(mute.trim() == "yes");
+        } else if let (Some(current), Some((k, v))) = (inputs.last_mut(), trimmed.split_once(" = "))
+        {
+            current
+                .properties
+                .push((k.to_string(), v.trim_matches('"').to_string()));
+        }
+    }
+    Ok(inputs)
+}
+
+/// Sink inputs that appear to belong to the player with this MPRIS identity
+pub fn sink_inputs_for_player(identity: &str) -> Result<Vec<SinkInput>, String> {
+    let identity = identity.to_lowercase();
+    Ok(list_sink_inputs()?
+        .into_iter()
+        .filter(|input| {
+            ["application.name", "application.process.binary"]
+                .iter()
+                .filter_map(|key| input.property(key))
+                .any(|v| {
+                    let v = v.to_lowercase();
+                    v.contains(&identity) || identity.contains(&v)
+                })
+        })
+        .collect())
+}
+
+/// Names of all sinks, from `pactl list short sinks`
+pub fn list_sink_names() -> Result<Vec<String>, String> {
+    Ok(pactl(&["list", "short", "sinks"])?
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1).map(str::to_string))
+        .collect())
+}
+
+/// Set every channel of one stream to `percent`
+pub fn set_sink_input_volume(index: u32, percent: u32) -> Result<(), String> {
+    pactl(&[
+        "set-sink-input-volume",
+        &index.to_string(),
+        &format!("{percent}%"),
+    ])
+    .map(|_| ())
+}
+
+/// Mute or unmute one stream
+pub fn set_sink_input_mute(index: u32, muted: bool) -> Result<(), String> {
+    let flag = if muted { "1" } else { "0" };
+    pactl(&["set-sink-input-mute", &index.to_string(), flag]).map(|_| ())
+}
+
+/// One output or input device, from `pactl list sinks` or `pactl list sources`
+pub struct SinkInfo {
+    pub name: String,
+    pub description: Option<String>,
+    pub volume: Option<u32>,
+    pub muted: Option<bool>,
+    // Sources only: the sink a monitor source records from
+    pub monitor_of: Option<String>,
+}
+
+/// Parse `pactl list sinks` into name, description, volume and mute
+pub fn list_sinks() -> Result<Vec<SinkInfo>, String> {
+    list_devices("sinks", "Sink #")
+}
+
+/// Parse `pactl list sources` like [`list_sinks`], noting monitor sources
+pub fn list_sources() -> Result<Vec<SinkInfo>, String> {
+    list_devices("sources", "Source #")
+}
+
+fn list_devices(kind: &str, header: &str) -> Result<Vec<SinkInfo>, String> {
+    let out = pactl(&["list", kind])?;
+    let mut devices: Vec<SinkInfo> = Vec::new();
+    for line in out.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with(header) {
+            devices.push(SinkInfo {
+                name: String::new(),
+                description: None,
+                volume: None,
+                muted: None,
+                monitor_of: None,
+            });
+            continue;
+        }
+        let Some(current) = devices.last_mut() else {
+            continue;
+        };
+        if let Some(name) = trimmed.strip_prefix("Name:") {
+            current.name = name.trim().to_string();
+        } else if let Some(description) = trimmed.strip_prefix("Description:") {
+            current.description = Some(description.trim().to_string());
+        } else if let Some(volume) = trimmed.strip_prefix("Volume:") {
+            current.volume = parse_volume_percent(volume);
+        } else if let Some(mute) = trimmed.strip_prefix("Mute:") {
+            current.muted = Some(mute.trim() == "yes");
+        } else if let Some(sink) = trimmed.strip_prefix("Monitor of Sink:") {
+            current.monitor_of = Some(sink.trim())
+                .filter(|s| *s != "n/a")
+                .map(str::to_string);
+        }
+    }
+    Ok(devices)
+}
+
+/// Name of the default sink, from `pactl info`
+pub fn default_sink_name() -> Result<String, String> {
+    let out = pactl(&["info"])?;
+    out.lines()
+        .find_map(|line| line.strip_prefix("Default Sink:"))
+        .map(|name| name.trim().to_string())
+        .ok_or_else(|| "pactl info didn't name a default sink".to_string())
+}
+
+/// Name of the default source, from `pactl info`
+pub fn default_source_name() -> Result<String, String> {
+    let out = pactl(&["info"])?;
+    out.lines()
+        .find_map(|line| line.strip_prefix("Default Source:"))
+        .map(|name| name.trim().to_string())
+        .ok_or_else(|| "pactl info didn't name a default source".to_string())
+}
+
+/// Make `sink` the default output
+pub fn set_default_sink(sink: &str) -> Result<(), String> {
+    pactl(&["set-default-sink", sink]).map(|_| ())
+}
+
+/// Move a stream to another sink
+pub fn move_sink_input(index: u32, sink: &str) -> Result<(), String> {
+    pactl(&["move-sink-input", &index.to_string(), sink]).map(|_| ())
+}
+
+/// Check for a well-formed `AA:BB:CC:DD:EE:FF` Bluetooth address
+pub fn is_bluetooth_address(mac: &str) -> bool {
+    let parts: Vec<_> = mac.split(':').collect();
+    parts.len() == 6
+        && parts
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Connect a paired Bluetooth device and wait for its sink to appear.
+///
+/// BlueZ sinks are named `bluez_sink.AA_BB_...` (PulseAudio) or
+/// `bluez_output.AA_BB_...` (PipeWire), so we look for the underscored address.
+pub fn connect_bluetooth_sink(mac: &str, wait: Duration) -> Result<String, String> {
+    run("bluetoothctl", &["connect", mac])?;
+
+    let needle = mac.replace(':', "_").to_uppercase();
+    let deadline = Instant::now() + wait;
+    loop {
+        if let Some(sink) = list_sink_names()?
+            .into_iter()
+            .find(|s| s.to_uppercase().contains(&needle))
+        {
+            return Ok(sink);
+        }
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "connected {mac} but no sink appeared within {wait:?}"
+            ));
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
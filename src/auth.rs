@@ -0,0 +1,769 @@
+//! API token checks.
+//!
+//! A request presents at most one token: `Authorization: Bearer <token>`, or
+//! on the streaming routes and the simple GET API a query parameter, which
+//! only counts without a Bearer header. [`presented`] reads
+//! it from the request, and a [`Verifier`] asks each of its
+//! [`TokenSource`]s in turn what [`Scope`] the token grants. The middleware
+//! only enforces the answer, so the rules can be tested without a server,
+//! and other ways of proving who's calling (JWTs, client certificates) fit
+//! in as further sources.
+//!
+//! The standard sources are `MEDIA_CONTROL_API_TOKEN`, with full access,
+//! the tokens in `MEDIA_CONTROL_SCOPED_TOKENS`, which may read everything
+//! but only use the kinds of control they're given, and the guest token
+//! from `POST /admin/guest`, which may use everything but `/admin`.
+
+use crate::actions::Action;
+use crate::error::AppError;
+use crate::guest::GuestAccess;
+use crate::{config, logging, rate_limit};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Routes that also accept the token as `?access_token=`, because
+/// `EventSource` and browser WebSockets can't send an `Authorization` header
+const QUERY_TOKEN_ROUTES: &[&str] = &["/events", "/ws"];
+
+/// GET routes served without a token: probes for load balancers and
+/// orchestrators, the API description, and the remote control page, which
+/// asks for a token itself
+const PUBLIC_ROUTES: &[&str] = &["/healthz", "/readyz", "/openapi.json", "/docs", "/ui"];
+
+/// What a valid token may do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Every endpoint
+    Full,
+    /// Everything except the `/admin` endpoints
+    Guest,
+    /// Reading state, and only the given kinds of control
+    Limited(Grants),
+}
+
+impl Scope {
+    pub fn allows(self, method: &Method, path: &str) -> bool {
+        self.covers(area(method, path))
+    }
+
+    /// Whether the scope reaches into `area`
+    pub fn covers(self, area: Area) -> bool {
+        match self {
+            Scope::Full => true,
+            Scope::Guest => area != Area::Admin,
+            Scope::Limited(grants) => match area {
+                Area::Read => true,
+                Area::Playback => grants.playback,
+                Area::Volume => grants.volume,
+                Area::AudioRouting => grants.audio_routing,
+                Area::Other | Area::Admin => false,
+            },
+        }
+    }
+
+    /// The scope the auth middleware let `req` in with. Reading only if it
+    /// didn't run, so a missing scope never grants more.
+    pub fn of(req: &HttpRequest) -> Scope {
+        req.extensions()
+            .get::<Scope>()
+            .copied()
+            .unwrap_or(Scope::Limited(Grants::default()))
+    }
+}
+
+/// The kinds of control a limited token has
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Grants {
+    // Play, pause, skip, seek, the queue, shuffle, loop, the sleep timer and
+    // picking a player
+    pub playback: bool,
+    // System, application and player volume, and muting
+    pub volume: bool,
+    // The default output device and Bluetooth speakers
+    pub audio_routing: bool,
+}
+
+impl Grants {
+    /// Parse a list like `playback+volume`; an empty one grants reading only
+    pub fn parse(list: &str) -> Result<Grants, String> {
+        let mut grants = Grants::default();
+        for name in list
+            .split('+')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match name {
+                "playback" => grants.playback = true,
+                "volume" => grants.volume = true,
+                "audio-routing" => grants.audio_routing = true,
+                _ => {
+                    return Err(format!(
+                        "unknown scope {name:?}, expected playback, volume or audio-routing"
+                    ))
+                }
+            }
+        }
+        Ok(grants)
+    }
+}
+
+impl fmt::Display for Grants {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = [
+            (self.playback, "playback"),
+            (self.volume, "volume"),
+            (self.audio_routing, "audio-routing"),
+        ]
+        .into_iter()
+        .filter_map(|(granted, name)| granted.then_some(name))
+        .collect();
+        if names.is_empty() {
+            f.write_str("reading")
+        } else {
+            f.write_str(&names.join(", "))
+        }
+    }
+}
+
+/// What part of the API a request is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Area {
+    /// Any GET outside `/admin` and `/do/`
+    Read,
+    Playback,
+    Volume,
+    AudioRouting,
+    /// Other changes, like snapshots, sync, personas or the lock
+    Other,
+    Admin,
+}
+
+/// The area running `action` falls in. Commands run against the mixer
+/// rather than a player are volume ones.
+pub fn command_area(action: Action) -> Area {
+    if action.targets_player() {
+        Area::Playback
+    } else {
+        Area::Volume
+    }
+}
+
+/// The area a position alarm falls in: that of its action, or admin for a
+/// webhook, which has the server call any URL it's given
+pub fn position_alarm_area(action: Option<Action>) -> Area {
+    action.map_or(Area::Admin, command_area)
+}
+
+/// The area a request for `path` falls in. `POST /position_alarm` counts as
+/// playback here; the handler checks the alarm itself with
+/// [`position_alarm_area`].
+pub fn area(method: &Method, path: &str) -> Area {
+    let command = |name: &str| Action::from_name(name).map_or(Area::Other, command_area);
+    if path.starts_with("/admin") {
+        return Area::Admin;
+    }
+    if let Some(name) = path.strip_prefix("/do/") {
+        return command(name);
+    }
+    if method == Method::GET || method == Method::HEAD {
+        return Area::Read;
+    }
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["volume" | "volume_up" | "volume_down" | "mute" | "unmute"]
+        | ["mute", "toggle"]
+        | ["volume", "apps", _]
+        | ["players" | "groups", _, "volume"] => Area::Volume,
+        ["sinks", "default"] | ["audio", "bluetooth", ..] => Area::AudioRouting,
+        ["play" | "pause" | "toggle" | "stop" | "next" | "previous" | "seek" | "seek_forward"
+        | "seek_backward" | "open" | "skim" | "shuffle" | "loop" | "position_alarm"
+        | "sleep_timer" | "vote_skip" | "pause_all"]
+        | ["queue", ..]
+        | ["position_alarm", _]
+        | ["players", _, "play_exclusive"]
+        | ["players", "select" | "unselect"] => Area::Playback,
+        ["players" | "groups", _, name] => command(name),
+        _ => Area::Other,
+    }
+}
+
+/// Something that knows which tokens are valid
+pub trait TokenSource: Send + Sync {
+    /// The scope `token` grants, or `None` if this source doesn't know it
+    fn scope(&self, token: &str) -> Option<Scope>;
+}
+
+/// One fixed token
+pub struct FixedToken {
+    token: String,
+    scope: Scope,
+}
+
+impl FixedToken {
+    pub fn new(token: impl Into<String>, scope: Scope) -> Self {
+        FixedToken {
+            token: token.into(),
+            scope,
+        }
+    }
+}
+
+impl TokenSource for FixedToken {
+    fn scope(&self, token: &str) -> Option<Scope> {
+        // An empty setting must not let an empty token in
+        (!self.token.is_empty() && tokens_match(token, &self.token)).then_some(self.scope)
+    }
+}
+
+/// `MEDIA_CONTROL_API_TOKEN`, read on every check so a config reload takes
+/// effect
+pub struct ApiToken;
+
+impl TokenSource for ApiToken {
+    fn scope(&self, token: &str) -> Option<Scope> {
+        let expected = config::var("MEDIA_CONTROL_API_TOKEN").unwrap_or_default();
+        FixedToken::new(expected, Scope::Full).scope(token)
+    }
+}
+
+/// `MEDIA_CONTROL_SCOPED_TOKENS`, a comma-separated list of
+/// `<token>:<scope>+<scope>` entries, also read on every check
+pub struct ScopedTokens;
+
+impl ScopedTokens {
+    pub fn parse(setting: &str) -> Result<Vec<FixedToken>, String> {
+        setting
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (token, list) = entry
+                    .rsplit_once(':')
+                    .ok_or_else(|| format!("expected <token>:<scopes>, got {entry:?}"))?;
+                Ok(FixedToken::new(
+                    token.trim(),
+                    Scope::Limited(Grants::parse(list)?),
+                ))
+            })
+            .collect()
+    }
+
+    /// Warn about a setting that can't be parsed, which lets none of its
+    /// tokens in
+    pub fn check() {
+        if let Ok(setting) = config::var("MEDIA_CONTROL_SCOPED_TOKENS") {
+            if let Err(e) = ScopedTokens::parse(&setting) {
+                warn!("Ignoring MEDIA_CONTROL_SCOPED_TOKENS: {e}");
+            }
+        }
+    }
+}
+
+impl TokenSource for ScopedTokens {
+    fn scope(&self, token: &str) -> Option<Scope> {
+        let setting = config::var("MEDIA_CONTROL_SCOPED_TOKENS").ok()?;
+        ScopedTokens::parse(&setting)
+            .ok()?
+            .iter()
+            .find_map(|scoped| scoped.scope(token))
+    }
+}
+
+impl TokenSource for GuestAccess {
+    fn scope(&self, token: &str) -> Option<Scope> {
+        self.is_valid(token).then_some(Scope::Guest)
+    }
+}
+
+/// What a request offered as credentials
+#[derive(Debug, PartialEq, Eq)]
+pub enum Presented {
+    Nothing,
+    Token(String),
+    /// An `Authorization` header we can't make sense of
+    Malformed(&'static str),
+}
+
+/// The token in `headers`, or else the token query parameter `query`
+pub fn presented(headers: &HeaderMap, query: Option<String>) -> Presented {
+    let mut values = headers.get_all(header::AUTHORIZATION);
+    let from_query = || query.clone().map_or(Presented::Nothing, Presented::Token);
+    let Some(value) = values.next() else {
+        return from_query();
+    };
+    if values.next().is_some() {
+        return Presented::Malformed("more than one Authorization header");
+    }
+    let Ok(value) = value.to_str() else {
+        return Presented::Malformed("unreadable Authorization header");
+    };
+    let (scheme, token) = value.trim().split_once(' ').unwrap_or((value.trim(), ""));
+    // Auth schemes are case-insensitive (RFC 7235), tokens are not. Other
+    // schemes are for someone else, like a proxy's Basic auth.
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return from_query();
+    }
+    match token.trim() {
+        "" => Presented::Malformed("empty Bearer token"),
+        token if token.contains(char::is_whitespace) => {
+            Presented::Malformed("Bearer token contains spaces")
+        }
+        token => Presented::Token(token.to_string()),
+    }
+}
+
+/// What to do with a request
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allow(Scope),
+    /// No token at all
+    Missing,
+    /// A token no source knows
+    Invalid,
+    Malformed(&'static str),
+    /// A valid token whose scope doesn't cover the route
+    Forbidden(Scope),
+}
+
+/// Token sources, asked in order
+pub struct Verifier {
+    sources: Vec<Arc<dyn TokenSource>>,
+}
+
+impl Verifier {
+    pub fn new(sources: Vec<Arc<dyn TokenSource>>) -> Self {
+        Verifier { sources }
+    }
+
+    /// The API token, then the scoped tokens, then the guest token
+    pub fn standard(guest: Arc<GuestAccess>) -> Self {
+        ScopedTokens::check();
+        Verifier::new(vec![Arc::new(ApiToken), Arc::new(ScopedTokens), guest])
+    }
+
+    /// The first scope any source grants `token`
+    pub fn scope(&self, token: &str) -> Option<Scope> {
+        self.sources.iter().find_map(|source| source.scope(token))
+    }
+
+    /// Decide on a `method` request for `path` that presented `presented`
+    pub fn decide(&self, presented: &Presented, method: &Method, path: &str) -> Decision {
+        let token = match presented {
+            Presented::Nothing => return Decision::Missing,
+            Presented::Malformed(why) => return Decision::Malformed(why),
+            Presented::Token(token) => token,
+        };
+        match self.scope(token) {
+            Some(scope) if scope.allows(method, path) => Decision::Allow(scope),
+            Some(scope) => Decision::Forbidden(scope),
+            None => Decision::Invalid,
+        }
+    }
+}
+
+/// The path as the router sees it, with percent-encoding undone. Rules on
+/// the raw `req.path()` would let `/%61dmin/...` past them to the admin
+/// handlers.
+pub fn routed_path(req: &ServiceRequest) -> &str {
+    req.match_info().as_str()
+}
+
+/// The token query parameter, on routes that allow it: `access_token` for
+/// the streaming routes, `token` for the simple GET API
+fn query_token(req: &ServiceRequest) -> Option<String> {
+    let path = routed_path(req);
+    let name = if QUERY_TOKEN_ROUTES.contains(&path) {
+        "access_token"
+    } else if path.starts_with("/do/") {
+        "token"
+    } else {
+        return None;
+    };
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()?
+        .remove(name)
+}
+
+pub fn is_public_route(req: &ServiceRequest) -> bool {
+    req.method() == Method::GET && PUBLIC_ROUTES.contains(&routed_path(req))
+}
+
+/// Compare tokens in constant time, so response timing can't be used to
+/// guess them byte by byte. Only the length can leak.
+pub fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Let only requests with a valid token through to the handlers
+pub async fn auth_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if is_public_route(&req) {
+        return next.call(req).await;
+    }
+
+    let presented = presented(req.headers(), query_token(&req));
+    let path = routed_path(&req);
+    let decision = match req.app_data::<web::Data<Verifier>>() {
+        Some(verifier) => verifier.decide(&presented, req.method(), path),
+        None => Verifier::new(vec![Arc::new(ApiToken)]).decide(&presented, req.method(), path),
+    };
+    let path = path.to_string();
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let refusal = match decision {
+        Decision::Allow(scope) => {
+            rate_limit::record_success(ip);
+            req.extensions_mut().insert(scope);
+            return next.call(req).await;
+        }
+        Decision::Forbidden(Scope::Limited(grants)) => {
+            rate_limit::record_success(ip);
+            warn!(
+                path,
+                client = %logging::client(&req),
+                "Refused a scoped token outside its scopes"
+            );
+            AppError::new(
+                StatusCode::FORBIDDEN,
+                format!("This token is limited to {grants}"),
+            )
+        }
+        Decision::Forbidden(_) => {
+            rate_limit::record_success(ip);
+            warn!(
+                path,
+                client = %logging::client(&req),
+                "Refused a guest token on an admin endpoint"
+            );
+            AppError::new(
+                StatusCode::FORBIDDEN,
+                "Guest tokens cannot use admin endpoints",
+            )
+        }
+        Decision::Invalid => {
+            warn!(
+                path,
+                client = %logging::client(&req),
+                "Rejected an invalid API token"
+            );
+            rate_limit::record_failure(ip);
+            AppError::new(StatusCode::UNAUTHORIZED, "Invalid or missing API token")
+        }
+        Decision::Malformed(why) => {
+            warn!(
+                path,
+                client = %logging::client(&req),
+                "Rejected a malformed Authorization header: {why}"
+            );
+            rate_limit::record_failure(ip);
+            AppError::new(
+                StatusCode::UNAUTHORIZED,
+                format!("Invalid Authorization header: {why}"),
+            )
+        }
+        Decision::Missing => {
+            AppError::new(StatusCode::UNAUTHORIZED, "Invalid or missing API token")
+        }
+    };
+    Ok(req.error_response(refusal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+    use std::time::Duration;
+
+    fn headers(values: &[&[u8]]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for value in values {
+            map.append(
+                header::AUTHORIZATION,
+                HeaderValue::from_bytes(value).unwrap(),
+            );
+        }
+        map
+    }
+
+    fn bearer(value: &str) -> Presented {
+        presented(&headers(&[value.as_bytes()]), None)
+    }
+
+    fn verifier() -> Verifier {
+        Verifier::new(vec![
+            Arc::new(FixedToken::new("secret", Scope::Full)),
+            Arc::new(FixedToken::new("visitor", Scope::Guest)),
+        ])
+    }
+
+    #[test]
+    fn reads_bearer_tokens() {
+        assert_eq!(bearer("Bearer secret"), Presented::Token("secret".into()));
+        assert_eq!(
+            bearer("  Bearer   secret  "),
+            Presented::Token("secret".into())
+        );
+    }
+
+    #[test]
+    fn scheme_is_case_insensitive_but_token_is_not() {
+        assert_eq!(bearer("bearer secret"), Presented::Token("secret".into()));
+        assert_eq!(bearer("BEARER secret"), Presented::Token("secret".into()));
+        assert_eq!(
+            verifier().decide(&bearer("Bearer SECRET"), &Method::POST, "/play"),
+            Decision::Invalid
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for value in ["Bearer", "Bearer ", "Bearer se cret"] {
+            assert!(
+                matches!(bearer(value), Presented::Malformed(_)),
+                "{value:?} should be malformed"
+            );
+        }
+        assert_eq!(
+            presented(&headers(&[b"Bearer \xff"]), None),
+            Presented::Malformed("unreadable Authorization header")
+        );
+        assert!(matches!(
+            verifier().decide(&bearer("Bearer"), &Method::POST, "/play"),
+            Decision::Malformed(_)
+        ));
+    }
+
+    #[test]
+    fn ignores_other_schemes() {
+        assert_eq!(bearer("Basic c2VjcmV0"), Presented::Nothing);
+        assert_eq!(bearer("secret"), Presented::Nothing);
+        // A proxy's Basic auth doesn't hide the query token
+        let basic = headers(&[b"Basic c2VjcmV0"]);
+        assert_eq!(
+            presented(&basic, Some("secret".into())),
+            Presented::Token("secret".into())
+        );
+    }
+
+    #[test]
+    fn rejects_more_than_one_header() {
+        let both = headers(&[b"Bearer secret", b"Bearer visitor"]);
+        assert_eq!(
+            presented(&both, None),
+            Presented::Malformed("more than one Authorization header")
+        );
+    }
+
+    #[test]
+    fn header_takes_precedence_over_query_token() {
+        let header = headers(&[b"Bearer wrong"]);
+        let presented_both = presented(&header, Some("secret".into()));
+        assert_eq!(presented_both, Presented::Token("wrong".into()));
+        assert_eq!(
+            verifier().decide(&presented_both, &Method::GET, "/events"),
+            Decision::Invalid
+        );
+
+        let query_only = presented(&HeaderMap::new(), Some("secret".into()));
+        assert_eq!(
+            verifier().decide(&query_only, &Method::GET, "/events"),
+            Decision::Allow(Scope::Full)
+        );
+        assert_eq!(presented(&HeaderMap::new(), None), Presented::Nothing);
+    }
+
+    #[test]
+    fn asks_every_source_in_order() {
+        assert_eq!(
+            verifier().decide(&bearer("Bearer secret"), &Method::POST, "/play"),
+            Decision::Allow(Scope::Full)
+        );
+        assert_eq!(
+            verifier().decide(&bearer("Bearer visitor"), &Method::POST, "/play"),
+            Decision::Allow(Scope::Guest)
+        );
+        // The first source to know a token decides its scope
+        let shadowed = Verifier::new(vec![
+            Arc::new(FixedToken::new("same", Scope::Guest)),
+            Arc::new(FixedToken::new("same", Scope::Full)),
+        ]);
+        assert_eq!(shadowed.scope("same"), Some(Scope::Guest));
+        assert_eq!(
+            verifier().decide(&Presented::Nothing, &Method::POST, "/play"),
+            Decision::Missing
+        );
+    }
+
+    #[test]
+    fn guests_stay_out_of_admin() {
+        let v = verifier();
+        assert_eq!(
+            v.decide(
+                &bearer("Bearer visitor"),
+                &Method::GET,
+                "/admin/diagnostics"
+            ),
+            Decision::Forbidden(Scope::Guest)
+        );
+        assert_eq!(
+            v.decide(&bearer("Bearer secret"), &Method::GET, "/admin/diagnostics"),
+            Decision::Allow(Scope::Full)
+        );
+    }
+
+    #[test]
+    fn rules_apply_to_the_decoded_path() {
+        let req = actix_web::test::TestRequest::with_uri("/%61dmin/lock?x=%61").to_srv_request();
+        assert_eq!(routed_path(&req), "/admin/lock");
+        assert_eq!(
+            verifier().decide(
+                &bearer("Bearer visitor"),
+                &Method::DELETE,
+                routed_path(&req)
+            ),
+            Decision::Forbidden(Scope::Guest)
+        );
+
+        let req = actix_web::test::TestRequest::with_uri("/%61dmin/diagnostics").to_srv_request();
+        let tokens = ScopedTokens::parse("kids:playback").unwrap();
+        let v = Verifier::new(tokens.into_iter().map(|t| Arc::new(t) as _).collect());
+        assert!(matches!(
+            v.decide(&bearer("Bearer kids"), &Method::GET, routed_path(&req)),
+            Decision::Forbidden(_)
+        ));
+
+        let req = actix_web::test::TestRequest::with_uri("/%65vents?access_token=secret")
+            .to_srv_request();
+        assert_eq!(query_token(&req).as_deref(), Some("secret"));
+        let req = actix_web::test::TestRequest::with_uri("/heal%74hz").to_srv_request();
+        assert!(is_public_route(&req));
+    }
+
+    #[test]
+    fn empty_token_setting_lets_nobody_in() {
+        let unset = FixedToken::new("", Scope::Full);
+        assert_eq!(unset.scope(""), None);
+        assert_eq!(unset.scope("anything"), None);
+    }
+
+    #[test]
+    fn guest_tokens_expire() {
+        let guests = Arc::new(GuestAccess::default());
+        let token = guests.issue(Duration::from_secs(60));
+        let v = Verifier::new(vec![guests.clone()]);
+        assert_eq!(v.scope(&token), Some(Scope::Guest));
+
+        let expired = guests.issue(Duration::ZERO);
+        assert_eq!(v.scope(&expired), None);
+        // Issuing replaced the first token
+        assert_eq!(v.scope(&token), None);
+    }
+
+    #[test]
+    fn sorts_routes_into_areas() {
+        let post = |path| area(&Method::POST, path);
+        assert_eq!(post("/next"), Area::Playback);
+        assert_eq!(post("/queue/goto/spotify:track:1"), Area::Playback);
+        assert_eq!(post("/players/vlc/pause"), Area::Playback);
+        assert_eq!(post("/pause_all"), Area::Playback);
+        assert_eq!(area(&Method::DELETE, "/position_alarm/3"), Area::Playback);
+        assert_eq!(post("/players/vlc/play_exclusive"), Area::Playback);
+        assert_eq!(post("/volume"), Area::Volume);
+        assert_eq!(post("/mute/toggle"), Area::Volume);
+        assert_eq!(post("/volume/apps/42"), Area::Volume);
+        assert_eq!(post("/players/vlc/volume"), Area::Volume);
+        assert_eq!(post("/groups/rooms/pause"), Area::Playback);
+        assert_eq!(post("/groups/rooms/volume"), Area::Volume);
+        assert_eq!(post("/groups"), Area::Other);
+        assert_eq!(post("/sinks/default"), Area::AudioRouting);
+        assert_eq!(
+            post("/audio/bluetooth/00:11:22:33:44:55/connect"),
+            Area::AudioRouting
+        );
+        assert_eq!(post("/snapshot"), Area::Other);
+        assert_eq!(area(&Method::GET, "/volume"), Area::Read);
+        assert_eq!(area(&Method::GET, "/admin/diagnostics"), Area::Admin);
+        // The simple API runs commands from a GET
+        assert_eq!(area(&Method::GET, "/do/next"), Area::Playback);
+        assert_eq!(area(&Method::GET, "/do/volume_up"), Area::Volume);
+    }
+
+    #[test]
+    fn position_alarms_need_the_scope_of_what_they_do() {
+        let kids = Scope::Limited(Grants::parse("playback").unwrap());
+        assert!(kids.covers(position_alarm_area(Some(Action::Next))));
+        assert!(!kids.covers(position_alarm_area(Some(Action::VolumeUp))));
+        assert!(!kids.covers(position_alarm_area(Some(Action::Mute))));
+        let kitchen = Scope::Limited(Grants::parse("playback+volume").unwrap());
+        assert!(kitchen.covers(position_alarm_area(Some(Action::VolumeDown))));
+    }
+
+    #[test]
+    fn webhook_alarms_need_the_main_token() {
+        let everything = Scope::Limited(Grants::parse("playback+volume+audio-routing").unwrap());
+        assert_eq!(position_alarm_area(None), Area::Admin);
+        assert!(!everything.covers(position_alarm_area(None)));
+        assert!(!Scope::Guest.covers(position_alarm_area(None)));
+        assert!(Scope::Full.covers(position_alarm_area(None)));
+    }
+
+    #[test]
+    fn scoped_tokens_only_control_what_they_are_given() {
+        let tokens = ScopedTokens::parse("kids:playback, kitchen:playback+volume").unwrap();
+        let v = Verifier::new(tokens.into_iter().map(|t| Arc::new(t) as _).collect());
+        let kids = bearer("Bearer kids");
+        assert!(matches!(
+            v.decide(&kids, &Method::POST, "/next"),
+            Decision::Allow(_)
+        ));
+        assert!(matches!(
+            v.decide(&kids, &Method::GET, "/status"),
+            Decision::Allow(_)
+        ));
+        for (method, path) in [
+            (Method::POST, "/volume_up"),
+            (Method::POST, "/sinks/default"),
+            (Method::POST, "/snapshot"),
+            (Method::GET, "/admin/diagnostics"),
+        ] {
+            assert!(
+                matches!(v.decide(&kids, &method, path), Decision::Forbidden(_)),
+                "{method} {path} should be forbidden"
+            );
+        }
+        let kitchen = bearer("Bearer kitchen");
+        assert!(matches!(
+            v.decide(&kitchen, &Method::POST, "/volume"),
+            Decision::Allow(_)
+        ));
+        assert!(matches!(
+            v.decide(&kitchen, &Method::POST, "/sinks/default"),
+            Decision::Forbidden(_)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_scopes() {
+        assert!(ScopedTokens::parse("kids:playbak").is_err());
+        assert!(ScopedTokens::parse("no-scopes-given").is_err());
+        let read_only = ScopedTokens::parse("tv:").unwrap();
+        assert_eq!(
+            read_only[0].scope("tv"),
+            Some(Scope::Limited(Grants::default()))
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Several commands in one request.
+//!
+//! `POST /batch` takes an ordered list of steps, each an action as for
+//! [`crate::schedules`] and an optional delay before it:
+//!
+//! ```json
+//! {"steps": [
+//!     {"action": "select", "player": "vlc"},
+//!     {"action": "volume", "volume": 40},
+//!     {"action": "open", "uri": "file:///films/tonight.mkv", "delay_ms": 500},
+//!     {"action": "play"}
+//! ]}
+//! ```
+//!
+//! Every step is checked before the first one runs, so a typo in the last
+//! step doesn't leave the first ones done. Only one batch runs at a time: a
+//! batch sent while another is running is refused with `409` rather than
+//! queued. A batch isn't atomic, though: other requests still run between
+//! its steps, and a step that fails stops the batch without undoing the
+//! steps before it. The response lists what each step that ran did.
+
+use crate::error::AppError;
+use crate::schedules::{Job, Task};
+use crate::AppState;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use media_controller::models::{BatchResult, BatchStepResult, ErrorBody};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Most steps a batch may have
+const MAX_STEPS: usize = 32;
+/// Longest delay before one step
+const MAX_DELAY_MS: u64 = 60_000;
+
+/// Set while a batch runs, so two batches can't interleave
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The running batch's claim on [`RUNNING`], given up when dropped
+struct Running;
+
+impl Running {
+    fn claim() -> Option<Running> {
+        RUNNING
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| Running)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+/// One step of POST /batch
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BatchStep {
+    // select, unselect, play, pause, open, volume or any other /do/{action}
+    // name; leave it out for a step that only waits
+    action: Option<String>,
+    // What `open` opens
+    uri: Option<String>,
+    // What `volume` sets the system volume to, in percent
+    volume: Option<u32>,
+    // Player selector as for /players/{id}/...; the auto-selected player if missing
+    player: Option<String>,
+    // How long to wait before the step, in milliseconds
+    #[serde(default)]
+    delay_ms: u64,
+}
+
+/// Body of POST /batch
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BatchRequest {
+    steps: Vec<BatchStep>,
+}
+
+/// A checked step: the wait before it and what it does
+struct Step {
+    delay: Duration,
+    task: Option<Task>,
+}
+
+impl Step {
+    fn new(step: BatchStep) -> Result<Self, String> {
+        if step.delay_ms > MAX_DELAY_MS {
+            return Err(format!("delay_ms can be at most {MAX_DELAY_MS}"));
+        }
+        let task = match step.action {
+            Some(action) => Some(Task::new(
+                Job::new(&action, step.uri, step.volume)?,
+                step.player,
+            )?),
+            None if step.uri.is_some() || step.volume.is_some() || step.player.is_some() => {
+                return Err("a step without an action can only wait".into())
+            }
+            None => None,
+        };
+        Ok(Step {
+            delay: Duration::from_millis(step.delay_ms),
+            task,
+        })
+    }
+}
+
+fn run(state: &AppState, steps: Vec<Step>) -> BatchResult {
+    let mut results = Vec::with_capacity(steps.len());
+    for step in steps {
+        thread::sleep(step.delay);
+        let Some(task) = step.task else {
+            results.push(BatchStepResult {
+                action: "wait".into(),
+                ok: true,
+                message: format!("waited {} ms", step.delay.as_millis()),
+            });
+            continue;
+        };
+        let (ok, message) = match task.run(state) {
+            Ok(msg) => (true, msg),
+            Err(e) => (false, e),
+        };
+        results.push(BatchStepResult {
+            action: task.job.name(),
+            ok,
+            message,
+        });
+        if !ok {
+            return BatchResult { ok, results };
+        }
+    }
+    BatchResult { ok: true, results }
+}
+
+/// POST /batch — run several commands in order, with optional delays between them
+///
+/// All steps are checked first; then they run until one fails. Only one
+/// batch runs at a time, but other requests can run between its steps.
+#[utoipa::path(
+    post,
+    path = "/batch",
+    tag = "Playback",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Every step ran", body = BatchResult),
+        (status = 400, description = "A step is invalid, and none ran", body = ErrorBody),
+        (status = 409, description = "Another batch is running", body = ErrorBody),
+        (status = 500, description = "A step failed and the rest didn't run", body = BatchResult),
+    ),
+)]
+pub async fn batch(
+    state: web::Data<AppState>,
+    body: web::Json<BatchRequest>,
+) -> Result<HttpResponse, AppError> {
+    let steps = body.into_inner().steps;
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(AppError::bad_request(format!(
+            "a batch needs 1 to {MAX_STEPS} steps"
+        )));
+    }
+    let steps = steps
+        .into_iter()
+        .enumerate()
+        .map(|(i, step)| Step::new(step).map_err(|e| format!("step {}: {e}", i + 1)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::bad_request)?;
+
+    // Refused up front, so waiting batches don't each hold a blocking thread
+    let running = Running::claim().ok_or_else(|| AppError::conflict("another batch is running"))?;
+    let count = steps.len();
+    let result = web::block(move || {
+        let _running = running;
+        run(&state, steps)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("batch failed: {e}")))?;
+    if result.ok {
+        info!("Ran a batch of {count} steps");
+        Ok(HttpResponse::Ok().json(result))
+    } else {
+        let failed = result.results.last().map(|r| r.message.as_str());
+        warn!(
+            "Batch stopped at step {} of {count}: {}",
+            result.results.len(),
+            failed.unwrap_or_default()
+        );
+        Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).json(result))
+    }
+}
@@ -0,0 +1,188 @@
+//! `media-controller-tray`: a system tray icon for a running server (the
+//! `tray` feature).
+//!
+//! Shows the current track in the icon's tooltip and offers play/pause,
+//! next and previous in its menu; clicking the icon toggles playback and
+//! scrolling over it changes the volume. It connects to
+//! `MEDIA_CONTROL_URL` (default `http://localhost:8080`, or the URL given as
+//! the first argument) with `MEDIA_CONTROL_API_TOKEN`, and follows
+//! `/events` so the tooltip changes as soon as the track does. The icon is
+//! a StatusNotifierItem, as shown by KDE, and GNOME with the AppIndicator
+//! extension.
+
+use ksni::menu::StandardItem;
+use ksni::{MenuItem, ToolTip, TrayService};
+use media_controller::client::Client;
+use media_controller::models::NowPlaying;
+use std::env;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long to wait before reconnecting a dropped event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+/// What the tray asks the server to do
+#[derive(Clone, Copy, Debug)]
+enum Command {
+    Toggle,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+}
+
+struct Tray {
+    commands: mpsc::UnboundedSender<Command>,
+    // Latest state from the event stream, None while disconnected
+    now: Option<NowPlaying>,
+}
+
+impl Tray {
+    fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    fn playing(&self) -> bool {
+        self.now
+            .as_ref()
+            .is_some_and(|n| n.playback.as_deref() == Some("Playing"))
+    }
+}
+
+impl ksni::Tray for Tray {
+    fn id(&self) -> String {
+        "media-controller".into()
+    }
+
+    fn title(&self) -> String {
+        "Media Controller".into()
+    }
+
+    fn icon_name(&self) -> String {
+        match &self.now {
+            None => "network-offline",
+            Some(_) if self.playing() => "media-playback-start",
+            Some(n) if n.playback.as_deref() == Some("Paused") => "media-playback-pause",
+            Some(_) => "media-playback-stop",
+        }
+        .into()
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        let (title, description) = match &self.now {
+            None => ("Not connected".to_string(), String::new()),
+            Some(n) => {
+                let track = match (&n.artist, &n.title) {
+                    (Some(artist), Some(title)) => format!("{artist} – {title}"),
+                    (None, Some(title)) => title.clone(),
+                    _ => "Nothing playing".to_string(),
+                };
+                let player = n.player.as_deref().unwrap_or("no player");
+                let playback = n.playback.as_deref().unwrap_or("Stopped");
+                (track, format!("{playback} on {player}"))
+            }
+        };
+        ToolTip {
+            title,
+            description,
+            icon_name: String::new(),
+            icon_pixmap: Vec::new(),
+        }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.send(Command::Toggle);
+    }
+
+    fn scroll(&mut self, delta: i32, dir: &str) {
+        if dir == "vertical" {
+            // Most desktops report scrolling up as a negative delta
+            self.send(if delta < 0 {
+                Command::VolumeUp
+            } else {
+                Command::VolumeDown
+            });
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let item = |label: &str, command: Command| -> MenuItem<Self> {
+            StandardItem {
+                label: label.into(),
+                enabled: self.now.is_some(),
+                activate: Box::new(move |tray: &mut Self| tray.send(command)),
+                ..Default::default()
+            }
+            .into()
+        };
+        vec![
+            item(
+                if self.playing() { "Pause" } else { "Play" },
+                Command::Toggle,
+            ),
+            item("Next", Command::Next),
+            item("Previous", Command::Previous),
+            MenuItem::Separator,
+            StandardItem {
+                label: "Quit".into(),
+                icon_name: "application-exit".into(),
+                activate: Box::new(|_| std::process::exit(0)),
+                ..Default::default()
+            }
+            .into(),
+        ]
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let url = env::args()
+        .nth(1)
+        .or_else(|| env::var("MEDIA_CONTROL_URL").ok())
+        .unwrap_or_else(|| "http://localhost:8080".to_string());
+    let token = env::var("MEDIA_CONTROL_API_TOKEN").unwrap_or_default();
+    let client = Client::new(url, token);
+
+    let (tx, mut commands) = mpsc::unbounded_channel();
+    let service = TrayService::new(Tray {
+        commands: tx,
+        now: None,
+    });
+    let tray = service.handle();
+    service.spawn();
+
+    // Menu clicks arrive on the tray's own thread; run them here
+    let runner = client.clone();
+    tokio::spawn(async move {
+        while let Some(command) = commands.recv().await {
+            let result = match command {
+                Command::Toggle => runner.toggle().await,
+                Command::Next => runner.next().await,
+                Command::Previous => runner.previous().await,
+                Command::VolumeUp => runner.volume_up().await,
+                Command::VolumeDown => runner.volume_down().await,
+            };
+            if let Err(e) = result {
+                eprintln!("{command:?} failed: {e}");
+            }
+        }
+    });
+
+    loop {
+        match client.events().await {
+            Ok(mut events) => loop {
+                match events.next().await {
+                    Ok(Some(event)) => tray.update(|t| t.now = Some(event.now_playing)),
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Lost the event stream: {e}");
+                        break;
+                    }
+                }
+            },
+            Err(e) => eprintln!("Couldn't connect to {}: {e}", client.base_url()),
+        }
+        tray.update(|t| t.now = None);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
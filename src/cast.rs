@@ -0,0 +1,321 @@
+//! Google Cast devices, such as Chromecasts, as remote players.
+//!
+//! Devices are found with mDNS (`_googlecast._tcp`) on the local network.
+//! `MEDIA_CONTROL_CAST_DEVICES` adds devices discovery can't see, e.g. on
+//! another subnet, and `MEDIA_CONTROL_CAST_DISCOVERY=false` turns discovery
+//! off. Each device is listed as `cast:<instance>` under its friendly name.
+//!
+//! Every command opens a new connection, first to the device and then to the
+//! media app running on it. Play, pause, toggle, stop and seeking act on that
+//! app's media session. Cast has no next or previous for a plain media
+//! session, so those are refused. The volume is the device's own.
+
+use crate::actions::{Action, ActionError};
+use crate::config;
+use crate::i18n::Message;
+use crate::player::seek_step_for;
+use crate::remote::{self, RemotePlayer, RemoteStatus};
+use crate::template::format_duration;
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use rust_cast::channels::media::{Metadata, PlayerState, StatusEntry};
+use rust_cast::CastDevice;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// mDNS service type Cast devices announce
+const SERVICE: &str = "_googlecast._tcp.local.";
+/// Port Cast devices listen on
+const DEFAULT_PORT: u16 = 8009;
+/// How long to wait for a device to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// The platform end of a device, as opposed to the apps running on it
+const RECEIVER: &str = "receiver-0";
+/// Namespace of apps that play media
+const MEDIA_NAMESPACE: &str = "urn:x-cast:com.google.cast.media";
+
+/// The media session of the app playing on a device
+struct Session {
+    transport_id: String,
+    entry: StatusEntry,
+}
+
+struct CastPlayer {
+    id: String,
+    name: String,
+    host: String,
+    port: u16,
+}
+
+impl CastPlayer {
+    fn connect(&self) -> Result<CastDevice<'static>, String> {
+        // rust_cast has no connect timeout, and a device that was switched off
+        // would otherwise hold the request for minutes
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| format!("can't resolve {}", self.host))?;
+        TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .map_err(|e| format!("can't reach {}: {e}", self.name))?;
+        let device = CastDevice::connect_without_host_verification(self.host.clone(), self.port)
+            .map_err(|e| e.to_string())?;
+        device
+            .connection
+            .connect(RECEIVER)
+            .map_err(|e| e.to_string())?;
+        Ok(device)
+    }
+
+    fn session(&self, device: &CastDevice<'static>) -> Result<Option<Session>, String> {
+        let status = device.receiver.get_status().map_err(|e| e.to_string())?;
+        let Some(app) = status
+            .applications
+            .into_iter()
+            .find(|app| app.namespaces.iter().any(|n| n == MEDIA_NAMESPACE))
+        else {
+            return Ok(None);
+        };
+        device
+            .connection
+            .connect(app.transport_id.clone())
+            .map_err(|e| e.to_string())?;
+        let media = device
+            .media
+            .get_status(app.transport_id.clone(), None)
+            .map_err(|e| e.to_string())?;
+        Ok(media.entries.into_iter().next().map(|entry| Session {
+            transport_id: app.transport_id,
+            entry,
+        }))
+    }
+
+    fn command(&self, action: Action) -> Result<Message, String> {
+        let device = self.connect()?;
+        let session = self
+            .session(&device)?
+            .ok_or_else(|| format!("nothing is playing on {}", self.name))?;
+        let (media, transport) = (&device.media, session.transport_id.clone());
+        let id = session.entry.media_session_id;
+        let playing = matches!(
+            session.entry.player_state,
+            PlayerState::Playing | PlayerState::Buffering
+        );
+        let position = f64::from(session.entry.current_time.unwrap_or_default());
+        let seek = |seconds: f64| {
+            media
+                .seek(transport.clone(), id, Some(seconds.max(0.0) as f32), None)
+                .map_err(|e| e.to_string())
+        };
+        let step = seek_step_for(&self.name).as_secs();
+        let message = match action {
+            Action::Play => media.play(transport, id).map(|_| Message::new("playing")),
+            Action::Toggle if !playing => {
+                media.play(transport, id).map(|_| Message::new("playing"))
+            }
+            Action::Pause | Action::Toggle => {
+                media.pause(transport, id).map(|_| Message::new("paused"))
+            }
+            Action::Stop => media.stop(transport, id).map(|_| Message::new("stopped")),
+            Action::SeekForward | Action::SeekBackward => {
+                let forward = action == Action::SeekForward;
+                let delta = if forward { step as f64 } else { -(step as f64) };
+                seek(position + delta)?;
+                return Ok(Message::new("seeked")
+                    .arg("direction", if forward { "forward" } else { "backward" })
+                    .arg("seconds", step));
+            }
+            Action::SeekBy(ms) => {
+                seek(position + ms as f64 / 1000.0)?;
+                return Ok(Message::new("seeked")
+                    .arg("direction", if ms < 0 { "backward" } else { "forward" })
+                    .arg("seconds", ms.unsigned_abs() as f64 / 1000.0));
+            }
+            Action::SeekTo(ms) => {
+                seek(ms as f64 / 1000.0)?;
+                return Ok(Message::new("seeked-to")
+                    .arg("position", format_duration(Duration::from_millis(ms))));
+            }
+            _ => unreachable!("{action:?} isn't supported"),
+        };
+        message.map_err(|e| e.to_string())
+    }
+}
+
+impl RemotePlayer for CastPlayer {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn identity(&self) -> &str {
+        &self.name
+    }
+
+    fn status(&self) -> Result<RemoteStatus, String> {
+        let device = self.connect()?;
+        let volume = device
+            .receiver
+            .get_status()
+            .map_err(|e| e.to_string())?
+            .volume
+            .level
+            .map(|level| (level * 100.0).round() as u32);
+        let Some(session) = self.session(&device)? else {
+            return Ok(RemoteStatus {
+                volume,
+                ..RemoteStatus::default()
+            });
+        };
+        let media = session.entry.media;
+        let length = media
+            .as_ref()
+            .and_then(|m| m.duration)
+            .filter(|d| d.is_finite() && *d >= 0.0)
+            .map(Duration::from_secs_f32);
+        let (title, artist, album) = match media.and_then(|m| m.metadata) {
+            Some(Metadata::MusicTrack(track)) => (track.title, track.artist, track.album_name),
+            Some(Metadata::Generic(generic)) => (generic.title, generic.subtitle, None),
+            Some(Metadata::Movie(movie)) => (movie.title, movie.studio, None),
+            Some(Metadata::TvShow(show)) => (show.episode_title, show.series_title, None),
+            Some(Metadata::Photo(photo)) => (photo.title, photo.artist, None),
+            None => (None, None, None),
+        };
+        let playback = match session.entry.player_state {
+            PlayerState::Playing | PlayerState::Buffering => "Playing",
+            PlayerState::Paused => "Paused",
+            PlayerState::Idle => "Stopped",
+        };
+        Ok(RemoteStatus {
+            playback: Some(playback.to_string()),
+            title,
+            artist,
+            album,
+            volume,
+            position: session
+                .entry
+                .current_time
+                .filter(|t| t.is_finite() && *t >= 0.0)
+                .map(Duration::from_secs_f32),
+            length,
+            // 2 in the supported-commands bitmask is SEEK
+            can_seek: session.entry.supported_media_commands & 2 != 0,
+        })
+    }
+
+    fn supports(&self, action: Action) -> bool {
+        matches!(
+            action,
+            Action::Play
+                | Action::Pause
+                | Action::Toggle
+                | Action::Stop
+                | Action::SeekForward
+                | Action::SeekBackward
+                | Action::SeekBy(_)
+                | Action::SeekTo(_)
+        )
+    }
+
+    fn run(&self, action: Action) -> Result<Message, ActionError> {
+        self.command(action)
+            .map_err(|e| ActionError::Failed(Message::new("player-call-failed").arg("error", e)))
+    }
+
+    fn set_volume(&self, percent: u32) -> Result<(), String> {
+        let device = self.connect()?;
+        device
+            .receiver
+            .set_volume(percent.min(100) as f32 / 100.0)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Parse `host` or `host:port`
+fn static_device(entry: &str) -> Option<CastPlayer> {
+    let (host, port) = match entry.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (entry, DEFAULT_PORT),
+    };
+    Some(CastPlayer {
+        id: format!("cast:{host}"),
+        name: host.to_string(),
+        host: host.to_string(),
+        port,
+    })
+}
+
+/// Add the configured devices, and start looking for others unless discovery
+/// is off
+pub fn start() {
+    // rust_cast builds its TLS config from the process-wide provider, which
+    // is ambiguous when rustls has both ring and aws-lc-rs built in
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+    let devices = config::var("MEDIA_CONTROL_CAST_DEVICES").unwrap_or_default();
+    for entry in devices.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match static_device(entry) {
+            Some(device) => remote::add(Arc::new(device)),
+            None => warn!(
+                "Ignoring {entry:?} in MEDIA_CONTROL_CAST_DEVICES, expected host or host:port"
+            ),
+        }
+    }
+    let discovery = config::var("MEDIA_CONTROL_CAST_DISCOVERY").map_or(true, |v| {
+        !matches!(v.to_lowercase().as_str(), "0" | "false" | "no")
+    });
+    if discovery {
+        thread::spawn(discover);
+    }
+}
+
+/// Follow the Cast devices announced on the network
+fn discover() {
+    let events = match ServiceDaemon::new().and_then(|daemon| daemon.browse(SERVICE)) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("Can't look for Cast devices: {e}");
+            return;
+        }
+    };
+    while let Ok(event) = events.recv() {
+        match event {
+            ServiceEvent::ServiceResolved(service) => {
+                let Some(address) = service.get_addresses_v4().into_iter().next() else {
+                    continue;
+                };
+                let instance = instance_name(service.get_fullname());
+                let name = service
+                    .txt_properties
+                    .get_property_val_str("fn")
+                    .unwrap_or(instance)
+                    .to_string();
+                let id = format!("cast:{instance}");
+                if remote::find(&id).is_none() {
+                    info!("Found Cast device {name} at {address}");
+                }
+                remote::add(Arc::new(CastPlayer {
+                    id,
+                    name,
+                    host: address.to_string(),
+                    port: service.get_port(),
+                }));
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                let id = format!("cast:{}", instance_name(&fullname));
+                info!("Cast device {id} went away");
+                remote::remove(&id);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `Chromecast-1f2e` from `Chromecast-1f2e._googlecast._tcp.local.`
+fn instance_name(fullname: &str) -> &str {
+    fullname
+        .strip_suffix(SERVICE)
+        .map_or(fullname, |name| name.strip_suffix('.').unwrap_or(name))
+}
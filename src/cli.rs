@@ -0,0 +1,180 @@
+//! `media-controller play`, `media-controller volume set 40` and friends:
+//! one-shot commands against a running server, for shell scripts (the
+//! `client` feature).
+//!
+//! The server and token are found like the terminal UI finds them, from the
+//! same environment and config file as the service, so scripts don't need
+//! to repeat the token. Output is the server's own message, or JSON with
+//! `--json` where a command returns data. Failures go to stderr with exit
+//! status 1, and usage errors exit with 2.
+
+use crate::player::parse_position;
+use media_controller::client::{Client, Error};
+use serde::Serialize;
+use std::fmt::Display;
+use std::io::Write;
+
+const USAGE: &str = "\
+usage: media-controller <command> [--url URL] [--json]
+
+  play | pause | toggle | stop | next | previous   [--player NAME]
+  seek POSITION | seek +SECONDS | seek -SECONDS
+  open URI [--player NAME]
+  volume [get] | volume set PERCENT | volume up|down [POINTS]
+  mute [toggle] | unmute
+  status
+  players
+  select NAME | unselect
+
+Volume, status and players print JSON with --json.";
+
+/// Options that may appear anywhere after the command
+struct Options {
+    url: Option<String>,
+    json: bool,
+    player: Option<String>,
+    rest: Vec<String>,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options {
+        url: None,
+        json: false,
+        player: None,
+        rest: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => options.url = Some(args.next().unwrap_or_else(|| usage())),
+            "--player" => options.player = Some(args.next().unwrap_or_else(|| usage())),
+            "--json" => options.json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            // Only `--` marks a flag, so `seek -10` still works
+            _ if arg.starts_with("--") => usage(),
+            _ => options.rest.push(arg),
+        }
+    }
+    options
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+/// Run `command` against the server and exit
+pub async fn run(command: &str, args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    let options = parse(args);
+    let client = crate::local_client(options.url.clone());
+    match execute(&client, command, &options).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            eprintln!("media-controller: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn execute(client: &Client, command: &str, options: &Options) -> Result<(), Error> {
+    let rest: Vec<&str> = options.rest.iter().map(String::as_str).collect();
+    let player = options.player.as_deref();
+    match (command, rest.as_slice()) {
+        ("play" | "pause" | "toggle" | "stop" | "next" | "previous", []) => {
+            let message = match (player, command) {
+                (Some(player), _) => client.player_command(player, command).await?,
+                (None, "play") => client.play().await?,
+                (None, "pause") => client.pause().await?,
+                (None, "toggle") => client.toggle().await?,
+                (None, "stop") => client.stop().await?,
+                (None, "next") => client.next().await?,
+                (None, _) => client.previous().await?,
+            };
+            say(message);
+        }
+        ("seek", [position]) if player.is_none() => {
+            let message = if position.starts_with(['+', '-']) {
+                let seconds: f64 = position.parse().unwrap_or_else(|_| usage());
+                client.seek_by(seconds).await?
+            } else {
+                let position = parse_position(position).unwrap_or_else(|| usage());
+                client.seek_to(position).await?
+            };
+            say(message);
+        }
+        ("open", [uri]) => say(client.open(uri, player).await?),
+        ("volume", [] | ["get"]) => show_volume(client.volume().await?, options.json),
+        ("volume", ["set", percent]) => {
+            let percent = percent.trim_end_matches('%');
+            let percent = percent.parse().unwrap_or_else(|_| usage());
+            show_volume(client.set_volume(percent).await?, options.json);
+        }
+        ("volume", [direction @ ("up" | "down")]) => {
+            if *direction == "up" {
+                say(client.volume_up().await?);
+            } else {
+                say(client.volume_down().await?);
+            }
+        }
+        ("volume", [direction @ ("up" | "down"), points]) => {
+            let points: i32 = points.parse().unwrap_or_else(|_| usage());
+            let points = if *direction == "up" { points } else { -points };
+            show_volume(client.adjust_volume(points).await?, options.json);
+        }
+        ("mute", []) => say(client.mute().await?),
+        ("mute", ["toggle"]) => say(client.toggle_mute().await?),
+        ("unmute", []) => say(client.unmute().await?),
+        ("status", []) => {
+            let status = client.status().await?;
+            if options.json {
+                print_json(&status);
+            } else {
+                say(crate::status_line(&status));
+            }
+        }
+        ("players", []) => {
+            let players = client.players().await?;
+            if options.json {
+                print_json(&players);
+                return Ok(());
+            }
+            for p in players {
+                let marker = if p.selected { "*" } else { " " };
+                let playback = p.playback.as_deref().unwrap_or("Stopped");
+                say(format!(
+                    "{marker} {} ({}) {playback}",
+                    p.identity, p.bus_name
+                ));
+            }
+        }
+        ("select", [selector]) => {
+            let chosen = client.select_player(selector).await?;
+            say(format!("pinned {} ({})", chosen.identity, chosen.bus_name));
+        }
+        ("unselect", []) => say(client.unselect_player().await?),
+        _ => usage(),
+    }
+    Ok(())
+}
+
+/// Print a line, ignoring a closed stdout as in `media-controller status | head -1`
+fn say(message: impl Display) {
+    let _ = writeln!(std::io::stdout(), "{message}");
+}
+
+fn show_volume(volume: u32, json: bool) {
+    if json {
+        print_json(&serde_json::json!({ "volume": volume }));
+    } else {
+        say(format!("{volume}%"));
+    }
+}
+
+fn print_json(value: &impl Serialize) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => say(json),
+        Err(e) => eprintln!("Couldn't format the response: {e}"),
+    }
+}
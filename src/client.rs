@@ -0,0 +1,424 @@
+//! Typed async client for the HTTP API (the `client` feature).
+//!
+//! Responses are parsed into the same [`models`](crate::models) the server
+//! builds them from. Commands return the server's human-readable outcome,
+//! e.g. "paused". Any status other than 2xx becomes
+//! [`Error::Status`] carrying the server's message.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), media_controller::client::Error> {
+//! use media_controller::client::Client;
+//!
+//! let client = Client::new("http://localhost:8080", "my-token");
+//! client.toggle().await?;
+//! let status = client.status().await?;
+//! println!("{:?} by {:?}", status.title, status.artist);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::models::{
+    AppStream, Candidate, DefaultSink, ErrorBody, Event, LockState, Overview, PlayerInfo,
+    PlayerVolume, Queue, Sink, Source, Status, VolumeLevel,
+};
+use reqwest::{Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+use std::time::Duration;
+
+/// What went wrong talking to the server
+#[derive(Debug)]
+pub enum Error {
+    // The request didn't get a response, or the body didn't parse
+    Http(reqwest::Error),
+    // The server answered with an error status
+    Status { status: u16, message: String },
+    // An event on the stream didn't parse
+    Event(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "request failed: {e}"),
+            Error::Status { status, message } => write!(f, "server returned {status}: {message}"),
+            Error::Event(e) => write!(f, "unreadable event: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Deserialize)]
+struct Shuffle {
+    shuffle: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Loop {
+    loop_status: Option<String>,
+}
+
+/// A media-controller server and the token to use with it
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl Client {
+    /// Client for the server at `base_url`, e.g. `http://localhost:8080`
+    pub fn new(base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url, token)
+    }
+
+    /// Same as [`Client::new`], reusing a configured `reqwest` client
+    pub fn with_http_client(
+        http: reqwest::Client,
+        base_url: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        Client {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+        }
+    }
+
+    /// The server's address, as given to [`Client::new`]
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}{path}", self.base_url))
+            .bearer_auth(&self.token)
+    }
+
+    async fn send(request: RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let text = response.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<ErrorBody>(&text) {
+            Ok(body) => body.error,
+            Err(_) => text,
+        };
+        Err(Error::Status {
+            status: status.as_u16(),
+            message,
+        })
+    }
+
+    async fn json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    async fn text(request: RequestBuilder) -> Result<String> {
+        Ok(Self::send(request).await?.text().await?)
+    }
+
+    async fn command(&self, path: &str) -> Result<String> {
+        Self::text(self.request(Method::POST, path)).await
+    }
+
+    /// GET /status
+    pub async fn status(&self) -> Result<Status> {
+        Self::json(
+            self.request(Method::GET, "/status")
+                .header("Accept", "application/json"),
+        )
+        .await
+    }
+
+    /// GET /overview
+    pub async fn overview(&self) -> Result<Overview> {
+        Self::json(self.request(Method::GET, "/overview")).await
+    }
+
+    /// GET /players
+    pub async fn players(&self) -> Result<Vec<PlayerInfo>> {
+        Self::json(self.request(Method::GET, "/players")).await
+    }
+
+    /// POST /players/select: pin a player for auto-selected commands
+    pub async fn select_player(&self, selector: &str) -> Result<Candidate> {
+        let body = json!({ "player": selector });
+        Self::json(self.request(Method::POST, "/players/select").json(&body)).await
+    }
+
+    /// POST /players/unselect
+    pub async fn unselect_player(&self) -> Result<String> {
+        self.command("/players/unselect").await
+    }
+
+    /// POST /players/{id}/{command}, e.g. `("spotify", "next")`
+    pub async fn player_command(&self, selector: &str, command: &str) -> Result<String> {
+        self.command(&format!("/players/{selector}/{command}"))
+            .await
+    }
+
+    /// POST /play
+    pub async fn play(&self) -> Result<String> {
+        self.command("/play").await
+    }
+
+    /// POST /pause
+    pub async fn pause(&self) -> Result<String> {
+        self.command("/pause").await
+    }
+
+    /// POST /toggle
+    pub async fn toggle(&self) -> Result<String> {
+        self.command("/toggle").await
+    }
+
+    /// POST /stop
+    pub async fn stop(&self) -> Result<String> {
+        self.command("/stop").await
+    }
+
+    /// POST /next
+    pub async fn next(&self) -> Result<String> {
+        self.command("/next").await
+    }
+
+    /// POST /previous
+    pub async fn previous(&self) -> Result<String> {
+        self.command("/previous").await
+    }
+
+    /// POST /seek_forward, by the player's configured step
+    pub async fn seek_forward(&self) -> Result<String> {
+        self.command("/seek_forward").await
+    }
+
+    /// POST /seek_backward, by the player's configured step
+    pub async fn seek_backward(&self) -> Result<String> {
+        self.command("/seek_backward").await
+    }
+
+    /// POST /seek: jump to `position` in the current track
+    pub async fn seek_to(&self, position: Duration) -> Result<String> {
+        let body = json!({ "position": position.as_secs_f64() });
+        Self::text(self.request(Method::POST, "/seek").json(&body)).await
+    }
+
+    /// POST /seek: move by `seconds`, negative to go back
+    pub async fn seek_by(&self, seconds: f64) -> Result<String> {
+        let body = json!({ "offset": seconds });
+        Self::text(self.request(Method::POST, "/seek").json(&body)).await
+    }
+
+    /// POST /open: have a player (the auto-selected one if None) play a URI
+    pub async fn open(&self, uri: &str, player: Option<&str>) -> Result<String> {
+        let body = json!({ "uri": uri, "player": player });
+        Self::text(self.request(Method::POST, "/open").json(&body)).await
+    }
+
+    /// GET /shuffle; None if the player doesn't support shuffle
+    pub async fn shuffle(&self) -> Result<Option<bool>> {
+        let shuffle: Shuffle = Self::json(self.request(Method::GET, "/shuffle")).await?;
+        Ok(shuffle.shuffle)
+    }
+
+    /// POST /shuffle; None flips the current state
+    pub async fn set_shuffle(&self, shuffle: Option<bool>) -> Result<String> {
+        let body = json!({ "shuffle": shuffle });
+        Self::text(self.request(Method::POST, "/shuffle").json(&body)).await
+    }
+
+    /// GET /loop: `None`, `Track` or `Playlist`, or None if unsupported
+    pub async fn loop_status(&self) -> Result<Option<String>> {
+        let status: Loop = Self::json(self.request(Method::GET, "/loop")).await?;
+        Ok(status.loop_status)
+    }
+
+    /// POST /loop; None steps to the next mode
+    pub async fn set_loop_status(&self, loop_status: Option<&str>) -> Result<String> {
+        let body = json!({ "loop_status": loop_status });
+        Self::text(self.request(Method::POST, "/loop").json(&body)).await
+    }
+
+    /// GET /volume: the system volume in percent
+    pub async fn volume(&self) -> Result<u32> {
+        let level: VolumeLevel = Self::json(self.request(Method::GET, "/volume")).await?;
+        Ok(level.volume)
+    }
+
+    /// POST /volume with `set`, returning the new level
+    pub async fn set_volume(&self, percent: u32) -> Result<u32> {
+        let body = json!({ "set": percent });
+        let level: VolumeLevel =
+            Self::json(self.request(Method::POST, "/volume").json(&body)).await?;
+        Ok(level.volume)
+    }
+
+    /// POST /volume with `adjust`, returning the new level
+    pub async fn adjust_volume(&self, points: i32) -> Result<u32> {
+        let body = json!({ "adjust": points });
+        let level: VolumeLevel =
+            Self::json(self.request(Method::POST, "/volume").json(&body)).await?;
+        Ok(level.volume)
+    }
+
+    /// POST /volume_up
+    pub async fn volume_up(&self) -> Result<String> {
+        self.command("/volume_up").await
+    }
+
+    /// POST /volume_down
+    pub async fn volume_down(&self) -> Result<String> {
+        self.command("/volume_down").await
+    }
+
+    /// POST /mute
+    pub async fn mute(&self) -> Result<String> {
+        self.command("/mute").await
+    }
+
+    /// POST /unmute
+    pub async fn unmute(&self) -> Result<String> {
+        self.command("/unmute").await
+    }
+
+    /// POST /mute/toggle
+    pub async fn toggle_mute(&self) -> Result<String> {
+        self.command("/mute/toggle").await
+    }
+
+    /// GET /players/{id}/volume
+    pub async fn player_volume(&self, selector: &str) -> Result<PlayerVolume> {
+        let path = format!("/players/{selector}/volume");
+        Self::json(self.request(Method::GET, &path)).await
+    }
+
+    /// POST /players/{id}/volume with `set`
+    pub async fn set_player_volume(&self, selector: &str, percent: u32) -> Result<PlayerVolume> {
+        let path = format!("/players/{selector}/volume");
+        let body = json!({ "set": percent });
+        Self::json(self.request(Method::POST, &path).json(&body)).await
+    }
+
+    /// GET /volume/apps
+    pub async fn app_volumes(&self) -> Result<Vec<AppStream>> {
+        Self::json(self.request(Method::GET, "/volume/apps")).await
+    }
+
+    /// POST /volume/apps/{id} with `set`
+    pub async fn set_app_volume(&self, id: u32, percent: u32) -> Result<AppStream> {
+        let path = format!("/volume/apps/{id}");
+        let body = json!({ "set": percent });
+        Self::json(self.request(Method::POST, &path).json(&body)).await
+    }
+
+    /// POST /volume/apps/{id} with `muted`
+    pub async fn set_app_muted(&self, id: u32, muted: bool) -> Result<AppStream> {
+        let path = format!("/volume/apps/{id}");
+        let body = json!({ "muted": muted });
+        Self::json(self.request(Method::POST, &path).json(&body)).await
+    }
+
+    /// GET /sinks
+    pub async fn sinks(&self) -> Result<Vec<Sink>> {
+        Self::json(self.request(Method::GET, "/sinks")).await
+    }
+
+    /// GET /audio/sources
+    pub async fn sources(&self) -> Result<Vec<Source>> {
+        Self::json(self.request(Method::GET, "/audio/sources")).await
+    }
+
+    /// POST /sinks/default, moving playing streams along if `move_streams`
+    pub async fn set_default_sink(&self, sink: &str, move_streams: bool) -> Result<DefaultSink> {
+        let body = json!({ "sink": sink, "move_streams": move_streams });
+        Self::json(self.request(Method::POST, "/sinks/default").json(&body)).await
+    }
+
+    /// GET /queue of the auto-selected player
+    pub async fn queue(&self) -> Result<Queue> {
+        Self::json(self.request(Method::GET, "/queue")).await
+    }
+
+    /// POST /queue/goto/{trackid}
+    pub async fn go_to_track(&self, track_id: &str) -> Result<String> {
+        let path = format!("/queue/goto/{}", track_id.trim_start_matches('/'));
+        self.command(&path).await
+    }
+
+    /// DELETE /queue/{trackid}
+    pub async fn remove_track(&self, track_id: &str) -> Result<String> {
+        let path = format!("/queue/{}", track_id.trim_start_matches('/'));
+        Self::text(self.request(Method::DELETE, &path)).await
+    }
+
+    /// GET /lock: whether volume and seeking are locked
+    pub async fn locked(&self) -> Result<bool> {
+        let state: LockState = Self::json(self.request(Method::GET, "/lock")).await?;
+        Ok(state.locked)
+    }
+
+    /// POST /lock
+    pub async fn lock(&self) -> Result<bool> {
+        let state: LockState = Self::json(self.request(Method::POST, "/lock")).await?;
+        Ok(state.locked)
+    }
+
+    /// DELETE /admin/lock; needs the main API token
+    pub async fn unlock(&self) -> Result<bool> {
+        let state: LockState = Self::json(self.request(Method::DELETE, "/admin/lock")).await?;
+        Ok(state.locked)
+    }
+
+    /// GET /events: playback events as they happen, starting with a
+    /// `snapshot` of the current state
+    pub async fn events(&self) -> Result<Events> {
+        let response = Self::send(self.request(Method::GET, "/events")).await?;
+        Ok(Events {
+            response,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+/// The `/events` stream, read with [`Events::next`]
+pub struct Events {
+    response: reqwest::Response,
+    // Received bytes not yet making up a whole event
+    buffer: Vec<u8>,
+}
+
+impl Events {
+    /// Wait for the next event, or None once the server closes the stream
+    pub async fn next(&mut self) -> Result<Option<Event>> {
+        loop {
+            while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+                let frame: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                let frame = String::from_utf8_lossy(&frame);
+                // Keep-alive comments have no data line
+                let data = frame.lines().find_map(|line| line.strip_prefix("data: "));
+                if let Some(data) = data {
+                    return serde_json::from_str(data).map(Some).map_err(Error::Event);
+                }
+            }
+            match self.response.chunk().await? {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => return Ok(None),
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Request coalescing ("singleflight") for read-only bus queries.
+//!
+//! When several clients ask for the same thing at once, only the first
+//! request runs the query; the others wait for and share its result. Nothing
+//! is cached after the query finishes, so results are never staler than a
+//! single round trip.
+
+use crate::error::MutexExt;
+use actix_web::web;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+type Flight<T> = Shared<BoxFuture<'static, Result<T, String>>>;
+
+/// One coalesced query
+pub struct Coalesced<T: Clone> {
+    in_flight: Mutex<Option<Flight<T>>>,
+    // Requests that started a query
+    runs: AtomicU64,
+    // Requests that joined a query already running
+    joined: AtomicU64,
+}
+
+/// How often requests shared a query, for diagnostics and metrics
+#[derive(Clone, Copy, Serialize)]
+pub struct Stats {
+    pub runs: u64,
+    pub joined: u64,
+}
+
+impl<T: Clone> Default for Coalesced<T> {
+    fn default() -> Self {
+        Coalesced {
+            in_flight: Mutex::new(None),
+            runs: AtomicU64::new(0),
+            joined: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Coalesced<T> {
+    /// Run `query` on the blocking pool, or join the run already in flight
+    pub async fn run<F>(&self, query: F) -> Result<T, String>
+    where
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (flight, started) = {
+            let mut in_flight = self.in_flight.locked();
+            // A finished flight left behind by a starter that went away
+            // before reading it doesn't count
+            match in_flight.as_ref().filter(|f| f.peek().is_none()) {
+                Some(flight) => {
+                    self.joined.fetch_add(1, Ordering::Relaxed);
+                    (flight.clone(), false)
+                }
+                None => {
+                    self.runs.fetch_add(1, Ordering::Relaxed);
+                    let flight = web::block(query)
+                        .map(|r| r.map_err(|e| e.to_string()))
+                        .boxed()
+                        .shared();
+                    *in_flight = Some(flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        let result = flight.clone().await;
+        if started {
+            let mut in_flight = self.in_flight.locked();
+            if in_flight.as_ref().is_some_and(|f| f.ptr_eq(&flight)) {
+                *in_flight = None;
+            }
+        }
+        result
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            runs: self.runs.load(Ordering::Relaxed),
+            joined: self.joined.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! `media-controller.toml`: the `MEDIA_CONTROL_*` settings, in a file.
+//!
+//! Every top-level key names a variable without its prefix, in lowercase, so
+//! `api_token = "..."` stands for `MEDIA_CONTROL_API_TOKEN` and
+//! `player_priority = ["spotify", "firefox"]` for
+//! `MEDIA_CONTROL_PLAYER_PRIORITY=spotify,firefox`. Arrays are joined with
+//! commas and tables become `key=value` pairs, e.g.
+//! `player_aliases = { tv = "mpv" }`. Variables set in the environment win
+//! over the file, which keeps systemd drop-ins and one-off overrides working.
+//!
+//! The file is `MEDIA_CONTROL_CONFIG` if set, otherwise
+//! `$XDG_CONFIG_HOME/media-controller/media-controller.toml` (usually under
+//! `~/.config`) or `./media-controller.toml`, whichever exists. Running
+//! without one is fine.
+//!
+//! Settings are looked up through [`var`] whenever they're used, so
+//! [`reload`] (on `SIGHUP` or `POST /admin/reload`) applies a changed file
+//! straight away to everything read per request or per event: player
+//! selection, seek and volume steps, the API token, the TLS certificate,
+//! webhook targets and so on. What is set up once at startup, such as the
+//! listen address, worker threads, volume backend, hooks and background
+//! monitors, still needs a restart.
+
+use crate::error::AppError;
+use actix_web::{HttpResponse, Responder, ResponseError};
+use media_controller::models::ErrorBody;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{PoisonError, RwLock};
+use tracing::{info, warn};
+
+const FILE_NAME: &str = "media-controller.toml";
+const PREFIX: &str = "MEDIA_CONTROL_";
+
+/// Variables by full name, e.g. `MEDIA_CONTROL_API_TOKEN`
+type Vars = HashMap<String, String>;
+
+/// Variables read from the config file
+static FILE_VARS: RwLock<Option<Vars>> = RwLock::new(None);
+
+/// Look up a setting: the environment variable `name` if set, otherwise its
+/// key in the config file. Drop-in for [`env::var`].
+pub fn var(name: &str) -> Result<String, env::VarError> {
+    match env::var(name) {
+        Err(env::VarError::NotPresent) => FILE_VARS
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .and_then(|vars| vars.get(name).cloned())
+            .ok_or(env::VarError::NotPresent),
+        found => found,
+    }
+}
+
+/// Every `MEDIA_CONTROL_*` setting in effect, from the environment and the
+/// config file
+pub fn vars() -> Vars {
+    let mut vars = FILE_VARS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_default();
+    vars.extend(env::vars().filter(|(name, _)| name.starts_with(PREFIX)));
+    vars
+}
+
+/// The config file to read, and whether it was asked for explicitly
+fn path() -> Option<(PathBuf, bool)> {
+    if let Some(path) = env::var_os("MEDIA_CONTROL_CONFIG") {
+        return Some((PathBuf::from(path), true));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    config_home
+        .map(|dir| dir.join("media-controller").join(FILE_NAME))
+        .into_iter()
+        .chain([PathBuf::from(FILE_NAME)])
+        .find(|path| path.is_file())
+        .map(|path| (path, false))
+}
+
+/// The environment variable form of a config value
+fn env_value(value: &toml::Value) -> Option<String> {
+    use toml::Value;
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::Float(n) => Some(n.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Array(items) => items
+            .iter()
+            .map(env_value)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| env_value(value).map(|value| format!("{key}={value}")))
+            .collect::<Option<Vec<_>>>()
+            .map(|pairs| pairs.join(",")),
+        Value::Datetime(_) => None,
+    }
+}
+
+/// Read the config file's variables, with the file they came from
+fn read() -> Result<Option<(PathBuf, Vars)>, String> {
+    let Some((path, explicit)) = path() else {
+        return Ok(None);
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("couldn't read {}: {e}", path.display())),
+    };
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| format!("couldn't parse {}: {e}", path.display()))?;
+
+    let mut vars = HashMap::new();
+    for (key, value) in &table {
+        match env_value(value) {
+            Some(value) => {
+                vars.insert(format!("{PREFIX}{}", key.to_uppercase()), value);
+            }
+            None => warn!("Ignoring {key} in {}: unsupported value", path.display()),
+        }
+    }
+    Ok(Some((path, vars)))
+}
+
+/// Read the config file at startup. Must run before anything reads its
+/// settings.
+pub fn load() -> Result<(), String> {
+    reload().map(|_| ())
+}
+
+/// Read the config file again and switch to its settings, returning the
+/// file read. On error the previous settings stay in effect.
+pub fn reload() -> Result<Option<PathBuf>, String> {
+    let (path, vars) = read()?.unzip();
+    // Don't lock everyone out by dropping the token
+    let token = "MEDIA_CONTROL_API_TOKEN";
+    let had_token = var(token).is_ok();
+    let has_token =
+        env::var_os(token).is_some() || vars.as_ref().is_some_and(|v| v.contains_key(token));
+    if had_token && !has_token {
+        return Err("the new settings have no api_token, keeping the current ones".to_string());
+    }
+    *FILE_VARS.write().unwrap_or_else(PoisonError::into_inner) = vars;
+    crate::logging::reload();
+    if let Some(path) = &path {
+        info!("Loaded settings from {}", path.display());
+    }
+    #[cfg(feature = "tls")]
+    crate::tls::reload()
+        .map_err(|e| format!("the settings were applied, but not the certificate: {e}"))?;
+    Ok(path)
+}
+
+/// Reload on every `SIGHUP`, e.g. from systemd's `ExecReload`
+pub fn reload_on_hangup() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Reloading on SIGHUP disabled: {e}");
+            return;
+        }
+    };
+    actix_web::rt::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(e) = reload() {
+                warn!("Reload failed, {e}");
+            }
+        }
+    });
+}
+
+/// POST /admin/reload — re-read the config file and apply it
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    tag = "Admin",
+    responses((status = 500, description = "The config file is invalid", body = ErrorBody)),
+)]
+pub async fn reload_endpoint() -> impl Responder {
+    match reload() {
+        Ok(path) => HttpResponse::Ok().json(json!({ "file": path })),
+        Err(e) => {
+            warn!("Reload failed, {e}");
+            AppError::internal(format!("reload failed, {e}")).error_response()
+        }
+    }
+}
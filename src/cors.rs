@@ -0,0 +1,79 @@
+//! CORS, so web pages served from another host can call the API.
+//!
+//! `MEDIA_CONTROL_CORS_ORIGINS` lists the allowed origins, comma-separated,
+//! e.g. `http://remote.lan:8000`, or `*` for any. Preflight `OPTIONS`
+//! requests from those origins are answered here, before authentication,
+//! and every response to them carries `Access-Control-Allow-Origin` so the
+//! page can read it, errors included. The token still has to be sent as
+//! `Authorization: Bearer ...` on the real request.
+
+use crate::config;
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+
+/// Default for `MEDIA_CONTROL_CORS_METHODS`
+const DEFAULT_METHODS: &str = "GET, POST, DELETE";
+/// Default for `MEDIA_CONTROL_CORS_HEADERS`
+const DEFAULT_HEADERS: &str = "Authorization, Content-Type";
+/// Default for `MEDIA_CONTROL_CORS_MAX_AGE_SECS`
+const DEFAULT_MAX_AGE_SECS: &str = "600";
+
+/// Whether `origin` may call the API, if CORS is configured at all
+fn allowed(origin: &str) -> bool {
+    config::var("MEDIA_CONTROL_CORS_ORIGINS").is_ok_and(|origins| {
+        origins
+            .split(',')
+            .map(|o| o.trim().trim_end_matches('/'))
+            .any(|o| o == "*" || o.eq_ignore_ascii_case(origin))
+    })
+}
+
+/// Middleware answering preflights and adding CORS headers for allowed origins
+pub async fn cors_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(origin) = req
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|o| o.to_str().is_ok_and(allowed))
+        .cloned()
+    else {
+        return next.call(req).await;
+    };
+
+    let preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if preflight {
+        let methods =
+            config::var("MEDIA_CONTROL_CORS_METHODS").unwrap_or_else(|_| DEFAULT_METHODS.into());
+        let headers =
+            config::var("MEDIA_CONTROL_CORS_HEADERS").unwrap_or_else(|_| DEFAULT_HEADERS.into());
+        let max_age = config::var("MEDIA_CONTROL_CORS_MAX_AGE_SECS")
+            .unwrap_or_else(|_| DEFAULT_MAX_AGE_SECS.into());
+        let response = HttpResponse::NoContent()
+            .insert_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_ALLOW_METHODS, methods))
+            .insert_header((header::ACCESS_CONTROL_ALLOW_HEADERS, headers))
+            .insert_header((header::ACCESS_CONTROL_MAX_AGE, max_age))
+            .insert_header((header::VARY, "Origin"))
+            .finish();
+        return Ok(req.into_response(response));
+    }
+
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static("Retry-After"),
+    );
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    Ok(res)
+}
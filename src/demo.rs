@@ -0,0 +1,345 @@
+//! `--demo`: a built-in fake player for development and demos.
+//!
+//! In demo mode the service controls an in-memory player instead of the MPRIS
+//! players on the session bus. It plays through a short playlist with its
+//! position advancing in real time, and answers play, pause, toggle, stop,
+//! next, previous, seek, shuffle and loop, and its playlist shows up in
+//! `/queue`. It has no window to raise or quit. Everything fed from the live snapshot (`/status`,
+//! `/events`, `/ws`, the widget) behaves as it would with a real player, so
+//! the web UI and clients can be worked on without a media player or a D-Bus
+//! session. Our MPRIS publisher isn't registered in this mode.
+
+use crate::actions::{next_loop_status, on_off, Action, ActionError};
+use crate::error::MutexExt;
+use crate::i18n::Message;
+use crate::live::{self, NowPlaying};
+use crate::player::{resolve_player_alias, seek_step_for};
+use crate::template::format_duration;
+use crate::AppState;
+use media_controller::models::QueueEntry;
+use mpris::LoopStatus;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Identity the demo player reports
+pub const IDENTITY: &str = "Demo Player";
+/// Stands in for a bus name wherever one is reported
+pub const BUS_NAME: &str = "demo";
+
+struct Track {
+    title: &'static str,
+    artist: &'static str,
+    album: &'static str,
+    length: Duration,
+}
+
+const PLAYLIST: &[Track] = &[
+    Track {
+        title: "Alison",
+        artist: "Slowdive",
+        album: "Souvlaki",
+        length: Duration::from_secs(231),
+    },
+    Track {
+        title: "Machine Gun",
+        artist: "Slowdive",
+        album: "Souvlaki",
+        length: Duration::from_secs(267),
+    },
+    Track {
+        title: "Souvlaki Space Station",
+        artist: "Slowdive",
+        album: "Souvlaki",
+        length: Duration::from_secs(359),
+    },
+    Track {
+        title: "When the Sun Hits",
+        artist: "Slowdive",
+        album: "Souvlaki",
+        length: Duration::from_secs(286),
+    },
+];
+
+struct Progress {
+    track: usize,
+    // Position when `since` was taken
+    position: Duration,
+    // When playback last started; None while paused or stopped
+    since: Option<Instant>,
+    stopped: bool,
+    // Only reported; the playlist always plays in order
+    shuffle: bool,
+    loop_status: LoopStatus,
+}
+
+impl Progress {
+    /// Fold the time played since the last update into the position, moving
+    /// on to the next track whenever one ends
+    fn advance(&mut self) {
+        if let Some(since) = &mut self.since {
+            self.position += since.elapsed();
+            *since = Instant::now();
+        }
+        while self.position >= PLAYLIST[self.track].length {
+            self.position -= PLAYLIST[self.track].length;
+            if self.loop_status != LoopStatus::Track {
+                self.track = (self.track + 1) % PLAYLIST.len();
+            }
+        }
+    }
+
+    fn skip(&mut self, by: isize) {
+        let len = PLAYLIST.len() as isize;
+        self.track = (self.track as isize + by).rem_euclid(len) as usize;
+        self.position = Duration::ZERO;
+    }
+}
+
+/// Track id of the playlist entry at `index`
+fn track_id(index: usize) -> String {
+    format!("/demo/track/{index}")
+}
+
+pub struct DemoPlayer {
+    progress: Mutex<Progress>,
+}
+
+impl DemoPlayer {
+    /// Start at the top of the playlist, playing
+    pub fn new() -> Self {
+        DemoPlayer {
+            progress: Mutex::new(Progress {
+                track: 0,
+                position: Duration::ZERO,
+                since: Some(Instant::now()),
+                stopped: false,
+                shuffle: false,
+                loop_status: LoopStatus::Playlist,
+            }),
+        }
+    }
+
+    /// Whether a player selector picks the demo player
+    pub fn matches(selector: &str) -> bool {
+        let wanted = resolve_player_alias(selector).to_lowercase();
+        wanted == BUS_NAME || IDENTITY.to_lowercase().contains(&wanted)
+    }
+
+    /// Snapshot of the demo player, for the live view
+    pub fn now_playing(&self, previous: &NowPlaying) -> NowPlaying {
+        let mut progress = self.progress.locked();
+        progress.advance();
+        let track = &PLAYLIST[progress.track];
+        let playing = progress.since.is_some();
+        let playback = match (playing, progress.stopped) {
+            (true, _) => "Playing",
+            (false, false) => "Paused",
+            (false, true) => "Stopped",
+        };
+
+        let mut now = NowPlaying {
+            player: Some(IDENTITY.to_string()),
+            bus_name: Some(BUS_NAME.to_string()),
+            playback: Some(playback.to_string()),
+            title: Some(track.title.to_string()),
+            artist: Some(track.artist.to_string()),
+            album: Some(track.album.to_string()),
+            length: Some(track.length),
+            shuffle: Some(progress.shuffle),
+            loop_status: Some(format!("{:?}", progress.loop_status)),
+            volume: previous.volume,
+            muted: previous.muted,
+            sinks: previous.sinks.clone(),
+            ..NowPlaying::default()
+        };
+        now.set_position(progress.position, 1.0, playing, previous);
+        now
+    }
+
+    /// The playlist, for GET /queue
+    pub fn queue(&self) -> Vec<QueueEntry> {
+        let current = self.progress.locked().track;
+        PLAYLIST
+            .iter()
+            .enumerate()
+            .map(|(i, track)| QueueEntry {
+                id: track_id(i),
+                title: Some(track.title.to_string()),
+                artist: Some(track.artist.to_string()),
+                album: Some(track.album.to_string()),
+                length_ms: Some(track.length.as_millis() as u64),
+                current: i == current,
+            })
+            .collect()
+    }
+
+    /// Whether `id` is one of the playlist's track ids
+    pub fn has_track(&self, id: &str) -> bool {
+        (0..PLAYLIST.len()).any(|i| track_id(i) == id)
+    }
+
+    /// Start the playlist track `id` from the top
+    pub fn go_to(&self, state: &AppState, id: &str) {
+        if let Some(track) = (0..PLAYLIST.len()).find(|&i| track_id(i) == id) {
+            let mut progress = self.progress.locked();
+            progress.advance();
+            progress.track = track;
+            progress.position = Duration::ZERO;
+        }
+        live::refresh(state);
+    }
+
+    /// Carry out a player action, returning what a real player's would
+    fn apply(&self, action: Action) -> Message {
+        let mut progress = self.progress.locked();
+        progress.advance();
+        if matches!(action, Action::Play | Action::Toggle) {
+            progress.stopped = false;
+        }
+        match action {
+            Action::Play => {
+                progress.since.get_or_insert_with(Instant::now);
+                Message::new("playing")
+            }
+            Action::Stop => {
+                progress.since = None;
+                progress.stopped = true;
+                progress.position = Duration::ZERO;
+                Message::new("stopped")
+            }
+            Action::Pause => {
+                progress.since = None;
+                Message::new("paused")
+            }
+            Action::Toggle if progress.since.is_some() => {
+                progress.since = None;
+                Message::new("paused")
+            }
+            Action::Toggle => {
+                progress.since = Some(Instant::now());
+                Message::new("playing")
+            }
+            Action::Next => {
+                progress.skip(1);
+                Message::new("next-track")
+            }
+            Action::Previous => {
+                progress.skip(-1);
+                Message::new("previous-track")
+            }
+            Action::SeekForward | Action::SeekBackward => {
+                let step = seek_step_for(IDENTITY);
+                let direction = if action == Action::SeekForward {
+                    progress.position += step;
+                    progress.advance();
+                    "forward"
+                } else {
+                    progress.position = progress.position.saturating_sub(step);
+                    "backward"
+                };
+                Message::new("seeked")
+                    .arg("direction", direction)
+                    .arg("seconds", step.as_secs())
+            }
+            Action::SeekBy(ms) => {
+                let offset = Duration::from_millis(ms.unsigned_abs());
+                if ms < 0 {
+                    progress.position = progress.position.saturating_sub(offset);
+                } else {
+                    progress.position += offset;
+                    progress.advance();
+                }
+                Message::new("seeked")
+                    .arg("direction", if ms < 0 { "backward" } else { "forward" })
+                    .arg("seconds", ms.unsigned_abs() as f64 / 1000.0)
+            }
+            Action::SeekTo(ms) => {
+                let track = &PLAYLIST[progress.track];
+                progress.position = Duration::from_millis(ms).min(track.length);
+                progress.advance();
+                Message::new("seeked-to").arg("position", format_duration(progress.position))
+            }
+            Action::SetShuffle(shuffle) => {
+                progress.shuffle = shuffle.unwrap_or(!progress.shuffle);
+                Message::new("shuffle-set").arg("state", on_off(progress.shuffle))
+            }
+            Action::SetLoop(status) => {
+                progress.loop_status =
+                    status.unwrap_or_else(|| next_loop_status(progress.loop_status));
+                Message::new("loop-set").arg("mode", format!("{:?}", progress.loop_status))
+            }
+            _ => unreachable!("{action:?} isn't sent to players"),
+        }
+    }
+
+    /// Run a player action (see [`Action::targets_player`]) on the demo
+    /// player, updating the live snapshot straight away
+    pub fn run(&self, state: &AppState, action: Action) -> Result<Message, ActionError> {
+        Self::check(action)?;
+        let message = self.apply(action);
+        live::refresh(state);
+        Ok(message)
+    }
+
+    /// Refuse what the demo player can't do, the way a real player's
+    /// capabilities would
+    fn check(action: Action) -> Result<(), ActionError> {
+        match action {
+            Action::Raise => Err(ActionError::Unsupported(Message::new("cannot-raise"))),
+            Action::Quit => Err(ActionError::Unsupported(Message::new("cannot-quit"))),
+            _ => Ok(()),
+        }
+    }
+
+    /// What `run` would do, for dry runs
+    pub fn plan(&self, action: Action) -> Result<Message, ActionError> {
+        Self::check(action)?;
+        let player = format!("{IDENTITY} ({BUS_NAME})");
+        Ok(match action {
+            Action::Next => Message::new("plan-next").arg("player", player),
+            Action::Previous => Message::new("plan-previous").arg("player", player),
+            Action::SeekForward | Action::SeekBackward => {
+                let direction = if action == Action::SeekForward {
+                    "forward"
+                } else {
+                    "backward"
+                };
+                Message::new("plan-seek")
+                    .arg("player", player)
+                    .arg("direction", direction)
+                    .arg("seconds", seek_step_for(IDENTITY).as_secs())
+            }
+            Action::SeekBy(ms) => Message::new("plan-seek")
+                .arg("player", player)
+                .arg("direction", if ms < 0 { "backward" } else { "forward" })
+                .arg("seconds", ms.unsigned_abs() as f64 / 1000.0),
+            Action::SeekTo(ms) => Message::new("plan-seek-to")
+                .arg("player", player)
+                .arg("position", format_duration(Duration::from_millis(ms))),
+            Action::SetShuffle(shuffle) => {
+                let current = self.progress.locked().shuffle;
+                Message::new("plan-shuffle")
+                    .arg("player", player)
+                    .arg("state", on_off(shuffle.unwrap_or(!current)))
+            }
+            Action::SetLoop(status) => {
+                let current = self.progress.locked().loop_status;
+                let status = status.unwrap_or_else(|| next_loop_status(current));
+                Message::new("plan-loop")
+                    .arg("player", player)
+                    .arg("mode", format!("{status:?}"))
+            }
+            _ => {
+                let verb = match action {
+                    Action::Play => "play",
+                    Action::Pause => "pause",
+                    Action::Stop => "stop",
+                    _ => "toggle",
+                };
+                Message::new("plan-playback")
+                    .arg("verb", verb)
+                    .arg("player", player)
+            }
+        })
+    }
+}
@@ -0,0 +1,169 @@
+//! Email notifications over SMTP (the `email` feature).
+//!
+//! Set `MEDIA_CONTROL_SMTP_HOST`, `MEDIA_CONTROL_SMTP_FROM` and
+//! `MEDIA_CONTROL_SMTP_TO` (comma-separated) to get mail about events, with
+//! `MEDIA_CONTROL_SMTP_USERNAME` and `MEDIA_CONTROL_SMTP_PASSWORD` if the
+//! server wants a login. `MEDIA_CONTROL_SMTP_TLS` is `starttls` (default,
+//! port 587), `tls` (port 465) or `none` (port 25, for a local relay), and
+//! `MEDIA_CONTROL_SMTP_PORT` overrides the port.
+//!
+//! Each [`Event`] has a subject and body rendered with [`crate::template`];
+//! `MEDIA_CONTROL_EMAIL_<EVENT>_SUBJECT` and `..._BODY` replace the built-in
+//! ones, e.g. `MEDIA_CONTROL_EMAIL_GUEST_CREATED_BODY`. Mail goes out from a
+//! background thread and failures are only logged.
+
+use crate::config;
+use crate::template::{self, Vars};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How long to wait on the mail server
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something worth an email, with its default templates
+pub struct Event {
+    // Lowercase name, used in the template variables
+    name: &'static str,
+    subject: &'static str,
+    body: &'static str,
+}
+
+/// A guest token was issued: `{{minutes}}` and `{{expires}}` (local time).
+/// The token itself is never mailed.
+pub const GUEST_CREATED: Event = Event {
+    name: "guest_created",
+    subject: "media-controller: guest access for {{minutes}} minutes",
+    body: "A guest token was issued and stays valid for {{minutes}} minutes, \
+           until {{expires}}.\n\n\
+           Revoke it early with DELETE /admin/guest.",
+};
+
+/// An alert started firing: `{{alert}}` and `{{message}}`
+pub const ALERT_FIRING: Event = Event {
+    name: "alert_firing",
+    subject: "media-controller: {{alert}} alert",
+    body: "{{message}}",
+};
+
+/// An alert cleared: `{{alert}}` and `{{message}}`
+pub const ALERT_RESOLVED: Event = Event {
+    name: "alert_resolved",
+    subject: "media-controller: {{alert}} resolved",
+    body: "{{message}}",
+};
+
+/// How to secure the connection to the mail server
+#[derive(Clone, Copy)]
+enum Security {
+    StartTls,
+    Tls,
+    None,
+}
+
+/// Mail server settings, if email is configured
+struct Smtp {
+    host: String,
+    port: Option<u16>,
+    security: Security,
+    credentials: Option<Credentials>,
+    from: String,
+    to: Vec<String>,
+}
+
+impl Smtp {
+    fn from_env() -> Option<Smtp> {
+        let host = config::var("MEDIA_CONTROL_SMTP_HOST").ok()?;
+        let (Ok(from), Ok(to)) = (
+            config::var("MEDIA_CONTROL_SMTP_FROM"),
+            config::var("MEDIA_CONTROL_SMTP_TO"),
+        ) else {
+            warn!("Email disabled, set MEDIA_CONTROL_SMTP_FROM and MEDIA_CONTROL_SMTP_TO");
+            return None;
+        };
+        let security = match config::var("MEDIA_CONTROL_SMTP_TLS").as_deref() {
+            Ok("tls") => Security::Tls,
+            Ok("none") => Security::None,
+            Ok("starttls") | Err(_) => Security::StartTls,
+            Ok(other) => {
+                warn!("Unknown MEDIA_CONTROL_SMTP_TLS={other:?}, using starttls");
+                Security::StartTls
+            }
+        };
+        let credentials = config::var("MEDIA_CONTROL_SMTP_USERNAME").ok().map(|user| {
+            let password = config::var("MEDIA_CONTROL_SMTP_PASSWORD").unwrap_or_default();
+            Credentials::new(user, password)
+        });
+        Some(Smtp {
+            host,
+            port: config::var("MEDIA_CONTROL_SMTP_PORT")
+                .ok()
+                .and_then(|p| p.trim().parse().ok()),
+            security,
+            credentials,
+            from,
+            to: to
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    fn send(&self, subject: &str, body: String) -> Result<(), String> {
+        let mut message = Message::builder()
+            .from(self.from.parse().map_err(|e| format!("bad sender: {e}"))?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.parse().map_err(|e| format!("bad recipient {to}: {e}"))?);
+        }
+        let message = message.body(body).map_err(|e| e.to_string())?;
+
+        let mut transport = match self.security {
+            Security::StartTls => SmtpTransport::starttls_relay(&self.host),
+            Security::Tls => SmtpTransport::relay(&self.host),
+            Security::None => Ok(SmtpTransport::builder_dangerous(&self.host)),
+        }
+        .map_err(|e| e.to_string())?
+        .timeout(Some(TIMEOUT));
+        if let Some(port) = self.port {
+            transport = transport.port(port);
+        }
+        if let Some(credentials) = &self.credentials {
+            transport = transport.credentials(credentials.clone());
+        }
+        transport
+            .build()
+            .send(&message)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Email `event` in the background, if email is configured
+pub fn notify(event: &Event, vars: Vars) {
+    let Some(smtp) = Smtp::from_env() else {
+        return;
+    };
+    let template_var = |part: &str| {
+        config::var(&format!(
+            "MEDIA_CONTROL_EMAIL_{}_{part}",
+            event.name.to_uppercase()
+        ))
+    };
+    let subject = template_var("SUBJECT").unwrap_or_else(|_| event.subject.to_string());
+    let body = template_var("BODY").unwrap_or_else(|_| event.body.to_string());
+    let subject = template::render(&subject, &vars);
+    let body = template::render(&body, &vars);
+    let name = event.name;
+
+    thread::spawn(move || match smtp.send(&subject, body) {
+        Ok(()) => info!("Emailed {name} to {}", smtp.to.join(", ")),
+        Err(e) => error!("Couldn't email {name}: {e}"),
+    });
+}
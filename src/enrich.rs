@@ -0,0 +1,201 @@
+//! Optional metadata enrichment from MusicBrainz.
+//!
+//! Browser players often report only an artist and a title. When enabled with
+//! `MEDIA_CONTROL_ENRICH_METADATA`, tracks missing an album or artwork are
+//! looked up on MusicBrainz by artist and title, and the release's front cover
+//! is taken from the Cover Art Archive. Lookups run in the background and are
+//! cached for the life of the process; the live snapshot picks the result up
+//! on its next refresh, and from there `/status`, events and our publisher.
+//!
+//! Values the player does report are never replaced.
+
+use crate::config;
+use crate::error::MutexExt;
+use crate::live::NowPlaying;
+use crate::outbound::{self, Integration};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const RECORDING_SEARCH: &str = "https://musicbrainz.org/ws/2/recording";
+const COVER_ART: &str = "https://coverartarchive.org/release";
+
+/// MusicBrainz asks clients to stay below one request per second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+/// Give up on a lookup after this long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// MusicBrainz asks for a way to contact the client's authors
+const USER_AGENT: &str = concat!(
+    "media-controller/",
+    env!("CARGO_PKG_VERSION"),
+    " ( ",
+    env!("CARGO_PKG_REPOSITORY"),
+    " )"
+);
+/// Search hits scoring lower than this (out of 100) are ignored
+const MIN_SCORE: u32 = 90;
+/// The cache starts over once it holds this many tracks
+const CACHE_LIMIT: usize = 1000;
+
+/// What a lookup found for one track
+#[derive(Clone, Debug, Default)]
+struct Enrichment {
+    album: Option<String>,
+    art_url: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+enum Lookup {
+    Pending,
+    Done(Enrichment),
+}
+
+/// Artist and title
+type TrackKey = (String, String);
+
+pub struct Enricher {
+    enabled: bool,
+    cache: Mutex<HashMap<TrackKey, Lookup>>,
+    // When the last request was sent, to pace lookups
+    last_request: Mutex<Option<Instant>>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Deserialize)]
+struct Recording {
+    #[serde(default)]
+    score: u32,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    id: String,
+    title: String,
+}
+
+impl Enricher {
+    /// Read `MEDIA_CONTROL_ENRICH_METADATA` (default: disabled)
+    pub fn from_env() -> Self {
+        let enabled = config::var("MEDIA_CONTROL_ENRICH_METADATA")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        Enricher {
+            enabled,
+            cache: Mutex::new(HashMap::new()),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Fill in a snapshot's missing album and artwork from the cache,
+    /// starting a lookup if this track hasn't been seen yet
+    pub fn apply(self: &Arc<Self>, now: &mut NowPlaying) {
+        if !self.enabled || (now.album.is_some() && now.art_url.is_some()) {
+            return;
+        }
+        let (Some(artist), Some(title)) = (&now.artist, &now.title) else {
+            return;
+        };
+        let key = (artist.clone(), title.clone());
+
+        let mut cache = self.cache.locked();
+        match cache.get(&key) {
+            Some(Lookup::Done(found)) => {
+                if now.album.is_none() {
+                    now.album = found.album.clone();
+                }
+                if now.art_url.is_none() {
+                    now.art_url = found.art_url.clone();
+                }
+            }
+            Some(Lookup::Pending) => {}
+            None => {
+                if cache.len() >= CACHE_LIMIT {
+                    cache.clear();
+                }
+                cache.insert(key.clone(), Lookup::Pending);
+                let enricher = self.clone();
+                thread::spawn(move || {
+                    let found = enricher.look_up(&key.0, &key.1).unwrap_or_else(|e| {
+                        warn!("Metadata lookup for {} – {} failed: {e}", key.0, key.1);
+                        Enrichment::default()
+                    });
+                    enricher.cache.locked().insert(key, Lookup::Done(found));
+                });
+            }
+        }
+    }
+
+    /// Wait until another request to MusicBrainz is allowed
+    fn pace(&self) {
+        let mut last = self.last_request.locked();
+        if let Some(wait) = last.and_then(|at| REQUEST_INTERVAL.checked_sub(at.elapsed())) {
+            thread::sleep(wait);
+        }
+        *last = Some(Instant::now());
+    }
+
+    /// Search MusicBrainz for a recording and its first release's cover
+    fn look_up(&self, artist: &str, title: &str) -> Result<Enrichment, ureq::Error> {
+        let query = format!(
+            "recording:\"{}\" AND artist:\"{}\"",
+            lucene_escape(title),
+            lucene_escape(artist)
+        );
+        self.pace();
+        let agent = outbound::agent(Integration::Enrichment);
+        let result: SearchResult = agent
+            .get(RECORDING_SEARCH)
+            .config()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .user_agent(USER_AGENT)
+            .build()
+            .query("query", &query)
+            .query("fmt", "json")
+            .query("limit", "1")
+            .call()?
+            .body_mut()
+            .read_json()?;
+
+        let Some(release) = result
+            .recordings
+            .into_iter()
+            .filter(|r| r.score >= MIN_SCORE)
+            .find_map(|r| r.releases.into_iter().next())
+        else {
+            return Ok(Enrichment::default());
+        };
+
+        // Not every release has artwork; only link covers that exist
+        let cover = format!("{COVER_ART}/{}/front-500", release.id);
+        let head = agent
+            .head(&cover)
+            .config()
+            .timeout_global(Some(REQUEST_TIMEOUT))
+            .user_agent(USER_AGENT)
+            .build();
+        let art_url = match head.call() {
+            Ok(_) => Some(cover),
+            Err(ureq::Error::StatusCode(404)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Enrichment {
+            album: Some(release.title),
+            art_url,
+        })
+    }
+}
+
+/// Quote a value for use inside a Lucene phrase
+fn lucene_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,103 @@
+//! Error responses, and locks that survive a panic.
+//!
+//! Every failed request is answered with JSON like
+//! `{"error": "no matching player found", "code": 404}`, where `code`
+//! repeats the HTTP status ([`ErrorBody`]). Handlers build these from an
+//! [`AppError`], which also covers malformed JSON, query strings and paths,
+//! unknown routes and the auth and rate-limit middleware.
+
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use media_controller::models::ErrorBody;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// A failed request: the status to answer with and what went wrong
+#[derive(Debug)]
+pub struct AppError {
+    status: StatusCode,
+    message: String,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        AppError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.message.fmt(f)
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(ErrorBody {
+            error: self.message.clone(),
+            code: self.status.as_u16(),
+        })
+    }
+}
+
+/// Answer malformed JSON bodies with an [`AppError`]
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|e: JsonPayloadError, _: &HttpRequest| {
+        let status = e.status_code();
+        AppError::new(status, e.to_string()).into()
+    })
+}
+
+/// Answer malformed query strings with an [`AppError`]
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|e: QueryPayloadError, _: &HttpRequest| {
+        AppError::bad_request(e.to_string()).into()
+    })
+}
+
+/// Answer malformed path segments with an [`AppError`]
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default()
+        .error_handler(|e: PathError, _: &HttpRequest| AppError::not_found(e.to_string()).into())
+}
+
+/// Fallback for routes we don't serve
+pub async fn unknown_route() -> HttpResponse {
+    AppError::not_found("no such endpoint").error_response()
+}
+
+/// Locking that shrugs off poisoning: a handler that panicked while holding
+/// a lock must not make every later request fail too
+pub trait MutexExt<T> {
+    fn locked(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn locked(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,406 @@
+//! Push notifications of playback changes.
+//!
+//! Events are derived by comparing successive live snapshots (see
+//! [`crate::live`]). Each one names what changed and carries the full new
+//! snapshot, so a client never has to merge partial updates:
+//!
+//! ```json
+//! {"type": "track", "now_playing": {"player": "Spotify", "title": "...", ...}}
+//! ```
+//!
+//! The types are `player` (a different player is being controlled),
+//! `playback` (play/pause/stop, shuffle and loop), `track` (title, artist, album or art) and
+//! `volume` (system volume and mute). `sink_added` means a new output device
+//! (USB DAC, HDMI, Bluetooth) appeared; compare `now_playing.sinks` to see
+//! which. `votes` carries the skip votes on the current track (see
+//! [`crate::vote_skip`]) in `votes` next to `now_playing`, and
+//! `guest_expired` that the guest token from `/admin/guest` ran out.
+//! Clients first receive a `snapshot` event with the current state.
+//!
+//! The same events are offered as a WebSocket (`/ws`) and as Server-Sent
+//! Events (`/events`), where the type is also the SSE event name.
+//!
+//! WebSocket clients that only need part of this, such as e-ink dashboards,
+//! can send a subscription at any time, replacing the previous one:
+//!
+//! ```json
+//! {"types": ["track", "position"], "players": ["spotify"], "position_interval_ms": 30000}
+//! ```
+//!
+//! `types` limits the event types sent, `players` only sends events while a
+//! player whose identity or bus name contains one of the names is being
+//! controlled, and `position_interval_ms` adds `position` events, with
+//! `position_ms` and `length_ms` next to `now_playing`, at most that often
+//! while playing. Every field is optional. The server answers with a
+//! `subscribed` message echoing the subscription, or an `error` message, and
+//! then sends a fresh `snapshot` if it passes the filters.
+
+use crate::live::NowPlaying;
+use crate::AppState;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_ws::Message;
+use futures_util::stream;
+use media_controller::models::{Event, VoteTally};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// Idle SSE streams get a comment this often so proxies don't drop them
+const SSE_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Every event type a subscription can ask for
+const EVENT_TYPES: &[&str] = &[
+    "snapshot",
+    "player",
+    "playback",
+    "track",
+    "volume",
+    "sink_added",
+    "position",
+    "votes",
+    "guest_expired",
+];
+
+/// Position events are never sent more often than this
+const MIN_POSITION_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What a WebSocket client asked to receive; everything by default
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Subscription {
+    // Event types to send, all but position if left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    types: Option<Vec<String>>,
+    // Lowercase identity or bus name substrings of the players to follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    players: Option<Vec<String>>,
+    // Send position events at most this often while playing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position_interval_ms: Option<u64>,
+}
+
+impl Subscription {
+    /// Parse and check a subscription message
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut subscription: Subscription =
+            serde_json::from_str(text).map_err(|e| format!("invalid subscription: {e}"))?;
+        if let Some(unknown) = subscription
+            .types
+            .iter()
+            .flatten()
+            .find(|kind| !EVENT_TYPES.contains(&kind.as_str()))
+        {
+            return Err(format!(
+                "unknown event type {unknown:?}, expected one of {}",
+                EVENT_TYPES.join(", ")
+            ));
+        }
+        if let Some(players) = &mut subscription.players {
+            for player in players.iter_mut() {
+                *player = player.to_lowercase();
+            }
+        }
+        Ok(subscription)
+    }
+
+    /// Whether a `kind` event about `now` should be sent
+    fn wants(&self, kind: &str, now: &NowPlaying) -> bool {
+        let wanted_type = match &self.types {
+            Some(types) => types.iter().any(|t| t == kind),
+            None => kind != "position",
+        };
+        let wanted_player = self.players.as_ref().map_or(true, |players| {
+            let names = [&now.player, &now.bus_name];
+            players.iter().any(|wanted| {
+                names
+                    .iter()
+                    .filter_map(|name| name.as_deref())
+                    .any(|name| name.to_lowercase().contains(wanted.as_str()))
+            })
+        });
+        wanted_type && wanted_player
+    }
+
+    /// Timer for position events, if asked for
+    fn position_timer(&self) -> Option<Interval> {
+        let interval = Duration::from_millis(self.position_interval_ms?).max(MIN_POSITION_INTERVAL);
+        let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        Some(timer)
+    }
+}
+
+/// Wait for the next position tick, or forever without a timer
+async fn tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// A `position` event: the snapshot plus where playback is
+fn position_json(now: &NowPlaying) -> String {
+    json!({
+        "type": "position",
+        "now_playing": now,
+        "position_ms": now.position().map(|d| d.as_millis() as u64),
+        "length_ms": now.length.map(|d| d.as_millis() as u64),
+    })
+    .to_string()
+}
+
+/// A `votes` event: the snapshot plus the skip votes on it
+fn votes_json(now: &NowPlaying, tally: &VoteTally) -> String {
+    json!({ "type": "votes", "now_playing": now, "votes": tally }).to_string()
+}
+
+/// What changed between two snapshots, in a stable order
+pub fn changes(old: &NowPlaying, new: &NowPlaying) -> Vec<&'static str> {
+    let mut kinds = Vec::new();
+    if old.bus_name != new.bus_name {
+        kinds.push("player");
+    }
+    if (&old.playback, old.shuffle, &old.loop_status)
+        != (&new.playback, new.shuffle, &new.loop_status)
+    {
+        kinds.push("playback");
+    }
+    if (&old.title, &old.artist, &old.album, &old.art_url)
+        != (&new.title, &new.artist, &new.album, &new.art_url)
+    {
+        kinds.push("track");
+    }
+    if (old.volume, old.muted) != (new.volume, new.muted) {
+        kinds.push("volume");
+    }
+    if !added_sinks(old, new).is_empty() {
+        kinds.push("sink_added");
+    }
+    kinds
+}
+
+/// Output devices in `new` that weren't in `old`. Nothing counts as added
+/// until the devices have been read once.
+pub fn added_sinks<'a>(old: &NowPlaying, new: &'a NowPlaying) -> Vec<&'a str> {
+    let (Some(old), Some(new)) = (&old.sinks, &new.sinks) else {
+        return Vec::new();
+    };
+    new.iter()
+        .filter(|name| !old.contains(name))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Serialize one event
+pub fn to_json(kind: &str, now: &NowPlaying) -> String {
+    json!({ "type": kind, "now_playing": now }).to_string()
+}
+
+/// GET /ws — WebSocket stream of playback events
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "Events",
+    responses((status = 101, description = "WebSocket of Event messages")),
+)]
+pub async fn websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut updates = state.live.subscribe();
+    let mut votes = state.votes.subscribe();
+    let mut notices = state.notices.subscribe();
+
+    actix_web::rt::spawn(async move {
+        let mut last = updates.borrow_and_update().clone();
+        if session.text(to_json("snapshot", &last)).await.is_err() {
+            return;
+        }
+        let mut subscription = Subscription::default();
+        let mut position_timer = None;
+
+        loop {
+            tokio::select! {
+                changed = updates.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let now = updates.borrow_and_update().clone();
+                    for kind in changes(&last, &now) {
+                        if !subscription.wants(kind, &now) {
+                            continue;
+                        }
+                        if session.text(to_json(kind, &now)).await.is_err() {
+                            return;
+                        }
+                    }
+                    last = now;
+                }
+                changed = votes.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let tally = votes.borrow_and_update().clone();
+                    if subscription.wants("votes", &last)
+                        && session.text(votes_json(&last, &tally)).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                notice = notices.recv() => match notice {
+                    Ok(kind) => {
+                        if subscription.wants(kind, &last)
+                            && session.text(to_json(kind, &last)).await.is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                _ = tick(&mut position_timer) => {
+                    let playing = last.playback.as_deref() == Some("Playing");
+                    if playing
+                        && subscription.wants("position", &last)
+                        && session.text(position_json(&last)).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                msg = messages.recv() => match msg {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = match Subscription::parse(&text) {
+                            Ok(new) => {
+                                subscription = new;
+                                position_timer = subscription.position_timer();
+                                json!({ "type": "subscribed", "subscription": subscription })
+                            }
+                            Err(e) => json!({ "type": "error", "error": e }),
+                        };
+                        if session.text(reply.to_string()).await.is_err() {
+                            return;
+                        }
+                        if subscription.wants("snapshot", &last)
+                            && session.text(to_json("snapshot", &last)).await.is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    // Binary and other frames mean nothing here
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+/// Format one event as an SSE frame
+fn sse_frame(kind: &str, now: &NowPlaying) -> String {
+    format!("event: {kind}\ndata: {}\n\n", to_json(kind, now))
+}
+
+/// GET /events — Server-Sent Events stream of playback events
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "Events",
+    responses((status = 200, description = "Server-Sent Events, each an Event", body = Event, content_type = "text/event-stream")),
+)]
+pub async fn event_stream(state: web::Data<AppState>) -> HttpResponse {
+    /// What woke the stream up
+    enum Woken {
+        Snapshot,
+        Votes,
+        Notice(&'static str),
+    }
+
+    let updates = state.live.subscribe();
+    let votes = state.votes.subscribe();
+    let notices = state.notices.subscribe();
+    let frames = stream::unfold(
+        (updates, votes, notices, None::<NowPlaying>),
+        |(mut updates, mut votes, mut notices, last)| async move {
+            let Some(mut last) = last else {
+                let now = updates.borrow_and_update().clone();
+                let frame = sse_frame("snapshot", &now);
+                return Some((
+                    Ok::<_, Error>(Bytes::from(frame)),
+                    (updates, votes, notices, Some(now)),
+                ));
+            };
+            loop {
+                let next = tokio::time::timeout(SSE_KEEPALIVE, async {
+                    tokio::select! {
+                        changed = updates.changed() => changed.map(|_| Some(Woken::Snapshot)).ok(),
+                        changed = votes.changed() => changed.map(|_| Some(Woken::Votes)).ok(),
+                        notice = notices.recv() => match notice {
+                            Ok(kind) => Some(Some(Woken::Notice(kind))),
+                            Err(RecvError::Lagged(_)) => Some(None),
+                            Err(RecvError::Closed) => None,
+                        },
+                    }
+                });
+                match next.await {
+                    Err(_) => {
+                        let ping = Bytes::from_static(b": keep-alive\n\n");
+                        return Some((Ok(ping), (updates, votes, notices, Some(last))));
+                    }
+                    Ok(None) => return None,
+                    Ok(Some(None)) => {}
+                    Ok(Some(Some(Woken::Votes))) => {
+                        let tally = votes.borrow_and_update().clone();
+                        let frame =
+                            format!("event: votes\ndata: {}\n\n", votes_json(&last, &tally));
+                        return Some((
+                            Ok(Bytes::from(frame)),
+                            (updates, votes, notices, Some(last)),
+                        ));
+                    }
+                    Ok(Some(Some(Woken::Notice(kind)))) => {
+                        let frame = sse_frame(kind, &last);
+                        return Some((
+                            Ok(Bytes::from(frame)),
+                            (updates, votes, notices, Some(last)),
+                        ));
+                    }
+                    Ok(Some(Some(Woken::Snapshot))) => {
+                        let now = updates.borrow_and_update().clone();
+                        let frames: String = changes(&last, &now)
+                            .into_iter()
+                            .map(|kind| sse_frame(kind, &now))
+                            .collect();
+                        last = now;
+                        if !frames.is_empty() {
+                            return Some((
+                                Ok(Bytes::from(frames)),
+                                (updates, votes, notices, Some(last)),
+                            ));
+                        }
+                    }
+                }
+            }
+        },
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(frames)
+}
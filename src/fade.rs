@@ -0,0 +1,264 @@
+//! Fading the volume around play and pause.
+//!
+//! `POST /pause?fade_ms=1500` lowers the volume to nothing over a second and
+//! a half, pauses, and puts the volume back, so the next play doesn't start
+//! silent. `POST /play?fade_ms=1500` starts at nothing and raises it over
+//! that time, and `/toggle` fades whichever way it goes. Without `?fade_ms=`
+//! the defaults from `MEDIA_CONTROL_FADE_OUT_MS` (pause and stop) and
+//! `MEDIA_CONTROL_FADE_IN_MS` (play) apply, so remotes and buttons that
+//! can't add parameters get fades too.
+//!
+//! `MEDIA_CONTROL_FADE_VOLUME=player` fades the player's own MPRIS volume
+//! instead of the system one, leaving other sounds alone. A fade started
+//! while another is running takes over from where that one got to and puts
+//! back the level from before either. A play, pause or stop without a fade
+//! stops the fade and puts the level back at once.
+//!
+//! Fading doesn't happen in dry run, with `?players=` or while `/lock` is on;
+//! the command then runs as a hard cut.
+
+use crate::actions::{Action, Target};
+use crate::config;
+use crate::error::MutexExt;
+use crate::{volume, AppState};
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::blocking::Connection;
+use mpris::PlaybackStatus;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Longest fade `?fade_ms=` can ask for
+pub const MAX_FADE_MS: u64 = 10_000;
+/// How often the volume is changed during a fade
+const STEP: Duration = Duration::from_millis(50);
+
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const PROPERTY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The running fade's number, and the level it puts back.
+/// `None` while no fade is running.
+static CURRENT: Mutex<Option<(u64, f64)>> = Mutex::new(None);
+/// Numbers the fades, so a fade notices another one taking over
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Whether `action` can fade
+pub fn fades(action: Action) -> bool {
+    matches!(
+        action,
+        Action::Play | Action::Pause | Action::Toggle | Action::Stop
+    )
+}
+
+/// Which way the volume goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// Down to nothing, before pausing or stopping
+    Out,
+    /// Up from nothing, after playing
+    In,
+}
+
+/// Milliseconds from `var`, 0 (no fade) when unset
+fn default_ms(var: &str) -> u64 {
+    config::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+        .min(MAX_FADE_MS)
+}
+
+/// What is faded
+enum Knob {
+    /// The default sink, in percent
+    System,
+    /// The player's MPRIS `Volume`, from 0 to 1
+    Player { conn: Connection, bus_name: String },
+}
+
+impl Knob {
+    /// The knob `MEDIA_CONTROL_FADE_VOLUME` names, for the player at
+    /// `bus_name`
+    fn configured(bus_name: &str) -> Result<Knob, String> {
+        let player = config::var("MEDIA_CONTROL_FADE_VOLUME")
+            .is_ok_and(|v| v.trim().eq_ignore_ascii_case("player"));
+        if !player {
+            return Ok(Knob::System);
+        }
+        let conn = Connection::new_session().map_err(|e| e.to_string())?;
+        Ok(Knob::Player {
+            conn,
+            bus_name: bus_name.to_string(),
+        })
+    }
+
+    fn get(&self) -> Result<f64, String> {
+        match self {
+            Knob::System => volume::backend().get_volume_exact(),
+            Knob::Player { conn, bus_name } => conn
+                .with_proxy(bus_name.as_str(), MPRIS_PATH, PROPERTY_TIMEOUT)
+                .get(PLAYER_INTERFACE, "Volume")
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    fn set(&self, level: f64) -> Result<(), String> {
+        match self {
+            Knob::System => volume::backend().set_volume_exact(level),
+            Knob::Player { conn, bus_name } => conn
+                .with_proxy(bus_name.as_str(), MPRIS_PATH, PROPERTY_TIMEOUT)
+                .set(PLAYER_INTERFACE, "Volume", level)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// A fade that has been started, and the level to go back to
+pub struct Fade {
+    pub direction: Direction,
+    knob: Knob,
+    generation: u64,
+    /// Where the volume starts from
+    from: f64,
+    /// Where it's put back to
+    level: f64,
+    duration: Duration,
+}
+
+/// Start fading `action` on the player `target` picks, for `requested`
+/// milliseconds or the configured default. `None` when there is nothing to
+/// fade: no player, a fade of 0, or a player already where `action` would
+/// take it.
+pub fn start(
+    state: &AppState,
+    target: &Target,
+    action: Action,
+    requested: Option<u64>,
+) -> Option<Fade> {
+    let player = target.find(state)?;
+    let playing = matches!(player.get_playback_status(), Ok(PlaybackStatus::Playing));
+    let direction = match action {
+        Action::Play if !playing => Direction::In,
+        Action::Pause | Action::Stop if playing => Direction::Out,
+        Action::Toggle if playing => Direction::Out,
+        Action::Toggle => Direction::In,
+        _ => return None,
+    };
+    let ms = requested.unwrap_or_else(|| {
+        default_ms(match direction {
+            Direction::Out => "MEDIA_CONTROL_FADE_OUT_MS",
+            Direction::In => "MEDIA_CONTROL_FADE_IN_MS",
+        })
+    });
+    if ms == 0 {
+        return None;
+    }
+    let knob = Knob::configured(player.bus_name())
+        .and_then(|knob| Ok((knob.get()?, knob)))
+        .map_err(|e| warn!("Not fading, couldn't read the volume: {e}"));
+    let (now, knob) = knob.ok()?;
+
+    let mut current = CURRENT.locked();
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    // Taking over a running fade carries on from where it got to, back to
+    // the level from before it
+    let (from, level) = match *current {
+        Some((_, level)) => (now, level),
+        None if direction == Direction::In => (0.0, now),
+        None => (now, now),
+    };
+    *current = Some((generation, level));
+    Some(Fade {
+        direction,
+        knob,
+        generation,
+        from,
+        level,
+        duration: Duration::from_millis(ms),
+    })
+}
+
+/// Stop a running fade and put its level back, for a command that doesn't
+/// fade
+pub fn interrupt() {
+    CURRENT.locked().take();
+}
+
+impl Fade {
+    /// Whether this is still the running fade
+    fn is_current(&self) -> bool {
+        matches!(*CURRENT.locked(), Some((generation, _)) if generation == self.generation)
+    }
+
+    /// Whether a command without a fade stopped this one, so it has to put
+    /// the level back itself
+    fn interrupted(&self) -> bool {
+        CURRENT.locked().is_none()
+    }
+
+    /// Move the volume from `from` to `to` over the fade's duration. False
+    /// if another command took over before it got there.
+    fn ramp(&self, from: f64, to: f64) -> bool {
+        let started = Instant::now();
+        loop {
+            if !self.is_current() {
+                if self.interrupted() {
+                    let _ = self.knob.set(self.level);
+                }
+                return false;
+            }
+            let done = (started.elapsed().as_secs_f64() / self.duration.as_secs_f64()).min(1.0);
+            // Carry on with the command regardless
+            if let Err(e) = self.knob.set(from + (to - from) * done) {
+                warn!("Fade stopped, couldn't change the volume: {e}");
+                return true;
+            }
+            if done >= 1.0 {
+                return true;
+            }
+            thread::sleep(STEP);
+        }
+    }
+
+    /// Lower the volume to nothing, before the pause. False if another
+    /// command took over, and the pause shouldn't happen.
+    pub fn down(&self) -> bool {
+        self.ramp(self.from, 0.0)
+    }
+
+    /// Silence the player before it starts playing
+    pub fn mute(&self) {
+        if let Err(e) = self.knob.set(self.from) {
+            warn!("Couldn't lower the volume before fading in: {e}");
+        }
+    }
+
+    /// Raise the volume back to its level, in the background
+    pub fn up(self) {
+        thread::spawn(move || {
+            self.ramp(self.from, self.level);
+            self.finish();
+        });
+    }
+
+    /// Put the level back at once and end the fade
+    pub fn restore(self) {
+        if !self.is_current() {
+            return;
+        }
+        if let Err(e) = self.knob.set(self.level) {
+            warn!("Couldn't put the volume back after fading: {e}");
+        }
+        self.finish();
+    }
+
+    fn finish(&self) {
+        let mut current = CURRENT.locked();
+        if matches!(*current, Some((generation, _)) if generation == self.generation) {
+            *current = None;
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Which application currently has window focus (X11).
+//!
+//! Uses `xprop` on the root window's `_NET_ACTIVE_WINDOW`, then reads the
+//! focused window's `WM_CLASS`. Wayland compositors don't expose this to
+//! ordinary clients; XWayland windows still work.
+
+use std::process::Command;
+
+/// The `WM_CLASS` instance and class names of the focused window, lowercased
+pub fn focused_window_classes() -> Option<Vec<String>> {
+    let root = run_xprop(&["-root", "_NET_ACTIVE_WINDOW"])?;
+    // _NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007
+    let window_id = root.split('#').nth(1)?.trim().split(',').next()?.trim();
+    if window_id == "0x0" {
+        return None;
+    }
+
+    let class = run_xprop(&["-id", window_id, "WM_CLASS"])?;
+    // WM_CLASS(STRING) = "Navigator", "firefox"
+    let classes: Vec<String> = class
+        .split_once('=')?
+        .1
+        .split(',')
+        .map(|c| c.trim().trim_matches('"').to_lowercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    (!classes.is_empty()).then_some(classes)
+}
+
+/// Whether a player looks like it belongs to one of the focused window's classes
+pub fn matches_player(classes: &[String], identity: &str, desktop_entry: Option<&str>) -> bool {
+    let identity = identity.to_lowercase();
+    let desktop_entry = desktop_entry.map(str::to_lowercase);
+    classes.iter().any(|class| {
+        identity.contains(class.as_str())
+            || class.contains(identity.as_str())
+            || desktop_entry.as_deref() == Some(class.as_str())
+    })
+}
+
+fn run_xprop(args: &[&str]) -> Option<String> {
+    let out = Command::new("xprop").args(args).output().ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
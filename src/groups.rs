@@ -0,0 +1,307 @@
+//! Named groups of players that commands fan out to.
+//!
+//! `MEDIA_CONTROL_GROUPS` defines groups like `rooms=mpd-kitchen|mpd-lounge`,
+//! and `POST /groups` adds more until the service restarts. Each member is a
+//! selector as for `/players/{id}/...`. `POST /groups/{name}/play` (or any
+//! other player command) sends the command to every member, and
+//! `POST /groups/{name}/volume` sets each member's own volume.
+//!
+//! These are not the `MEDIA_CONTROL_PLAYER_GROUPS` of player selection, which
+//! only say which identities a priority entry stands for. Nor do they line
+//! positions up; `/sync` does that.
+
+use crate::actions::Action;
+use crate::error::{AppError, MutexExt};
+use crate::{config, lock, AppState, CommandOptions, VolumeRequest};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use media_controller::models::{ErrorBody, PlayerGroup};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Most players one group may have
+const MAX_MEMBERS: usize = 16;
+/// Longest group name taken
+const MAX_NAME_LEN: usize = 64;
+
+struct Group {
+    players: Vec<String>,
+    // From MEDIA_CONTROL_GROUPS, so it can't be changed at run time
+    configured: bool,
+}
+
+/// The player groups, by name
+pub struct Groups {
+    groups: Mutex<BTreeMap<String, Group>>,
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !name.chars().all(valid) {
+        return Err(format!(
+            "a group name is 1 to {MAX_NAME_LEN} letters, digits, - or _"
+        ));
+    }
+    Ok(())
+}
+
+fn check_players(players: &[String]) -> Result<(), String> {
+    if players.is_empty() || players.len() > MAX_MEMBERS {
+        return Err(format!("a group needs 1 to {MAX_MEMBERS} players"));
+    }
+    // Members are handed on as a ?players= list
+    if players.iter().any(|p| p.is_empty() || p.contains(',')) {
+        return Err("player selectors can't be empty or contain commas".into());
+    }
+    Ok(())
+}
+
+impl Groups {
+    /// Read `MEDIA_CONTROL_GROUPS`, leaving out groups that don't parse
+    pub fn from_env() -> Self {
+        let text = config::var("MEDIA_CONTROL_GROUPS").unwrap_or_default();
+        let mut groups = BTreeMap::new();
+        for entry in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let parsed = entry
+                .split_once('=')
+                .ok_or_else(|| "expected a name, = and players separated by |".to_string())
+                .and_then(|(name, members)| {
+                    let name = name.trim().to_lowercase();
+                    let players: Vec<String> = members
+                        .split('|')
+                        .map(|m| m.trim().to_string())
+                        .filter(|m| !m.is_empty())
+                        .collect();
+                    check_name(&name)?;
+                    check_players(&players)?;
+                    Ok((name, players))
+                });
+            match parsed {
+                Ok((name, players)) => {
+                    groups.insert(
+                        name,
+                        Group {
+                            players,
+                            configured: true,
+                        },
+                    );
+                }
+                Err(e) => warn!("Ignoring group {entry:?} in MEDIA_CONTROL_GROUPS: {e}"),
+            }
+        }
+        if !groups.is_empty() {
+            info!("{} player groups configured", groups.len());
+        }
+        Groups {
+            groups: Mutex::new(groups),
+        }
+    }
+
+    fn members(&self, name: &str) -> Result<Vec<String>, AppError> {
+        self.groups
+            .locked()
+            .get(&name.to_lowercase())
+            .map(|group| group.players.clone())
+            .ok_or_else(|| AppError::not_found(format!("no group {name}")))
+    }
+}
+
+fn view(name: &str, group: &Group) -> PlayerGroup {
+    PlayerGroup {
+        name: name.to_string(),
+        players: group.players.clone(),
+        configured: group.configured,
+    }
+}
+
+/// GET /groups — every player group
+#[utoipa::path(
+    get,
+    path = "/groups",
+    tag = "Players",
+    responses((status = 200, description = "The groups", body = [PlayerGroup])),
+)]
+pub async fn list_groups(groups: web::Data<Groups>) -> HttpResponse {
+    let groups: Vec<PlayerGroup> = groups
+        .groups
+        .locked()
+        .iter()
+        .map(|(name, group)| view(name, group))
+        .collect();
+    HttpResponse::Ok().json(groups)
+}
+
+/// Body of POST /groups
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GroupRequest {
+    // Letters, digits, - and _
+    name: String,
+    // Player selectors as for /players/{id}/...
+    players: Vec<String>,
+}
+
+/// POST /groups — add or replace a player group until the service restarts
+#[utoipa::path(
+    post,
+    path = "/groups",
+    tag = "Players",
+    request_body = GroupRequest,
+    responses(
+        (status = 201, description = "The group", body = PlayerGroup),
+        (status = 400, description = "Invalid name or players", body = ErrorBody),
+        (status = 409, description = "A configured group has that name", body = ErrorBody),
+    ),
+)]
+pub async fn add_group(
+    groups: web::Data<Groups>,
+    body: web::Json<GroupRequest>,
+) -> Result<HttpResponse, AppError> {
+    let GroupRequest { name, players } = body.into_inner();
+    let name = name.trim().to_lowercase();
+    let players: Vec<String> = players.iter().map(|p| p.trim().to_string()).collect();
+    check_name(&name)
+        .and_then(|()| check_players(&players))
+        .map_err(AppError::bad_request)?;
+
+    let mut groups = groups.groups.locked();
+    if groups.get(&name).is_some_and(|group| group.configured) {
+        return Err(AppError::conflict(format!(
+            "{name} is set in MEDIA_CONTROL_GROUPS"
+        )));
+    }
+    info!("Group {name} set to {}", players.join(", "));
+    let group = Group {
+        players,
+        configured: false,
+    };
+    let view = view(&name, &group);
+    groups.insert(name, group);
+    Ok(HttpResponse::Created().json(view))
+}
+
+/// DELETE /groups/{name} — remove a group added with POST /groups
+#[utoipa::path(
+    delete,
+    path = "/groups/{name}",
+    tag = "Players",
+    params(("name" = String, Path, description = "Group name")),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 404, description = "No such group", body = ErrorBody),
+        (status = 409, description = "The group is set in MEDIA_CONTROL_GROUPS", body = ErrorBody),
+    ),
+)]
+pub async fn remove_group(
+    groups: web::Data<Groups>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let name = name.to_lowercase();
+    let mut groups = groups.groups.locked();
+    match groups.get(&name) {
+        None => Err(AppError::not_found(format!("no group {name}"))),
+        Some(group) if group.configured => Err(AppError::conflict(format!(
+            "{name} is set in MEDIA_CONTROL_GROUPS"
+        ))),
+        Some(_) => {
+            groups.remove(&name);
+            info!("Group {name} removed");
+            Ok(HttpResponse::NoContent().finish())
+        }
+    }
+}
+
+/// POST /groups/{name}/volume — set or adjust every member's own volume
+///
+/// Answers like a command with `?players=`, with each member's new volume;
+/// only a member without a volume control fails.
+#[utoipa::path(
+    post,
+    path = "/groups/{name}/volume",
+    tag = "Players",
+    params(("name" = String, Path, description = "Group name")),
+    request_body = VolumeRequest,
+    responses(
+        (status = 200, description = "One result per member", body = Object),
+        (status = 400, description = "Not exactly one of set or adjust", body = ErrorBody),
+        (status = 404, description = "No such group", body = ErrorBody),
+    ),
+)]
+pub async fn group_volume(
+    state: web::Data<AppState>,
+    groups: web::Data<Groups>,
+    name: web::Path<String>,
+    body: web::Json<VolumeRequest>,
+) -> Result<HttpResponse, AppError> {
+    if state.lock.is_locked() {
+        return Ok(lock::refused());
+    }
+    let request = body.into_inner();
+    request.check_player_volume()?;
+    let dry_run = crate::get_dry_run_mode();
+    let mut first_error = None;
+    let mut any_ok = false;
+    let results: Vec<_> = groups
+        .members(&name)?
+        .into_iter()
+        .map(
+            |selector| match crate::change_player_volume(&selector, &request, dry_run) {
+                Ok(volume) => {
+                    any_ok = true;
+                    json!({ "player": selector, "ok": true, "volume": volume.volume })
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e.status_code());
+                    json!({ "player": selector, "ok": false, "error": e.to_string() })
+                }
+            },
+        )
+        .collect();
+    let status = match first_error {
+        Some(status) if !any_ok => status,
+        _ => StatusCode::OK,
+    };
+    Ok(HttpResponse::build(status).json(json!({ "results": results })))
+}
+
+/// POST /groups/{name}/{command} — run a player command on every member
+///
+/// `{command}` is any player command endpoint name, as for
+/// `/players/{id}/{command}`. Answers like the command with `?players=`.
+#[utoipa::path(
+    post,
+    path = "/groups/{name}/{command}",
+    tag = "Players",
+    params(
+        ("name" = String, Path, description = "Group name"),
+        ("command" = String, Path, description = "Command endpoint name, e.g. play or pause"),
+        CommandOptions,
+    ),
+    responses(
+        (status = 200, description = "One result per member", body = Object),
+        (status = 404, description = "No such group or command", body = ErrorBody),
+    ),
+)]
+pub async fn group_command(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    groups: web::Data<Groups>,
+    path: web::Path<(String, String)>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    let (name, command) = path.into_inner();
+    if opts.players.is_some() {
+        return AppError::bad_request("?players= doesn't apply to groups").error_response();
+    }
+    let Some(action) = Action::from_name(&command).filter(|a| a.targets_player()) else {
+        return AppError::not_found(format!("unknown player command: {command}")).error_response();
+    };
+    match groups.members(&name) {
+        Ok(players) => crate::run_group(&req, &state, &players.join(","), action, &opts).await,
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,327 @@
+//! gRPC API (the `grpc` feature).
+//!
+//! Set `MEDIA_CONTROL_GRPC_LISTEN` to an address such as `0.0.0.0:50051` to
+//! serve `proto/media_controller.proto` there, next to the REST API. The
+//! calls run through [`crate::actions`] like the endpoints do, honouring the
+//! lock and dry run, and `WatchStatus` streams the `GET /status` body on
+//! every change of the live snapshot.
+//!
+//! Tokens are checked by the same [`Verifier`] as REST requests, each call
+//! counting as the REST route it mirrors, so a token limited to volume may
+//! call `SetVolume` but not `Play`. Failed tokens count towards the lockout
+//! and the rate limit applies per client IP.
+
+// Every call answers with tonic's Status, large or not
+#![allow(clippy::result_large_err)]
+
+use crate::actions::{self, Action, ActionError, Target};
+use crate::auth::{self, Decision, Verifier};
+use crate::error::AppError;
+use crate::{config, get_dry_run_mode, notify, rate_limit, volume, AppState, VolumeRequest};
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, ResponseError};
+use futures_util::Stream;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::thread;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+mod pb {
+    tonic::include_proto!("media_controller.v1");
+}
+
+use pb::media_controller_server::{MediaController, MediaControllerServer};
+use pb::{seek_request, volume_request};
+
+struct Service {
+    state: web::Data<AppState>,
+    verifier: web::Data<Verifier>,
+}
+
+/// The status code closest to an endpoint's HTTP status
+fn code_for(status: StatusCode) -> tonic::Code {
+    match status {
+        StatusCode::BAD_REQUEST => tonic::Code::InvalidArgument,
+        StatusCode::NOT_FOUND => tonic::Code::NotFound,
+        StatusCode::LOCKED => tonic::Code::FailedPrecondition,
+        _ => tonic::Code::Internal,
+    }
+}
+
+fn app_error(e: AppError) -> Status {
+    Status::new(code_for(e.status_code()), e.to_string())
+}
+
+fn action_error(e: ActionError) -> Status {
+    Status::new(code_for(e.status()), e.to_string())
+}
+
+fn target(player: Option<String>) -> Target {
+    player.map_or(Target::Auto, Target::Player)
+}
+
+fn status_message(status: media_controller::models::Status) -> pb::Status {
+    pb::Status {
+        our_playback: status.our_playback,
+        other_playback: status.other_playback,
+        title: status.title,
+        controlled_player: status.controlled_player,
+        artist: status.artist,
+        album: status.album,
+        art_url: status.art_url,
+        length_ms: status.length_ms,
+        position_ms: status.position_ms,
+        shuffle: status.shuffle,
+        loop_status: status.loop_status,
+        volume: status.volume,
+        muted: status.muted,
+    }
+}
+
+impl Service {
+    /// Let the call through if its token may use the REST route `path`
+    fn authorize<T>(&self, request: &Request<T>, method: Method, path: &str) -> Result<(), Status> {
+        let ip = request.remote_addr().map(|addr| addr.ip());
+        if ip.and_then(rate_limit::check).is_some() {
+            return Err(Status::resource_exhausted(
+                "Too many requests, try again later",
+            ));
+        }
+        let mut headers = HeaderMap::new();
+        for value in request.metadata().get_all("authorization") {
+            if let Ok(value) = HeaderValue::from_bytes(value.as_encoded_bytes()) {
+                headers.append(header::AUTHORIZATION, value);
+            }
+        }
+        let presented = auth::presented(&headers, None);
+        match self.verifier.decide(&presented, &method, path) {
+            Decision::Allow(_) => {
+                rate_limit::record_success(ip);
+                Ok(())
+            }
+            Decision::Forbidden(_) => {
+                rate_limit::record_success(ip);
+                warn!(path, "Refused a gRPC call outside the token's scopes");
+                Err(Status::permission_denied("This token can't make this call"))
+            }
+            Decision::Invalid => {
+                warn!(path, "Rejected an invalid API token on a gRPC call");
+                rate_limit::record_failure(ip);
+                Err(Status::unauthenticated("Invalid or missing API token"))
+            }
+            Decision::Malformed(why) => {
+                rate_limit::record_failure(ip);
+                Err(Status::unauthenticated(format!(
+                    "Invalid authorization metadata: {why}"
+                )))
+            }
+            Decision::Missing => Err(Status::unauthenticated("Invalid or missing API token")),
+        }
+    }
+
+    /// Run `action` on the player the call names, as its REST route does
+    async fn command(
+        &self,
+        request: Request<pb::CommandRequest>,
+        action: Action,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.authorize(&request, Method::POST, &format!("/{}", action.name()))?;
+        if let Some(ip) = request.remote_addr().map(|addr| addr.ip()) {
+            if !ip.is_loopback() {
+                notify::remote_command(ip.to_string());
+            }
+        }
+        let target = target(request.into_inner().player);
+        self.run(target, action).await
+    }
+
+    async fn run(
+        &self,
+        target: Target,
+        action: Action,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        let state = self.state.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            if get_dry_run_mode() {
+                actions::plan(&state, &target, action)
+            } else {
+                actions::run_on(&state, &target, action)
+            }
+        })
+        .await
+        .map_err(|e| Status::internal(format!("command task failed: {e}")))?;
+        let message = result.map_err(action_error)?;
+        Ok(Response::new(pb::CommandReply {
+            message: message.to_string(),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl MediaController for Service {
+    async fn play(
+        &self,
+        request: Request<pb::CommandRequest>,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.command(request, Action::Play).await
+    }
+
+    async fn pause(
+        &self,
+        request: Request<pb::CommandRequest>,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.command(request, Action::Pause).await
+    }
+
+    async fn toggle(
+        &self,
+        request: Request<pb::CommandRequest>,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.command(request, Action::Toggle).await
+    }
+
+    async fn next(
+        &self,
+        request: Request<pb::CommandRequest>,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.command(request, Action::Next).await
+    }
+
+    async fn previous(
+        &self,
+        request: Request<pb::CommandRequest>,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.command(request, Action::Previous).await
+    }
+
+    async fn seek(
+        &self,
+        request: Request<pb::SeekRequest>,
+    ) -> Result<Response<pb::CommandReply>, Status> {
+        self.authorize(&request, Method::POST, "/seek")?;
+        let pb::SeekRequest { player, target: to } = request.into_inner();
+        let action = match to {
+            Some(seek_request::Target::PositionMs(ms)) => Action::SeekTo(ms),
+            Some(seek_request::Target::OffsetMs(ms)) => Action::SeekBy(ms),
+            None => {
+                return Err(Status::invalid_argument(
+                    "expected one of position_ms or offset_ms",
+                ))
+            }
+        };
+        self.run(target(player), action).await
+    }
+
+    async fn set_volume(
+        &self,
+        request: Request<pb::VolumeRequest>,
+    ) -> Result<Response<pb::VolumeReply>, Status> {
+        self.authorize(&request, Method::POST, "/volume")?;
+        if self.state.lock.is_locked() {
+            return Err(Status::failed_precondition("volume and seeking are locked"));
+        }
+        let pb::VolumeRequest { player, change } = request.into_inner();
+        let (set, adjust) = match change {
+            Some(volume_request::Change::Set(level)) => (Some(level), None),
+            Some(volume_request::Change::Adjust(delta)) => (None, Some(delta)),
+            None => return Err(Status::invalid_argument("expected one of set or adjust")),
+        };
+        let request = VolumeRequest { set, adjust };
+        let dry_run = get_dry_run_mode();
+        let level = tokio::task::spawn_blocking(move || -> Result<u32, Status> {
+            if let Some(selector) = player {
+                request.check_player_volume().map_err(app_error)?;
+                let volume =
+                    crate::change_player_volume(&selector, &request, dry_run).map_err(app_error)?;
+                return Ok(volume.volume.unwrap_or_default());
+            }
+            if set.is_some_and(|level| level > volume::MAX_VOLUME) {
+                return Err(Status::invalid_argument(format!(
+                    "set must be between 0 and {}",
+                    volume::MAX_VOLUME
+                )));
+            }
+            let backend = volume::backend();
+            match (set, adjust) {
+                (Some(level), _) if dry_run => Ok(level),
+                (Some(level), _) => backend.set_volume(level).map(|()| level),
+                (None, delta) if dry_run => backend
+                    .get_volume()
+                    .map(|current| volume::adjusted(current, delta.unwrap_or(0))),
+                (None, delta) => backend.adjust_volume(delta.unwrap_or(0)),
+            }
+            .map_err(Status::internal)
+        })
+        .await
+        .map_err(|e| Status::internal(format!("volume task failed: {e}")))??;
+        Ok(Response::new(pb::VolumeReply { volume: level }))
+    }
+
+    async fn get_status(
+        &self,
+        request: Request<pb::StatusRequest>,
+    ) -> Result<Response<pb::Status>, Status> {
+        self.authorize(&request, Method::GET, "/status")?;
+        Ok(Response::new(status_message(crate::status_json(
+            &self.state,
+        ))))
+    }
+
+    type WatchStatusStream = Pin<Box<dyn Stream<Item = Result<pb::Status, Status>> + Send>>;
+
+    async fn watch_status(
+        &self,
+        request: Request<pb::StatusRequest>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        self.authorize(&request, Method::GET, "/status")?;
+        let state = self.state.clone();
+        let updates = state.live.subscribe();
+        let stream = futures_util::stream::unfold(
+            (state, updates, true),
+            |(state, mut updates, first)| async move {
+                // The first message is the status as it is now
+                if !first && updates.changed().await.is_err() {
+                    return None;
+                }
+                let status = status_message(crate::status_json(&state));
+                Some((Ok(status), (state, updates, false)))
+            },
+        );
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serve the gRPC API if `MEDIA_CONTROL_GRPC_LISTEN` is set
+pub fn spawn(state: web::Data<AppState>, verifier: web::Data<Verifier>) {
+    let Ok(listen) = config::var("MEDIA_CONTROL_GRPC_LISTEN") else {
+        return;
+    };
+    let addr: SocketAddr = match listen.trim().parse() {
+        Ok(addr) => addr,
+        Err(_) => {
+            warn!("Ignoring MEDIA_CONTROL_GRPC_LISTEN={listen:?}, expected an address like 0.0.0.0:50051");
+            return;
+        }
+    };
+    let service = MediaControllerServer::new(Service { state, verifier });
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                warn!("Can't start the gRPC server: {e}");
+                return;
+            }
+        };
+        info!("gRPC API listening on {addr}");
+        let server = Server::builder().add_service(service).serve(addr);
+        if let Err(e) = runtime.block_on(server) {
+            warn!("gRPC server on {addr} stopped: {e}");
+        }
+    });
+}
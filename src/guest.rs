@@ -0,0 +1,156 @@
+//! Time-boxed guest tokens.
+//!
+//! `POST /admin/guest` hands out a random token that may use the control and
+//! status endpoints but nothing under `/admin`. It stops working once its
+//! window is over, or when it's revoked early with `DELETE /admin/guest`.
+//! Only one guest token exists at a time; issuing a new one replaces it.
+//! When a token runs out, a `guest_expired` event goes out on `/ws` and
+//! `/events`.
+
+use crate::error::{AppError, MutexExt};
+use crate::AppState;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use media_controller::models::ErrorBody;
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Default guest window when the request doesn't specify one
+const DEFAULT_MINUTES: u64 = 120;
+/// Longest guest window `minutes` can ask for, a week
+const MAX_MINUTES: u64 = 7 * 24 * 60;
+
+/// The currently active guest token, if any
+#[derive(Default)]
+pub struct GuestAccess {
+    current: Mutex<Option<GuestToken>>,
+}
+
+struct GuestToken {
+    token: String,
+    expires: Instant,
+}
+
+impl GuestAccess {
+    /// Whether `token` is the live guest token. An expired one is left for
+    /// the expiry task to clear, which announces it.
+    pub fn is_valid(&self, token: &str) -> bool {
+        match self.current.locked().as_ref() {
+            Some(guest) if guest.expires <= Instant::now() => false,
+            Some(guest) => crate::auth::tokens_match(token, &guest.token),
+            None => false,
+        }
+    }
+
+    /// Replace any guest token with a fresh one valid for `window`
+    pub fn issue(&self, window: Duration) -> String {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        *self.current.locked() = Some(GuestToken {
+            token: token.clone(),
+            expires: Instant::now() + window,
+        });
+        token
+    }
+
+    /// Drop the guest token if it is still `token`, returning whether it was
+    fn revoke_if(&self, token: &str) -> bool {
+        let mut current = self.current.locked();
+        if current.as_ref().is_some_and(|g| g.token == token) {
+            *current = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Body of POST /admin/guest
+#[derive(Deserialize, ToSchema)]
+pub struct GuestRequest {
+    // How long the guest token stays valid
+    minutes: Option<u64>,
+}
+
+/// POST /admin/guest — issue a control-only guest token for a limited time
+#[utoipa::path(
+    post,
+    path = "/admin/guest",
+    tag = "Admin",
+    request_body(content = Option<GuestRequest>),
+    responses(
+        (status = 200, description = "The guest token and how long it lasts"),
+        (status = 400, description = "minutes is out of range", body = ErrorBody),
+    ),
+)]
+pub async fn create_guest(
+    state: web::Data<AppState>,
+    guests: web::Data<GuestAccess>,
+    body: Option<web::Json<GuestRequest>>,
+) -> impl Responder {
+    let minutes = body.and_then(|b| b.minutes).unwrap_or(DEFAULT_MINUTES);
+    let Some(window) = Some(minutes)
+        .filter(|minutes| (1..=MAX_MINUTES).contains(minutes))
+        .and_then(|minutes| minutes.checked_mul(60))
+        .map(Duration::from_secs)
+    else {
+        return AppError::bad_request(format!("minutes must be between 1 and {MAX_MINUTES}"))
+            .error_response();
+    };
+    let token = guests.issue(window);
+    info!("Guest access enabled for {minutes} minutes");
+    // Mail isn't a safe place for a live token, so only the expiry is sent
+    #[cfg(feature = "email")]
+    {
+        let expires = chrono::TimeDelta::from_std(window)
+            .ok()
+            .and_then(|left| chrono::Local::now().checked_add_signed(left))
+            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        crate::email::notify(
+            &crate::email::GUEST_CREATED,
+            [("minutes", minutes.to_string()), ("expires", expires)].into(),
+        );
+    }
+
+    // Revoke on time even if nobody uses the token again
+    let expiring = token.clone();
+    actix_web::rt::spawn(async move {
+        actix_web::rt::time::sleep(window).await;
+        if guests.revoke_if(&expiring) {
+            info!("Guest access expired");
+            let _ = state.notices.send("guest_expired");
+        }
+    });
+
+    HttpResponse::Ok().json(json!({ "token": token, "expires_in_minutes": minutes }))
+}
+
+/// DELETE /admin/guest — revoke the guest token early
+#[utoipa::path(
+    delete,
+    path = "/admin/guest",
+    tag = "Admin",
+    responses(
+        (status = 200, description = "Guest access was revoked", body = String, content_type = "text/plain"),
+        (status = 404, description = "No guest access active", body = ErrorBody),
+    ),
+)]
+pub async fn revoke_guest(guests: web::Data<GuestAccess>) -> impl Responder {
+    let revoked = guests
+        .current
+        .locked()
+        .take()
+        .is_some_and(|guest| guest.expires > Instant::now());
+    if revoked {
+        info!("Guest access revoked");
+        HttpResponse::Ok().body("guest access revoked")
+    } else {
+        AppError::not_found("no guest access active").error_response()
+    }
+}
@@ -0,0 +1,543 @@
+//! Playback history (the `history` feature).
+//!
+//! With `MEDIA_CONTROL_HISTORY` on, every track that plays is recorded in
+//! an SQLite database, `MEDIA_CONTROL_HISTORY_FILE`, by default
+//! `$XDG_STATE_HOME/media-controller/history.sqlite` (usually under
+//! `~/.local/state`). A play is written as soon as a track starts playing,
+//! and its listened time, which leaves out pauses, is brought up to date
+//! whenever it pauses or another track starts.
+//!
+//! `GET /history` lists the plays newest first, a page at a time, optionally
+//! only those that started between two times. Times may be dates or dates
+//! with a time of day, taken as local time unless they carry an offset, so
+//! "yesterday afternoon" is `?from=2026-10-13 12:00&to=2026-10-13 18:00`.
+//!
+//! `GET /stats` sums the plays up over the last week, month or year, or any
+//! window: top artists and tracks, listening time per player and a weekday
+//! by hour heatmap. Plays shorter than 30 seconds count as skips there.
+
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use crate::live::NowPlaying;
+use crate::AppState;
+use actix_web::{web, HttpResponse};
+use media_controller::models::{
+    ErrorBody, ListeningStats, Play, PlayHistory, StatsEntry, TrackStats,
+};
+use rusqlite::{params, Connection, ToSql};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+use utoipa::IntoParams;
+
+/// Page size when `?limit` is missing
+const DEFAULT_LIMIT: u32 = 50;
+/// Largest page `?limit` can ask for
+const MAX_LIMIT: u32 = 500;
+/// How long a query waits for the recorder to finish writing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Entries in each top list when `?limit` is missing
+const DEFAULT_TOP: u32 = 10;
+/// Longest top list `?limit` can ask for
+const MAX_TOP: u32 = 100;
+/// Plays shorter than this count as skips in the statistics
+const MIN_LISTENED_MS: i64 = 30_000;
+/// Plays between the times bound to ?1 and ?2, either of which may be NULL
+const WINDOW: &str = "(?1 IS NULL OR started_at >= ?1) AND (?2 IS NULL OR started_at < ?2)";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS plays (
+        id INTEGER PRIMARY KEY,
+        -- Milliseconds since the Unix epoch
+        started_at INTEGER NOT NULL,
+        title TEXT,
+        artist TEXT,
+        album TEXT,
+        player TEXT,
+        bus_name TEXT,
+        length_ms INTEGER,
+        listened_ms INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS plays_started_at ON plays (started_at);
+";
+
+/// Whether `MEDIA_CONTROL_HISTORY` is set
+pub fn enabled() -> bool {
+    config::var("MEDIA_CONTROL_HISTORY")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Where the database lives
+fn file_path() -> Option<PathBuf> {
+    if let Ok(path) = config::var("MEDIA_CONTROL_HISTORY_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state_home.join("media-controller").join("history.sqlite"))
+}
+
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    let db = Connection::open(path)?;
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    // Lets `GET /history` read while the recorder writes
+    db.pragma_update(None, "journal_mode", "WAL")?;
+    db.execute_batch(SCHEMA)?;
+    Ok(db)
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// What the recorder thread writes
+enum Record {
+    /// A new play, starting now
+    Started(Box<NowPlaying>, u64),
+    /// The listened time of the latest play
+    Listened(Duration),
+    /// Answer once everything queued before it is written
+    Flush(mpsc::Sender<()>),
+}
+
+/// Write records as they come, keeping the id of the latest play
+fn record(db: Connection, records: mpsc::Receiver<Record>) {
+    let mut latest = None;
+    for record in records {
+        let result = match record {
+            Record::Started(now, started_at) => db
+                .execute(
+                    "INSERT INTO plays (started_at, title, artist, album, player, bus_name, length_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        started_at as i64,
+                        now.title,
+                        now.artist,
+                        now.album,
+                        now.player,
+                        now.bus_name,
+                        now.length.map(|d| d.as_millis() as i64),
+                    ],
+                )
+                .map(|_| latest = Some(db.last_insert_rowid())),
+            Record::Listened(listened) => match latest {
+                Some(id) => db
+                    .execute(
+                        "UPDATE plays SET listened_ms = ?1 WHERE id = ?2",
+                        params![listened.as_millis() as i64, id],
+                    )
+                    .map(|_| ()),
+                None => Ok(()),
+            },
+            Record::Flush(done) => {
+                let _ = done.send(());
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            warn!("Couldn't record playback history: {e}");
+        }
+    }
+}
+
+/// The track being played, and how long it has played
+struct Listening {
+    // Bus name, title and artist, which tell plays apart
+    key: (Option<String>, String, Option<String>),
+    listened: Duration,
+    // Since when it has been playing, while it is
+    playing_since: Option<Instant>,
+    recorded: bool,
+}
+
+impl Listening {
+    /// End the current stretch of playing, if any, and bring the record up
+    /// to date
+    fn pause(&mut self, records: &mpsc::Sender<Record>) {
+        if let Some(since) = self.playing_since.take() {
+            self.listened += since.elapsed();
+            let _ = records.send(Record::Listened(self.listened));
+        }
+    }
+}
+
+/// Account for a new snapshot, queueing what needs writing
+fn follow(listening: &mut Option<Listening>, now: &NowPlaying, records: &mpsc::Sender<Record>) {
+    let key = now
+        .title
+        .clone()
+        .map(|title| (now.bus_name.clone(), title, now.artist.clone()));
+    if listening.as_ref().map(|l| &l.key) != key.as_ref() {
+        if let Some(l) = listening.as_mut() {
+            l.pause(records);
+        }
+        *listening = key.map(|key| Listening {
+            key,
+            listened: Duration::ZERO,
+            playing_since: None,
+            recorded: false,
+        });
+    }
+    let Some(l) = listening.as_mut() else {
+        return;
+    };
+    if now.playback.as_deref() != Some("Playing") {
+        l.pause(records);
+        return;
+    }
+    if !l.recorded {
+        l.recorded = true;
+        let _ = records.send(Record::Started(Box::new(now.clone()), unix_ms()));
+    }
+    l.playing_since.get_or_insert_with(Instant::now);
+}
+
+/// The recorded plays, for `GET /history`
+pub struct History {
+    db: Mutex<Connection>,
+    // What the recorder is following, to bring it up to date on exit
+    listening: Arc<Mutex<Option<Listening>>>,
+    records: Mutex<mpsc::Sender<Record>>,
+}
+
+impl History {
+    /// Write out the listened time of the track playing now, before exiting
+    pub fn flush(&self) {
+        let records = self.records.locked().clone();
+        if let Some(l) = self.listening.locked().as_mut() {
+            l.pause(&records);
+        }
+        let (done, written) = mpsc::channel();
+        if records.send(Record::Flush(done)).is_ok() {
+            let _ = written.recv_timeout(BUSY_TIMEOUT);
+        }
+    }
+}
+
+/// Start recording if `MEDIA_CONTROL_HISTORY` is set, returning the history
+/// to serve
+pub fn spawn(state: web::Data<AppState>) -> Option<History> {
+    if !enabled() {
+        return None;
+    }
+    let Some(path) = file_path() else {
+        error!("Not recording playback history, set MEDIA_CONTROL_HISTORY_FILE or HOME");
+        return None;
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            error!(
+                "Not recording playback history, can't create {}: {e}",
+                dir.display()
+            );
+            return None;
+        }
+    }
+    let (writer, reader) = match open(&path).and_then(|writer| Ok((writer, open(&path)?))) {
+        Ok(connections) => connections,
+        Err(e) => {
+            error!(
+                "Not recording playback history, can't open {}: {e}",
+                path.display()
+            );
+            return None;
+        }
+    };
+    info!("Recording playback history in {}", path.display());
+
+    let (records, queue) = mpsc::channel();
+    thread::spawn(move || record(writer, queue));
+
+    let listening = Arc::new(Mutex::new(None));
+    let mut updates = state.live.subscribe();
+    {
+        let (listening, records) = (listening.clone(), records.clone());
+        actix_web::rt::spawn(async move {
+            loop {
+                let now = updates.borrow_and_update().clone();
+                follow(&mut listening.locked(), &now, &records);
+                if updates.changed().await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    Some(History {
+        db: Mutex::new(reader),
+        listening,
+        records: Mutex::new(records),
+    })
+}
+
+/// Query options of GET /history
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryOptions {
+    // Only plays that started at or after this time, e.g. `2026-10-13` or `2026-10-13 15:00`
+    from: Option<String>,
+    // Only plays that started before this time
+    to: Option<String>,
+    // Plays per page, at most 500 (default 50)
+    limit: Option<u32>,
+    // Plays to skip, for later pages (default 0)
+    offset: Option<u64>,
+}
+
+impl History {
+    /// `time` in milliseconds since the Unix epoch, using SQLite's date
+    /// parsing; local time unless it has an offset
+    fn bound(db: &Connection, name: &str, time: Option<&str>) -> Result<Option<i64>, AppError> {
+        let Some(time) = time else {
+            return Ok(None);
+        };
+        db.query_row(
+            "SELECT CAST(round((julianday(?1, 'utc') - 2440587.5) * 86400000) AS INTEGER)",
+            [time],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .map_err(internal)?
+        .map(Some)
+        .ok_or_else(|| {
+            AppError::bad_request(format!(
+                "{name}: expected a time like 2026-10-13 or 2026-10-13 15:00, got {time:?}"
+            ))
+        })
+    }
+
+    fn page(&self, opts: &HistoryOptions) -> Result<PlayHistory, AppError> {
+        let db = self.db.locked();
+        let from = History::bound(&db, "from", opts.from.as_deref())?;
+        let to = History::bound(&db, "to", opts.to.as_deref())?;
+        let limit = opts.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let offset = opts.offset.unwrap_or(0);
+
+        let total: u64 = db
+            .query_row(
+                &format!("SELECT count(*) FROM plays WHERE {WINDOW}"),
+                params![from, to],
+                |row| row.get(0),
+            )
+            .map_err(internal)?;
+        let mut query = db
+            .prepare(&format!(
+                "SELECT id, started_at, strftime('%Y-%m-%dT%H:%M:%SZ', started_at / 1000, 'unixepoch'),
+                        title, artist, album, player, length_ms, listened_ms
+                 FROM plays WHERE {WINDOW}
+                 ORDER BY started_at DESC, id DESC LIMIT ?3 OFFSET ?4"
+            ))
+            .map_err(internal)?;
+        let plays = query
+            .query_map(params![from, to, limit, offset as i64], |row| {
+                Ok(Play {
+                    id: row.get(0)?,
+                    started_at_ms: row.get::<_, i64>(1)? as u64,
+                    started_at: row.get(2)?,
+                    title: row.get(3)?,
+                    artist: row.get(4)?,
+                    album: row.get(5)?,
+                    player: row.get(6)?,
+                    length_ms: row.get::<_, Option<i64>>(7)?.map(|ms| ms as u64),
+                    listened_ms: row.get::<_, i64>(8)? as u64,
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(internal)?;
+        Ok(PlayHistory {
+            total,
+            limit,
+            offset,
+            plays,
+        })
+    }
+
+    fn stats(&self, opts: &StatsOptions) -> Result<ListeningStats, AppError> {
+        let db = self.db.locked();
+        let days = match opts.period.as_deref().unwrap_or("month") {
+            "week" => Some(7),
+            "month" => Some(30),
+            "year" => Some(365),
+            "all" => None,
+            other => {
+                return Err(AppError::bad_request(format!(
+                    "period: expected week, month, year or all, got {other:?}"
+                )))
+            }
+        };
+        let since = days.map(|days: i64| unix_ms() as i64 - days * 86_400_000);
+        let from = History::bound(&db, "from", opts.from.as_deref())?.or(since);
+        let to = History::bound(&db, "to", opts.to.as_deref())?;
+        let limit = opts.limit.unwrap_or(DEFAULT_TOP).clamp(1, MAX_TOP);
+        let counted = format!("{WINDOW} AND listened_ms >= ?3");
+        let bind: &[&dyn ToSql] = params![from, to, MIN_LISTENED_MS, limit];
+
+        let (plays, listened_ms) = db
+            .query_row(
+                &format!(
+                    "SELECT count(*), coalesce(sum(listened_ms), 0) FROM plays WHERE {counted}"
+                ),
+                &bind[..3],
+                |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as u64)),
+            )
+            .map_err(internal)?;
+        // The most played artists, or every player
+        let top = |column: &str, order: &str, capped: bool| -> Result<Vec<StatsEntry>, AppError> {
+            let (cap, bind) = if capped {
+                ("LIMIT ?4", bind)
+            } else {
+                ("", &bind[..3])
+            };
+            let mut query = db
+                .prepare(&format!(
+                    "SELECT {column}, count(*), sum(listened_ms) FROM plays
+                     WHERE {counted} AND {column} IS NOT NULL
+                     GROUP BY {column} ORDER BY {order} {cap}"
+                ))
+                .map_err(internal)?;
+            query
+                .query_map(bind, |row| {
+                    Ok(StatsEntry {
+                        name: row.get(0)?,
+                        plays: row.get(1)?,
+                        listened_ms: row.get::<_, i64>(2)? as u64,
+                    })
+                })
+                .and_then(|rows| rows.collect())
+                .map_err(internal)
+        };
+        let top_artists = top("artist", "count(*) DESC, sum(listened_ms) DESC", true)?;
+        let players = top("player", "sum(listened_ms) DESC", false)?;
+
+        let mut query = db
+            .prepare(&format!(
+                "SELECT title, artist, count(*), sum(listened_ms) FROM plays
+                 WHERE {counted} AND title IS NOT NULL
+                 GROUP BY title, artist
+                 ORDER BY count(*) DESC, sum(listened_ms) DESC LIMIT ?4"
+            ))
+            .map_err(internal)?;
+        let top_tracks = query
+            .query_map(bind, |row| {
+                Ok(TrackStats {
+                    title: row.get(0)?,
+                    artist: row.get(1)?,
+                    plays: row.get(2)?,
+                    listened_ms: row.get::<_, i64>(3)? as u64,
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(internal)?;
+
+        let mut heatmap = vec![vec![0; 24]; 7];
+        let mut query = db
+            .prepare(&format!(
+                "SELECT (CAST(strftime('%w', started_at / 1000, 'unixepoch', 'localtime') AS INTEGER) + 6) % 7,
+                        CAST(strftime('%H', started_at / 1000, 'unixepoch', 'localtime') AS INTEGER),
+                        count(*)
+                 FROM plays WHERE {counted} GROUP BY 1, 2"
+            ))
+            .map_err(internal)?;
+        let cells = query
+            .query_map(&bind[..3], |row| {
+                Ok((
+                    row.get::<_, usize>(0)?,
+                    row.get::<_, usize>(1)?,
+                    row.get(2)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(internal)?;
+        for (weekday, hour, plays) in cells {
+            if let Some(cell) = heatmap.get_mut(weekday).and_then(|row| row.get_mut(hour)) {
+                *cell = plays;
+            }
+        }
+
+        Ok(ListeningStats {
+            from_ms: from.map(|ms| ms as u64),
+            to_ms: to.map(|ms| ms as u64),
+            plays,
+            listened_ms,
+            top_artists,
+            top_tracks,
+            players,
+            heatmap,
+        })
+    }
+}
+
+fn internal(e: rusqlite::Error) -> AppError {
+    AppError::internal(e.to_string())
+}
+
+/// Query options of GET /stats
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsOptions {
+    // `week`, `month` or `year` for the last 7, 30 or 365 days, or `all` (default `month`)
+    period: Option<String>,
+    // Start of the window instead of the period's, as for /history
+    from: Option<String>,
+    // End of the window (default now)
+    to: Option<String>,
+    // Entries in each top list, at most 100 (default 10)
+    limit: Option<u32>,
+}
+
+/// GET /history — tracks played, newest first
+///
+/// Only served when built with the `history` feature and
+/// `MEDIA_CONTROL_HISTORY` is set.
+#[utoipa::path(
+    get,
+    path = "/history",
+    tag = "Extras",
+    params(HistoryOptions),
+    responses(
+        (status = 200, description = "One page of plays", body = PlayHistory),
+        (status = 400, description = "A time that can't be read", body = ErrorBody),
+    ),
+)]
+pub async fn list_plays(
+    history: web::Data<History>,
+    opts: web::Query<HistoryOptions>,
+) -> Result<HttpResponse, AppError> {
+    let page = web::block(move || history.page(&opts))
+        .await
+        .map_err(|e| AppError::internal(e.to_string()))??;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// GET /stats — listening statistics: top artists and tracks, time per
+/// player and a weekday by hour heatmap
+///
+/// Only served when built with the `history` feature and
+/// `MEDIA_CONTROL_HISTORY` is set.
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "Extras",
+    params(StatsOptions),
+    responses(
+        (status = 200, description = "Statistics for the window", body = ListeningStats),
+        (status = 400, description = "An unknown period or a time that can't be read", body = ErrorBody),
+    ),
+)]
+pub async fn stats(
+    history: web::Data<History>,
+    opts: web::Query<StatsOptions>,
+) -> Result<HttpResponse, AppError> {
+    let stats = web::block(move || history.stats(&opts))
+        .await
+        .map_err(|e| AppError::internal(e.to_string()))??;
+    Ok(HttpResponse::Ok().json(stats))
+}
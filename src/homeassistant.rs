@@ -0,0 +1,135 @@
+//! Home Assistant MQTT discovery (the `mqtt` feature).
+//!
+//! With `MEDIA_CONTROL_MQTT_DISCOVERY` on, the MQTT bridge publishes
+//! discovery configs so the service shows up in Home Assistant as a device
+//! without any YAML. Home Assistant's MQTT integration has no `media_player`
+//! platform, so the device is made of the entities it does have, reading
+//! the bridge's state topics and sending to its command topics: playback and
+//! track sensors, the artwork as an image, play/pause, next and previous
+//! buttons, and a volume slider.
+//!
+//! Configs are retained and sent again whenever Home Assistant announces on
+//! `<discovery prefix>/status` that it has restarted.
+
+use crate::config;
+use serde_json::{json, Value};
+
+/// Whether `MEDIA_CONTROL_MQTT_DISCOVERY` is set
+pub fn enabled() -> bool {
+    config::var("MEDIA_CONTROL_MQTT_DISCOVERY")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Topic prefix Home Assistant listens on
+pub fn discovery_prefix() -> String {
+    config::var("MEDIA_CONTROL_MQTT_DISCOVERY_PREFIX")
+        .map(|v| v.trim().trim_end_matches('/').to_string())
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "homeassistant".into())
+}
+
+/// Discovery topics and their payloads, for the bridge publishing under
+/// `prefix` as MQTT client `client_id`
+pub fn configs(prefix: &str, client_id: &str) -> Vec<(String, String)> {
+    // Discovery topics allow only these characters in the node id
+    let node: String = client_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let device = json!({
+        "identifiers": [node],
+        "name": "Media Controller",
+        "model": "media-controller",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    let entities: [(&str, &str, Value); 7] = [
+        (
+            "sensor",
+            "playback",
+            json!({
+                "name": "Playback",
+                "icon": "mdi:play-pause",
+                "state_topic": format!("{prefix}/status"),
+                "value_template": "{{ value_json.other_playback or 'Stopped' }}",
+            }),
+        ),
+        (
+            "sensor",
+            "track",
+            json!({
+                "name": "Now playing",
+                "icon": "mdi:music",
+                "state_topic": format!("{prefix}/track"),
+                "value_template":
+                    "{{ [value_json.artist, value_json.title] | select | join(' – ') }}",
+                "json_attributes_topic": format!("{prefix}/track"),
+            }),
+        ),
+        (
+            "image",
+            "artwork",
+            json!({
+                "name": "Artwork",
+                "url_topic": format!("{prefix}/track"),
+                "url_template": "{{ value_json.art_url }}",
+            }),
+        ),
+        (
+            "button",
+            "toggle",
+            json!({
+                "name": "Play/pause",
+                "icon": "mdi:play-pause",
+                "command_topic": format!("{prefix}/cmd/toggle"),
+            }),
+        ),
+        (
+            "button",
+            "next",
+            json!({
+                "name": "Next",
+                "icon": "mdi:skip-next",
+                "command_topic": format!("{prefix}/cmd/next"),
+            }),
+        ),
+        (
+            "button",
+            "previous",
+            json!({
+                "name": "Previous",
+                "icon": "mdi:skip-previous",
+                "command_topic": format!("{prefix}/cmd/previous"),
+            }),
+        ),
+        (
+            "number",
+            "volume",
+            json!({
+                "name": "Volume",
+                "icon": "mdi:volume-high",
+                "min": 0,
+                "max": 100,
+                "unit_of_measurement": "%",
+                "state_topic": format!("{prefix}/volume"),
+                "value_template": "{{ value_json.volume }}",
+                "command_topic": format!("{prefix}/cmd/volume"),
+            }),
+        ),
+    ];
+
+    let discovery = discovery_prefix();
+    entities
+        .into_iter()
+        .map(|(component, object, mut payload)| {
+            payload["unique_id"] = json!(format!("{node}_{object}"));
+            payload["availability_topic"] = json!(format!("{prefix}/available"));
+            payload["device"] = device.clone();
+            (
+                format!("{discovery}/{component}/{node}/{object}/config"),
+                payload.to_string(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,114 @@
+//! Per-endpoint shell hooks.
+//!
+//! Hooks are configured with environment variables named after the route,
+//! e.g. `MEDIA_CONTROL_HOOK_AFTER_PLAY="~/bin/dim-lights"` or
+//! `MEDIA_CONTROL_HOOK_BEFORE_SEEK_FORWARD=...`. Commands run through `sh -c`.
+//! "Before" hooks are awaited (bounded by the timeout) so they finish before
+//! the handler runs; "after" hooks are spawned in the background once the
+//! response is ready. Hook output is logged, never returned to the client.
+
+use crate::config;
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+const PREFIX_BEFORE: &str = "MEDIA_CONTROL_HOOK_BEFORE_";
+const PREFIX_AFTER: &str = "MEDIA_CONTROL_HOOK_AFTER_";
+
+/// Hook commands keyed by endpoint name (`play`, `seek_forward`, ...)
+#[derive(Default)]
+pub struct Hooks {
+    before: HashMap<String, String>,
+    after: HashMap<String, String>,
+    timeout: Duration,
+}
+
+impl Hooks {
+    /// Collect every `MEDIA_CONTROL_HOOK_{BEFORE,AFTER}_*` variable
+    pub fn from_env() -> Self {
+        let mut hooks = Hooks {
+            timeout: Duration::from_secs(
+                config::var("MEDIA_CONTROL_HOOK_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(10),
+            ),
+            ..Default::default()
+        };
+        for (key, value) in config::vars() {
+            if let Some(name) = key.strip_prefix(PREFIX_BEFORE) {
+                hooks.before.insert(name.to_lowercase(), value);
+            } else if let Some(name) = key.strip_prefix(PREFIX_AFTER) {
+                hooks.after.insert(name.to_lowercase(), value);
+            }
+        }
+        for name in hooks.before.keys() {
+            info!("Registered before-hook for /{name}");
+        }
+        for name in hooks.after.keys() {
+            info!("Registered after-hook for /{name}");
+        }
+        hooks
+    }
+}
+
+/// Middleware running the configured hooks around each endpoint
+pub async fn hook_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(hooks) = req.app_data::<web::Data<Hooks>>().cloned() else {
+        return next.call(req).await;
+    };
+    let endpoint = crate::auth::routed_path(&req)
+        .trim_matches('/')
+        .replace('/', "_");
+
+    if let Some(cmd) = hooks.before.get(&endpoint) {
+        run_hook(&endpoint, "before", cmd, None, hooks.timeout).await;
+    }
+
+    let res = next.call(req).await?;
+
+    if let Some(cmd) = hooks.after.get(&endpoint).cloned() {
+        let status = res.status().as_u16();
+        let timeout = hooks.timeout;
+        actix_web::rt::spawn(async move {
+            run_hook(&endpoint, "after", &cmd, Some(status), timeout).await;
+        });
+    }
+    Ok(res)
+}
+
+/// Run one hook command, killing it if it exceeds `timeout`
+///
+/// The endpoint name and (for after-hooks) the response status are exported as
+/// `MEDIA_CONTROL_HOOK_ENDPOINT` and `MEDIA_CONTROL_HOOK_STATUS`.
+async fn run_hook(endpoint: &str, phase: &str, cmd: &str, status: Option<u16>, timeout: Duration) {
+    let mut command = Command::new("sh");
+    command
+        .args(["-c", cmd])
+        .env("MEDIA_CONTROL_HOOK_ENDPOINT", endpoint)
+        .kill_on_drop(true);
+    if let Some(status) = status {
+        command.env("MEDIA_CONTROL_HOOK_STATUS", status.to_string());
+    }
+
+    match tokio::time::timeout(timeout, command.output()).await {
+        Ok(Ok(out)) => {
+            info!(
+                "hook {phase} /{endpoint} exited with {}: stdout={:?} stderr={:?}",
+                out.status,
+                String::from_utf8_lossy(&out.stdout).trim(),
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        Ok(Err(e)) => warn!("hook {phase} /{endpoint} failed to start: {e}"),
+        Err(_) => warn!("hook {phase} /{endpoint} timed out after {timeout:?}"),
+    }
+}
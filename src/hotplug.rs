@@ -0,0 +1,106 @@
+//! What to do when a new output device appears.
+//!
+//! Every new sink (USB DAC, HDMI, Bluetooth) is announced as a `sink_added`
+//! event, so a client can offer to switch to it. `MEDIA_CONTROL_NEW_SINK_POLICY`
+//! can also act on it headlessly, the way desktop environments do:
+//!
+//! - `ignore` (default): only send the event
+//! - `move`: move the controlled player's streams onto the new sink
+//! - `default`: make the new sink the default output and move every stream
+//!   onto it
+
+use crate::config;
+use crate::events::added_sinks;
+use crate::live::NowPlaying;
+use crate::{audio, get_dry_run_mode, volume, AppState};
+use actix_web::web;
+use tracing::{error, info, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Policy {
+    Ignore,
+    MovePlayer,
+    MakeDefault,
+}
+
+/// Read `MEDIA_CONTROL_NEW_SINK_POLICY`
+fn policy_from_env() -> Policy {
+    match config::var("MEDIA_CONTROL_NEW_SINK_POLICY")
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "" | "ignore" => Policy::Ignore,
+        "move" => Policy::MovePlayer,
+        "default" => Policy::MakeDefault,
+        other => {
+            warn!("Ignoring unknown MEDIA_CONTROL_NEW_SINK_POLICY {other:?}");
+            Policy::Ignore
+        }
+    }
+}
+
+/// Apply `policy` to a newly added sink
+fn handle(policy: Policy, now: &NowPlaying, sink: &str) -> Result<(), String> {
+    let backend = volume::backend();
+    let streams: Vec<u32> = match policy {
+        Policy::Ignore => return Ok(()),
+        Policy::MovePlayer => {
+            let Some(player) = &now.player else {
+                info!("New output {sink}: no controlled player to move");
+                return Ok(());
+            };
+            audio::sink_inputs_for_player(player)?
+                .into_iter()
+                .map(|input| input.index)
+                .collect()
+        }
+        Policy::MakeDefault => backend.list_apps()?.into_iter().map(|a| a.id).collect(),
+    };
+
+    if get_dry_run_mode() {
+        info!("[dry run] New output {sink}: would move streams {streams:?} ({policy:?})");
+        return Ok(());
+    }
+    if policy == Policy::MakeDefault {
+        backend.set_default_sink(sink)?;
+    }
+    for id in &streams {
+        backend.move_app(*id, sink)?;
+    }
+    info!("New output {sink}: moved streams {streams:?} ({policy:?})");
+    Ok(())
+}
+
+/// Start following `state.live` for new sinks
+pub fn spawn(state: web::Data<AppState>) {
+    let policy = policy_from_env();
+    if policy == Policy::Ignore {
+        return;
+    }
+    info!("New outputs will be handled with policy {policy:?}");
+
+    let mut updates = state.live.subscribe();
+    actix_web::rt::spawn(async move {
+        let mut last = updates.borrow_and_update().clone();
+        while updates.changed().await.is_ok() {
+            let now = updates.borrow_and_update().clone();
+            let added: Vec<String> = added_sinks(&last, &now)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            last = now.clone();
+            if added.is_empty() {
+                continue;
+            }
+            let result =
+                web::block(move || added.iter().try_for_each(|sink| handle(policy, &now, sink)))
+                    .await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Failed to switch to the new output: {e}"),
+                Err(e) => error!("New output task failed: {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,117 @@
+//! Localized response text.
+//!
+//! Command endpoints answer with a [`Message`]: a stable id plus arguments.
+//! The text is rendered from the Fluent files in `locales/` in the first
+//! language from the client's `Accept-Language` that we have a translation
+//! for, falling back to English. JSON keys never change, only the text.
+
+use actix_web::http::header::{AcceptLanguage, Header};
+use actix_web::HttpRequest;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+/// Language used when the client asks for nothing we have
+const FALLBACK: &str = "en";
+
+/// Bundled translations, by primary language subtag
+const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+    ("es", include_str!("../locales/es.ftl")),
+];
+
+type Bundle = FluentBundle<FluentResource>;
+
+fn bundles() -> &'static HashMap<&'static str, Bundle> {
+    static BUNDLES: OnceLock<HashMap<&'static str, Bundle>> = OnceLock::new();
+    BUNDLES.get_or_init(|| {
+        LOCALES
+            .iter()
+            .map(|(lang, source)| {
+                let id: LanguageIdentifier = lang.parse().expect("valid language tag");
+                let resource = FluentResource::try_new(source.to_string())
+                    .unwrap_or_else(|(_, errors)| panic!("invalid {lang}.ftl: {errors:?}"));
+                let mut bundle = FluentBundle::new_concurrent(vec![id]);
+                // Plain-text responses shouldn't carry Unicode isolation marks
+                bundle.set_use_isolating(false);
+                bundle
+                    .add_resource(resource)
+                    .unwrap_or_else(|errors| panic!("duplicate ids in {lang}.ftl: {errors:?}"));
+                (*lang, bundle)
+            })
+            .collect()
+    })
+}
+
+/// Primary language subtags from `Accept-Language`, best first
+pub fn languages(req: &HttpRequest) -> Vec<String> {
+    AcceptLanguage::parse(req)
+        .map(|header| {
+            header
+                .ranked()
+                .into_iter()
+                .filter_map(|pref| pref.item().map(|tag| tag.primary_language().to_lowercase()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A human-readable message, rendered in the client's language on output
+#[derive(Clone, Debug)]
+pub struct Message {
+    id: &'static str,
+    args: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn new(id: &'static str) -> Self {
+        Message {
+            id,
+            args: Vec::new(),
+        }
+    }
+
+    /// Add a `{ $name }` argument
+    pub fn arg(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.args.push((name, value.to_string()));
+        self
+    }
+
+    /// Render in the first of `languages` that has this message, else English
+    pub fn localize(&self, languages: &[String]) -> String {
+        let bundles = bundles();
+        languages
+            .iter()
+            .map(String::as_str)
+            .chain([FALLBACK])
+            .filter_map(|lang| bundles.get(lang))
+            .find_map(|bundle| self.format(bundle))
+            .unwrap_or_else(|| self.id.to_string())
+    }
+
+    fn format(&self, bundle: &Bundle) -> Option<String> {
+        let pattern = bundle.get_message(self.id)?.value()?;
+        let mut args = FluentArgs::new();
+        for (name, value) in &self.args {
+            args.set(*name, value.clone());
+        }
+        let mut errors = Vec::new();
+        let text = bundle.format_pattern(pattern, Some(&args), &mut errors);
+        if !errors.is_empty() {
+            warn!("Translation errors in '{}': {errors:?}", self.id);
+        }
+        Some(text.into_owned())
+    }
+}
+
+/// English text, for logs
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(&[]))
+    }
+}
@@ -0,0 +1,239 @@
+//! Input devices: IR receivers, macro pads and spare keyboards.
+//!
+//! `MEDIA_CONTROL_INPUT_DEVICES` lists `/dev/input/event*` devices to read
+//! key presses from, preferably by their stable `/dev/input/by-id/...` path.
+//! `MEDIA_CONTROL_INPUT_KEYS` maps key names (`KEY_PLAYPAUSE`) or codes
+//! (`164`) to actions named like the endpoints (`toggle`, `next`,
+//! `volume_up`), e.g. in the config file:
+//!
+//! ```toml
+//! input_devices = ["/dev/input/by-id/usb-flirc.tv_flirc-if01-event-kbd"]
+//! input_keys = { KEY_PLAYPAUSE = "toggle", KEY_NEXTSONG = "next", KEY_UP = "volume_up" }
+//! ```
+//!
+//! Each press runs through [`crate::actions`] like the HTTP endpoint. Holding
+//! a key repeats the volume actions only. Devices are grabbed, so the desktop
+//! doesn't see the keys too, and opened again when they come back after
+//! being unplugged.
+
+use crate::actions::{self, Action};
+use crate::config;
+use crate::AppState;
+use actix_web::web;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// `EV_KEY` events are key presses and releases
+const EV_KEY: u16 = 0x01;
+/// `EVIOCGRAB`, `_IOW('E', 0x90, int)`
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+/// How often to look for a device that isn't there
+const REOPEN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Key names from `linux/input-event-codes.h` likely to be on a remote
+const KEY_NAMES: &[(&str, u16)] = &[
+    ("KEY_ESC", 1),
+    ("KEY_1", 2),
+    ("KEY_2", 3),
+    ("KEY_3", 4),
+    ("KEY_4", 5),
+    ("KEY_5", 6),
+    ("KEY_6", 7),
+    ("KEY_7", 8),
+    ("KEY_8", 9),
+    ("KEY_9", 10),
+    ("KEY_0", 11),
+    ("KEY_BACKSPACE", 14),
+    ("KEY_ENTER", 28),
+    ("KEY_SPACE", 57),
+    ("KEY_UP", 103),
+    ("KEY_LEFT", 105),
+    ("KEY_RIGHT", 106),
+    ("KEY_DOWN", 108),
+    ("KEY_MUTE", 113),
+    ("KEY_VOLUMEDOWN", 114),
+    ("KEY_VOLUMEUP", 115),
+    ("KEY_POWER", 116),
+    ("KEY_PAUSE", 119),
+    ("KEY_MENU", 139),
+    ("KEY_BACK", 158),
+    ("KEY_NEXTSONG", 163),
+    ("KEY_PLAYPAUSE", 164),
+    ("KEY_PREVIOUSSONG", 165),
+    ("KEY_STOPCD", 166),
+    ("KEY_REWIND", 168),
+    ("KEY_HOMEPAGE", 172),
+    ("KEY_F13", 183),
+    ("KEY_F14", 184),
+    ("KEY_F15", 185),
+    ("KEY_F16", 186),
+    ("KEY_F17", 187),
+    ("KEY_F18", 188),
+    ("KEY_F19", 189),
+    ("KEY_F20", 190),
+    ("KEY_F21", 191),
+    ("KEY_F22", 192),
+    ("KEY_F23", 193),
+    ("KEY_F24", 194),
+    ("KEY_PLAYCD", 200),
+    ("KEY_PAUSECD", 201),
+    ("KEY_PLAY", 207),
+    ("KEY_FASTFORWARD", 208),
+    ("KEY_OK", 0x160),
+    ("KEY_SELECT", 0x161),
+    ("KEY_INFO", 0x166),
+    ("KEY_CHANNELUP", 0x192),
+    ("KEY_CHANNELDOWN", 0x193),
+    ("KEY_NEXT", 0x197),
+    ("KEY_PREVIOUS", 0x19c),
+];
+
+/// Used when `MEDIA_CONTROL_INPUT_KEYS` isn't set: the media keys do what
+/// they say
+const DEFAULT_KEYS: &[(&str, Action)] = &[
+    ("KEY_PLAYPAUSE", Action::Toggle),
+    ("KEY_PLAY", Action::Play),
+    ("KEY_PLAYCD", Action::Play),
+    ("KEY_PAUSE", Action::Pause),
+    ("KEY_PAUSECD", Action::Pause),
+    ("KEY_STOPCD", Action::Stop),
+    ("KEY_NEXTSONG", Action::Next),
+    ("KEY_PREVIOUSSONG", Action::Previous),
+    ("KEY_FASTFORWARD", Action::SeekForward),
+    ("KEY_REWIND", Action::SeekBackward),
+    ("KEY_VOLUMEUP", Action::VolumeUp),
+    ("KEY_VOLUMEDOWN", Action::VolumeDown),
+    ("KEY_MUTE", Action::ToggleMute),
+];
+
+/// A key code from its name or number
+fn key_code(key: &str) -> Option<u16> {
+    let key = key.trim();
+    KEY_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|&(_, code)| code)
+        .or_else(|| key.parse().ok())
+}
+
+/// Key codes and their actions, from `MEDIA_CONTROL_INPUT_KEYS`
+fn key_map() -> HashMap<u16, Action> {
+    let Ok(list) = config::var("MEDIA_CONTROL_INPUT_KEYS") else {
+        return DEFAULT_KEYS
+            .iter()
+            .filter_map(|&(name, action)| Some((key_code(name)?, action)))
+            .collect();
+    };
+    let mut keys = HashMap::new();
+    for entry in list.split(',').filter(|e| !e.trim().is_empty()) {
+        let Some((key, name)) = entry.split_once('=') else {
+            warn!("Ignoring input key {entry:?}, expected KEY=action");
+            continue;
+        };
+        match (key_code(key), Action::from_name(name.trim())) {
+            (Some(code), Some(action)) => {
+                keys.insert(code, action);
+            }
+            (None, _) => warn!("Ignoring input key {key:?}, not a known key name or code"),
+            (_, None) => warn!("Ignoring input key {key:?}, {name:?} is not an action"),
+        }
+    }
+    keys
+}
+
+/// Whether `MEDIA_CONTROL_INPUT_GRAB` leaves the devices grabbed
+fn grab() -> bool {
+    config::var("MEDIA_CONTROL_INPUT_GRAB")
+        .map(|v| !matches!(v.to_lowercase().as_str(), "0" | "false" | "no"))
+        .unwrap_or(true)
+}
+
+/// Start a thread for each device in `MEDIA_CONTROL_INPUT_DEVICES`
+pub fn spawn(state: web::Data<AppState>) {
+    let Ok(devices) = config::var("MEDIA_CONTROL_INPUT_DEVICES") else {
+        return;
+    };
+    let keys = Arc::new(key_map());
+    if keys.is_empty() {
+        warn!("Input devices ignored, MEDIA_CONTROL_INPUT_KEYS maps no keys");
+        return;
+    }
+    let grab = grab();
+    for path in devices.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (state, keys, path) = (state.clone(), keys.clone(), path.to_string());
+        thread::spawn(move || {
+            let mut missing = false;
+            loop {
+                match File::open(&path) {
+                    Ok(device) => {
+                        missing = false;
+                        info!("Reading keys from {path}");
+                        if let Err(e) = read(&state, &keys, device, grab) {
+                            warn!("Stopped reading keys from {path}: {e}");
+                        }
+                    }
+                    // Said once, not every time it's looked for
+                    Err(e) if !missing => {
+                        missing = true;
+                        warn!("Can't open input device {path}, trying again until it's there: {e}");
+                    }
+                    Err(_) => {}
+                }
+                thread::sleep(REOPEN_INTERVAL);
+            }
+        });
+    }
+}
+
+/// Run the actions of the keys pressed on `device` until it goes away
+fn read(
+    state: &AppState,
+    keys: &HashMap<u16, Action>,
+    mut device: File,
+    grab: bool,
+) -> io::Result<()> {
+    // Safety: EVIOCGRAB takes an int by value
+    if grab && unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB, 1 as libc::c_int) } < 0 {
+        warn!(
+            "Couldn't grab the input device, the desktop sees its keys too: {}",
+            io::Error::last_os_error()
+        );
+    }
+    // struct input_event: a timeval, then type, code and value
+    let mut event = [0u8; std::mem::size_of::<libc::input_event>()];
+    let tail = event.len() - 8;
+    loop {
+        device.read_exact(&mut event)?;
+        let kind = u16::from_ne_bytes([event[tail], event[tail + 1]]);
+        let code = u16::from_ne_bytes([event[tail + 2], event[tail + 3]]);
+        let value = i32::from_ne_bytes([
+            event[tail + 4],
+            event[tail + 5],
+            event[tail + 6],
+            event[tail + 7],
+        ]);
+        let Some(&action) = keys.get(&code).filter(|_| kind == EV_KEY) else {
+            continue;
+        };
+        // 1 is a press, 2 the key repeating while held, 0 its release
+        let repeats = matches!(action, Action::VolumeUp | Action::VolumeDown);
+        if !(value == 1 || value == 2 && repeats) {
+            continue;
+        }
+        let result = if crate::get_dry_run_mode() {
+            actions::plan(state, &actions::Target::Auto, action)
+        } else {
+            actions::run(state, action)
+        };
+        match result {
+            Ok(msg) => info!("input key {code}: {msg}"),
+            Err(e) => warn!("input key {code} failed: {e}"),
+        }
+    }
+}
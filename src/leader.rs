@@ -0,0 +1,87 @@
+//! Single-instance leader election over the session bus.
+//!
+//! Every instance asks for the same well-known D-Bus name. The primary owner
+//! is the leader and goes on to register the MPRIS publisher, handle media
+//! keys and serve HTTP. Any other instance is queued by the bus daemon and
+//! waits as a hot standby: when the leader exits the bus hands the name to the
+//! next instance in line, which then starts up normally. This keeps autostart
+//! races from producing duplicate "My Player" entries or running commands
+//! twice.
+//!
+//! A standby doesn't forward anything to the leader. It hasn't bound its
+//! listen address, read media keys or opened input devices, so it has no
+//! commands of its own to pass on. Instances started from the same config
+//! would fight over the same port anyway. Clients, and the `media-controller`
+//! subcommands, reach the leader through its HTTP API directly.
+
+use dbus::blocking::stdintf::org_freedesktop_dbus::RequestNameReply;
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Well-known name owned by the leading instance
+pub const LEADER_BUS_NAME: &str = "io.github.grimvoodoo.MediaController";
+
+/// Block until this instance owns [`LEADER_BUS_NAME`].
+///
+/// The connection holding the name is moved to a background thread that lives
+/// for the rest of the process, since dropping it would release leadership.
+/// If the session bus is unavailable we log it and carry on as the only
+/// instance.
+pub fn wait_for_leadership() {
+    let (tx, rx) = mpsc::channel::<()>();
+
+    thread::spawn(move || {
+        let conn = match Connection::new_session() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Leader election disabled, couldn't connect to session bus: {e}");
+                let _ = tx.send(());
+                return;
+            }
+        };
+
+        let acquired_tx = tx.clone();
+        let rule = MatchRule::new_signal("org.freedesktop.DBus", "NameAcquired");
+        let subscribed = conn.add_match(rule, move |(name,): (String,), _, _| {
+            if name == LEADER_BUS_NAME {
+                let _ = acquired_tx.send(());
+            }
+            true
+        });
+        if let Err(e) = subscribed {
+            warn!("Leader election disabled, couldn't subscribe to NameAcquired: {e}");
+            let _ = tx.send(());
+            return;
+        }
+
+        match conn.request_name(LEADER_BUS_NAME, false, false, false) {
+            Ok(RequestNameReply::PrimaryOwner) | Ok(RequestNameReply::AlreadyOwner) => {
+                let _ = tx.send(());
+            }
+            Ok(_) => {
+                info!("Another media-controller instance is running, waiting as standby");
+            }
+            Err(e) => {
+                warn!("Leader election disabled, couldn't request {LEADER_BUS_NAME}: {e}");
+                let _ = tx.send(());
+            }
+        }
+
+        // Keep the connection (and therefore the name) alive
+        loop {
+            if let Err(e) = conn.process(Duration::from_secs(60)) {
+                warn!("Lost session bus connection used for leader election: {e}");
+                return;
+            }
+        }
+    });
+
+    // A send on either path means we may start; if the thread died without
+    // sending we also fall through rather than hanging forever.
+    let _ = rx.recv();
+    info!("This instance is the leader");
+}
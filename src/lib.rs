@@ -0,0 +1,9 @@
+//! Library side of media-controller.
+//!
+//! [`models`] holds the JSON bodies of the HTTP API, shared by the server and
+//! by Rust programs talking to it. With the `client` feature, `client`
+//! wraps the API in a typed async client.
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod models;
@@ -0,0 +1,362 @@
+//! Live view of what the controlled player is doing.
+//!
+//! A background thread subscribes to MPRIS `PropertiesChanged` and `Seeked`
+//! signals and re-reads the controlled player whenever one arrives, keeping a
+//! [`NowPlaying`] snapshot in a watch channel. Handlers read the snapshot from
+//! memory instead of querying the player, and other subsystems can
+//! `subscribe()` to react to changes as they happen.
+//!
+//! Between signals the snapshot is re-read on a timer, which backs off while
+//! no player exists (a headless machine, a laptop with nothing open) so an
+//! idle service hardly ever wakes up. A player appearing on the bus is
+//! noticed through `NameOwnerChanged` straight away.
+//!
+//! The system volume and mute flag, and the names of the output devices, are
+//! part of the snapshot too. They are followed with `pactl subscribe`, which
+//! reports every sink change.
+
+use crate::player;
+use crate::{volume, AppState};
+use actix_web::web;
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use mpris::{PlaybackStatus, Player};
+use serde::Serialize;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::warn;
+
+/// How long to wait for signals before re-checking anyway. Selection can
+/// change without any player emitting a signal (focus, pinning), so the
+/// snapshot is refreshed at least this often.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The timer backs off up to this while there's no player to watch
+const IDLE_REFRESH_MAX: Duration = Duration::from_secs(30);
+
+/// Players send a burst of signals for one change, one per property; the
+/// snapshot is re-read once the burst has been quiet this long...
+const SIGNAL_BURST_GAP: Duration = Duration::from_millis(20);
+/// ...or has lasted this long, for players that never stop talking
+const SIGNAL_BURST_MAX: Duration = Duration::from_millis(250);
+
+/// The current timer interval in milliseconds, for diagnostics
+static REFRESH_EVERY_MS: AtomicU64 = AtomicU64::new(REFRESH_INTERVAL.as_millis() as u64);
+
+/// Reported positions within this much of our extrapolation count as unchanged
+const POSITION_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Shared handle to the live snapshot
+pub type Live = watch::Sender<NowPlaying>;
+
+/// Snapshot of the controlled player
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NowPlaying {
+    pub player: Option<String>,
+    pub bus_name: Option<String>,
+    pub playback: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub art_url: Option<String>,
+    pub shuffle: Option<bool>,
+    // MPRIS LoopStatus: None, Track or Playlist
+    pub loop_status: Option<String>,
+    // Default sink volume in percent
+    pub volume: Option<u32>,
+    pub muted: Option<bool>,
+    // Output device names; None until first read
+    pub sinks: Option<Vec<String>>,
+    #[serde(skip)]
+    pub length: Option<Duration>,
+    #[serde(skip)]
+    pub clock: Option<PositionClock>,
+}
+
+/// Position as last reported, so it can be advanced without asking the player
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionClock {
+    position: Duration,
+    at: Instant,
+    rate: f64,
+    playing: bool,
+}
+
+impl PositionClock {
+    fn now(&self) -> Duration {
+        if !self.playing {
+            return self.position;
+        }
+        self.position + self.at.elapsed().mul_f64(self.rate.max(0.0))
+    }
+}
+
+impl NowPlaying {
+    /// Current playback position, extrapolated from the last report
+    pub fn position(&self) -> Option<Duration> {
+        let position = self.clock?.now();
+        Some(match self.length {
+            Some(length) => position.min(length),
+            None => position,
+        })
+    }
+
+    /// Record `position` as reported just now
+    pub fn set_position(
+        &mut self,
+        position: Duration,
+        rate: f64,
+        playing: bool,
+        previous: &NowPlaying,
+    ) {
+        let mut clock = PositionClock {
+            position,
+            at: Instant::now(),
+            rate,
+            playing,
+        };
+
+        // Keep the old clock while it still agrees with the player, so a
+        // refresh on its own doesn't look like a change
+        if let Some(old) = previous.clock {
+            let drift = if old.now() > clock.position {
+                old.now() - clock.position
+            } else {
+                clock.position - old.now()
+            };
+            if drift < POSITION_TOLERANCE && old.rate == clock.rate && old.playing == clock.playing
+            {
+                clock = old;
+            }
+        }
+        self.clock = Some(clock);
+    }
+
+    fn read(player: &Player, previous: &NowPlaying) -> NowPlaying {
+        let metadata = player.get_metadata().ok();
+        let status = player.get_playback_status().ok();
+
+        let mut now = NowPlaying {
+            player: Some(player.identity().to_string()),
+            bus_name: Some(player.bus_name().to_string()),
+            playback: status.map(|s| format!("{s:?}")),
+            title: metadata
+                .as_ref()
+                .and_then(|m| m.title().map(str::to_string)),
+            artist: metadata
+                .as_ref()
+                .and_then(|m| m.artists())
+                .map(|a| a.join(", ")),
+            album: metadata
+                .as_ref()
+                .and_then(|m| m.album_name().map(str::to_string)),
+            art_url: metadata
+                .as_ref()
+                .and_then(|m| m.art_url().map(str::to_string)),
+            length: metadata.as_ref().and_then(|m| m.length()),
+            shuffle: player.get_shuffle().ok(),
+            loop_status: player.get_loop_status().ok().map(|l| format!("{l:?}")),
+            volume: previous.volume,
+            muted: previous.muted,
+            sinks: previous.sinks.clone(),
+            clock: None,
+        };
+        if let Ok(position) = player.get_position() {
+            now.set_position(
+                position,
+                player.get_playback_rate().unwrap_or(1.0),
+                status == Some(PlaybackStatus::Playing),
+                previous,
+            );
+        }
+        now
+    }
+}
+
+/// Re-read the controlled player into the snapshot, notifying subscribers
+/// only if something changed
+pub fn refresh(state: &AppState) {
+    let player = match state.demo {
+        Some(_) => None,
+        None => player::select_player(&state.pinned).0,
+    };
+    state.live.send_if_modified(|current| {
+        let mut next = match (&state.demo, &player) {
+            (Some(demo), _) => demo.now_playing(current),
+            (None, Some(p)) => NowPlaying::read(p, current),
+            (None, None) => NowPlaying {
+                volume: current.volume,
+                muted: current.muted,
+                sinks: current.sinks.clone(),
+                ..NowPlaying::default()
+            },
+        };
+        state.enricher.apply(&mut next);
+        if *current == next {
+            return false;
+        }
+        *current = next;
+        true
+    });
+}
+
+/// How long to wait before the next timed refresh. The timer doubles from
+/// `REFRESH_INTERVAL` up to `IDLE_REFRESH_MAX` for as long as timed refreshes
+/// find no player, and starts over whenever there is one or a signal came in.
+fn next_interval(state: &AppState, current: Duration, signalled: bool) -> Duration {
+    let idle = state.demo.is_none() && state.live.borrow().bus_name.is_none();
+    let next = if idle && !signalled {
+        (current * 2).min(IDLE_REFRESH_MAX)
+    } else {
+        REFRESH_INTERVAL
+    };
+    REFRESH_EVERY_MS.store(next.as_millis() as u64, Ordering::Relaxed);
+    next
+}
+
+/// How often the snapshot is currently re-read without a signal
+pub fn refresh_interval() -> Duration {
+    Duration::from_millis(REFRESH_EVERY_MS.load(Ordering::Relaxed))
+}
+
+/// Re-read the default sink volume and mute flag into the snapshot
+fn refresh_volume(state: &AppState) {
+    let backend = volume::backend();
+    let (volume, muted) = (backend.get_volume().ok(), backend.get_mute().ok());
+    state.live.send_if_modified(|current| {
+        if (current.volume, current.muted) == (volume, muted) {
+            return false;
+        }
+        current.volume = volume;
+        current.muted = muted;
+        true
+    });
+}
+
+/// Re-read the output device names into the snapshot
+fn refresh_sinks(state: &AppState) {
+    let Ok(sinks) = volume::backend().list_sinks() else {
+        return;
+    };
+    let names: Vec<String> = sinks.into_iter().map(|s| s.name).collect();
+    state.live.send_if_modified(|current| {
+        if current.sinks.as_ref() == Some(&names) {
+            return false;
+        }
+        current.sinks = Some(names);
+        true
+    });
+}
+
+/// Follow sink changes reported by `pactl subscribe`
+fn watch_volume(state: &AppState) {
+    refresh_volume(state);
+    refresh_sinks(state);
+    let child = Command::new("pactl")
+        .arg("subscribe")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Some(stdout) = child.ok().and_then(|mut c| c.stdout.take()) else {
+        warn!("Live status won't follow volume changes, couldn't run pactl subscribe");
+        return;
+    };
+    // Lines look like `Event 'change' on sink #0`
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if line.contains(" on sink #") {
+            if !line.contains("'change'") {
+                refresh_sinks(state);
+            }
+            refresh_volume(state);
+        }
+    }
+    warn!("Live status stopped following volume changes: pactl subscribe exited");
+}
+
+/// Refresh on a timer only, for when signals aren't available
+fn poll(state: &AppState) -> ! {
+    let mut interval = REFRESH_INTERVAL;
+    loop {
+        thread::sleep(interval);
+        refresh(state);
+        interval = next_interval(state, interval, false);
+    }
+}
+
+/// Start the threads that keep `state.live` up to date
+pub fn spawn_watcher(state: web::Data<AppState>) {
+    {
+        let state = state.clone();
+        thread::spawn(move || watch_volume(&state));
+    }
+    thread::spawn(move || {
+        refresh(&state);
+
+        let conn = match Connection::new_session() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Live status falling back to polling, couldn't connect to session bus: {e}");
+                poll(&state);
+            }
+        };
+
+        // The callbacks only flag that something changed; the snapshot is
+        // re-read once per batch of signals below
+        let dirty = Arc::new(AtomicBool::new(false));
+        let rules = [
+            MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+                .with_path(player::MPRIS_PATH),
+            MatchRule::new_signal("org.mpris.MediaPlayer2.Player", "Seeked")
+                .with_path(player::MPRIS_PATH),
+        ];
+        for rule in rules {
+            let dirty = dirty.clone();
+            if let Err(e) = conn.add_match(rule, move |_: (), _, _| {
+                dirty.store(true, Ordering::Relaxed);
+                true
+            }) {
+                warn!("Live status may lag, couldn't subscribe to player signals: {e}");
+            }
+        }
+        // Players coming and going, so a backed-off timer doesn't delay them
+        let names = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged");
+        let name_dirty = dirty.clone();
+        if let Err(e) = conn.add_match(
+            names,
+            move |(name, _, _): (String, String, String), _, _| {
+                if name.starts_with(player::MPRIS_PREFIX) {
+                    name_dirty.store(true, Ordering::Relaxed);
+                }
+                true
+            },
+        ) {
+            warn!("Live status may lag, couldn't subscribe to NameOwnerChanged: {e}");
+        }
+
+        let mut interval = REFRESH_INTERVAL;
+        let mut next_refresh = Instant::now() + interval;
+        loop {
+            let wait = next_refresh.saturating_duration_since(Instant::now());
+            let mut processed = conn.process(wait);
+            let burst_end = Instant::now() + SIGNAL_BURST_MAX;
+            while matches!(processed, Ok(true)) && Instant::now() < burst_end {
+                processed = conn.process(SIGNAL_BURST_GAP);
+            }
+            if let Err(e) = processed {
+                warn!("Live status falling back to polling, lost session bus: {e}");
+                poll(&state);
+            }
+            let signalled = dirty.swap(false, Ordering::Relaxed);
+            if signalled || Instant::now() >= next_refresh {
+                refresh(&state);
+                interval = next_interval(&state, interval, signalled);
+                next_refresh = Instant::now() + interval;
+            }
+        }
+    });
+}
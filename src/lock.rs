@@ -0,0 +1,79 @@
+//! "Party safe" lock.
+//!
+//! `POST /lock` freezes everything that changes the volume or the playback
+//! position: the system, application and player volume endpoints, mute, and
+//! seeking. Play, pause and skipping keep working, so the remote UI can be
+//! handed to guests without anyone cranking the volume to 100%. Only the main
+//! API token can lift the lock, with `DELETE /admin/lock`, since guest tokens
+//! can't use `/admin`. The lock lasts until it's lifted or the service
+//! restarts.
+
+use crate::error::AppError;
+use crate::AppState;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use media_controller::models::LockState;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+#[derive(Default)]
+pub struct Lock {
+    locked: AtomicBool,
+}
+
+impl Lock {
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// Set the lock, returning whether that changed anything
+    fn set(&self, locked: bool) -> bool {
+        self.locked.swap(locked, Ordering::Relaxed) != locked
+    }
+}
+
+/// Response for endpoints refused while locked
+pub fn refused() -> HttpResponse {
+    AppError::new(StatusCode::LOCKED, "volume and seeking are locked").error_response()
+}
+
+/// GET /lock — whether volume and seeking are locked
+#[utoipa::path(
+    get,
+    path = "/lock",
+    tag = "Admin",
+    responses((status = 200, description = "Whether volume and seeking are locked", body = LockState)),
+)]
+pub async fn lock_state(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(LockState {
+        locked: state.lock.is_locked(),
+    })
+}
+
+/// POST /lock — freeze volume and seeking until an admin unlocks them
+#[utoipa::path(
+    post,
+    path = "/lock",
+    tag = "Admin",
+    responses((status = 200, description = "Volume and seeking are locked", body = LockState)),
+)]
+pub async fn lock(state: web::Data<AppState>) -> impl Responder {
+    if state.lock.set(true) {
+        info!("Volume and seeking locked");
+    }
+    HttpResponse::Ok().json(LockState { locked: true })
+}
+
+/// DELETE /admin/lock — lift the lock
+#[utoipa::path(
+    delete,
+    path = "/admin/lock",
+    tag = "Admin",
+    responses((status = 200, description = "Volume and seeking are unlocked", body = LockState)),
+)]
+pub async fn unlock(state: web::Data<AppState>) -> impl Responder {
+    if state.lock.set(false) {
+        info!("Volume and seeking unlocked");
+    }
+    HttpResponse::Ok().json(LockState { locked: false })
+}
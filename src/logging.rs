@@ -0,0 +1,200 @@
+//! Logging through `tracing`.
+//!
+//! `MEDIA_CONTROL_LOG_LEVEL` is a filter such as `debug` or
+//! `warn,media_controller::player=debug` (default `info`), and
+//! `MEDIA_CONTROL_LOG_FORMAT=json` writes one JSON object per line, for
+//! shipping to Loki and the like, instead of plain text. Both are applied
+//! again when the config is reloaded.
+//!
+//! Every request runs in a `request` span carrying its method, path and
+//! client, so whatever the handler logs can be traced back to it, and is
+//! logged once answered. Both use the `request` target, so
+//! `warn,request=info` keeps access logs while quietening the rest.
+//!
+//! The last few hundred lines are also kept in memory for
+//! `/admin/diagnostics`.
+
+use crate::config;
+use crate::error::MutexExt;
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value as Json};
+use std::collections::VecDeque;
+use std::fmt::{self as std_fmt, Write};
+use std::io::IsTerminal;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::{info, info_span, warn, Event, Instrument, Subscriber};
+use tracing_subscriber::layer::{Context, Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type Output = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// Handles for swapping the filter and output format on reload
+struct Handles {
+    filter: reload::Handle<EnvFilter, Registry>,
+    output: reload::Handle<Output, Filtered>,
+}
+
+static HANDLES: OnceLock<Handles> = OnceLock::new();
+
+/// How many lines [`recent`] keeps
+const RECENT_LINES: usize = 300;
+
+/// The last lines logged, oldest first
+static RECENT: Mutex<VecDeque<Json>> = Mutex::new(VecDeque::new());
+
+/// Keeps what passes the filter for [`recent`]
+struct Recent;
+
+/// An event's message followed by its other fields, as `name=value`
+#[derive(Default)]
+struct Line(String);
+
+impl Visit for Line {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.insert_str(0, value);
+        } else {
+            let _ = write!(self.0, " {}={value}", field.name());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std_fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{value:?}"));
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Recent {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut line = Line::default();
+        event.record(&mut line);
+        let meta = event.metadata();
+        let entry = json!({
+            "time": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "level": meta.level().as_str(),
+            "target": meta.target(),
+            "message": line.0,
+        });
+        let mut recent = RECENT.locked();
+        if recent.len() == RECENT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+    }
+}
+
+/// The last lines logged, oldest first, each with its `time`, `level`,
+/// `target` and `message`
+pub fn recent() -> Vec<Json> {
+    RECENT.locked().iter().cloned().collect()
+}
+
+/// The filter from `MEDIA_CONTROL_LOG_LEVEL`
+fn filter() -> Result<EnvFilter, String> {
+    let level = config::var("MEDIA_CONTROL_LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
+    EnvFilter::try_new(&level)
+        .map_err(|e| format!("Ignoring MEDIA_CONTROL_LOG_LEVEL={level:?}: {e}"))
+}
+
+/// Plain text, or JSON with `MEDIA_CONTROL_LOG_FORMAT=json`
+fn output() -> Output {
+    let json =
+        config::var("MEDIA_CONTROL_LOG_FORMAT").is_ok_and(|v| v.eq_ignore_ascii_case("json"));
+    if json {
+        fmt::layer()
+            .with_writer(std::io::stderr)
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .boxed()
+    } else {
+        fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_ansi(std::io::stderr().is_terminal())
+            .boxed()
+    }
+}
+
+/// Start logging. Only the environment is read here, as this runs before
+/// the config file is loaded; [`reload`] then applies the file's settings.
+pub fn init() {
+    let (filter, error) = match filter() {
+        Ok(filter) => (filter, None),
+        Err(e) => (EnvFilter::new("info"), Some(e)),
+    };
+    let (filter, filter_handle) = reload::Layer::new(filter);
+    let (output, output_handle) = reload::Layer::new(output());
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(Recent)
+        .init();
+    let _ = HANDLES.set(Handles {
+        filter: filter_handle,
+        output: output_handle,
+    });
+    if let Some(e) = error {
+        warn!("{e}");
+    }
+}
+
+/// Switch to the log level and format now in the settings. An invalid level
+/// keeps the current one.
+pub fn reload() {
+    let Some(handles) = HANDLES.get() else {
+        return;
+    };
+    match filter() {
+        Ok(filter) => {
+            let _ = handles.filter.reload(filter);
+        }
+        Err(e) => warn!("{e}"),
+    }
+    let _ = handles.output.reload(output());
+}
+
+/// Who sent a request, for logs
+pub fn client(req: &ServiceRequest) -> String {
+    req.peer_addr()
+        .map_or_else(|| "unix socket".to_string(), |addr| addr.ip().to_string())
+}
+
+/// Run each request in a span, and log how it was answered
+pub async fn request_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let span = info_span!(
+        target: "request",
+        "request",
+        method = %req.method(),
+        path = req.path(),
+        client = %client(&req),
+    );
+    let started = Instant::now();
+    let res = next.call(req).instrument(span.clone()).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let elapsed_ms = (started.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0;
+    span.in_scope(|| {
+        if status.is_server_error() {
+            warn!(target: "request", status = status.as_u16(), elapsed_ms, "answered");
+        } else {
+            info!(target: "request", status = status.as_u16(), elapsed_ms, "answered");
+        }
+    });
+    res
+}
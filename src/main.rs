@@ -1,44 +1,212 @@
-use actix_web::body::BoxBody;
-use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::error::ErrorUnauthorized;
-use actix_web::http::header;
-use actix_web::middleware::{from_fn, Next};
-use actix_web::{web, App, Error, HttpResponse, HttpServer, Responder};
-use mpris::{PlaybackStatus, Player, PlayerFinder};
-use serde::Serialize;
-use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
+use actions::{Action, Target};
+use actix_web::http::header::{self, Accept, ContentType, Header};
+use actix_web::http::{KeepAlive, StatusCode};
+use actix_web::middleware::from_fn;
+use actix_web::{mime, web, App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError};
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use error::{AppError, MutexExt};
+use media_controller::models::{
+    AppStream, Candidate, DefaultSink, ErrorBody, Overview, PlayerInfo, PlayerVolume, Sink, Source,
+    Status, VolumeLevel,
+};
+use player::{find_player, find_player_by_selector, metadata_value_to_json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use souvlaki::{MediaControlEvent, MediaControls, MediaPlayback, PlatformConfig};
 use std::env;
-use std::process::Command;
-use std::sync::{Arc, Mutex};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+mod actions;
+mod alerts;
+mod audio;
+mod auth;
+mod batch;
+#[cfg(feature = "cast")]
+mod cast;
+#[cfg(feature = "client")]
+mod cli;
+mod coalesce;
+mod config;
+mod cors;
+mod demo;
+mod diagnostics;
+#[cfg(feature = "email")]
+mod email;
+mod enrich;
+mod error;
+mod events;
+mod fade;
+mod focus;
+mod groups;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod guest;
+#[cfg(feature = "history")]
+mod history;
+#[cfg(feature = "mqtt")]
+mod homeassistant;
+mod hooks;
+mod hotplug;
+mod i18n;
+mod input;
+mod leader;
+mod live;
+mod lock;
+mod logging;
+mod media_keys;
+mod metrics;
+mod mirror;
+mod mpd;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod no_player;
+mod notify;
+mod openapi;
+mod outbound;
+mod pause_timeout;
+mod personas;
+mod player;
+mod position_alarm;
+mod queue;
+mod rate_limit;
+mod rating;
+mod registry;
+mod remote;
+mod reporting;
+mod resume;
+mod rules;
+mod schedules;
+mod scrobble;
+mod selection;
+mod shutdown;
+mod silence;
+mod skim;
+mod sleep_timer;
+mod snapshot;
+mod speech;
+#[cfg(feature = "client")]
+mod statusbar;
+mod sync;
+mod systemd;
+mod template;
+mod timings;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tui")]
+mod tui;
+mod ui;
+mod volume;
+mod vote_skip;
+mod webhooks;
+mod wol;
+#[cfg(feature = "zeroconf")]
+mod zeroconf;
 
 /// Application state, shared between handlers.
 struct AppState {
     // Your MPRIS *publisher* ("My Player")
     controls: Arc<Mutex<MediaControls>>,
     // Your own copy of what metadata you last set
-    copy_meta: Arc<Mutex<MediaMetadata<'static>>>,
+    copy_meta: Arc<Mutex<mirror::PublishedMetadata>>,
     // Your own copy of what playback state you last set
     copy_playback: Arc<Mutex<MediaPlayback>>,
-}
-
-/// JSON view returned by GET /status
-#[derive(Serialize)]
-struct Status {
-    // What *you* last told the system (Playing/Paused)
-    our_playback: String,
-    // What the *other* active player reports (if any)
-    other_playback: Option<String>,
-    // What title you last set
-    title: Option<String>,
-    // Which player is being controlled (identity)
-    controlled_player: Option<String>,
+    // Bus name of the player pinned with /players/select
+    pinned: Arc<Mutex<Option<String>>>,
+    // What the controlled player is doing, kept current from its signals
+    live: Arc<live::Live>,
+    // Concurrent GET /players requests share one bus query
+    players_query: Arc<coalesce::Coalesced<Vec<PlayerInfo>>>,
+    // Concurrent GET /status requests share one read, which can reach out
+    // to a remote player when no local one is playing
+    status_query: Arc<coalesce::Coalesced<Status>>,
+    // Feeds media key events from our publisher to the worker running them
+    media_keys: mpsc::Sender<MediaControlEvent>,
+    // Fills in album and artwork the player doesn't report
+    enricher: Arc<enrich::Enricher>,
+    // The built-in fake player, when started with --demo
+    demo: Option<demo::DemoPlayer>,
+    // Set by POST /lock to freeze volume and seeking
+    lock: lock::Lock,
+    // Players kept in sync by POST /sync
+    sync: sync::SyncGroup,
+    // Faster playback started by POST /skim
+    skim: skim::Skim,
+    // Extra publishers from MEDIA_CONTROL_PERSONAS
+    personas: personas::Personas,
+    // Votes to skip the current track, from POST /vote_skip
+    votes: vote_skip::SkipVotes,
+    // One-off events that aren't snapshot changes, like `guest_expired`
+    notices: tokio::sync::broadcast::Sender<&'static str>,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let token = get_api_token();
-    let token_data = web::Data::new(token);
+    logging::init();
+    if let Err(e) = config::load() {
+        error!("{e}");
+        std::process::exit(1);
+    }
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("tui") => {
+            #[cfg(feature = "tui")]
+            return tui::run(args.next()).await;
+            #[cfg(not(feature = "tui"))]
+            {
+                eprintln!("This build has no terminal UI, rebuild with --features tui");
+                std::process::exit(1);
+            }
+        }
+        Some("client") => {
+            #[cfg(feature = "client")]
+            return client_command(args).await;
+            #[cfg(not(feature = "client"))]
+            {
+                eprintln!("This build has no client commands, rebuild with --features client");
+                std::process::exit(1);
+            }
+        }
+        Some(command) if CLIENT_COMMANDS.contains(&command) => {
+            #[cfg(feature = "client")]
+            return cli::run(command, args).await;
+            #[cfg(not(feature = "client"))]
+            {
+                eprintln!("This build has no client commands, rebuild with --features client");
+                std::process::exit(1);
+            }
+        }
+        _ => {}
+    }
+    check_api_token();
+    let hooks_data = web::Data::new(hooks::Hooks::from_env());
+    let guest_data = web::Data::new(guest::GuestAccess::default());
+    let verifier_data = web::Data::new(auth::Verifier::standard(guest_data.clone().into_inner()));
+    let snapshot_data = web::Data::new(snapshot::Snapshots::default());
+    let reports_data = web::Data::new(reporting::Reports::default());
+    let sleep_timer_data = web::Data::new(sleep_timer::SleepTimer::default());
+    let position_alarm_data = web::Data::new(position_alarm::PositionAlarms::default());
+    let schedules_data = web::Data::new(schedules::Schedules::from_env());
+    let groups_data = web::Data::new(groups::Groups::from_env());
+    let demo = get_demo_mode();
+    if demo {
+        info!("Demo mode: controlling the built-in demo player");
+    }
+
+    // 0) Only one instance per session may own the publisher and the port
+    leader::wait_for_leadership();
+
+    // Keep track of players appearing and leaving instead of rescanning per request
+    registry::spawn_watcher();
+    remote::start();
+    selection::check();
 
     // On Linux/macOS we don't need an HWND; on Windows you'd supply it here.
     #[cfg(not(target_os = "windows"))]
@@ -54,22 +222,30 @@ async fn main() -> std::io::Result<()> {
     };
     let mut controls = MediaControls::new(config).expect("failed to init MediaControls");
 
-    // Optional: log any hardware key events
-    controls
-        .attach(|evt: MediaControlEvent| println!("media key: {evt:?}"))
-        .unwrap();
+    // Media keys and widget buttons are handed to a worker once state exists
+    let (key_tx, key_rx) = mpsc::channel();
+    if !demo {
+        controls
+            .attach(media_keys::forwarder(key_tx.clone()))
+            .unwrap();
+    }
 
     // 2) Set some initial metadata & playback state
-    let initial_meta: MediaMetadata<'static> = MediaMetadata {
-        title: Some("Souvlaki Space Station"),
-        artist: Some("Slowdive"),
-        album: Some("Souvlaki"),
+    // (replaced by the controlled player's track as soon as one is seen)
+    let initial_meta = mirror::PublishedMetadata {
+        title: Some("Souvlaki Space Station".to_string()),
+        artist: Some("Slowdive".to_string()),
+        album: Some("Souvlaki".to_string()),
         ..Default::default()
     };
     let initial_pb = MediaPlayback::Paused { progress: None };
 
-    controls.set_metadata(initial_meta.clone()).unwrap();
-    controls.set_playback(initial_pb.clone()).unwrap();
+    if !demo {
+        controls
+            .set_metadata(initial_meta.as_media_metadata())
+            .unwrap();
+        controls.set_playback(initial_pb.clone()).unwrap();
+    }
 
     // 3) Wrap everything in Arcs+Mutex for sharing across Actix handlers
     // let state = web::Data::new(AppState {
@@ -82,318 +258,2372 @@ async fn main() -> std::io::Result<()> {
         controls: Arc::new(Mutex::new(controls)),
         copy_meta: Arc::new(Mutex::new(initial_meta)),
         copy_playback: Arc::new(Mutex::new(initial_pb)),
+        pinned: Arc::new(Mutex::new(shutdown::saved_pin())),
+        live: Arc::new(live::Live::new(live::NowPlaying::default())),
+        players_query: Arc::default(),
+        status_query: Arc::default(),
+        media_keys: key_tx,
+        enricher: Arc::new(enrich::Enricher::from_env()),
+        demo: demo.then(demo::DemoPlayer::new),
+        lock: lock::Lock::default(),
+        sync: sync::SyncGroup::default(),
+        skim: skim::Skim::default(),
+        personas: personas::Personas::from_env(!demo),
+        votes: vote_skip::SkipVotes::default(),
+        notices: tokio::sync::broadcast::channel(16).0,
     });
 
-    // let token_data = web::Data::new(token.clone());
+    // Re-register our publisher whenever the machine wakes from suspend
+    if !demo {
+        let state = shared_state.clone();
+        thread::spawn(move || watch_for_resume(state));
+    }
+    live::spawn_watcher(shared_state.clone());
+    mirror::spawn(shared_state.clone());
+    metrics::spawn(shared_state.clone());
+    hotplug::spawn(shared_state.clone());
+    media_keys::spawn(shared_state.clone(), key_rx);
+    input::spawn(shared_state.clone());
+    silence::spawn_monitor(shared_state.clone());
+    pause_timeout::spawn_monitor(shared_state.clone());
+    resume::spawn_monitor(shared_state.clone());
+    alerts::spawn_monitor(shared_state.clone());
+    notify::spawn(shared_state.clone());
+    webhooks::spawn(shared_state.clone());
+    reporting::spawn(shared_state.clone());
+    vote_skip::spawn(shared_state.clone());
+    schedules::spawn(shared_state.clone(), schedules_data.clone());
+    schedules::spawn_wind_down(shared_state.clone());
+    rules::spawn(shared_state.clone());
+    #[cfg(feature = "history")]
+    let history_data = history::spawn(shared_state.clone()).map(web::Data::new);
+    let scrobbler_data = web::Data::new(scrobble::spawn(shared_state.clone()));
+    #[cfg(feature = "mqtt")]
+    mqtt::spawn(shared_state.clone());
+    #[cfg(feature = "grpc")]
+    grpc::spawn(shared_state.clone(), verifier_data.clone());
+    config::reload_on_hangup();
+
+    let simple_api = get_simple_api_mode();
+    if simple_api {
+        info!("Simple GET API enabled under /do/");
+    }
+
+    // Kept for cleaning up once the server has stopped
+    let exit_state = shared_state.clone();
+    #[cfg(feature = "history")]
+    let exit_history = history_data.clone();
 
     // 4) Spin up the HTTP server
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .app_data(token_data.clone())
-            .wrap(from_fn(auth_middleware))
+            .app_data(hooks_data.clone())
+            .app_data(guest_data.clone())
+            .app_data(verifier_data.clone())
+            .app_data(snapshot_data.clone())
+            .app_data(reports_data.clone())
+            .app_data(sleep_timer_data.clone())
+            .app_data(position_alarm_data.clone())
+            .app_data(schedules_data.clone())
+            .app_data(groups_data.clone())
+            .app_data(scrobbler_data.clone())
+            .app_data(error::json_config())
+            .app_data(error::query_config())
+            .app_data(error::path_config())
+            .wrap(from_fn(hooks::hook_middleware))
+            .wrap(from_fn(notify::remote_middleware))
+            .wrap(from_fn(auth::auth_middleware))
+            .wrap(from_fn(rate_limit::rate_limit_middleware))
+            .wrap(from_fn(cors::cors_middleware))
+            .wrap(from_fn(logging::request_middleware))
             .app_data(shared_state.clone())
             .route("/play", web::post().to(play))
             .route("/pause", web::post().to(pause))
+            .route("/pause_all", web::post().to(pause_all))
             .route("/toggle", web::post().to(toggle))
+            .route("/stop", web::post().to(stop))
             .route("/volume_up", web::post().to(volume_up))
             .route("/volume_down", web::post().to(volume_down))
+            .route("/mute", web::post().to(mute))
+            .route("/unmute", web::post().to(unmute))
+            .route("/mute/toggle", web::post().to(toggle_mute))
+            .route("/volume/apps", web::get().to(list_app_volumes))
+            .route("/volume/apps/{id}", web::post().to(set_app_volume))
+            .route("/volume", web::get().to(get_volume))
+            .route("/volume", web::post().to(set_volume))
+            .route("/sinks", web::get().to(list_sinks))
+            .route("/sinks/default", web::post().to(set_default_sink))
+            .route("/audio/sources", web::get().to(list_sources))
             .route("/next", web::post().to(next_track))
             .route("/previous", web::post().to(prev_track))
+            .route("/seek", web::post().to(seek))
+            .route("/open", web::post().to(open_uri))
             .route("/seek_forward", web::post().to(seek_forward))
             .route("/seek_backward", web::post().to(seek_backward))
+            .route("/skim", web::post().to(skim::skim))
+            .route("/queue", web::get().to(queue::get_queue))
+            .route(
+                "/queue/goto/{trackid:.*}",
+                web::post().to(queue::goto_track),
+            )
+            .route("/queue/{trackid:.*}", web::delete().to(queue::remove_track))
+            .route("/shuffle", web::get().to(get_shuffle))
+            .route("/shuffle", web::post().to(set_shuffle))
+            .route("/loop", web::get().to(get_loop))
+            .route("/loop", web::post().to(set_loop))
+            .route(
+                "/position_alarm",
+                web::get().to(position_alarm::get_position_alarms),
+            )
+            .route(
+                "/position_alarm",
+                web::post().to(position_alarm::set_position_alarm),
+            )
+            .route(
+                "/position_alarm/{id}",
+                web::delete().to(position_alarm::cancel_position_alarm),
+            )
+            .route("/schedules", web::get().to(schedules::list_schedules))
+            .route("/schedules", web::post().to(schedules::add_schedule))
+            .route(
+                "/schedules/{id}",
+                web::delete().to(schedules::remove_schedule),
+            )
+            .route("/batch", web::post().to(batch::batch))
+            .route("/vote_skip", web::get().to(vote_skip::get_votes))
+            .route("/vote_skip", web::post().to(vote_skip::vote_skip))
+            .route("/rate", web::post().to(rating::rate))
+            .route("/sleep_timer", web::get().to(sleep_timer::get_sleep_timer))
+            .route("/sleep_timer", web::post().to(sleep_timer::set_sleep_timer))
+            .route(
+                "/sleep_timer",
+                web::delete().to(sleep_timer::cancel_sleep_timer),
+            )
+            .route(
+                "/audio/bluetooth/{mac}/connect",
+                web::post().to(bluetooth_connect),
+            )
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
             .route("/status", web::get().to(status))
-    })
-    .bind(("0.0.0.0", 8080))?
-    .run()
-    .await
+            .route("/status/spoken", web::get().to(spoken_status))
+            .route("/overview", web::get().to(overview))
+            .route("/metrics", web::get().to(metrics::metrics_endpoint))
+            .route("/openapi.json", web::get().to(openapi::openapi_json))
+            .route("/docs", web::get().to(openapi::docs))
+            .route("/ui", web::get().to(ui::page))
+            .route("/ui/art", web::get().to(ui::art))
+            .route("/say_track", web::post().to(say_track))
+            .route("/ws", web::get().to(events::websocket))
+            .route("/events", web::get().to(events::event_stream))
+            .route("/widget", web::get().to(widget))
+            .route("/players", web::get().to(list_players))
+            .route("/players/select", web::post().to(select_player))
+            .route("/players/unselect", web::post().to(unselect_player))
+            .route("/players/{id}/metadata", web::get().to(player_metadata))
+            .route("/players/{id}/volume", web::get().to(get_player_volume))
+            .route("/players/{id}/volume", web::post().to(set_player_volume))
+            .route(
+                "/players/{id}/play_exclusive",
+                web::post().to(play_exclusive),
+            )
+            .route("/players/{id}/{command}", web::post().to(player_command))
+            .route("/groups", web::get().to(groups::list_groups))
+            .route("/groups", web::post().to(groups::add_group))
+            .route("/groups/{name}", web::delete().to(groups::remove_group))
+            .route(
+                "/groups/{name}/volume",
+                web::post().to(groups::group_volume),
+            )
+            .route(
+                "/groups/{name}/{command}",
+                web::post().to(groups::group_command),
+            )
+            .route("/wol/{target}", web::post().to(wol::wake_target))
+            .route("/snapshot", web::post().to(snapshot::create_snapshot))
+            .route(
+                "/snapshot/{id}/restore",
+                web::post().to(snapshot::restore_snapshot),
+            )
+            .route("/sync", web::get().to(sync::sync_state))
+            .route("/sync", web::post().to(sync::start_sync))
+            .route("/sync", web::delete().to(sync::stop_sync))
+            .route("/reports", web::get().to(reporting::list_reports))
+            .route("/reports", web::post().to(reporting::receive_report))
+            .route("/personas", web::get().to(personas::list_personas))
+            .route("/personas/{id}", web::get().to(personas::get_persona))
+            .route("/personas/{id}", web::post().to(personas::update_persona))
+            .route("/personas/{id}", web::delete().to(personas::clear_persona))
+            .route("/lock", web::get().to(lock::lock_state))
+            .route("/lock", web::post().to(lock::lock))
+            .route("/admin/lock", web::delete().to(lock::unlock))
+            .route("/admin/guest", web::post().to(guest::create_guest))
+            .route("/admin/guest", web::delete().to(guest::revoke_guest))
+            .route("/admin/reload", web::post().to(config::reload_endpoint))
+            .route(
+                "/admin/diagnostics",
+                web::get().to(diagnostics::diagnostics),
+            )
+            .configure(|cfg| {
+                if simple_api {
+                    cfg.route("/do/{command}", web::get().to(simple_command));
+                }
+                #[cfg(feature = "history")]
+                if let Some(history) = &history_data {
+                    cfg.app_data(history.clone())
+                        .route("/history", web::get().to(history::list_plays))
+                        .route("/stats", web::get().to(history::stats));
+                }
+            })
+            .default_service(web::to(error::unknown_route))
+    });
+
+    // Optional tuning; anything unset keeps actix's defaults (one worker per
+    // core, 5 s keep-alive, 5 s request-head timeout, 1 s disconnect timeout)
+    if let Some(n) = env_number("MEDIA_CONTROL_WORKERS") {
+        server = server.workers(n.max(1) as usize);
+    }
+    if let Some(n) = env_number("MEDIA_CONTROL_MAX_CONNECTIONS") {
+        server = server.max_connections(n.max(1) as usize);
+    }
+    if let Some(secs) = env_number("MEDIA_CONTROL_KEEP_ALIVE_SECS") {
+        server = server.keep_alive(if secs == 0 {
+            KeepAlive::Disabled
+        } else {
+            KeepAlive::Timeout(Duration::from_secs(secs))
+        });
+    }
+    if let Some(ms) = env_number("MEDIA_CONTROL_CLIENT_REQUEST_TIMEOUT_MS") {
+        server = server.client_request_timeout(Duration::from_millis(ms));
+    }
+    if let Some(ms) = env_number("MEDIA_CONTROL_CLIENT_DISCONNECT_TIMEOUT_MS") {
+        server = server.client_disconnect_timeout(Duration::from_millis(ms));
+    }
+    // Signals are handled in `shutdown`, so SIGINT drains requests too
+    server = server.disable_signals();
+    if let Some(secs) = env_number("MEDIA_CONTROL_SHUTDOWN_TIMEOUT_SECS") {
+        server = server.shutdown_timeout(secs);
+    }
+
+    let bound = 'bound: {
+        if let Some(listeners) = systemd::listeners() {
+            #[cfg(feature = "tls")]
+            let tls_config = tls::server_config().unwrap_or_else(|e| {
+                error!("{e}");
+                std::process::exit(1);
+            });
+            for listener in listeners {
+                server = match listener {
+                    #[cfg(feature = "tls")]
+                    systemd::Listener::Tcp(tcp) => match &tls_config {
+                        Some(tls_config) => server.listen_rustls_0_23(tcp, tls_config.clone())?,
+                        None => server.listen(tcp)?,
+                    },
+                    #[cfg(not(feature = "tls"))]
+                    systemd::Listener::Tcp(tcp) => server.listen(tcp)?,
+                    systemd::Listener::Unix(unix) => server.listen_uds(unix)?,
+                };
+            }
+            break 'bound server;
+        }
+        if let Ok(path) = config::var("MEDIA_CONTROL_UNIX_SOCKET") {
+            // A socket left over from the last run would make binding fail
+            if fs::metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+                fs::remove_file(&path)?;
+            }
+            server = server.bind_uds(&path)?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(get_socket_mode()))?;
+            info!("Listening on {path}");
+            // Only listen on TCP as well if asked to
+            if config::var("MEDIA_CONTROL_BIND").is_err()
+                && config::var("MEDIA_CONTROL_PORT").is_err()
+            {
+                break 'bound server;
+            }
+        }
+
+        let address = config::var("MEDIA_CONTROL_BIND").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let port = env_number("MEDIA_CONTROL_PORT")
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(8080);
+        #[cfg(feature = "tls")]
+        match tls::server_config() {
+            Ok(Some(tls_config)) => {
+                break 'bound server.bind_rustls_0_23((address.as_str(), port), tls_config)?
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(not(feature = "tls"))]
+        if config::var("MEDIA_CONTROL_TLS_CERT").is_ok() {
+            error!("MEDIA_CONTROL_TLS_CERT is set, but this build has no HTTPS support (build with --features tls)");
+            std::process::exit(1);
+        }
+        server.bind((address.as_str(), port))?
+    };
+
+    #[cfg(feature = "zeroconf")]
+    let advertisement = zeroconf::advertise(&bound.addrs_with_scheme());
+    let running = bound.run();
+
+    shutdown::stop_on_signals(running.handle());
+    systemd::ready(demo);
+    let result = running.await;
+    systemd::stopping();
+    #[cfg(feature = "zeroconf")]
+    if let Some(advertisement) = advertisement {
+        advertisement.withdraw();
+    }
+    #[cfg(feature = "history")]
+    if let Some(history) = &exit_history {
+        history.flush();
+    }
+    shutdown::finish(&exit_state);
+    result
+}
+
+/// Read an optional numeric env var, warning about unparsable values
+fn env_number(name: &str) -> Option<u64> {
+    let value = config::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            warn!("Ignoring {name}={value:?}: not a whole number");
+            None
+        }
+    }
+}
+
+/// Permissions of the Unix socket, from `MEDIA_CONTROL_UNIX_SOCKET_MODE`
+/// in octal
+fn get_socket_mode() -> u32 {
+    let default = 0o660;
+    let Ok(value) = config::var("MEDIA_CONTROL_UNIX_SOCKET_MODE") else {
+        return default;
+    };
+    match u32::from_str_radix(value.trim().trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o777 => mode,
+        _ => {
+            warn!("Ignoring MEDIA_CONTROL_UNIX_SOCKET_MODE={value:?}: not an octal mode like 660");
+            default
+        }
+    }
+}
+
+/// Subcommands run against a running server rather than starting one, see
+/// the `cli` module
+const CLIENT_COMMANDS: &[&str] = &[
+    "play", "pause", "toggle", "stop", "next", "previous", "seek", "open", "volume", "mute",
+    "unmute", "status", "players", "select", "unselect",
+];
+
+/// A client for the server configured here: the given URL, otherwise
+/// `MEDIA_CONTROL_URL`, this host's `MEDIA_CONTROL_UNIX_SOCKET`, or its
+/// `MEDIA_CONTROL_PORT`. A URL of `unix:/path` also goes over a socket.
+#[cfg(feature = "client")]
+fn local_client(url: Option<String>) -> media_controller::client::Client {
+    let token = config::var("MEDIA_CONTROL_API_TOKEN").unwrap_or_default();
+    let url = url.or_else(|| config::var("MEDIA_CONTROL_URL").ok());
+    let socket = match &url {
+        Some(url) => url.strip_prefix("unix:").map(str::to_string),
+        None => config::var("MEDIA_CONTROL_UNIX_SOCKET").ok(),
+    };
+    if let Some(socket) = socket {
+        let http = reqwest::Client::builder()
+            .unix_socket(socket.as_str())
+            .build();
+        match http {
+            // The host is only used for the Host header
+            Ok(http) => {
+                return media_controller::client::Client::with_http_client(
+                    http,
+                    "http://localhost",
+                    token,
+                )
+            }
+            Err(e) => warn!("Couldn't use the socket {socket}: {e}"),
+        }
+    }
+    let url = url
+        .filter(|url| !url.starts_with("unix:"))
+        .unwrap_or_else(|| {
+            let port = config::var("MEDIA_CONTROL_PORT").unwrap_or_else(|_| "8080".to_string());
+            format!("http://localhost:{port}")
+        });
+    media_controller::client::Client::new(url, token)
+}
+
+/// `media-controller client <command>`: talk to a running server
+#[cfg(feature = "client")]
+async fn client_command(mut args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    match args.next().as_deref() {
+        Some("statusbar") => statusbar::run(args).await,
+        Some(command) if CLIENT_COMMANDS.contains(&command) => cli::run(command, args).await,
+        _ => {
+            eprintln!("usage: media-controller client statusbar [--format waybar|polybar] [URL]");
+            eprintln!(
+                "       media-controller [client] <command> ..., see media-controller play --help"
+            );
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Refuse to start without a token; it's read again on every request so a
+/// reload can change it
+fn check_api_token() {
+    config::var("MEDIA_CONTROL_API_TOKEN").expect("must set MEDIA_CONTROL_API_TOKEN");
+}
+
+/// Whether the GET-only `/do/{command}` routes are served
+fn get_simple_api_mode() -> bool {
+    config::var("MEDIA_CONTROL_SIMPLE_API")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Whether the server was started with `--demo`, controlling the built-in
+/// demo player instead of real ones
+fn get_demo_mode() -> bool {
+    env::args().skip(1).any(|arg| arg == "--demo")
+}
+
+/// Whether the whole server runs in dry-run mode (commands are never executed)
+fn get_dry_run_mode() -> bool {
+    config::var("MEDIA_CONTROL_DRY_RUN")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Read the single-line status template used for `Accept: text/plain` on /status
+fn get_status_format() -> String {
+    config::var("MEDIA_CONTROL_STATUS_FORMAT")
+        .unwrap_or_else(|_| "{{artist}} – {{title}} [{{status}}]".to_string())
+}
+
+/// Block on the system bus waiting for logind's `PrepareForSleep` signal.
+///
+/// After a resume the session bus names of browsers and other players are often
+/// re-registered, and our own publisher can be left in a stale state. When the
+/// signal reports `false` (i.e. we just woke up) we tear the publisher down,
+/// attach it again and re-publish the last metadata/playback we set, and
+/// drop the cached player lists so the next request discovers players afresh.
+fn watch_for_resume(state: web::Data<AppState>) {
+    let conn = match Connection::new_system() {
+        Ok(c) => c,
+        Err(e) => {
+            warn!("Sleep/wake detection disabled, couldn't connect to system bus: {e}");
+            return;
+        }
+    };
+
+    let rule = MatchRule::new_signal("org.freedesktop.login1.Manager", "PrepareForSleep");
+    let added = conn.add_match(rule, move |(going_to_sleep,): (bool,), _, _| {
+        if !going_to_sleep {
+            info!("System resumed from sleep, re-registering MPRIS publisher");
+            reregister_publisher(&state);
+            state.personas.reregister();
+            registry::invalidate();
+        }
+        true
+    });
+    if let Err(e) = added {
+        warn!("Sleep/wake detection disabled, couldn't subscribe to PrepareForSleep: {e}");
+        return;
+    }
+
+    loop {
+        if let Err(e) = conn.process(Duration::from_secs(60)) {
+            warn!("Sleep/wake detection stopped: {e}");
+            return;
+        }
+    }
+}
+
+/// Restart our MPRIS publisher and push our last known state back onto it
+fn reregister_publisher(state: &AppState) {
+    let mut ctrls = state.controls.locked();
+    if let Err(e) = ctrls.detach() {
+        error!("Failed to detach MPRIS publisher: {e:?}");
+    }
+    if let Err(e) = ctrls.attach(media_keys::forwarder(state.media_keys.clone())) {
+        error!("Failed to re-attach MPRIS publisher: {e:?}");
+        return;
+    }
+    let meta = state.copy_meta.locked();
+    let pb = state.copy_playback.locked();
+    let _ = ctrls.set_metadata(meta.as_media_metadata());
+    let _ = ctrls.set_playback(pb.clone());
+}
+
+/// GET /healthz — the process is up and serving requests
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "Status",
+    security(()),
+    responses((status = 200, description = "The service is up", body = String, content_type = "text/plain")),
+)]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+/// GET /readyz — the session bus can be reached, so commands can work
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "Status",
+    security(()),
+    responses(
+        (status = 200, description = "The session bus can be reached"),
+        (status = 503, description = "The session bus can't be reached"),
+    ),
+)]
+async fn readyz(state: web::Data<AppState>) -> impl Responder {
+    if state.demo.is_some() {
+        return HttpResponse::Ok().json(json!({ "ready": true }));
+    }
+    match web::block(|| registry::players().map(|_| ())).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(json!({ "ready": true })),
+        Ok(Err(e)) => {
+            HttpResponse::ServiceUnavailable().json(json!({ "ready": false, "error": e }))
+        }
+        Err(e) => HttpResponse::ServiceUnavailable()
+            .json(json!({ "ready": false, "error": e.to_string() })),
+    }
 }
 
-/// Read the token from an env var
-fn get_api_token() -> String {
-    env::var("MEDIA_CONTROL_API_TOKEN").expect("must set MEDIA_CONTROL_API_TOKEN")
+/// Compact now-playing view for desktop widgets (GET /widget)
+#[derive(Serialize, Hash, ToSchema)]
+struct WidgetView {
+    player: Option<String>,
+    playing: bool,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    art_url: Option<String>,
+    length_ms: Option<u64>,
 }
 
-/// Read the preferred player from env var, defaulting to "chromium"
-fn get_preferred_player() -> String {
-    env::var("MEDIA_CONTROL_PREFERRED_PLAYER")
-        .unwrap_or_else(|_| "chromium".to_string())
-        .to_lowercase()
+/// Query options for GET /widget
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct WidgetOptions {
+    // Long-poll: hold the request open up to this many seconds until the view
+    // differs from the client's If-None-Match ETag
+    wait_secs: Option<u64>,
 }
 
-/// This middleware will run *before* every handler.
-async fn auth_middleware(
-    req: ServiceRequest,
-    next: Next<BoxBody>, // <-- note BoxBody here
-) -> Result<ServiceResponse<BoxBody>, Error> {
-    // Grab expected token from app data
-    let expected = req
-        .app_data::<web::Data<String>>()
-        .map(|d| d.get_ref().clone())
-        .unwrap_or_default();
+/// Read the widget view and its ETag
+///
+/// Position is left out on purpose so the ETag only changes on real state
+/// changes (track, play/pause, player), not every second.
+fn widget_view(state: &AppState) -> (WidgetView, String) {
+    let (player, _) = player::select_player(&state.pinned);
+    let metadata = player.as_ref().and_then(|p| p.get_metadata().ok());
+    let view = WidgetView {
+        player: player.as_ref().map(|p| p.identity().to_string()),
+        playing: player
+            .as_ref()
+            .and_then(|p| p.get_playback_status().ok())
+            .is_some_and(|s| s == mpris::PlaybackStatus::Playing),
+        title: metadata
+            .as_ref()
+            .and_then(|m| m.title().map(str::to_string)),
+        artist: metadata
+            .as_ref()
+            .and_then(|m| m.artists())
+            .map(|a| a.join(", ")),
+        album: metadata
+            .as_ref()
+            .and_then(|m| m.album_name().map(str::to_string)),
+        art_url: metadata
+            .as_ref()
+            .and_then(|m| m.art_url().map(str::to_string)),
+        length_ms: metadata
+            .as_ref()
+            .and_then(|m| m.length())
+            .map(|d| d.as_millis() as u64),
+    };
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    view.hash(&mut hasher);
+    let etag = format!("\"{:016x}\"", hasher.finish());
+    (view, etag)
+}
 
-    // Check for `Authorization: Bearer <token>`
-    let authorized = req
+/// GET /widget — minimal now-playing data for lock screen widgets and applets
+///
+/// Responses carry an ETag. Send it back as `If-None-Match` to get a 304 when
+/// nothing changed, and add `?wait_secs=N` to long-poll for the next change
+/// instead of polling.
+#[utoipa::path(
+    get,
+    path = "/widget",
+    tag = "Status",
+    params(WidgetOptions),
+    responses((status = 200, description = "Now playing", body = WidgetView)),
+)]
+async fn widget(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<WidgetOptions>,
+) -> impl Responder {
+    let known = req
         .headers()
-        .get(header::AUTHORIZATION)
+        .get(header::IF_NONE_MATCH)
         .and_then(|h| h.to_str().ok())
-        .map(|val| val == format!("Bearer {expected}"))
-        .unwrap_or(false);
+        .map(str::to_string);
+    let deadline =
+        std::time::Instant::now() + Duration::from_secs(opts.wait_secs.unwrap_or(0).min(120));
 
-    if authorized {
-        // forward to the actual handler
-        let res: ServiceResponse<BoxBody> = next.call(req).await?;
-        Ok(res)
-    } else {
-        // short-circuit with 401
-        Err(ErrorUnauthorized("Invalid or missing API token"))
+    loop {
+        let state = state.clone();
+        let (view, etag) = match web::block(move || widget_view(&state)).await {
+            Ok(v) => v,
+            Err(e) => {
+                return AppError::internal(format!("widget task failed: {e}")).error_response()
+            }
+        };
+        if known.as_deref() != Some(etag.as_str()) {
+            return HttpResponse::Ok()
+                .insert_header((header::ETAG, etag))
+                .json(view);
+        }
+        if std::time::Instant::now() >= deadline {
+            return HttpResponse::NotModified()
+                .insert_header((header::ETAG, etag))
+                .finish();
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(500)).await;
     }
 }
 
-/// Helper: find the best MPRIS player to control, prioritizing the preferred player.
-fn find_player() -> Option<Player> {
-    let pf = PlayerFinder::new().ok()?;
-    let all = pf.find_all().ok()?;
-    let preferred_player = get_preferred_player();
+/// GET /players — list every external MPRIS player we can see
+///
+/// Clients polling at the same moment share a single bus query.
+#[utoipa::path(
+    get,
+    path = "/players",
+    tag = "Players",
+    responses((status = 200, description = "Every external MPRIS player", body = Vec<PlayerInfo>)),
+)]
+async fn list_players(state: web::Data<AppState>) -> impl Responder {
+    let query_state = state.clone();
+    match state
+        .players_query
+        .run(move || player_infos(&query_state))
+        .await
+    {
+        Ok(players) => HttpResponse::Ok().json(players),
+        Err(e) => AppError::internal(format!("player query failed: {e}")).error_response(),
+    }
+}
 
-    // Filter out our own "My Player" service
-    let external_players: Vec<_> = all
-        .into_iter()
-        .filter(|p| p.identity() != "My Player")
-        .collect();
+/// Read every external player for GET /players
+fn player_infos(state: &AppState) -> Vec<PlayerInfo> {
+    let (selected, _) = player::select_player(&state.pinned);
+    let selected_bus = selected.map(|p| p.bus_name().to_string());
 
-    if external_players.is_empty() {
-        println!("No external MPRIS players found");
-        return None;
+    let demo = state.demo.as_ref().map(|_| {
+        let now = state.live.borrow();
+        PlayerInfo {
+            identity: demo::IDENTITY.to_string(),
+            bus_name: demo::BUS_NAME.to_string(),
+            playback: now.playback.clone(),
+            can_seek: true,
+            can_control: true,
+            title: now.title.clone(),
+            artist: now.artist.clone(),
+            selected: true,
+        }
+    });
+    demo.into_iter()
+        .chain(player::external_players().iter().map(|p| {
+            let metadata = p.get_metadata().ok();
+            PlayerInfo {
+                identity: p.identity().to_string(),
+                bus_name: p.bus_name().to_string(),
+                playback: p.get_playback_status().ok().map(|s| format!("{s:?}")),
+                can_seek: p.can_seek().unwrap_or(false),
+                can_control: p.can_control().unwrap_or(false),
+                title: metadata
+                    .as_ref()
+                    .and_then(|m| m.title().map(str::to_string)),
+                artist: metadata
+                    .as_ref()
+                    .and_then(|m| m.artists())
+                    .map(|a| a.join(", ")),
+                selected: state.demo.is_none() && selected_bus.as_deref() == Some(p.bus_name()),
+            }
+        }))
+        .chain(remote::player_infos())
+        .collect()
+}
+
+/// GET /players/{id}/metadata — dump the player's full, uncurated MPRIS metadata map
+#[utoipa::path(
+    get,
+    path = "/players/{id}/metadata",
+    tag = "Players",
+    params(("id" = String, Path, description = "Bus name, identity substring or alias")),
+    responses(
+        (status = 200, description = "The player's MPRIS metadata map", body = Object),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+async fn player_metadata(path: web::Path<String>) -> impl Responder {
+    let selector = path.into_inner();
+    let read = web::block(move || {
+        let player = find_player_by_selector(&selector)
+            .ok_or_else(|| AppError::not_found("no matching player found"))?;
+        let metadata = player
+            .get_metadata()
+            .map_err(|e| AppError::internal(format!("couldn't read metadata: {e}")))?;
+        Ok::<serde_json::Map<_, _>, AppError>(
+            metadata
+                .iter()
+                .map(|(k, v)| (k.to_string(), metadata_value_to_json(v)))
+                .collect(),
+        )
+    })
+    .await;
+    match read {
+        Ok(Ok(map)) => HttpResponse::Ok().json(map),
+        Ok(Err(e)) => e.error_response(),
+        Err(_) => AppError::internal("player task failed").error_response(),
     }
+}
 
-    // Find the best player based on priority
-    let mut selected_player = None;
-    let mut selection_reason = String::new();
+/// Read a player's own volume and whether it can be changed
+fn player_volume(player: &mpris::Player) -> Result<PlayerVolume, mpris::DBusError> {
+    let volume = player.checked_get_volume()?;
+    Ok(PlayerVolume {
+        player: player.identity().to_string(),
+        supported: volume.is_some() && player.can_control()?,
+        volume: volume.map(|v| (v * 100.0).round() as u32),
+    })
+}
 
-    // First priority: Look for the preferred player (default: chromium)
-    for player in &external_players {
-        if player.identity().to_lowercase().contains(&preferred_player) {
-            selected_player = Some(player);
-            selection_reason = format!(
-                "Found preferred player '{}': {}",
-                preferred_player,
-                player.identity()
-            );
-            break;
+/// GET /players/{id}/volume — one player's own volume (MPRIS `Volume`),
+/// separate from the system volume
+#[utoipa::path(
+    get,
+    path = "/players/{id}/volume",
+    tag = "Players",
+    params(("id" = String, Path, description = "Bus name, identity substring or alias")),
+    responses(
+        (status = 200, description = "The player's own volume", body = PlayerVolume),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+async fn get_player_volume(path: web::Path<String>) -> impl Responder {
+    let selector = path.into_inner();
+    let read = web::block(move || {
+        let read_failed =
+            |e: &dyn std::fmt::Display| AppError::internal(format!("couldn't read volume: {e}"));
+        if let Some(player) = find_player_by_selector(&selector) {
+            return player_volume(&player).map_err(|e| read_failed(&e));
         }
+        let remote = remote::find(&selector)
+            .ok_or_else(|| AppError::not_found("no matching player found"))?;
+        let status = remote.status().map_err(|e| read_failed(&e))?;
+        Ok(PlayerVolume {
+            player: remote.identity().to_string(),
+            supported: status.volume.is_some(),
+            volume: status.volume,
+        })
+    })
+    .await;
+    match read {
+        Ok(Ok(volume)) => HttpResponse::Ok().json(volume),
+        Ok(Err(e)) => e.error_response(),
+        Err(_) => AppError::internal("player task failed").error_response(),
+    }
+}
+
+/// POST /players/{id}/volume — set or adjust one player's own volume, e.g.
+/// to turn Spotify down while leaving the browser alone
+///
+/// With `?dry_run=true`, or under `MEDIA_CONTROL_DRY_RUN`, the volume is left
+/// alone and the response says what it would have been set to.
+#[utoipa::path(
+    post,
+    path = "/players/{id}/volume",
+    tag = "Players",
+    params(
+        ("id" = String, Path, description = "Bus name, identity substring or alias"),
+        CommandOptions,
+    ),
+    request_body = VolumeRequest,
+    responses(
+        (status = 200, description = "The player's new volume", body = PlayerVolume),
+        (status = 400, description = "Not exactly one of set or adjust", body = ErrorBody),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+async fn set_player_volume(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<VolumeRequest>,
+) -> impl Responder {
+    if state.lock.is_locked() {
+        return lock::refused();
+    }
+    let request = body.into_inner();
+    if let Err(e) = request.check_player_volume() {
+        return e.error_response();
+    }
+    let selector = path.into_inner();
+    let dry_run = opts.dry_run || get_dry_run_mode();
+    match web::block(move || change_player_volume(&selector, &request, dry_run)).await {
+        Ok(Ok(volume)) => HttpResponse::Ok().json(volume),
+        Ok(Err(e)) => e.error_response(),
+        Err(_) => AppError::internal("player task failed").error_response(),
     }
+}
 
-    // If preferred is "chromium" and not found, try "chrome" as fallback
-    if selected_player.is_none() && preferred_player == "chromium" {
-        for player in &external_players {
-            if player.identity().to_lowercase().contains("chrome") {
-                selected_player = Some(player);
-                selection_reason = format!(
-                    "Found Chrome player as Chromium fallback: {}",
-                    player.identity()
-                );
-                break;
+/// Set or adjust the own volume of the MPRIS or remote player `selector`
+/// picks, as a checked `request` asks, or only work out the new level when
+/// `dry_run` is set
+fn change_player_volume(
+    selector: &str,
+    request: &VolumeRequest,
+    dry_run: bool,
+) -> Result<PlayerVolume, AppError> {
+    let read_failed =
+        |e: &dyn std::fmt::Display| AppError::internal(format!("couldn't read volume: {e}"));
+    let set_failed =
+        |e: &dyn std::fmt::Display| AppError::internal(format!("couldn't set volume: {e}"));
+    let player = find_player_by_selector(selector);
+    let remote = player.is_none().then(|| remote::find(selector)).flatten();
+    let mut volume = match (&player, &remote) {
+        (Some(player), _) => player_volume(player).map_err(|e| read_failed(&e))?,
+        (None, Some(remote)) => {
+            let status = remote.status().map_err(|e| read_failed(&e))?;
+            PlayerVolume {
+                player: remote.identity().to_string(),
+                supported: status.volume.is_some(),
+                volume: status.volume,
             }
         }
+        (None, None) => return Err(AppError::not_found("no matching player found")),
+    };
+    let Some(current) = volume.volume.filter(|_| volume.supported) else {
+        return Err(AppError::bad_request(format!(
+            "{} doesn't support volume control",
+            volume.player
+        )));
+    };
+    let level = match request.set {
+        Some(level) => level,
+        None => volume::adjusted(current, request.adjust.unwrap_or_default()).min(100),
+    };
+    if !dry_run {
+        match (&player, &remote) {
+            (Some(player), _) => player
+                .set_volume(f64::from(level) / 100.0)
+                .map_err(|e| set_failed(&e))?,
+            (None, Some(remote)) => remote.set_volume(level).map_err(|e| set_failed(&e))?,
+            (None, None) => unreachable!(),
+        }
     }
+    volume.volume = Some(level);
+    Ok(volume)
+}
 
-    // Final fallback: Use the first available player
-    if selected_player.is_none() {
-        if let Some(fallback) = external_players.first() {
-            selected_player = Some(fallback);
-            selection_reason = format!(
-                "Using fallback player (preferred '{}' not found): {}",
-                preferred_player,
-                fallback.identity()
-            );
+/// POST /players/{id}/{command} — run a command endpoint against one specific player
+///
+/// `{id}` is matched like `/players/{id}/metadata`; `{command}` is any of the
+/// player command endpoint names (`play`, `next`, `seek_forward`, ...).
+#[utoipa::path(
+    post,
+    path = "/players/{id}/{command}",
+    tag = "Players",
+    params(
+        ("id" = String, Path, description = "Bus name, identity substring or alias"),
+        ("command" = String, Path, description = "Command endpoint name, e.g. play or seek_forward"),
+        CommandOptions,
+    ),
+    responses(openapi::CommandResponses),
+)]
+async fn player_command(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    let (selector, command) = path.into_inner();
+    let Some(action) = Action::from_name(&command).filter(|a| a.targets_player()) else {
+        return AppError::not_found(format!("unknown player command: {command}")).error_response();
+    };
+    run_faded(&req, &state, Target::Player(selector), action, &opts).await
+}
+
+/// GET /do/{command} — run a command endpoint from a plain GET, for IR bridges
+/// and buttons that can't send POST requests or headers.
+///
+/// Only served when `MEDIA_CONTROL_SIMPLE_API` is set.
+#[utoipa::path(
+    get,
+    path = "/do/{command}",
+    tag = "Extras",
+    params(("command" = String, Path, description = "Command endpoint name, e.g. toggle"), CommandOptions),
+    responses(openapi::CommandResponses),
+)]
+async fn simple_command(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    let command = path.into_inner();
+    let Some(action) = Action::from_name(&command) else {
+        return AppError::not_found(format!("unknown command: {command}")).error_response();
+    };
+    run_faded(&req, &state, Target::Auto, action, &opts).await
+}
+
+/// Request body for POST /players/select
+#[derive(Deserialize, ToSchema)]
+struct SelectPlayerRequest {
+    // Bus name, identity substring or alias, as for /players/{id}/...
+    player: String,
+}
+
+/// POST /players/select — pin a player so auto-selected commands keep going to it
+///
+/// The pin lasts until `/players/unselect` or until the player disappears from
+/// the bus.
+#[utoipa::path(
+    post,
+    path = "/players/select",
+    tag = "Players",
+    request_body = SelectPlayerRequest,
+    responses(
+        (status = 200, description = "The pinned player", body = Candidate),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+async fn select_player(
+    state: web::Data<AppState>,
+    body: web::Json<SelectPlayerRequest>,
+) -> impl Responder {
+    let selector = body.into_inner().player;
+    let pinned = web::block(move || {
+        let player = find_player_by_selector(&selector)?;
+        *state.pinned.locked() = Some(player.bus_name().to_string());
+        info!(
+            "Pinned player {} ({})",
+            player.identity(),
+            player.bus_name()
+        );
+        Some(player::candidate(&player))
+    })
+    .await;
+    match pinned {
+        Ok(Some(candidate)) => HttpResponse::Ok().json(candidate),
+        Ok(None) => AppError::not_found("no matching player found").error_response(),
+        Err(_) => AppError::internal("player task failed").error_response(),
+    }
+}
+
+/// POST /players/unselect — drop the pin and go back to normal selection
+#[utoipa::path(
+    post,
+    path = "/players/unselect",
+    tag = "Players",
+    responses((status = 200, description = "The pin was dropped", body = String, content_type = "text/plain")),
+)]
+async fn unselect_player(state: web::Data<AppState>) -> impl Responder {
+    match state.pinned.locked().take() {
+        Some(bus_name) => HttpResponse::Ok().body(format!("unpinned {bus_name}")),
+        None => HttpResponse::Ok().body("no player was pinned"),
+    }
+}
+
+/// Query options accepted by every command endpoint
+#[derive(Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CommandOptions {
+    // Include the player-selection reasoning in a JSON response
+    #[serde(default)]
+    explain: bool,
+    // Only report what would be done
+    #[serde(default)]
+    dry_run: bool,
+    // Include how long each part of the command took in a JSON response
+    #[serde(default)]
+    timings: bool,
+    // Comma-separated selectors to run the command on instead of the
+    // auto-selected player, e.g. `spotify,mpv`
+    players: Option<String>,
+    // Seek step in seconds for seek_forward and seek_backward, instead of the
+    // player's configured one
+    seconds: Option<u64>,
+    // Fade the volume over this many milliseconds (up to 10000) for play,
+    // pause, toggle and stop, instead of the configured default
+    fade_ms: Option<u64>,
+}
+
+/// Refuse `?fade_ms=` where it doesn't apply
+fn check_fade(action: Action, opts: &CommandOptions) -> Result<(), AppError> {
+    match opts.fade_ms {
+        Some(_) if !fade::fades(action) => Err(AppError::bad_request(
+            "?fade_ms= only applies to play, pause, toggle and stop",
+        )),
+        Some(ms) if ms > fade::MAX_FADE_MS => Err(AppError::bad_request(format!(
+            "?fade_ms= can be at most {}",
+            fade::MAX_FADE_MS
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Run a command endpoint's action and build its response
+///
+/// With `?explain=true` the plain-text body is replaced by JSON holding the
+/// outcome and the selection trace, and `?timings=true` adds the time spent
+/// selecting the player, on D-Bus, updating our publisher and in the audio
+/// backend (as `timings_ms`). With `?dry_run=true`, or when the server
+/// runs with `MEDIA_CONTROL_DRY_RUN`, nothing is executed and the response
+/// describes what would have happened. Messages are in the client's
+/// `Accept-Language` where we have a translation.
+///
+/// The command runs on the blocking pool: a no-player policy of `wait` or
+/// `launch` can hold it for seconds, which mustn't stall the worker.
+async fn run_command(
+    req: &HttpRequest,
+    state: &web::Data<AppState>,
+    target: &Target,
+    action: Action,
+    opts: &CommandOptions,
+) -> HttpResponse {
+    if let Err(e) = check_fade(action, opts) {
+        return e.error_response();
+    }
+    let seek_ms = |secs: u64| secs.checked_mul(1000).and_then(|ms| i64::try_from(ms).ok());
+    let action = match (action, opts.seconds.map(seek_ms)) {
+        (_, None) => action,
+        (Action::SeekForward | Action::SeekBackward, Some(None)) => {
+            return AppError::bad_request("?seconds= is too large").error_response()
+        }
+        (Action::SeekForward, Some(Some(ms))) => Action::SeekBy(ms),
+        (Action::SeekBackward, Some(Some(ms))) => Action::SeekBy(-ms),
+        _ => {
+            return AppError::bad_request(
+                "?seconds= only applies to seek_forward and seek_backward",
+            )
+            .error_response()
+        }
+    };
+    if let Some(players) = &opts.players {
+        if !action.targets_player() || !matches!(target, Target::Auto) {
+            return AppError::bad_request(
+                "?players= only applies to player commands without a player in the path",
+            )
+            .error_response();
+        }
+        return run_group(req, state, players, action, opts).await;
+    }
+
+    let ran = {
+        let (state, target, opts) = (state.clone(), target.clone(), opts.clone());
+        web::block(move || {
+            let (result, timeline) = timed(opts.timings, || {
+                if opts.dry_run || get_dry_run_mode() {
+                    actions::plan(&state, &target, action)
+                } else {
+                    actions::run_on(&state, &target, action)
+                }
+            });
+            let selection = opts.explain.then(|| match &target {
+                Target::Auto => json!(player::select_player(&state.pinned).1),
+                Target::Player(selector) => json!({
+                    "selector": selector,
+                    "resolved": player::resolve_player_alias(selector),
+                    "selected": find_player_by_selector(selector).map(|p| player::candidate(&p)),
+                }),
+            });
+            (result, timeline, selection)
+        })
+        .await
+    };
+    let Ok((result, timeline, selection)) = ran else {
+        return AppError::internal("command task failed").error_response();
+    };
+    let languages = i18n::languages(req);
+    if !opts.explain && !opts.timings {
+        return actions::respond(result, &languages);
+    }
+    let (mut resp, outcome) = match &result {
+        Ok(msg) => (
+            HttpResponse::Ok(),
+            json!({ "ok": true, "message": msg.localize(&languages) }),
+        ),
+        Err(e) => (
+            HttpResponse::build(e.status()),
+            json!({ "ok": false, "error": e.message().localize(&languages) }),
+        ),
+    };
+    let mut body = json!({ "result": outcome });
+    if let Some(selection) = selection {
+        body["selection"] = selection;
+    }
+    if let Some(timeline) = timeline {
+        body["timings_ms"] = timeline;
+    }
+    resp.json(body)
+}
+
+/// Run play, pause, toggle or stop like [`run_command`], fading the volume
+/// out before or in after it when `?fade_ms=` or the configured default
+/// asks for that (see [`fade`])
+///
+/// A fade out holds the response until the player has paused. If another
+/// command takes over during it, the player isn't paused and the answer is
+/// `409`. A fade in answers once the player plays and raises the volume in
+/// the background.
+async fn run_faded(
+    req: &HttpRequest,
+    state: &web::Data<AppState>,
+    target: Target,
+    action: Action,
+    opts: &CommandOptions,
+) -> HttpResponse {
+    if let Err(e) = check_fade(action, opts) {
+        return e.error_response();
+    }
+    let dry_run = opts.dry_run || get_dry_run_mode();
+    if !fade::fades(action) || dry_run || opts.players.is_some() || state.lock.is_locked() {
+        return run_command(req, state, &target, action, opts).await;
+    }
+    let started = {
+        let (state, target, requested) = (state.clone(), target.clone(), opts.fade_ms);
+        web::block(move || fade::start(&state, &target, action, requested)).await
+    };
+    let Ok(Some(fade)) = started else {
+        fade::interrupt();
+        return run_command(req, state, &target, action, opts).await;
+    };
+    match fade.direction {
+        fade::Direction::Out => {
+            let Ok((faded, fade)) = web::block(move || (fade.down(), fade)).await else {
+                return AppError::internal("fade task failed").error_response();
+            };
+            if !faded {
+                return AppError::conflict("another command interrupted the fade").error_response();
+            }
+            let resp = run_command(req, state, &target, action, opts).await;
+            let _ = web::block(move || fade.restore()).await;
+            resp
+        }
+        fade::Direction::In => {
+            let Ok(fade) = web::block(move || {
+                fade.mute();
+                fade
+            })
+            .await
+            else {
+                return AppError::internal("fade task failed").error_response();
+            };
+            let resp = run_command(req, state, &target, action, opts).await;
+            if resp.status().is_success() {
+                fade.up();
+            } else {
+                let _ = web::block(move || fade.restore()).await;
+            }
+            resp
         }
     }
+}
+
+/// Run `f`, and with `enabled` also return how long each of its phases took
+/// in milliseconds, plus the `total`
+fn timed<T>(enabled: bool, f: impl FnOnce() -> T) -> (T, Option<serde_json::Value>) {
+    if !enabled {
+        return (f(), None);
+    }
+    let started = std::time::Instant::now();
+    timings::start();
+    let value = f();
+    let phases = timings::finish();
+    let ms = |d: Duration| (d.as_secs_f64() * 1_000_000.0).round() / 1000.0;
+    let mut timeline: serde_json::Map<_, _> = phases
+        .into_iter()
+        .map(|(phase, d)| (phase.to_string(), json!(ms(d))))
+        .collect();
+    timeline.insert("total".to_string(), json!(ms(started.elapsed())));
+    (value, Some(timeline.into()))
+}
 
-    if let Some(player) = selected_player {
-        println!("{selection_reason}");
-        // Find the index and return the owned player
-        let identity = player.identity().to_string();
-        return external_players
-            .into_iter()
-            .find(|p| p.identity() == identity);
+/// Run a command on every player in a `?players=` list, reporting each outcome.
+///
+/// Succeeds if any player did; otherwise answers with the first failure's
+/// status code. Runs on the blocking pool, like [`run_command`].
+async fn run_group(
+    req: &HttpRequest,
+    state: &web::Data<AppState>,
+    players: &str,
+    action: Action,
+    opts: &CommandOptions,
+) -> HttpResponse {
+    let selectors: Vec<String> = players
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+    if selectors.is_empty() {
+        return AppError::bad_request("?players= needs at least one player").error_response();
     }
 
-    None
+    let languages = i18n::languages(req);
+    let (state, opts) = (state.clone(), opts.clone());
+    let ran = web::block(move || {
+        let mut first_error = None;
+        let mut any_ok = false;
+        let results: Vec<_> = selectors
+            .iter()
+            .map(|selector| {
+                let (outcome, error) = run_for_player(&state, &languages, selector, action, &opts);
+                if let Some(status) = error {
+                    first_error.get_or_insert(status);
+                } else {
+                    any_ok = true;
+                }
+                outcome
+            })
+            .collect();
+        let status = match first_error {
+            Some(status) if !any_ok => status,
+            _ => StatusCode::OK,
+        };
+        (status, results)
+    })
+    .await;
+    match ran {
+        Ok((status, results)) => HttpResponse::build(status).json(json!({ "results": results })),
+        Err(_) => AppError::internal("command task failed").error_response(),
+    }
 }
 
-/// POST /play — update *your* MPRIS state and tell the active player to play
-async fn play(state: web::Data<AppState>) -> impl Responder {
-    // 1) Update your own publisher state
-    {
-        let mut ctrls = state.controls.lock().unwrap();
-        let mut pb = state.copy_playback.lock().unwrap();
-        *pb = MediaPlayback::Playing { progress: None };
-        ctrls.set_playback(pb.clone()).unwrap();
+/// Run `action` against the player `selector` picks, returning the outcome
+/// as listed in a `results` array and, if it failed, the status to answer with
+fn run_for_player(
+    state: &AppState,
+    languages: &[String],
+    selector: &str,
+    action: Action,
+    opts: &CommandOptions,
+) -> (serde_json::Value, Option<StatusCode>) {
+    let target = Target::Player(selector.to_string());
+    let (result, timeline) = timed(opts.timings, || {
+        if opts.dry_run || get_dry_run_mode() {
+            actions::plan(state, &target, action)
+        } else {
+            actions::run_on(state, &target, action)
+        }
+    });
+    let (mut outcome, error) = match &result {
+        Ok(msg) => (
+            json!({ "player": selector, "ok": true, "message": msg.localize(languages) }),
+            None,
+        ),
+        Err(e) => (
+            json!({ "player": selector, "ok": false, "error": e.message().localize(languages) }),
+            Some(e.status()),
+        ),
+    };
+    if opts.explain {
+        outcome["selected"] =
+            json!(find_player_by_selector(selector).map(|p| player::candidate(&p)));
     }
-    // 2) Tell any other active player to play
-    if let Some(p) = find_player() {
-        let _ = p.play();
+    if let Some(timeline) = timeline {
+        outcome["timings_ms"] = timeline;
     }
-    HttpResponse::Ok().body("playing")
+    (outcome, error)
 }
 
-/// POST /pause — same pattern for pause
-async fn pause(state: web::Data<AppState>) -> impl Responder {
-    {
-        let mut ctrls = state.controls.lock().unwrap();
-        let mut pb = state.copy_playback.lock().unwrap();
-        *pb = MediaPlayback::Paused { progress: None };
-        ctrls.set_playback(pb.clone()).unwrap();
+/// POST /pause_all — pause every external player that is playing
+///
+/// Answers like a command with `?players=`: one entry per paused player.
+#[utoipa::path(
+    post,
+    path = "/pause_all",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(
+        (status = 200, description = "One result per player that was playing", body = Object),
+        (status = 400, description = "?players= was given", body = ErrorBody),
+    ),
+)]
+async fn pause_all(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    if opts.players.is_some() {
+        return AppError::bad_request("?players= doesn't apply to /pause_all").error_response();
     }
-    if let Some(p) = find_player() {
-        let _ = p.pause();
+    let playing = web::block(|| -> Vec<String> {
+        player::playing_players(None)
+            .iter()
+            .map(|p| p.bus_name().to_string())
+            .collect()
+    })
+    .await;
+    let Ok(playing) = playing else {
+        return AppError::internal("command task failed").error_response();
+    };
+    if playing.is_empty() {
+        return HttpResponse::Ok().json(json!({ "results": [] }));
+    }
+    run_group(&req, &state, &playing.join(","), Action::Pause, &opts).await
+}
+
+/// POST /players/{id}/play_exclusive — play one player and pause all the others
+///
+/// The others are paused first, so they never play over it. Answers like a
+/// command with `?players=`, the played player last; the status is that of
+/// playing it.
+#[utoipa::path(
+    post,
+    path = "/players/{id}/play_exclusive",
+    tag = "Players",
+    params(
+        ("id" = String, Path, description = "Bus name, identity substring or alias"),
+        CommandOptions,
+    ),
+    responses(
+        (status = 200, description = "A result per paused player, then one for playing this one", body = Object),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+async fn play_exclusive(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    if opts.players.is_some() {
+        return AppError::bad_request("?players= doesn't apply to play_exclusive").error_response();
     }
-    HttpResponse::Ok().body("paused")
+    let languages = i18n::languages(&req);
+    let (selector, opts) = (path.into_inner(), opts.into_inner());
+    // Pausing and playing can wait for a player, so off the worker
+    let ran = web::block(move || {
+        let player = find_player_by_selector(&selector)?;
+        let mut results: Vec<_> = player::playing_players(Some(player.bus_name()))
+            .iter()
+            .map(|other| {
+                run_for_player(&state, &languages, other.bus_name(), Action::Pause, &opts).0
+            })
+            .collect();
+        let (outcome, error) =
+            run_for_player(&state, &languages, player.bus_name(), Action::Play, &opts);
+        results.push(outcome);
+        Some((error.unwrap_or(StatusCode::OK), results))
+    })
+    .await;
+    match ran {
+        Ok(Some((status, results))) => {
+            HttpResponse::build(status).json(json!({ "results": results }))
+        }
+        Ok(None) => AppError::not_found("no matching player found").error_response(),
+        Err(_) => AppError::internal("command task failed").error_response(),
+    }
+}
+
+/// POST /play — update *your* MPRIS state and tell the active player to play
+#[utoipa::path(
+    post,
+    path = "/play",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn play(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_faded(&req, &state, Target::Auto, Action::Play, &opts).await
 }
 
-/// POST /toggle
-/// If the external player is playing, pause it; otherwise play it.
+/// POST /pause — update *your* MPRIS state and tell the active player to pause
+#[utoipa::path(
+    post,
+    path = "/pause",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn pause(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_faded(&req, &state, Target::Auto, Action::Pause, &opts).await
+}
+
+/// POST /toggle — pause the external player if it's playing, otherwise play it
+///
 /// Also update your own MPRIS service to match.
-async fn toggle(state: web::Data<AppState>) -> impl Responder {
-    // 1) Find the first real player
-    if let Some(player) = find_player() {
-        // 2) Query its status
-        match player.get_playback_status() {
-            Ok(PlaybackStatus::Playing) => {
-                // pause external
-                let _ = player.pause();
-                // update ours
-                let mut ctrls = state.controls.lock().unwrap();
-                let mut pb = state.copy_playback.lock().unwrap();
-                *pb = MediaPlayback::Paused { progress: None };
-                ctrls.set_playback(pb.clone()).unwrap();
-                HttpResponse::Ok().body("paused")
+#[utoipa::path(
+    post,
+    path = "/toggle",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn toggle(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_faded(&req, &state, Target::Auto, Action::Toggle, &opts).await
+}
+
+/// POST /stop — update *your* MPRIS state and tell the active player to stop
+#[utoipa::path(
+    post,
+    path = "/stop",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn stop(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_faded(&req, &state, Target::Auto, Action::Stop, &opts).await
+}
+
+/// POST /volume_up — bump the system volume by the volume step (5% by default)
+#[utoipa::path(
+    post,
+    path = "/volume_up",
+    tag = "Volume",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn volume_up(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::VolumeUp, &opts).await
+}
+
+/// POST /volume_down — lower the system volume by the volume step
+#[utoipa::path(
+    post,
+    path = "/volume_down",
+    tag = "Volume",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn volume_down(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::VolumeDown, &opts).await
+}
+
+/// POST /mute — mute the system volume
+#[utoipa::path(
+    post,
+    path = "/mute",
+    tag = "Volume",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn mute(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::Mute, &opts).await
+}
+
+/// POST /unmute — unmute the system volume
+#[utoipa::path(
+    post,
+    path = "/unmute",
+    tag = "Volume",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn unmute(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::Unmute, &opts).await
+}
+
+/// POST /mute/toggle — flip the system mute flag
+#[utoipa::path(
+    post,
+    path = "/mute/toggle",
+    tag = "Volume",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn toggle_mute(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::ToggleMute, &opts).await
+}
+
+/// Request body for POST /volume, with exactly one field set
+#[derive(Deserialize, ToSchema)]
+struct VolumeRequest {
+    // Absolute level in percent
+    set: Option<u32>,
+    // Change in percentage points, e.g. -10
+    adjust: Option<i32>,
+}
+
+impl VolumeRequest {
+    /// Check the request for a player's own volume, which only goes up to 100
+    fn check_player_volume(&self) -> Result<(), AppError> {
+        if self.set.is_some() == self.adjust.is_some() {
+            return Err(AppError::bad_request(
+                "expected exactly one of \"set\" or \"adjust\"",
+            ));
+        }
+        if self.set.is_some_and(|level| level > 100) {
+            return Err(AppError::bad_request("\"set\" must be between 0 and 100"));
+        }
+        Ok(())
+    }
+}
+
+/// GET /volume — the system volume in percent
+#[utoipa::path(
+    get,
+    path = "/volume",
+    tag = "Volume",
+    responses((status = 200, description = "System volume", body = VolumeLevel)),
+)]
+async fn get_volume() -> impl Responder {
+    match web::block(|| volume::backend().get_volume()).await {
+        Ok(Ok(volume)) => HttpResponse::Ok().json(VolumeLevel { volume }),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
+    }
+}
+
+/// POST /volume — set (`{"set": 40}`) or adjust (`{"adjust": -10}`) the
+/// system volume, returning the resulting level
+///
+/// With `?dry_run=true`, or under `MEDIA_CONTROL_DRY_RUN`, the volume is left
+/// alone and the response has the level it would have been set to.
+#[utoipa::path(
+    post,
+    path = "/volume",
+    tag = "Volume",
+    params(CommandOptions),
+    request_body = VolumeRequest,
+    responses(
+        (status = 200, description = "The new system volume", body = VolumeLevel),
+        (status = 400, description = "Not exactly one of set or adjust", body = ErrorBody),
+        (status = 423, description = "Volume is locked", body = ErrorBody),
+    ),
+)]
+async fn set_volume(
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<VolumeRequest>,
+) -> impl Responder {
+    if state.lock.is_locked() {
+        return lock::refused();
+    }
+    let (set, adjust) = (body.set, body.adjust);
+    if set.is_some() == adjust.is_some() {
+        return AppError::bad_request("expected exactly one of \"set\" or \"adjust\"")
+            .error_response();
+    }
+    if set.is_some_and(|level| level > volume::MAX_VOLUME) {
+        return AppError::bad_request(format!(
+            "\"set\" must be between 0 and {}",
+            volume::MAX_VOLUME
+        ))
+        .error_response();
+    }
+
+    let dry_run = opts.dry_run || get_dry_run_mode();
+    let result = web::block(move || -> Result<u32, String> {
+        let backend = volume::backend();
+        match (set, adjust) {
+            (Some(level), _) if dry_run => Ok(level),
+            (Some(level), _) => backend.set_volume(level).map(|()| level),
+            (None, delta) if dry_run => {
+                Ok(volume::adjusted(backend.get_volume()?, delta.unwrap_or(0)))
             }
-            Ok(_) => {
-                // play external
-                let _ = player.play();
-                // update ours
-                let mut ctrls = state.controls.lock().unwrap();
-                let mut pb = state.copy_playback.lock().unwrap();
-                *pb = MediaPlayback::Playing { progress: None };
-                ctrls.set_playback(pb.clone()).unwrap();
-                HttpResponse::Ok().body("playing")
+            (None, delta) => backend.adjust_volume(delta.unwrap_or(0)),
+        }
+    })
+    .await;
+
+    match result {
+        Ok(Ok(volume)) => HttpResponse::Ok().json(VolumeLevel { volume }),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
+    }
+}
+
+/// Request body for POST /volume/apps/{id}: `set` or `adjust` as for
+/// POST /volume, and/or `muted`
+#[derive(Deserialize, ToSchema)]
+struct AppVolumeRequest {
+    set: Option<u32>,
+    adjust: Option<i32>,
+    muted: Option<bool>,
+}
+
+/// GET /volume/apps — every application stream with its volume and mute flag
+#[utoipa::path(
+    get,
+    path = "/volume/apps",
+    tag = "Volume",
+    responses((status = 200, description = "Every application stream", body = Vec<AppStream>)),
+)]
+async fn list_app_volumes() -> impl Responder {
+    match web::block(|| volume::backend().list_apps()).await {
+        Ok(Ok(apps)) => HttpResponse::Ok().json(apps),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
+    }
+}
+
+/// POST /volume/apps/{id} — change one application's volume or mute flag
+/// without touching the system volume, returning the stream's new state
+///
+/// With `?dry_run=true`, or under `MEDIA_CONTROL_DRY_RUN`, the stream is left
+/// alone and the response shows the state it would have had.
+#[utoipa::path(
+    post,
+    path = "/volume/apps/{id}",
+    tag = "Volume",
+    params(
+        ("id" = u32, Path, description = "Stream id from GET /volume/apps"),
+        CommandOptions,
+    ),
+    request_body = AppVolumeRequest,
+    responses(
+        (status = 200, description = "The stream's new state", body = AppStream),
+        (status = 400, description = "Nothing to change", body = ErrorBody),
+        (status = 404, description = "No such stream", body = ErrorBody),
+    ),
+)]
+async fn set_app_volume(
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<AppVolumeRequest>,
+) -> impl Responder {
+    if state.lock.is_locked() {
+        return lock::refused();
+    }
+    let id = path.into_inner();
+    let AppVolumeRequest { set, adjust, muted } = body.into_inner();
+    if set.is_some() && adjust.is_some() {
+        return AppError::bad_request("expected at most one of \"set\" or \"adjust\"")
+            .error_response();
+    }
+    if set.is_none() && adjust.is_none() && muted.is_none() {
+        return AppError::bad_request("expected \"set\", \"adjust\" or \"muted\"").error_response();
+    }
+    if set.is_some_and(|level| level > volume::MAX_VOLUME) {
+        return AppError::bad_request(format!(
+            "\"set\" must be between 0 and {}",
+            volume::MAX_VOLUME
+        ))
+        .error_response();
+    }
+
+    let dry_run = opts.dry_run || get_dry_run_mode();
+    let result = web::block(move || -> Result<Option<volume::AppStream>, String> {
+        let backend = volume::backend();
+        let Some(mut app) = backend.list_apps()?.into_iter().find(|a| a.id == id) else {
+            return Ok(None);
+        };
+        let level = set.or(adjust.map(|delta| volume::adjusted(app.volume, delta)));
+        if let Some(level) = level {
+            if !dry_run {
+                backend.set_app_volume(id, level)?;
             }
-            Err(e) => {
-                eprintln!("Failed to get playback status: {e}");
-                HttpResponse::InternalServerError().body("couldn't read status")
+            app.volume = level;
+        }
+        if let Some(muted) = muted {
+            if !dry_run {
+                backend.set_app_mute(id, muted)?;
             }
+            app.muted = muted;
         }
-    } else {
-        // no external player found → just play
-        let mut ctrls = state.controls.lock().unwrap();
-        let mut pb = state.copy_playback.lock().unwrap();
-        *pb = MediaPlayback::Playing { progress: None };
-        ctrls.set_playback(pb.clone()).unwrap();
-        HttpResponse::Ok().body("playing (no external player)")
+        Ok(Some(app))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(app))) => HttpResponse::Ok().json(app),
+        Ok(Ok(None)) => AppError::not_found(format!("no application stream {id}")).error_response(),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
     }
 }
 
-/// POST /volume_up — bump the system volume by 5%
-async fn volume_up(_state: web::Data<AppState>) -> impl Responder {
-    let status = Command::new("pactl")
-        .args(["set-sink-volume", "@DEFAULT_SINK@", "+5%"])
-        .status();
+#[derive(Deserialize, ToSchema)]
+struct DefaultSinkRequest {
+    // Sink name, or its description (case-insensitive)
+    sink: String,
+    // Also move every playing stream onto the new default
+    #[serde(default = "default_true")]
+    move_streams: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// GET /sinks — every output device, with its volume and whether it's the default
+#[utoipa::path(
+    get,
+    path = "/sinks",
+    tag = "Volume",
+    responses((status = 200, description = "Every output device", body = Vec<Sink>)),
+)]
+async fn list_sinks() -> impl Responder {
+    match web::block(|| volume::backend().list_sinks()).await {
+        Ok(Ok(sinks)) => HttpResponse::Ok().json(sinks),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
+    }
+}
 
-    match status {
-        Ok(s) if s.success() => HttpResponse::Ok().body("system volume +5%"),
-        Ok(s) => HttpResponse::InternalServerError().body(format!("pactl exited with {s}")),
-        Err(e) => HttpResponse::InternalServerError().body(format!("failed to launch pactl: {e}")),
+/// GET /audio/sources — every input device, with its volume, mute flag and
+/// whether it's the default
+#[utoipa::path(
+    get,
+    path = "/audio/sources",
+    tag = "Volume",
+    responses((status = 200, description = "Every input device", body = Vec<Source>)),
+)]
+async fn list_sources() -> impl Responder {
+    match web::block(|| volume::backend().list_sources()).await {
+        Ok(Ok(sources)) => HttpResponse::Ok().json(sources),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
     }
 }
 
-/// POST /volume_down — lower the system volume by 5%
-async fn volume_down(_state: web::Data<AppState>) -> impl Responder {
-    let status = Command::new("pactl")
-        .args(["set-sink-volume", "@DEFAULT_SINK@", "-5%"])
-        .status();
+/// POST /sinks/default — make another output device the default, moving
+/// playing streams onto it
+///
+/// With `?dry_run=true`, or under `MEDIA_CONTROL_DRY_RUN`, nothing moves and
+/// the response lists the streams that would have.
+#[utoipa::path(
+    post,
+    path = "/sinks/default",
+    tag = "Volume",
+    params(CommandOptions),
+    request_body = DefaultSinkRequest,
+    responses(
+        (status = 200, description = "The new default output", body = DefaultSink),
+        (status = 404, description = "No such output", body = ErrorBody),
+    ),
+)]
+async fn set_default_sink(
+    opts: web::Query<CommandOptions>,
+    body: web::Json<DefaultSinkRequest>,
+) -> impl Responder {
+    let DefaultSinkRequest { sink, move_streams } = body.into_inner();
+    let dry_run = opts.dry_run || get_dry_run_mode();
 
-    match status {
-        Ok(s) if s.success() => HttpResponse::Ok().body("system volume -5%"),
-        Ok(s) => HttpResponse::InternalServerError().body(format!("pactl exited with {s}")),
-        Err(e) => HttpResponse::InternalServerError().body(format!("failed to launch pactl: {e}")),
+    let wanted = sink.clone();
+    let result = web::block(move || -> Result<Option<DefaultSink>, String> {
+        let backend = volume::backend();
+        let sinks = backend.list_sinks()?;
+        let Some(target) = sinks.iter().find(|s| s.name == wanted).or_else(|| {
+            sinks.iter().find(|s| {
+                s.description
+                    .as_deref()
+                    .is_some_and(|d| d.eq_ignore_ascii_case(&wanted))
+            })
+        }) else {
+            return Ok(None);
+        };
+
+        let mut moved = Vec::new();
+        if !dry_run {
+            backend.set_default_sink(&target.name)?;
+        }
+        if move_streams {
+            for app in backend.list_apps()? {
+                if !dry_run {
+                    backend.move_app(app.id, &target.name)?;
+                }
+                moved.push(app.id);
+            }
+        }
+        Ok(Some(DefaultSink {
+            sink: target.name.clone(),
+            moved_streams: moved,
+        }))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(body))) => HttpResponse::Ok().json(body),
+        Ok(Ok(None)) => AppError::not_found(format!("no sink named {sink:?}")).error_response(),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("volume task failed: {e}")).error_response(),
     }
 }
 
 /// POST /next – skip to next track
-async fn next_track(_state: web::Data<AppState>) -> impl Responder {
-    if let Some(p) = find_player() {
-        let _ = p.next(); // whole‐track skip :contentReference[oaicite:2]{index=2}
-        HttpResponse::Ok().body("skipped to next track")
-    } else {
-        HttpResponse::NotFound().body("no external player found")
-    }
+#[utoipa::path(
+    post,
+    path = "/next",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn next_track(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::Next, &opts).await
 }
 
 /// POST /previous – skip to previous track
-async fn prev_track(_state: web::Data<AppState>) -> impl Responder {
-    if let Some(p) = find_player() {
-        let _ = p.previous(); // whole‐track skip :contentReference[oaicite:3]{index=3}
-        HttpResponse::Ok().body("skipped to previous track")
-    } else {
-        HttpResponse::NotFound().body("no external player found")
-    }
+#[utoipa::path(
+    post,
+    path = "/previous",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn prev_track(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::Previous, &opts).await
 }
 
-/// POST /seek_forward – move forward 30 s within the current track
-async fn seek_forward(_state: web::Data<AppState>) -> impl Responder {
-    if let Some(p) = find_player() {
-        if p.can_seek().unwrap() {
-            let _ = p.seek_forwards(&Duration::from_secs(30)); // 30 s jump :contentReference[oaicite:4]{index=4}
-            HttpResponse::Ok().body("seeked forward 30s")
-        } else {
-            HttpResponse::BadRequest().body("player cannot seek")
+/// Request body for POST /open
+#[derive(Deserialize, ToSchema)]
+struct OpenRequest {
+    uri: String,
+    // Player selector as for /players/{id}/...; the auto-selected player if missing
+    player: Option<String>,
+}
+
+/// POST /open — have a player open and play a URI (MPRIS `OpenUri`), e.g. a
+/// radio stream
+///
+/// With `?dry_run=true`, or under `MEDIA_CONTROL_DRY_RUN`, the player and URI
+/// scheme are checked but nothing is opened.
+#[utoipa::path(
+    post,
+    path = "/open",
+    tag = "Playback",
+    params(CommandOptions),
+    request_body = OpenRequest,
+    responses(
+        (status = 200, description = "The player is opening the URI", body = String, content_type = "text/plain"),
+        (status = 400, description = "Not a URI, or the player can't open URIs", body = ErrorBody),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+async fn open_uri(
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<OpenRequest>,
+) -> impl Responder {
+    let OpenRequest { uri, player } = body.into_inner();
+    let Some((scheme, _)) = uri.split_once(':').filter(|(scheme, _)| !scheme.is_empty()) else {
+        return AppError::bad_request("\"uri\" must be a URI such as https://...").error_response();
+    };
+    let scheme = scheme.to_string();
+    let target = player.map_or(Target::Auto, Target::Player);
+    if target.is_demo(&state) {
+        return AppError::bad_request("the demo player can't open URIs").error_response();
+    }
+    let dry_run = opts.dry_run || get_dry_run_mode();
+    let opened = web::block(move || {
+        let player = target
+            .find(&state)
+            .ok_or_else(|| AppError::not_found("no matching player found"))?;
+
+        // Players list what they can open; trust the call if they don't say
+        let schemes = player.get_supported_uri_schemes().unwrap_or_default();
+        if !schemes.is_empty() && !schemes.iter().any(|s| s.eq_ignore_ascii_case(&scheme)) {
+            return Err(AppError::bad_request(format!(
+                "{} can't open {scheme} URIs (supports: {})",
+                player.identity(),
+                schemes.join(", ")
+            )));
         }
-    } else {
-        HttpResponse::NotFound().body("no external player found")
+        if dry_run {
+            return Ok(format!("would open {uri} in {}", player.identity()));
+        }
+        player::open_uri(player.bus_name(), &uri)
+            .map(|()| format!("opening {uri} in {}", player.identity()))
+            .map_err(|e| AppError::internal(format!("couldn't open {uri}: {e}")))
+    })
+    .await;
+    match opened {
+        Ok(Ok(msg)) => HttpResponse::Ok().body(msg),
+        Ok(Err(e)) => e.error_response(),
+        Err(_) => AppError::internal("player task failed").error_response(),
     }
 }
 
-/// POST /seek_backward – move back 30 s within the current track
-async fn seek_backward(_state: web::Data<AppState>) -> impl Responder {
-    if let Some(p) = find_player() {
-        if p.can_seek().unwrap() {
-            let _ = p.seek_backwards(&Duration::from_secs(30)); // 30 s jump :contentReference[oaicite:5]{index=5}
-            HttpResponse::Ok().body("seeked backward 30s")
-        } else {
-            HttpResponse::BadRequest().body("player cannot seek")
+/// Request body for POST /seek, with exactly one field set
+#[derive(Deserialize, ToSchema)]
+struct SeekRequest {
+    // Where to jump to: seconds, or "1:23" / "1:02:03"
+    position: Option<SeekPosition>,
+    // Seconds to move by, negative to go back
+    offset: Option<f64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+enum SeekPosition {
+    Seconds(f64),
+    Clock(String),
+}
+
+/// POST /seek – jump to a position in the current track, or move by an offset
+#[utoipa::path(
+    post,
+    path = "/seek",
+    tag = "Playback",
+    params(CommandOptions),
+    request_body = SeekRequest,
+    responses(openapi::CommandResponses),
+)]
+async fn seek(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<SeekRequest>,
+) -> impl Responder {
+    let action = match body.into_inner() {
+        SeekRequest {
+            position: Some(position),
+            offset: None,
+        } => {
+            let position = match position {
+                SeekPosition::Seconds(secs) => Duration::try_from_secs_f64(secs).ok(),
+                SeekPosition::Clock(clock) => player::parse_position(&clock),
+            };
+            let Some(position) = position else {
+                return AppError::bad_request(
+                    "\"position\" must be seconds or a time like \"1:23\"",
+                )
+                .error_response();
+            };
+            Action::SeekTo(position.as_millis() as u64)
+        }
+        SeekRequest {
+            position: None,
+            offset: Some(secs),
+        } if secs.is_finite() => Action::SeekBy((secs * 1000.0).round() as i64),
+        _ => {
+            return AppError::bad_request(
+                "expected exactly one of \"position\" or \"offset\" (seconds)",
+            )
+            .error_response()
+        }
+    };
+    run_command(&req, &state, &Target::Auto, action, &opts).await
+}
+
+/// POST /seek_forward – move forward within the current track (30 s unless
+/// overridden per player or with `?seconds=`)
+#[utoipa::path(
+    post,
+    path = "/seek_forward",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn seek_forward(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::SeekForward, &opts).await
+}
+
+/// POST /seek_backward – move back within the current track (30 s unless
+/// overridden per player or with `?seconds=`)
+#[utoipa::path(
+    post,
+    path = "/seek_backward",
+    tag = "Playback",
+    params(CommandOptions),
+    responses(openapi::CommandResponses)
+)]
+async fn seek_backward(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+) -> impl Responder {
+    run_command(&req, &state, &Target::Auto, Action::SeekBackward, &opts).await
+}
+
+/// GET /shuffle – the controlled player's shuffle state, null if unsupported
+#[utoipa::path(
+    get,
+    path = "/shuffle",
+    tag = "Playback",
+    responses((status = 200, description = "Shuffle state, null if unsupported", body = inline(ShuffleRequest))),
+)]
+async fn get_shuffle(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(json!({ "shuffle": state.live.borrow().shuffle }))
+}
+
+/// Request body for POST /shuffle
+#[derive(Deserialize, ToSchema)]
+struct ShuffleRequest {
+    // Flip the current state if missing
+    shuffle: Option<bool>,
+}
+
+/// POST /shuffle – turn shuffle on or off on the controlled player
+#[utoipa::path(
+    post,
+    path = "/shuffle",
+    tag = "Playback",
+    params(CommandOptions),
+    request_body = ShuffleRequest,
+    responses(openapi::CommandResponses),
+)]
+async fn set_shuffle(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<ShuffleRequest>,
+) -> impl Responder {
+    let action = Action::SetShuffle(body.shuffle);
+    run_command(&req, &state, &Target::Auto, action, &opts).await
+}
+
+/// GET /loop – the controlled player's loop mode, null if unsupported
+#[utoipa::path(
+    get,
+    path = "/loop",
+    tag = "Playback",
+    responses((status = 200, description = "Loop mode, null if unsupported", body = inline(LoopRequest))),
+)]
+async fn get_loop(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(json!({ "loop_status": state.live.borrow().loop_status }))
+}
+
+/// Request body for POST /loop
+#[derive(Deserialize, ToSchema)]
+struct LoopRequest {
+    // `None`, `Track` or `Playlist`; step through them if missing
+    loop_status: Option<String>,
+}
+
+/// POST /loop – change the controlled player's loop mode
+#[utoipa::path(
+    post,
+    path = "/loop",
+    tag = "Playback",
+    params(CommandOptions),
+    request_body = LoopRequest,
+    responses(openapi::CommandResponses),
+)]
+async fn set_loop(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    opts: web::Query<CommandOptions>,
+    body: web::Json<LoopRequest>,
+) -> impl Responder {
+    let status = match body.loop_status.as_deref().map(player::parse_loop_status) {
+        None => None,
+        Some(Some(status)) => Some(status),
+        Some(None) => {
+            return AppError::bad_request(
+                "\"loop_status\" must be \"None\", \"Track\" or \"Playlist\"",
+            )
+            .error_response()
         }
-    } else {
-        HttpResponse::NotFound().body("no external player found")
+    };
+    run_command(&req, &state, &Target::Auto, Action::SetLoop(status), &opts).await
+}
+
+/// Query options for POST /audio/bluetooth/{mac}/connect
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BluetoothConnectOptions {
+    // Move the controlled player's stream onto the new sink
+    #[serde(default)]
+    move_player: bool,
+    // Also make the new sink the system default
+    #[serde(default)]
+    make_default: bool,
+}
+
+/// POST /audio/bluetooth/{mac}/connect — connect a paired speaker and route audio to it
+#[utoipa::path(
+    post,
+    path = "/audio/bluetooth/{mac}/connect",
+    tag = "Volume",
+    params(("mac" = String, Path, description = "Address like AA:BB:CC:DD:EE:FF"), BluetoothConnectOptions),
+    responses(
+        (status = 200, description = "Connected, with the new sink and the streams moved onto it"),
+        (status = 400, description = "Not a Bluetooth address", body = ErrorBody),
+        (status = 500, description = "Connecting failed", body = ErrorBody),
+    ),
+)]
+async fn bluetooth_connect(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    opts: web::Query<BluetoothConnectOptions>,
+) -> impl Responder {
+    let mac = path.into_inner().to_uppercase();
+    if !audio::is_bluetooth_address(&mac) {
+        return AppError::bad_request("expected a Bluetooth address like AA:BB:CC:DD:EE:FF")
+            .error_response();
+    }
+    let opts = opts.into_inner();
+
+    let result = web::block(move || -> Result<serde_json::Value, String> {
+        let sink = audio::connect_bluetooth_sink(&mac, Duration::from_secs(15))?;
+
+        if opts.make_default {
+            volume::backend().set_default_sink(&sink)?;
+        }
+
+        let mut moved = Vec::new();
+        if opts.move_player {
+            let player = find_player(&state.pinned).ok_or("no external player found")?;
+            for input in audio::sink_inputs_for_player(player.identity())? {
+                volume::backend().move_app(input.index, &sink)?;
+                moved.push(input.index);
+            }
+        }
+
+        Ok(json!({ "sink": sink, "moved_streams": moved }))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(body)) => HttpResponse::Ok().json(body),
+        Ok(Err(e)) => AppError::internal(e).error_response(),
+        Err(e) => AppError::internal(format!("bluetooth task failed: {e}")).error_response(),
+    }
+}
+
+/// POST /say_track — speak the current artist and title
+#[utoipa::path(
+    post,
+    path = "/say_track",
+    tag = "Extras",
+    responses(
+        (status = 200, description = "What was said", body = String, content_type = "text/plain"),
+        (status = 500, description = "Speech failed", body = ErrorBody),
+    ),
+)]
+async fn say_track(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let languages = i18n::languages(&req);
+    let text = speech::announcement(&state.live.borrow()).localize(&languages);
+    let spoken = text.clone();
+    match web::block(move || speech::say(&spoken)).await {
+        Ok(Ok(())) => HttpResponse::Ok().body(text),
+        Ok(Err(msg)) => AppError::internal(msg.localize(&languages)).error_response(),
+        Err(e) => AppError::internal(format!("speech task failed: {e}")).error_response(),
     }
 }
 
 /// GET /status — report both your MPRIS state and the system's active player state
-async fn status(state: web::Data<AppState>) -> impl Responder {
-    // Read your last‐set playback
+///
+/// Clients sending `Accept: text/plain` get a single line rendered from
+/// `MEDIA_CONTROL_STATUS_FORMAT` instead of JSON, for status bars. Clients
+/// polling at the same moment share a single read.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "Status",
+    responses(
+        (status = 200, description = "Playback state and current track", body = Status),
+        (status = 200, description = "One line from MEDIA_CONTROL_STATUS_FORMAT, for Accept: text/plain", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn status(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let status = match shared_status(&state).await {
+        Ok(status) => status,
+        Err(e) => return e.error_response(),
+    };
+    if wants_plain_text(&req) {
+        return HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(status_line(&status));
+    }
+
+    HttpResponse::Ok().json(status)
+}
+
+/// `status` rendered through `MEDIA_CONTROL_STATUS_FORMAT`
+fn status_line(status: &Status) -> String {
+    let field = |value: &Option<String>| value.clone().unwrap_or_default();
+    let duration = |ms: Option<u64>| {
+        ms.map(|ms| template::format_duration(Duration::from_millis(ms)))
+            .unwrap_or_default()
+    };
+    let vars = template::Vars::from([
+        ("artist", field(&status.artist)),
+        ("title", field(&status.title)),
+        ("album", field(&status.album)),
+        (
+            "status",
+            status
+                .other_playback
+                .clone()
+                .unwrap_or_else(|| status.our_playback.clone()),
+        ),
+        ("player", field(&status.controlled_player)),
+        ("position", duration(status.position_ms)),
+        ("duration", duration(status.length_ms)),
+    ]);
+    template::render(&get_status_format(), &vars)
+}
+
+/// GET /status/spoken — the status as one sentence for voice assistants to
+/// read out
+///
+/// The sentence is in the client's `Accept-Language` where we have a
+/// translation.
+#[utoipa::path(
+    get,
+    path = "/status/spoken",
+    tag = "Status",
+    responses((status = 200, description = "What's playing, as a sentence", body = String, content_type = "text/plain")),
+)]
+async fn spoken_status(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let status = match shared_status(&state).await {
+        Ok(status) => status,
+        Err(e) => return e.error_response(),
+    };
+    let text = speech::spoken_status(&status, &i18n::languages(&req));
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(text)
+}
+
+/// The status for an HTTP request, read on the blocking pool and shared
+/// with any request already reading it
+async fn shared_status(state: &web::Data<AppState>) -> Result<Status, AppError> {
+    let query_state = state.clone();
+    state
+        .status_query
+        .run(move || status_json(&query_state))
+        .await
+        .map_err(|e| AppError::internal(format!("status query failed: {e}")))
+}
+
+/// The JSON body of GET /status
+fn status_json(state: &AppState) -> Status {
     let our_pb = {
-        let pb = state.copy_playback.lock().unwrap();
+        let pb = state.copy_playback.locked();
         format!("{pb:?}")
     };
-    // Ask the other player and get its identity
-    let player = find_player();
-    let other_pb = player
-        .as_ref()
-        .and_then(|p| p.get_playback_status().ok())
-        .map(|s| format!("{s:?}"));
-    let controlled_player = player.as_ref().map(|p| p.identity().to_string());
-
-    // Read your last‐set title
-    let title = {
-        let md = state.copy_meta.lock().unwrap();
-        md.title.as_ref().map(|cow| cow.to_string())
+    let title = state.copy_meta.locked().title.clone();
+    let now = state.live.borrow().clone();
+    if now.player.is_none() {
+        if let Some((remote, status)) = remote::status_fallback() {
+            return Status {
+                our_playback: our_pb,
+                other_playback: status.playback,
+                title: status.title.or(title),
+                controlled_player: Some(remote.identity().to_string()),
+                artist: status.artist,
+                album: status.album,
+                art_url: None,
+                length_ms: status.length.map(|d| d.as_millis() as u64),
+                position_ms: status.position.map(|d| d.as_millis() as u64),
+                shuffle: None,
+                loop_status: None,
+                volume: now.volume,
+                muted: now.muted,
+            };
+        }
+    }
+    Status {
+        our_playback: our_pb,
+        other_playback: now.playback.clone(),
+        title: now.title.clone().or(title),
+        controlled_player: now.player.clone(),
+        artist: now.artist.clone(),
+        album: now.album.clone(),
+        art_url: now.art_url.clone(),
+        length_ms: now.length.map(|d| d.as_millis() as u64),
+        position_ms: now.position().map(|d| d.as_millis() as u64),
+        shuffle: now.shuffle,
+        loop_status: now.loop_status.clone(),
+        volume: now.volume,
+        muted: now.muted,
+    }
+}
+
+/// GET /overview — everything a dashboard shows, in one poll
+#[utoipa::path(
+    get,
+    path = "/overview",
+    tag = "Status",
+    responses((status = 200, description = "Status, players and outputs", body = Overview)),
+)]
+async fn overview(state: web::Data<AppState>) -> impl Responder {
+    let query_state = state.clone();
+    let players = match state
+        .players_query
+        .run(move || player_infos(&query_state))
+        .await
+    {
+        Ok(players) => players,
+        Err(e) => return AppError::internal(format!("player query failed: {e}")).error_response(),
     };
+    let status = match shared_status(&state).await {
+        Ok(status) => status,
+        Err(e) => return e.error_response(),
+    };
+    HttpResponse::Ok().json(Overview {
+        status,
+        players,
+        sinks: state.live.borrow().sinks.clone(),
+    })
+}
 
-    let resp = Status {
-        our_playback: our_pb,
-        other_playback: other_pb,
-        title,
-        controlled_player,
+/// True when the client ranks `text/plain` above JSON in its `Accept` header
+fn wants_plain_text(req: &HttpRequest) -> bool {
+    let Ok(accept) = Accept::parse(req) else {
+        return false;
     };
-    HttpResponse::Ok().json(resp)
+    accept
+        .ranked()
+        .into_iter()
+        .find(|m| {
+            m.essence_str() == mime::TEXT_PLAIN.essence_str()
+                || m.essence_str() == mime::APPLICATION_JSON.essence_str()
+        })
+        .is_some_and(|m| m.essence_str() == mime::TEXT_PLAIN.essence_str())
 }
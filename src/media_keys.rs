@@ -0,0 +1,63 @@
+//! Hardware media keys and desktop widget buttons.
+//!
+//! These arrive as events on our own MPRIS publisher, on souvlaki's bus
+//! thread. The handler only forwards them over a channel; a worker thread
+//! runs each one through [`crate::actions`], exactly like the matching HTTP
+//! endpoint, so a key press controls the selected external player and updates
+//! our published state.
+
+use crate::actions::{self, Action};
+use crate::AppState;
+use actix_web::web;
+use souvlaki::{MediaControlEvent, SeekDirection};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use tracing::{info, warn};
+
+/// The action a media key maps to, if we handle it
+fn action_for(event: &MediaControlEvent) -> Option<Action> {
+    match event {
+        MediaControlEvent::Play => Some(Action::Play),
+        MediaControlEvent::Pause => Some(Action::Pause),
+        MediaControlEvent::Toggle => Some(Action::Toggle),
+        MediaControlEvent::Stop => Some(Action::Stop),
+        MediaControlEvent::Next => Some(Action::Next),
+        MediaControlEvent::Previous => Some(Action::Previous),
+        // Seeks always use our own step, whatever amount was requested
+        MediaControlEvent::Seek(SeekDirection::Forward)
+        | MediaControlEvent::SeekBy(SeekDirection::Forward, _) => Some(Action::SeekForward),
+        MediaControlEvent::Seek(SeekDirection::Backward)
+        | MediaControlEvent::SeekBy(SeekDirection::Backward, _) => Some(Action::SeekBackward),
+        _ => None,
+    }
+}
+
+/// Event handler to attach to our publisher, forwarding events to `keys`
+pub fn forwarder(keys: Sender<MediaControlEvent>) -> impl Fn(MediaControlEvent) + Send + 'static {
+    move |event| {
+        if keys.send(event).is_err() {
+            warn!("Media key handler has stopped, dropping event");
+        }
+    }
+}
+
+/// Start the thread that runs forwarded media key events
+pub fn spawn(state: web::Data<AppState>, keys: Receiver<MediaControlEvent>) {
+    thread::spawn(move || {
+        for event in keys {
+            let Some(action) = action_for(&event) else {
+                info!("media key: {event:?} (ignored)");
+                continue;
+            };
+            let result = if crate::get_dry_run_mode() {
+                actions::plan(&state, &actions::Target::Auto, action)
+            } else {
+                actions::run(&state, action)
+            };
+            match result {
+                Ok(msg) => info!("media key: {event:?}: {msg}"),
+                Err(e) => warn!("media key: {event:?} failed: {e}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,205 @@
+//! `GET /metrics`: per-player usage statistics in the Prometheus text format.
+//!
+//! - `media_controller_player_playing_seconds_total`: how long each player
+//!   was the controlled player and playing
+//! - `media_controller_player_commands_total` and
+//!   `media_controller_player_command_failures_total`: commands sent to each
+//!   player, by command. Commands issued while no player was found count
+//!   against `player="none"`.
+//! - `media_controller_player_selections_total`: how often each player was
+//!   picked for a command, by selection rule, which shows how often the
+//!   fallback rules kick in
+//! - `media_controller_query_runs_total` and
+//!   `media_controller_query_joined_total`: reads of `/players` and
+//!   `/status` that went to the bus, and requests that shared one already
+//!   running (see [`crate::coalesce`])
+//!
+//! Counters live in memory and start from zero when the service restarts.
+
+use crate::actions::Action;
+use crate::error::MutexExt;
+use crate::live::NowPlaying;
+use crate::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Metrics {
+    playing: BTreeMap<String, Duration>,
+    // The controlled player that is currently playing, and since when
+    playing_since: Option<(String, Instant)>,
+    // Keyed by (player, command)
+    commands: BTreeMap<(String, String), u64>,
+    failures: BTreeMap<(String, String), u64>,
+    // Keyed by (player, rule)
+    selections: BTreeMap<(String, &'static str), u64>,
+}
+
+fn metrics() -> &'static Mutex<Metrics> {
+    static METRICS: OnceLock<Mutex<Metrics>> = OnceLock::new();
+    METRICS.get_or_init(Mutex::default)
+}
+
+/// Count a command sent to `player` (an identity, or None if none was found)
+pub fn record_command(player: Option<&str>, action: Action, ok: bool) {
+    let key = (player.unwrap_or("none").to_string(), action.name());
+    let mut m = metrics().locked();
+    if !ok {
+        *m.failures.entry(key.clone()).or_default() += 1;
+    }
+    *m.commands.entry(key).or_default() += 1;
+}
+
+/// Count `player` being chosen by selection `rule`
+pub fn record_selection(player: &str, rule: &'static str) {
+    let mut m = metrics().locked();
+    *m.selections.entry((player.to_string(), rule)).or_default() += 1;
+}
+
+/// Track playing time from one live snapshot
+fn observe(now: &NowPlaying) {
+    let playing = match (&now.player, now.playback.as_deref()) {
+        (Some(player), Some("Playing")) => Some(player),
+        _ => None,
+    };
+    let mut m = metrics().locked();
+    if m.playing_since.as_ref().map(|(p, _)| p) == playing {
+        return;
+    }
+    if let Some((player, since)) = m.playing_since.take() {
+        *m.playing.entry(player).or_default() += since.elapsed();
+    }
+    m.playing_since = playing.map(|p| (p.clone(), Instant::now()));
+}
+
+/// Start following `state.live` for playing time
+pub fn spawn(state: web::Data<AppState>) {
+    let mut updates = state.live.subscribe();
+    actix_web::rt::spawn(async move {
+        loop {
+            observe(&updates.borrow_and_update());
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Quote a Prometheus label value
+fn label(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+fn header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+}
+
+fn render(state: &AppState) -> String {
+    let m = metrics().locked();
+    // Include the segment in progress
+    let mut playing = m.playing.clone();
+    if let Some((player, since)) = &m.playing_since {
+        *playing.entry(player.clone()).or_default() += since.elapsed();
+    }
+    let mut out = String::new();
+
+    let name = "media_controller_player_playing_seconds_total";
+    header(
+        &mut out,
+        name,
+        "Time the player spent playing while controlled",
+    );
+    for (player, time) in &playing {
+        let _ = writeln!(
+            out,
+            "{name}{{player={}}} {:.3}",
+            label(player),
+            time.as_secs_f64()
+        );
+    }
+
+    let counters = [
+        (
+            "media_controller_player_commands_total",
+            "Commands sent to the player",
+            &m.commands,
+        ),
+        (
+            "media_controller_player_command_failures_total",
+            "Commands sent to the player that failed",
+            &m.failures,
+        ),
+    ];
+    for (name, help, values) in counters {
+        header(&mut out, name, help);
+        for ((player, command), count) in values {
+            let _ = writeln!(
+                out,
+                "{name}{{player={},command={}}} {count}",
+                label(player),
+                label(command)
+            );
+        }
+    }
+
+    let name = "media_controller_player_selections_total";
+    header(
+        &mut out,
+        name,
+        "Times the player was selected for a command, by rule",
+    );
+    for ((player, rule), count) in &m.selections {
+        let _ = writeln!(
+            out,
+            "{name}{{player={},rule={}}} {count}",
+            label(player),
+            label(rule)
+        );
+    }
+
+    let queries = [
+        ("players", state.players_query.stats()),
+        ("status", state.status_query.stats()),
+    ];
+    let counters = [
+        (
+            "media_controller_query_runs_total",
+            "Reads that queried the bus",
+            queries.map(|(query, stats)| (query, stats.runs)),
+        ),
+        (
+            "media_controller_query_joined_total",
+            "Requests that shared a read already running",
+            queries.map(|(query, stats)| (query, stats.joined)),
+        ),
+    ];
+    for (name, help, values) in counters {
+        header(&mut out, name, help);
+        for (query, count) in values {
+            let _ = writeln!(out, "{name}{{query={}}} {count}", label(query));
+        }
+    }
+
+    out
+}
+
+/// GET /metrics — Prometheus scrape endpoint
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "Status",
+    responses((status = 200, description = "Prometheus metrics", body = String, content_type = "text/plain")),
+)]
+pub async fn metrics_endpoint(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(render(&state))
+}
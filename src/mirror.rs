@@ -0,0 +1,97 @@
+//! Mirror the controlled player into our own MPRIS publisher.
+//!
+//! Without this, "My Player" in desktop widgets shows whatever metadata we
+//! started with. A task follows the live snapshot and copies the external
+//! player's track, artwork, play state and position onto our publisher (and
+//! into `copy_meta`/`copy_playback`, so a re-registration restores them).
+
+use crate::error::MutexExt;
+use crate::live::NowPlaying;
+use crate::AppState;
+use actix_web::web;
+use souvlaki::{MediaMetadata, MediaPlayback, MediaPosition};
+use std::time::Duration;
+use tracing::error;
+
+/// Owned copy of the metadata we publish.
+///
+/// souvlaki's `MediaMetadata` borrows its strings, which doesn't work for
+/// values that change at runtime.
+#[derive(Clone, Debug, Default)]
+pub struct PublishedMetadata {
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub artist: Option<String>,
+    pub cover_url: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl PublishedMetadata {
+    pub fn as_media_metadata(&self) -> MediaMetadata<'_> {
+        MediaMetadata {
+            title: self.title.as_deref(),
+            album: self.album.as_deref(),
+            artist: self.artist.as_deref(),
+            cover_url: self.cover_url.as_deref(),
+            duration: self.duration,
+        }
+    }
+}
+
+/// Our publisher's playback state for a snapshot, if the player reported one
+fn playback_for(now: &NowPlaying) -> Option<MediaPlayback> {
+    let progress = now.position().map(MediaPosition);
+    match now.playback.as_deref()? {
+        "Playing" => Some(MediaPlayback::Playing { progress }),
+        "Paused" => Some(MediaPlayback::Paused { progress }),
+        _ => Some(MediaPlayback::Stopped),
+    }
+}
+
+/// Copy one snapshot onto the publisher
+fn publish(state: &AppState, now: &NowPlaying) {
+    // Keep showing the last track when no external player is around
+    if now.player.is_none() {
+        return;
+    }
+
+    let meta = PublishedMetadata {
+        title: now.title.clone(),
+        album: now.album.clone(),
+        artist: now.artist.clone(),
+        cover_url: now.art_url.clone(),
+        duration: now.length,
+    };
+    // The publisher isn't registered in demo mode; only keep our copies
+    let registered = state.demo.is_none();
+    let mut ctrls = state.controls.locked();
+    if registered {
+        if let Err(e) = ctrls.set_metadata(meta.as_media_metadata()) {
+            error!("Failed to mirror metadata onto our publisher: {e:?}");
+        }
+    }
+    *state.copy_meta.locked() = meta;
+
+    if let Some(playback) = playback_for(now) {
+        if registered {
+            if let Err(e) = ctrls.set_playback(playback.clone()) {
+                error!("Failed to mirror playback onto our publisher: {e:?}");
+            }
+        }
+        *state.copy_playback.locked() = playback;
+    }
+}
+
+/// Start mirroring `state.live` onto the publisher
+pub fn spawn(state: web::Data<AppState>) {
+    let mut updates = state.live.subscribe();
+    actix_web::rt::spawn(async move {
+        loop {
+            let now = updates.borrow_and_update().clone();
+            publish(&state, &now);
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    });
+}
@@ -0,0 +1,403 @@
+//! JSON bodies of the HTTP API.
+//!
+//! The server builds its responses from these types and the client (the
+//! `client` feature) parses them back into the same types, so the two can't
+//! drift apart.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// JSON view returned by GET /status
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Status {
+    // What *you* last told the system (Playing/Paused)
+    pub our_playback: String,
+    // What the *other* active player reports (if any)
+    pub other_playback: Option<String>,
+    // Track title, falling back to the one you last set
+    pub title: Option<String>,
+    // Which player is being controlled (identity)
+    pub controlled_player: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub art_url: Option<String>,
+    pub length_ms: Option<u64>,
+    // Extrapolated from the player's last report
+    pub position_ms: Option<u64>,
+    pub shuffle: Option<bool>,
+    pub loop_status: Option<String>,
+    // Default sink volume in percent
+    pub volume: Option<u32>,
+    pub muted: Option<bool>,
+}
+
+/// JSON view returned by GET /overview
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Overview {
+    // Same as GET /status, including system volume and mute
+    pub status: Status,
+    // Same as GET /players
+    pub players: Vec<PlayerInfo>,
+    // Names of the output devices, if read yet
+    pub sinks: Option<Vec<String>>,
+}
+
+/// One entry of GET /players
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlayerInfo {
+    pub identity: String,
+    pub bus_name: String,
+    pub playback: Option<String>,
+    pub can_seek: bool,
+    pub can_control: bool,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    // Whether find_player() would pick this one right now
+    pub selected: bool,
+}
+
+/// A player seen during discovery, e.g. the one pinned by POST /players/select
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Candidate {
+    pub identity: String,
+    pub bus_name: String,
+}
+
+/// Response of GET and POST /players/{id}/volume
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlayerVolume {
+    pub player: String,
+    // Whether the player exposes a Volume property we can set
+    pub supported: bool,
+    // Player volume in percent (MPRIS 0.0–1.0), if it reports one
+    pub volume: Option<u32>,
+}
+
+/// Response of GET and POST /volume
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct VolumeLevel {
+    // Default sink volume in percent
+    pub volume: u32,
+}
+
+/// One application's stream into a sink (a PulseAudio sink input)
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct AppStream {
+    // Sink input index; changes every time the app opens a new stream
+    pub id: u32,
+    // `application.name`, e.g. "Firefox"
+    pub app: Option<String>,
+    // `application.process.binary`, e.g. "firefox"
+    pub binary: Option<String>,
+    // Percent, averaged over channels
+    pub volume: u32,
+    pub muted: bool,
+}
+
+/// One output device
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Sink {
+    // PulseAudio sink name, e.g. "alsa_output.usb-...analog-stereo"
+    pub name: String,
+    // Human-readable name, e.g. "USB Audio Analog Stereo"
+    pub description: Option<String>,
+    pub default: bool,
+    // Percent, averaged over channels
+    pub volume: u32,
+    pub muted: bool,
+}
+
+/// One input device, from GET /audio/sources
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Source {
+    // PulseAudio source name, e.g. "alsa_input.usb-...mono-fallback"
+    pub name: String,
+    // Human-readable name, e.g. "USB Microphone Mono"
+    pub description: Option<String>,
+    pub default: bool,
+    // Percent, averaged over channels
+    pub volume: u32,
+    pub muted: bool,
+    // Whether this records an output device's sound rather than an input
+    pub monitor: bool,
+}
+
+/// Response of POST /sinks/default
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DefaultSink {
+    // Name of the new default sink
+    pub sink: String,
+    // Ids of the application streams moved onto it
+    pub moved_streams: Vec<u32>,
+}
+
+/// JSON view returned by GET /queue
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Queue {
+    pub player: String,
+    // Whether DELETE /queue/{trackid} can work
+    pub can_edit: bool,
+    pub tracks: Vec<QueueEntry>,
+}
+
+/// One track of GET /queue
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct QueueEntry {
+    pub id: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub length_ms: Option<u64>,
+    pub current: bool,
+}
+
+/// One page of GET /history, newest plays first
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlayHistory {
+    // Plays matching the filters, on every page
+    pub total: u64,
+    pub limit: u32,
+    pub offset: u64,
+    pub plays: Vec<Play>,
+}
+
+/// One track played, as listed by GET /history
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Play {
+    pub id: i64,
+    // When it started playing, in UTC, e.g. 2026-10-13T15:04:05Z
+    pub started_at: String,
+    pub started_at_ms: u64,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub player: Option<String>,
+    pub length_ms: Option<u64>,
+    // Time spent playing, without pauses
+    pub listened_ms: u64,
+}
+
+/// Listening statistics returned by GET /stats
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ListeningStats {
+    // Window the numbers cover, in milliseconds since the Unix epoch;
+    // None means open-ended
+    pub from_ms: Option<u64>,
+    pub to_ms: Option<u64>,
+    // Plays counted, skips left out
+    pub plays: u64,
+    pub listened_ms: u64,
+    pub top_artists: Vec<StatsEntry>,
+    pub top_tracks: Vec<TrackStats>,
+    // Every player, most listened first
+    pub players: Vec<StatsEntry>,
+    // Plays by local weekday (Monday first) and hour of day, 7 rows of 24
+    pub heatmap: Vec<Vec<u64>>,
+}
+
+/// An artist or player in GET /stats
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct StatsEntry {
+    pub name: String,
+    pub plays: u64,
+    pub listened_ms: u64,
+}
+
+/// A track in GET /stats
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TrackStats {
+    pub title: String,
+    pub artist: Option<String>,
+    pub plays: u64,
+    pub listened_ms: u64,
+}
+
+/// What a machine in reporting mode sends, and what POST /reports takes
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Report {
+    // Which machine this is, by default its hostname
+    pub instance: String,
+    pub timestamp_ms: u64,
+    // Event types since the last report, e.g. `track`; empty when it's a
+    // periodic one
+    pub events: Vec<String>,
+    // Seconds until the next periodic report at the latest
+    pub interval_secs: u64,
+    pub status: Status,
+}
+
+/// One machine as listed by GET /reports
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReportedInstance {
+    pub report: Report,
+    // When the report arrived, in milliseconds since the Unix epoch
+    pub received_ms: u64,
+    // Whether it was heard from within the last three intervals
+    pub online: bool,
+}
+
+/// Response of the /sleep_timer endpoints
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SleepTimerState {
+    pub active: bool,
+    // `pause` or `stop`
+    pub action: Option<String>,
+    pub fade: Option<bool>,
+    // Seconds until it fires
+    pub remaining_secs: Option<u64>,
+}
+
+/// A position alarm waiting to go off, from the /position_alarm endpoints
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PositionAlarm {
+    // For DELETE /position_alarm/{id}
+    pub id: u64,
+    pub player: String,
+    // Title of the track the alarm was set on
+    pub title: Option<String>,
+    pub at_seconds: u64,
+    // The command it runs, or
+    pub action: Option<String>,
+    // the scheme and host of the webhook it calls
+    pub webhook: Option<String>,
+}
+
+/// Skip votes on the current track, from /vote_skip and `votes` events
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct VoteTally {
+    // Devices that voted to skip this track
+    pub voters: Vec<String>,
+    pub votes: usize,
+    // Votes it takes to skip
+    pub threshold: usize,
+    // Whether the last vote reached the threshold and skipped the track
+    pub skipped: bool,
+}
+
+/// What POST /rate did with the current track
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Rated {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    // The rating the player took, if it took one
+    pub rating: Option<f64>,
+    // The love sent to the scrobbling services, if any are configured
+    pub love: Option<bool>,
+}
+
+/// A scheduled action, from MEDIA_CONTROL_SCHEDULES or POST /schedules
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Schedule {
+    // `config-1`, `config-2`, ... for the configured ones
+    pub id: String,
+    // Minute, hour, day of month, month and weekday, as in crontab
+    pub cron: String,
+    // play, pause, open, volume or any other /do/{action} name
+    pub action: String,
+    // What `open` opens
+    pub uri: Option<String>,
+    // What `volume` sets the system volume to, in percent
+    pub volume: Option<u32>,
+    // Player selector; the auto-selected player if missing
+    pub player: Option<String>,
+    // When it runs next, in local time
+    pub next_run: Option<String>,
+}
+
+/// What one step of POST /batch did
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchStepResult {
+    // The step's action, or `wait` for a step that only waits
+    pub action: String,
+    pub ok: bool,
+    // What was done, or why it failed
+    pub message: String,
+}
+
+/// Response of POST /batch
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchResult {
+    // Whether every step ran
+    pub ok: bool,
+    // The steps that ran, in order; a failed one is last
+    pub results: Vec<BatchStepResult>,
+}
+
+/// A named group of players, from MEDIA_CONTROL_GROUPS or POST /groups
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlayerGroup {
+    pub name: String,
+    // Player selectors, as for /players/{id}/...
+    pub players: Vec<String>,
+    // Whether it's from MEDIA_CONTROL_GROUPS, and so can't be changed
+    pub configured: bool,
+}
+
+/// Response of the /lock endpoints
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct LockState {
+    pub locked: bool,
+}
+
+/// One extra MPRIS publisher from `MEDIA_CONTROL_PERSONAS`, as listed by
+/// GET /personas
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PersonaState {
+    // Used in /personas/{id}
+    pub id: String,
+    // What desktop widgets show as the player's name
+    pub name: String,
+    pub bus_name: String,
+    // Playing, Paused or Stopped
+    pub playback: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub art_url: Option<String>,
+    pub length_ms: Option<u64>,
+    // As last set; not advanced while playing
+    pub position_ms: Option<u64>,
+}
+
+/// Everything the `/ws` and `/events` streams know about the controlled
+/// player, sent whole with every event
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct NowPlaying {
+    pub player: Option<String>,
+    pub bus_name: Option<String>,
+    pub playback: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub art_url: Option<String>,
+    pub shuffle: Option<bool>,
+    // MPRIS LoopStatus: None, Track or Playlist
+    pub loop_status: Option<String>,
+    // Default sink volume in percent
+    pub volume: Option<u32>,
+    pub muted: Option<bool>,
+    // Output device names; None until first read
+    pub sinks: Option<Vec<String>>,
+}
+
+/// One message of the `/ws` and `/events` streams
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Event {
+    // snapshot, player, playback, track, volume, sink_added, votes or
+    // guest_expired
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub now_playing: NowPlaying,
+    // The skip votes, on `votes` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub votes: Option<VoteTally>,
+}
+
+/// Body of every error response
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    // What went wrong, in the client's language where translated
+    pub error: String,
+    // The HTTP status code
+    pub code: u16,
+}
@@ -0,0 +1,270 @@
+//! An MPD server as a remote player, without an MPRIS bridge such as mpDris2.
+//!
+//! Setting `MEDIA_CONTROL_MPD_HOST` adds the server as `mpd:<host>`, named
+//! `MPD`. Each request opens a new connection and speaks MPD's text protocol:
+//! one command per line, answered by `key: value` lines and `OK`, or by an
+//! `ACK` line on error. Play, pause, toggle, stop, next, previous and seeking
+//! map onto MPD's own commands, and the volume is MPD's mixer volume.
+//!
+//! Unlike a Cast device, MPD usually plays on the machine the service runs
+//! on, so `/status` reports it while no MPRIS player is around.
+
+use crate::actions::{Action, ActionError};
+use crate::config;
+use crate::i18n::Message;
+use crate::player::seek_step_for;
+use crate::remote::{self, RemotePlayer, RemoteStatus};
+use crate::template::format_duration;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Port MPD listens on
+const DEFAULT_PORT: u16 = 6600;
+/// How long to wait for MPD to accept a connection or answer
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One open connection to MPD
+struct Connection {
+    reader: BufReader<TcpStream>,
+}
+
+impl Connection {
+    /// Send `command` and collect the `key: value` lines of the answer
+    fn call(&mut self, command: &str) -> Result<Vec<(String, String)>, String> {
+        let stream = self.reader.get_mut();
+        writeln!(stream, "{command}").map_err(|e| format!("can't talk to MPD: {e}"))?;
+        let mut pairs = Vec::new();
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Err("MPD closed the connection".into()),
+                Ok(_) => {}
+                Err(e) => return Err(format!("can't talk to MPD: {e}")),
+            }
+            let line = line.trim_end();
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if let Some(error) = line.strip_prefix("ACK ") {
+                // `ACK [50@0] {play} No such song`: keep the part after the command
+                let reason = error.split_once("} ").map_or(error, |(_, reason)| reason);
+                return Err(format!("MPD refused {command:?}: {reason}"));
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                pairs.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+
+    /// `status` or `currentsong`, by key
+    fn query(&mut self, command: &str) -> Result<HashMap<String, String>, String> {
+        Ok(self.call(command)?.into_iter().collect())
+    }
+}
+
+struct MpdPlayer {
+    id: String,
+    host: String,
+    port: u16,
+    password: Option<String>,
+}
+
+impl MpdPlayer {
+    fn connect(&self) -> Result<Connection, String> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| format!("can't resolve {}", self.host))?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+            .map_err(|e| format!("can't reach MPD at {}:{}: {e}", self.host, self.port))?;
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let _ = stream.set_write_timeout(Some(TIMEOUT));
+        let mut reader = BufReader::new(stream);
+        let mut greeting = String::new();
+        reader
+            .read_line(&mut greeting)
+            .map_err(|e| format!("can't talk to MPD: {e}"))?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(format!("{}:{} isn't an MPD server", self.host, self.port));
+        }
+        let mut connection = Connection { reader };
+        if let Some(password) = &self.password {
+            connection.call(&format!("password {}", quote(password)))?;
+        }
+        Ok(connection)
+    }
+
+    fn command(&self, action: Action) -> Result<Message, String> {
+        let mut mpd = self.connect()?;
+        let status = mpd.query("status")?;
+        let state = status.get("state").map(String::as_str);
+        let position = status
+            .get("elapsed")
+            .and_then(|e| e.parse::<f64>().ok())
+            .unwrap_or_default();
+        let step = seek_step_for(self.identity()).as_secs();
+        match action {
+            Action::Play | Action::Toggle if state != Some("play") => {
+                // `play` would start the paused song over
+                mpd.call(if state == Some("pause") {
+                    "pause 0"
+                } else {
+                    "play"
+                })?;
+                Ok(Message::new("playing"))
+            }
+            Action::Play => Ok(Message::new("playing")),
+            Action::Pause | Action::Toggle => {
+                mpd.call("pause 1")?;
+                Ok(Message::new("paused"))
+            }
+            Action::Stop => {
+                mpd.call("stop")?;
+                Ok(Message::new("stopped"))
+            }
+            Action::Next => {
+                mpd.call("next")?;
+                Ok(Message::new("next-track"))
+            }
+            Action::Previous => {
+                mpd.call("previous")?;
+                Ok(Message::new("previous-track"))
+            }
+            Action::SeekForward | Action::SeekBackward => {
+                let forward = action == Action::SeekForward;
+                mpd.call(&format!(
+                    "seekcur {}{step}",
+                    if forward { '+' } else { '-' }
+                ))?;
+                Ok(Message::new("seeked")
+                    .arg("direction", if forward { "forward" } else { "backward" })
+                    .arg("seconds", step))
+            }
+            Action::SeekBy(ms) => {
+                let target = (position + ms as f64 / 1000.0).max(0.0);
+                mpd.call(&format!("seekcur {target:.3}"))?;
+                Ok(Message::new("seeked")
+                    .arg("direction", if ms < 0 { "backward" } else { "forward" })
+                    .arg("seconds", ms.unsigned_abs() as f64 / 1000.0))
+            }
+            Action::SeekTo(ms) => {
+                mpd.call(&format!("seekcur {:.3}", ms as f64 / 1000.0))?;
+                Ok(Message::new("seeked-to")
+                    .arg("position", format_duration(Duration::from_millis(ms))))
+            }
+            _ => unreachable!("{action:?} isn't supported"),
+        }
+    }
+}
+
+/// An argument in the double quotes MPD expects
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Seconds as MPD reports them, e.g. `83.412`
+fn seconds(value: Option<&String>) -> Option<Duration> {
+    value
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+impl RemotePlayer for MpdPlayer {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn identity(&self) -> &str {
+        "MPD"
+    }
+
+    fn status(&self) -> Result<RemoteStatus, String> {
+        let mut mpd = self.connect()?;
+        let status = mpd.query("status")?;
+        let song = mpd.query("currentsong")?;
+        let playback = match status.get("state").map(String::as_str) {
+            Some("play") => "Playing",
+            Some("pause") => "Paused",
+            _ => "Stopped",
+        };
+        // Streams have no title tag, only the stream's own Name
+        let title = song
+            .get("Title")
+            .or_else(|| song.get("Name"))
+            .or_else(|| song.get("file"))
+            .cloned();
+        Ok(RemoteStatus {
+            playback: Some(playback.to_string()),
+            title,
+            artist: song.get("Artist").cloned(),
+            album: song.get("Album").cloned(),
+            // -1 when MPD has no mixer
+            volume: status.get("volume").and_then(|v| v.parse().ok()),
+            position: seconds(status.get("elapsed")),
+            length: seconds(status.get("duration")),
+            can_seek: status.contains_key("songid"),
+        })
+    }
+
+    fn supports(&self, action: Action) -> bool {
+        matches!(
+            action,
+            Action::Play
+                | Action::Pause
+                | Action::Toggle
+                | Action::Stop
+                | Action::Next
+                | Action::Previous
+                | Action::SeekForward
+                | Action::SeekBackward
+                | Action::SeekBy(_)
+                | Action::SeekTo(_)
+        )
+    }
+
+    fn run(&self, action: Action) -> Result<Message, ActionError> {
+        self.command(action)
+            .map_err(|e| ActionError::Failed(Message::new("player-call-failed").arg("error", e)))
+    }
+
+    fn set_volume(&self, percent: u32) -> Result<(), String> {
+        self.connect()?
+            .call(&format!("setvol {}", percent.min(100)))
+            .map(|_| ())
+    }
+
+    fn in_status(&self) -> bool {
+        true
+    }
+}
+
+/// Add the MPD server if `MEDIA_CONTROL_MPD_HOST` names one
+pub fn start() {
+    let host = config::var("MEDIA_CONTROL_MPD_HOST").unwrap_or_default();
+    let host = host.trim().to_string();
+    if host.is_empty() {
+        return;
+    }
+    let port = match config::var("MEDIA_CONTROL_MPD_PORT") {
+        Ok(port) => port.trim().parse().unwrap_or_else(|_| {
+            warn!("Ignoring MEDIA_CONTROL_MPD_PORT={port:?}, expected a port number");
+            DEFAULT_PORT
+        }),
+        Err(_) => DEFAULT_PORT,
+    };
+    info!("Controlling MPD at {host}:{port}");
+    remote::add(Arc::new(MpdPlayer {
+        id: format!("mpd:{host}"),
+        host,
+        port,
+        password: config::var("MEDIA_CONTROL_MPD_PASSWORD")
+            .ok()
+            .filter(|p| !p.is_empty()),
+    }));
+}
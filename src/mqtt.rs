@@ -0,0 +1,254 @@
+//! MQTT bridge (the `mqtt` feature).
+//!
+//! Set `MEDIA_CONTROL_MQTT_HOST` to connect to a broker. The live snapshot is
+//! published as retained JSON on `<prefix>/status` (the `GET /status` body)
+//! whenever anything changes, `<prefix>/track` when the track or player
+//! changes and `<prefix>/volume` when the volume or mute flag does, and
+//! `<prefix>/available` is `online` while we're connected, `offline` once
+//! the broker notices we're gone.
+//!
+//! Commands arrive on `<prefix>/cmd/<command>`: any command endpoint name
+//! such as `play`, `next` or `volume_up`, plus `volume` with a percentage
+//! and `seek` with a position like `1:05`, `+10` or `-10` as the payload.
+//! They run through [`crate::actions`] like the HTTP endpoints, honouring
+//! the lock and dry run; results are only logged.
+//!
+//! [`crate::homeassistant`] adds Home Assistant discovery on top.
+
+use crate::actions::{self, Action};
+use crate::events::changes;
+use crate::homeassistant;
+use crate::live::NowPlaying;
+use crate::player::parse_position;
+use crate::{config, notify, volume, AppState};
+use actix_web::web;
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Wait this long before reconnecting after the broker went away
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Outgoing messages queued while the broker is slow
+const QUEUE: usize = 64;
+
+fn prefix() -> String {
+    config::var("MEDIA_CONTROL_MQTT_TOPIC_PREFIX")
+        .map(|v| v.trim().trim_end_matches('/').to_string())
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "media-controller".into())
+}
+
+fn client_id() -> String {
+    config::var("MEDIA_CONTROL_MQTT_CLIENT_ID").unwrap_or_else(|_| "media-controller".into())
+}
+
+fn options(host: String, prefix: &str) -> MqttOptions {
+    let port = config::var("MEDIA_CONTROL_MQTT_PORT")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(1883);
+    let mut options = MqttOptions::new(client_id(), host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        format!("{prefix}/available"),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Ok(username) = config::var("MEDIA_CONTROL_MQTT_USERNAME") {
+        let password = config::var("MEDIA_CONTROL_MQTT_PASSWORD").unwrap_or_default();
+        options.set_credentials(username, password);
+    }
+    options
+}
+
+/// Publish a retained message without waiting for the broker
+fn publish(client: &Client, topic: String, payload: String) {
+    if let Err(e) = client.try_publish(&topic, QoS::AtLeastOnce, true, payload) {
+        warn!("Couldn't publish {topic} to MQTT: {e}");
+    }
+}
+
+fn track_json(now: &NowPlaying) -> String {
+    json!({
+        "player": now.player,
+        "title": now.title,
+        "artist": now.artist,
+        "album": now.album,
+        "art_url": now.art_url,
+        "length_ms": now.length.map(|d| d.as_millis() as u64),
+    })
+    .to_string()
+}
+
+fn volume_json(now: &NowPlaying) -> String {
+    json!({ "volume": now.volume, "muted": now.muted }).to_string()
+}
+
+/// Publish the topics that `kinds` of change affect
+fn publish_state(client: &Client, prefix: &str, state: &AppState, kinds: &[&str]) {
+    let now = state.live.borrow().clone();
+    if let Ok(status) = serde_json::to_string(&crate::status_json(state)) {
+        publish(client, format!("{prefix}/status"), status);
+    }
+    if kinds.iter().any(|kind| matches!(*kind, "track" | "player")) {
+        publish(client, format!("{prefix}/track"), track_json(&now));
+    }
+    if kinds.contains(&"volume") {
+        publish(client, format!("{prefix}/volume"), volume_json(&now));
+    }
+}
+
+/// Send Home Assistant our discovery configs and the current state, which
+/// it needs again after a restart
+fn announce(client: &Client, prefix: &str, state: &AppState) {
+    for (topic, config) in homeassistant::configs(prefix, &client_id()) {
+        publish(client, topic, config);
+    }
+    publish_state(client, prefix, state, &["track", "volume"]);
+}
+
+/// Run one command from `<prefix>/cmd/<command>`
+fn command(state: &AppState, name: &str, payload: &str) -> Result<String, String> {
+    let dry_run = crate::get_dry_run_mode();
+    let action = match name {
+        "volume" => return set_volume(state, payload, dry_run),
+        "seek" if payload.starts_with(['+', '-']) => {
+            let seconds: f64 = payload
+                .parse()
+                .map_err(|_| format!("expected seconds, got {payload:?}"))?;
+            Action::SeekBy((seconds * 1000.0) as i64)
+        }
+        "seek" => {
+            let position = parse_position(payload)
+                .ok_or_else(|| format!("expected a position, got {payload:?}"))?;
+            Action::SeekTo(position.as_millis() as u64)
+        }
+        _ => Action::from_name(name).ok_or_else(|| format!("unknown command {name:?}"))?,
+    };
+    notify::remote_command("MQTT".into());
+    let result = if dry_run {
+        actions::plan(state, &actions::Target::Auto, action)
+    } else {
+        actions::run(state, action)
+    };
+    result.map(|msg| msg.to_string()).map_err(|e| e.to_string())
+}
+
+fn set_volume(state: &AppState, payload: &str, dry_run: bool) -> Result<String, String> {
+    if state.lock.is_locked() {
+        return Err("volume is locked".into());
+    }
+    // Home Assistant's sliders send `42.0`
+    let level = payload
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|level| (0.0..=volume::MAX_VOLUME as f64).contains(level))
+        .map(|level| level.round() as u32)
+        .ok_or_else(|| {
+            format!(
+                "expected a percentage between 0 and {}, got {payload:?}",
+                volume::MAX_VOLUME
+            )
+        })?;
+    if !dry_run {
+        volume::backend().set_volume(level)?;
+    }
+    Ok(format!("volume set to {level}%"))
+}
+
+/// Connect to the broker if `MEDIA_CONTROL_MQTT_HOST` is set
+pub fn spawn(state: web::Data<AppState>) {
+    let Ok(host) = config::var("MEDIA_CONTROL_MQTT_HOST") else {
+        return;
+    };
+    let prefix = prefix();
+    let (client, mut connection) = Client::new(options(host.clone(), &prefix), QUEUE);
+    // Changes while disconnected aren't queued; connecting publishes everything
+    let connected = Arc::new(AtomicBool::new(false));
+
+    {
+        let (client, prefix, state) = (client.clone(), prefix.clone(), state.clone());
+        let connected = connected.clone();
+        thread::spawn(move || {
+            let commands = format!("{prefix}/cmd/");
+            let discovery = homeassistant::enabled();
+            let ha_status = format!("{}/status", homeassistant::discovery_prefix());
+            // Only the first failure of a streak is logged
+            let mut failing = false;
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to the MQTT broker at {host}");
+                        connected.store(true, Ordering::SeqCst);
+                        failing = false;
+                        // Sessions are clean, so subscribe and publish afresh
+                        // on every connect
+                        let mut topics = vec![format!("{commands}+")];
+                        if discovery {
+                            topics.push(ha_status.clone());
+                        }
+                        for topic in topics {
+                            if let Err(e) = client.try_subscribe(&topic, QoS::AtLeastOnce) {
+                                warn!("Couldn't subscribe to {topic}: {e}");
+                            }
+                        }
+                        publish(&client, format!("{prefix}/available"), "online".into());
+                        if discovery {
+                            announce(&client, &prefix, &state);
+                        } else {
+                            publish_state(&client, &prefix, &state, &["track", "volume"]);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(message))) if message.topic == ha_status => {
+                        if &message.payload[..] == b"online" {
+                            info!("Home Assistant restarted, sending discovery again");
+                            announce(&client, &prefix, &state);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(message))) => {
+                        let Some(name) = message.topic.strip_prefix(&commands) else {
+                            continue;
+                        };
+                        let payload = String::from_utf8_lossy(&message.payload);
+                        match command(&state, name, payload.trim()) {
+                            Ok(msg) => info!("mqtt: {name}: {msg}"),
+                            Err(e) => warn!("mqtt: {name} failed: {e}"),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if connected.swap(false, Ordering::SeqCst) {
+                            warn!("Lost the MQTT broker at {host}, reconnecting: {e}");
+                        } else if !failing {
+                            warn!("Couldn't reach the MQTT broker at {host}, retrying: {e}");
+                        }
+                        failing = true;
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+    }
+
+    // Taken now rather than in the task, which only starts with the server:
+    // a change in between happens after connecting reads the snapshot
+    let mut updates = state.live.subscribe();
+    let mut last = updates.borrow_and_update().clone();
+    actix_web::rt::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let now = updates.borrow_and_update().clone();
+            let kinds = changes(&last, &now);
+            if !kinds.is_empty() && connected.load(Ordering::SeqCst) {
+                publish_state(&client, &prefix, &state, &kinds);
+            }
+            last = now;
+        }
+    });
+}
@@ -0,0 +1,117 @@
+//! What a command does when there's no player to send it to.
+//!
+//! `MEDIA_CONTROL_NO_PLAYER` picks the behaviour for every command, and
+//! `MEDIA_CONTROL_NO_PLAYER_ACTIONS` overrides it per command, e.g.
+//! `play=launch,next=wait`:
+//!
+//! - `fail`: answer 404 straight away
+//! - `wait`: hold the request until a player shows up, for up to
+//!   `MEDIA_CONTROL_NO_PLAYER_WAIT_SECS` (default 10, at most 60), then run
+//!   it
+//! - `launch`: start `MEDIA_CONTROL_LAUNCH_COMMAND` (e.g. `spotify`), then
+//!   wait as above
+//! - `publisher`: only update our own publisher's playback state
+//!
+//! Unset, play, pause, toggle and stop update our publisher while every
+//! other command fails, as they always have.
+
+use crate::actions::{Action, Target};
+use crate::config;
+use crate::player::select_player;
+use crate::AppState;
+use mpris::Player;
+use serde::Deserialize;
+use std::process::Command;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// How often to look for a player while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Default for `MEDIA_CONTROL_NO_PLAYER_WAIT_SECS`
+const DEFAULT_WAIT_SECS: u64 = 10;
+/// Longest `MEDIA_CONTROL_NO_PLAYER_WAIT_SECS` can hold a command
+const MAX_WAIT_SECS: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    Fail,
+    Wait,
+    Launch,
+    Publisher,
+}
+
+impl Policy {
+    fn parse(value: &str) -> Option<Policy> {
+        let policy = serde_json::from_value(serde_json::Value::String(value.trim().to_lowercase()));
+        policy
+            .map_err(|_| warn!("Ignoring unknown no-player policy {value:?}"))
+            .ok()
+    }
+}
+
+/// The configured policy for `action`, None for the default behaviour
+pub fn policy_for(action: Action) -> Option<Policy> {
+    let name = action.name();
+    let per_action = config::var("MEDIA_CONTROL_NO_PLAYER_ACTIONS")
+        .ok()
+        .and_then(|value| {
+            value.split(',').find_map(|entry| {
+                let (key, policy) = entry.split_once('=')?;
+                (key.trim() == name).then(|| policy.to_string())
+            })
+        });
+    per_action
+        .or_else(|| config::var("MEDIA_CONTROL_NO_PLAYER").ok())
+        .and_then(|value| Policy::parse(&value))
+}
+
+/// How long `wait` and `launch` hold a command
+pub fn wait_time() -> Duration {
+    let secs = config::var("MEDIA_CONTROL_NO_PLAYER_WAIT_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_WAIT_SECS);
+    Duration::from_secs(secs.min(MAX_WAIT_SECS))
+}
+
+/// `MEDIA_CONTROL_LAUNCH_COMMAND`, if set
+pub fn launch_command() -> Option<String> {
+    config::var("MEDIA_CONTROL_LAUNCH_COMMAND")
+        .ok()
+        .filter(|cmd| !cmd.trim().is_empty())
+}
+
+/// Start the preferred player in the background
+pub fn launch() {
+    let Some(cmd) = launch_command() else {
+        warn!("No player to launch, MEDIA_CONTROL_LAUNCH_COMMAND isn't set");
+        return;
+    };
+    info!("No player found, launching: {cmd}");
+    match Command::new("sh").args(["-c", &cmd]).spawn() {
+        // Reap it whenever it exits so it doesn't linger as a zombie
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => error!("Couldn't launch {cmd:?}: {e}"),
+    }
+}
+
+/// Look for `target` until it appears or `timeout` passes
+pub fn wait_for(state: &AppState, target: &Target, timeout: Duration) -> Option<Rc<Player>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let found = match target {
+            // Without logging the selection on every attempt
+            Target::Auto => select_player(&state.pinned).0,
+            Target::Player(_) => target.find(state),
+        };
+        if found.is_some() || Instant::now() >= deadline {
+            return found;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,247 @@
+//! Desktop notifications on track change, with remote-control buttons.
+//!
+//! Enabled with `MEDIA_CONTROL_NOTIFICATIONS=1`. Whenever the controlled
+//! player moves on to another track a notification shows the title and
+//! artist, replacing the previous one, with "Next" and "Pause"/"Play"
+//! buttons. Pressing a button runs that command through [`crate::actions`],
+//! exactly like the HTTP endpoint, so every toast doubles as a small remote.
+//! Buttons only show up where the notification server supports actions.
+//!
+//! Commands from other machines, over HTTP, gRPC or MQTT, are notified too:
+//! when one pauses, resumes or stops playback the notification says so and
+//! who sent it, e.g. "Paused by remote". Requests from this machine don't
+//! count, so the web remote, tray and command line stay quiet.
+
+use crate::actions::{self, Action};
+use crate::config;
+use crate::error::MutexExt;
+use crate::live::NowPlaying;
+use crate::AppState;
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use dbus::arg::PropMap;
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+/// How long to wait for button presses before checking for a new track
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long after a remote command a change of playback is put down to it
+const REMOTE_WINDOW: Duration = Duration::from_secs(3);
+
+/// Who sent the last command from another machine, and when
+static REMOTE: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+/// Whether `MEDIA_CONTROL_NOTIFICATIONS` is set
+fn enabled() -> bool {
+    config::var("MEDIA_CONTROL_NOTIFICATIONS")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// The command a notification button stands for
+fn action_for(key: &str) -> Option<Action> {
+    match key {
+        "next" => Some(Action::Next),
+        "toggle" => Some(Action::Toggle),
+        _ => None,
+    }
+}
+
+/// Note a command from `client` on another machine, so the change it makes
+/// is notified as coming from there
+pub fn remote_command(client: String) {
+    *REMOTE.locked() = Some((client, Instant::now()));
+}
+
+/// The sender of a remote command made just now, if there was one
+fn recent_remote() -> Option<String> {
+    REMOTE
+        .locked()
+        .take()
+        .filter(|(_, at)| at.elapsed() < REMOTE_WINDOW)
+        .map(|(client, _)| client)
+}
+
+/// Middleware noting POSTs from other machines as remote commands. They're
+/// noted before they run, as the player may report the change before the
+/// response is out.
+pub async fn remote_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let remote = req
+        .peer_addr()
+        .map(|addr| addr.ip())
+        .filter(|ip| !ip.is_loopback() && req.method() == Method::POST);
+    if let Some(ip) = remote {
+        remote_command(ip.to_string());
+    }
+    next.call(req).await
+}
+
+/// Start the notification thread if notifications are enabled
+pub fn spawn(state: web::Data<AppState>) {
+    if !enabled() {
+        return;
+    }
+    thread::spawn(move || {
+        let conn = match Connection::new_session() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Notifications disabled, couldn't connect to session bus: {e}");
+                return;
+            }
+        };
+        // Id of the notification we showed last, the only one whose buttons count
+        let shown = Arc::new(AtomicU32::new(0));
+
+        let rule = MatchRule::new_signal(NOTIFICATIONS, "ActionInvoked");
+        let added = conn.add_match(rule, {
+            let state = state.clone();
+            let shown = shown.clone();
+            move |(id, key): (u32, String), _, _| {
+                if id == shown.load(Ordering::Relaxed) {
+                    pressed(&state, &key);
+                }
+                true
+            }
+        });
+        if let Err(e) = added {
+            warn!("Notification buttons disabled, couldn't subscribe to ActionInvoked: {e}");
+        }
+        info!("Showing a notification on track change and remote commands");
+        watch(&state, &conn, &shown);
+    });
+}
+
+/// Run the command behind a pressed button
+fn pressed(state: &AppState, key: &str) {
+    let Some(action) = action_for(key) else {
+        return;
+    };
+    let result = if crate::get_dry_run_mode() {
+        actions::plan(state, &actions::Target::Auto, action)
+    } else {
+        actions::run(state, action)
+    };
+    match result {
+        Ok(msg) => info!("notification: {key}: {msg}"),
+        Err(e) => warn!("notification: {key} failed: {e}"),
+    }
+}
+
+/// Follow the live snapshot, notifying each new track and each change of
+/// playback made remotely, while handling button presses in between
+fn watch(state: &AppState, conn: &Connection, shown: &AtomicU32) {
+    let mut updates = state.live.subscribe();
+    let mut last_track = None;
+    let mut last_playback = None;
+    loop {
+        if let Err(e) = conn.process(POLL_INTERVAL) {
+            warn!("Notifications stopped, lost session bus: {e}");
+            return;
+        }
+        if !updates.has_changed().unwrap_or(false) {
+            continue;
+        }
+        let now = updates.borrow_and_update().clone();
+        let track = (now.player.clone(), now.title.clone(), now.artist.clone());
+        let playback_changed = last_playback
+            .replace(now.playback.clone())
+            .is_some_and(|before| before != now.playback);
+        // A new track says more than who paused the old one
+        let remote = if now.title.is_some() && last_track.as_ref() != Some(&track) {
+            last_track = Some(track);
+            None
+        } else if playback_changed {
+            match recent_remote() {
+                Some(client) => Some(client),
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+        match show(conn, &now, remote.as_deref(), shown.load(Ordering::Relaxed)) {
+            Ok(id) => shown.store(id, Ordering::Relaxed),
+            Err(e) => error!("Couldn't show notification: {e}"),
+        }
+    }
+}
+
+/// Summary of a remote command's notification, from the playback it left
+fn remote_summary(playback: Option<&str>) -> &'static str {
+    match playback {
+        Some("Playing") => "Resumed by remote",
+        Some("Paused") => "Paused by remote",
+        _ => "Stopped by remote",
+    }
+}
+
+/// Show (or replace) the notification, returning its id. It's about the
+/// track, or with `remote` about the command that client just sent.
+fn show(
+    conn: &Connection,
+    now: &NowPlaying,
+    remote: Option<&str>,
+    replaces: u32,
+) -> Result<u32, dbus::Error> {
+    let track = now.title.as_deref().unwrap_or_default();
+    let details = match (&now.artist, &now.album) {
+        (Some(artist), Some(album)) => format!("{artist} — {album}"),
+        (Some(artist), None) => artist.clone(),
+        (None, album) => album.clone().unwrap_or_default(),
+    };
+    let (summary, body) = match remote {
+        Some(client) => {
+            let playing = match (track, now.artist.as_deref()) {
+                ("", _) => String::new(),
+                (title, Some(artist)) => format!("{title} — {artist}\n"),
+                (title, None) => format!("{title}\n"),
+            };
+            (
+                remote_summary(now.playback.as_deref()),
+                format!("{playing}from {client}"),
+            )
+        }
+        None => (track, details),
+    };
+    // Notification servers take local image URIs as icons
+    let icon = now
+        .art_url
+        .as_deref()
+        .filter(|url| url.starts_with("file://"))
+        .unwrap_or("audio-x-generic");
+    let toggle = if now.playback.as_deref() == Some("Playing") {
+        "Pause"
+    } else {
+        "Play"
+    };
+    let buttons = vec!["next", "Next", "toggle", toggle];
+
+    let proxy = conn.with_proxy(NOTIFICATIONS, NOTIFICATIONS_PATH, Duration::from_secs(2));
+    let (id,): (u32,) = proxy.method_call(
+        NOTIFICATIONS,
+        "Notify",
+        (
+            "media-controller",
+            replaces,
+            icon,
+            summary,
+            body,
+            buttons,
+            PropMap::new(),
+            -1i32,
+        ),
+    )?;
+    Ok(id)
+}
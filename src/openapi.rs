@@ -0,0 +1,216 @@
+//! `GET /openapi.json`, an OpenAPI description of the API, and `GET /docs`,
+//! Swagger UI to browse and try it.
+//!
+//! Each handler carries a `#[utoipa::path]` describing its route, and the
+//! request and response types derive `ToSchema`; [`ApiDoc`] gathers them.
+//! Both routes are served without a token so code generators and browsers
+//! can fetch them; the spec declares the bearer token every other endpoint
+//! needs, and Swagger UI's Authorize button asks for it.
+
+use actix_web::{HttpResponse, Responder};
+use media_controller::models::ErrorBody;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoResponses, Modify, OpenApi};
+
+/// What every command endpoint answers with
+#[derive(IntoResponses)]
+#[allow(dead_code)]
+pub enum CommandResponses {
+    /// What was done, as a sentence in the client's language. With
+    /// `?explain=true` or `?timings=true` it's JSON instead.
+    #[response(status = 200, content_type = "text/plain")]
+    Done(String),
+    /// The player doesn't support this command, or the options don't apply
+    #[response(status = 400)]
+    Unsupported(ErrorBody),
+    /// No player to send the command to
+    #[response(status = 404)]
+    NoPlayer(ErrorBody),
+    /// Volume and seeking are locked with `POST /lock`
+    #[response(status = 423)]
+    Locked(ErrorBody),
+    /// Talking to the player or mixer failed
+    #[response(status = 500)]
+    Failed(ErrorBody),
+}
+
+/// Declares the bearer token, required unless a route says otherwise
+struct BearerToken;
+
+impl Modify for BearerToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
+
+/// Drops the `POST /play — ` prefix the handlers' doc comments start with,
+/// which would otherwise repeat the route in every summary
+struct Summaries;
+
+impl Modify for Summaries {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [&mut item.get, &mut item.post, &mut item.delete];
+            for operation in operations.into_iter().flatten() {
+                operation.summary = operation.summary.take().and_then(|summary| {
+                    let (_, rest) = summary.split_once(" — ").or(summary.split_once(" – "))?;
+                    // Doc comments wrap; summaries are one line
+                    let rest = rest.split_whitespace().collect::<Vec<_>>().join(" ");
+                    let mut chars = rest.chars();
+                    let first = chars.next()?;
+                    Some(first.to_uppercase().chain(chars).collect())
+                });
+            }
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    modifiers(&BearerToken, &Summaries),
+    security(("token" = [])),
+    paths(
+        crate::play,
+        crate::pause,
+        crate::pause_all,
+        crate::toggle,
+        crate::stop,
+        crate::next_track,
+        crate::prev_track,
+        crate::seek_forward,
+        crate::seek_backward,
+        crate::seek,
+        crate::open_uri,
+        crate::get_shuffle,
+        crate::set_shuffle,
+        crate::get_loop,
+        crate::set_loop,
+        crate::position_alarm::set_position_alarm,
+        crate::position_alarm::get_position_alarms,
+        crate::position_alarm::cancel_position_alarm,
+        crate::sleep_timer::set_sleep_timer,
+        crate::sleep_timer::get_sleep_timer,
+        crate::sleep_timer::cancel_sleep_timer,
+        crate::vote_skip::vote_skip,
+        crate::vote_skip::get_votes,
+        crate::rating::rate,
+        crate::schedules::list_schedules,
+        crate::schedules::add_schedule,
+        crate::schedules::remove_schedule,
+        crate::batch::batch,
+        crate::skim::skim,
+        crate::volume_up,
+        crate::volume_down,
+        crate::mute,
+        crate::unmute,
+        crate::toggle_mute,
+        crate::get_volume,
+        crate::set_volume,
+        crate::list_app_volumes,
+        crate::set_app_volume,
+        crate::list_sinks,
+        crate::set_default_sink,
+        crate::list_sources,
+        crate::bluetooth_connect,
+        crate::list_players,
+        crate::select_player,
+        crate::unselect_player,
+        crate::player_metadata,
+        crate::get_player_volume,
+        crate::set_player_volume,
+        crate::play_exclusive,
+        crate::player_command,
+        crate::groups::list_groups,
+        crate::groups::add_group,
+        crate::groups::remove_group,
+        crate::groups::group_volume,
+        crate::groups::group_command,
+        crate::queue::get_queue,
+        crate::queue::goto_track,
+        crate::queue::remove_track,
+        crate::status,
+        crate::spoken_status,
+        crate::overview,
+        crate::widget,
+        crate::healthz,
+        crate::readyz,
+        crate::metrics::metrics_endpoint,
+        crate::events::websocket,
+        crate::events::event_stream,
+        crate::say_track,
+        crate::wol::wake_target,
+        crate::snapshot::create_snapshot,
+        crate::snapshot::restore_snapshot,
+        crate::sync::sync_state,
+        crate::sync::start_sync,
+        crate::sync::stop_sync,
+        crate::reporting::list_reports,
+        crate::reporting::receive_report,
+        crate::personas::list_personas,
+        crate::personas::get_persona,
+        crate::personas::update_persona,
+        crate::personas::clear_persona,
+        crate::lock::lock_state,
+        crate::lock::lock,
+        crate::lock::unlock,
+        crate::guest::create_guest,
+        crate::guest::revoke_guest,
+        crate::config::reload_endpoint,
+        crate::diagnostics::diagnostics,
+        crate::ui::art,
+        crate::simple_command,
+    )
+)]
+pub struct ApiDoc;
+
+/// Routes of the `history` feature
+#[cfg(feature = "history")]
+#[derive(OpenApi)]
+#[openapi(modifiers(&Summaries), paths(crate::history::list_plays, crate::history::stats))]
+struct HistoryDoc;
+
+/// [`ApiDoc`] plus the routes of the features built in
+pub fn spec() -> utoipa::openapi::OpenApi {
+    #[allow(unused_mut)]
+    let mut spec = ApiDoc::openapi();
+    #[cfg(feature = "history")]
+    spec.merge(HistoryDoc::openapi());
+    spec
+}
+
+/// GET /openapi.json — the OpenAPI description of every endpoint
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(spec())
+}
+
+/// GET /docs — Swagger UI for /openapi.json
+pub async fn docs() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(DOCS_PAGE)
+}
+
+// Swagger UI comes from a CDN, so a build doesn't have to bundle it
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>media-controller API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui", persistAuthorization: true });
+  </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,78 @@
+//! The HTTP client for outbound integrations: webhooks (including position
+//! alarms), scrobbling, reporting and metadata enrichment.
+//!
+//! They share one ureq agent, and with it the connection pool and proxy.
+//! `MEDIA_CONTROL_OUTBOUND_PROXY` sends them all through an HTTP proxy, such
+//! as `http://proxy.lan:3128`. Without it the usual `HTTPS_PROXY`,
+//! `HTTP_PROXY` and `ALL_PROXY` variables apply. Each integration can use a
+//! different proxy with its own variable (see [`Integration`]), where
+//! `direct` means no proxy; integrations configured alike still share an
+//! agent. Timeouts and the like are set per request by each integration.
+//!
+//! The proxy settings are read on every call, so integrations that ask for
+//! the agent per delivery pick up a config reload straight away.
+
+use crate::config;
+use crate::diagnostics::redact_url;
+use crate::error::MutexExt;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Agents by the proxy setting they were built for
+static AGENTS: Mutex<BTreeMap<String, ureq::Agent>> = Mutex::new(BTreeMap::new());
+
+/// Who is making a request, for its proxy override
+#[derive(Clone, Copy)]
+pub enum Integration {
+    /// `MEDIA_CONTROL_WEBHOOK_PROXY`
+    Webhooks,
+    /// `MEDIA_CONTROL_SCROBBLE_PROXY`
+    Scrobbling,
+    /// `MEDIA_CONTROL_REPORT_PROXY`
+    Reporting,
+    /// `MEDIA_CONTROL_ENRICH_PROXY`
+    Enrichment,
+}
+
+impl Integration {
+    fn proxy_var(self) -> &'static str {
+        match self {
+            Integration::Webhooks => "MEDIA_CONTROL_WEBHOOK_PROXY",
+            Integration::Scrobbling => "MEDIA_CONTROL_SCROBBLE_PROXY",
+            Integration::Reporting => "MEDIA_CONTROL_REPORT_PROXY",
+            Integration::Enrichment => "MEDIA_CONTROL_ENRICH_PROXY",
+        }
+    }
+}
+
+/// The agent `integration` sends its requests with
+pub fn agent(integration: Integration) -> ureq::Agent {
+    let setting = config::var(integration.proxy_var())
+        .or_else(|_| config::var("MEDIA_CONTROL_OUTBOUND_PROXY"))
+        .map(|v| v.trim().to_string())
+        .unwrap_or_default();
+    AGENTS
+        .locked()
+        .entry(setting)
+        .or_insert_with_key(|setting| build(setting))
+        .clone()
+}
+
+fn build(setting: &str) -> ureq::Agent {
+    let proxy = match setting {
+        "" => ureq::Proxy::try_from_env(),
+        "direct" => None,
+        url => ureq::Proxy::new(url)
+            .map_err(|e| {
+                let shown = redact_url(url).unwrap_or_default();
+                warn!("Ignoring outbound proxy {shown}, connecting directly: {e}");
+            })
+            .ok(),
+    };
+    ureq::Agent::config_builder()
+        .proxy(proxy)
+        .user_agent(concat!("media-controller/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .into()
+}
@@ -0,0 +1,83 @@
+//! Stop players that have been left paused for a long time.
+//!
+//! Enabled by setting `MEDIA_CONTROL_PAUSE_TIMEOUT_HOURS`. Once the controlled
+//! player has sat in `Paused` for that long it is sent `Stop`, and our
+//! publisher is set to `Stopped` so desktop widgets drop the stale progress.
+//! Forgotten sessions then stop showing up in status and holding on to
+//! streams.
+
+use crate::config;
+use crate::live::NowPlaying;
+use crate::{actions, player, AppState};
+use actix_web::web;
+use souvlaki::MediaPlayback;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// How often the paused time is checked; the timeout is measured in hours
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Read `MEDIA_CONTROL_PAUSE_TIMEOUT_HOURS` (fractions allowed, e.g. `0.5`)
+fn timeout_from_env() -> Option<Duration> {
+    let hours: f64 = config::var("MEDIA_CONTROL_PAUSE_TIMEOUT_HOURS")
+        .ok()?
+        .parse()
+        .map_err(|_| warn!("Ignoring MEDIA_CONTROL_PAUSE_TIMEOUT_HOURS, expected a number"))
+        .ok()?;
+    Duration::try_from_secs_f64(hours * 3600.0)
+        .ok()
+        .filter(|d| !d.is_zero())
+}
+
+/// Start the watcher thread if a pause timeout is configured
+pub fn spawn_monitor(state: web::Data<AppState>) {
+    let Some(timeout) = timeout_from_env() else {
+        return;
+    };
+    info!("Paused players will be stopped after {timeout:?}");
+    thread::spawn(move || monitor(&state, timeout));
+}
+
+fn monitor(state: &AppState, timeout: Duration) {
+    // The player we saw paused, and since when
+    let mut paused: Option<(String, Instant)> = None;
+
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+
+        let now: NowPlaying = state.live.borrow().clone();
+        let bus_name = match (now.playback.as_deref(), now.bus_name) {
+            (Some("Paused"), Some(bus_name)) => bus_name,
+            // Playing, stopped or gone: start counting afresh
+            _ => {
+                paused = None;
+                continue;
+            }
+        };
+
+        let since = match &paused {
+            Some((bus, since)) if *bus == bus_name => *since,
+            _ => {
+                paused = Some((bus_name, Instant::now()));
+                continue;
+            }
+        };
+        if since.elapsed() < timeout {
+            continue;
+        }
+
+        info!(
+            "{} has been paused for {:?}, stopping it",
+            now.player.as_deref().unwrap_or(&bus_name),
+            since.elapsed()
+        );
+        match player::find_player_by_selector(&bus_name).map(|p| p.stop()) {
+            Some(Ok(())) => {}
+            Some(Err(e)) => error!("Failed to stop {bus_name}: {e}"),
+            None => error!("Failed to stop {bus_name}: player has gone away"),
+        }
+        actions::set_our_playback(state, MediaPlayback::Stopped);
+        paused = None;
+    }
+}
@@ -0,0 +1,389 @@
+//! Extra MPRIS publishers with state of their own.
+//!
+//! "My Player" mirrors whatever player is being controlled. Personas are
+//! further publishers, listed in `MEDIA_CONTROL_PERSONAS` as `id=Name`
+//! entries (e.g. `house=House Audio,announce=Announcements`), whose track
+//! and playback state are only ever set through `POST /personas/{id}`. Each
+//! automation layer can then own a virtual player instead of fighting over
+//! one. Play, pause and stop pressed on a persona in a desktop widget change
+//! its playback state and nothing else.
+//!
+//! Personas are registered at startup, so changing the list needs a restart.
+
+use crate::error::{AppError, MutexExt};
+use crate::get_dry_run_mode;
+use crate::mirror::PublishedMetadata;
+use crate::AppState;
+use actix_web::{web, HttpResponse, Responder};
+use media_controller::models::{ErrorBody, PersonaState};
+use serde::Deserialize;
+use souvlaki::{MediaControlEvent, MediaControls, MediaPlayback, MediaPosition, PlatformConfig};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Bus name prefix of every persona, so discovery can leave them out
+pub const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.persona_";
+
+/// One extra publisher and what it currently shows
+pub struct Persona {
+    id: String,
+    name: String,
+    controls: Mutex<MediaControls>,
+    meta: Mutex<PublishedMetadata>,
+    playback: Mutex<MediaPlayback>,
+}
+
+impl Persona {
+    fn bus_name(&self) -> String {
+        format!("{BUS_PREFIX}{}", self.id)
+    }
+
+    fn view(&self) -> PersonaState {
+        self.view_of(&self.meta.locked(), &self.playback.locked())
+    }
+
+    /// How the persona looks with `meta` and `playback`
+    fn view_of(&self, meta: &PublishedMetadata, playback: &MediaPlayback) -> PersonaState {
+        let (playback, position) = match playback {
+            MediaPlayback::Playing { progress } => ("Playing", *progress),
+            MediaPlayback::Paused { progress } => ("Paused", *progress),
+            MediaPlayback::Stopped => ("Stopped", None),
+        };
+        PersonaState {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            bus_name: self.bus_name(),
+            playback: playback.to_string(),
+            title: meta.title.clone(),
+            artist: meta.artist.clone(),
+            album: meta.album.clone(),
+            art_url: meta.cover_url.clone(),
+            length_ms: meta.duration.map(|d| d.as_millis() as u64),
+            position_ms: position.map(|MediaPosition(d)| d.as_millis() as u64),
+        }
+    }
+
+    /// Push the stored metadata and playback state onto the bus
+    fn publish(&self) {
+        let mut controls = self.controls.locked();
+        if let Err(e) = controls.set_metadata(self.meta.locked().as_media_metadata()) {
+            error!("Failed to publish metadata for persona {}: {e:?}", self.id);
+        }
+        if let Err(e) = controls.set_playback(self.playback.locked().clone()) {
+            error!("Failed to publish playback for persona {}: {e:?}", self.id);
+        }
+    }
+}
+
+/// Every persona, and where their widgets' button presses go
+pub struct Personas {
+    personas: Vec<Arc<Persona>>,
+    events: Sender<(usize, MediaControlEvent)>,
+    // Nothing is put on the bus in demo mode
+    registered: bool,
+}
+
+impl Personas {
+    /// Create and register every persona in `MEDIA_CONTROL_PERSONAS`. With
+    /// `register` false they only keep their state, as in demo mode.
+    pub fn from_env(register: bool) -> Self {
+        let (events, received) = mpsc::channel();
+        let mut personas = Vec::new();
+        for (id, name) in configured() {
+            // souvlaki keeps these for the life of the publisher
+            let dbus_name: &'static str = Box::leak(format!("persona_{id}").into_boxed_str());
+            let display_name: &'static str = Box::leak(name.clone().into_boxed_str());
+            let config = PlatformConfig {
+                dbus_name,
+                display_name,
+                hwnd: None,
+            };
+            let mut controls = match MediaControls::new(config) {
+                Ok(controls) => controls,
+                Err(e) => {
+                    error!("Couldn't create persona {id}: {e:?}");
+                    continue;
+                }
+            };
+            if register {
+                let index = personas.len();
+                if let Err(e) = controls.attach(forwarder(events.clone(), index)) {
+                    error!("Couldn't register persona {id}: {e:?}");
+                    continue;
+                }
+            }
+            info!("Publishing persona {id} as {name:?}");
+            let persona = Arc::new(Persona {
+                id,
+                name,
+                controls: Mutex::new(controls),
+                meta: Mutex::default(),
+                playback: Mutex::new(MediaPlayback::Stopped),
+            });
+            if register {
+                persona.publish();
+            }
+            personas.push(persona);
+        }
+
+        let handled = personas.clone();
+        thread::spawn(move || {
+            for (index, event) in received {
+                let persona: &Persona = &handled[index];
+                if apply_event(persona, &event) {
+                    info!("persona {}: {event:?}", persona.id);
+                    persona.publish();
+                } else {
+                    info!("persona {}: {event:?} (ignored)", persona.id);
+                }
+            }
+        });
+        Personas {
+            personas,
+            events,
+            registered: register,
+        }
+    }
+
+    fn find(&self, id: &str) -> Option<&Arc<Persona>> {
+        self.personas.iter().find(|p| p.id == id)
+    }
+
+    /// Register every persona again and restore what it showed, after the
+    /// machine wakes from suspend
+    pub fn reregister(&self) {
+        if !self.registered {
+            return;
+        }
+        for (index, persona) in self.personas.iter().enumerate() {
+            {
+                let mut controls = persona.controls.locked();
+                if let Err(e) = controls.detach() {
+                    error!("Failed to detach persona {}: {e:?}", persona.id);
+                }
+                if let Err(e) = controls.attach(forwarder(self.events.clone(), index)) {
+                    error!("Failed to re-attach persona {}: {e:?}", persona.id);
+                    continue;
+                }
+            }
+            persona.publish();
+        }
+    }
+
+    /// Take every persona off the bus, before exiting
+    pub fn unregister(&self) {
+        if !self.registered {
+            return;
+        }
+        for persona in &self.personas {
+            if let Err(e) = persona.controls.locked().detach() {
+                error!("Failed to detach persona {}: {e:?}", persona.id);
+            }
+        }
+    }
+}
+
+/// `(id, name)` pairs from `MEDIA_CONTROL_PERSONAS`. Ids are lowercased and
+/// may only hold letters, digits and `_`, as they become part of a bus name.
+fn configured() -> Vec<(String, String)> {
+    let mut personas: Vec<(String, String)> = Vec::new();
+    let list = crate::config::var("MEDIA_CONTROL_PERSONAS").unwrap_or_default();
+    for entry in list.split(',').filter(|e| !e.trim().is_empty()) {
+        let (id, name) = entry.split_once('=').unwrap_or((entry, entry));
+        let id = id.trim().to_lowercase();
+        let name = name.trim().to_string();
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            warn!("Ignoring persona {entry:?}: ids may only use letters, digits and _");
+        } else if personas.iter().any(|(known, _)| *known == id) {
+            warn!("Ignoring duplicate persona {id}");
+        } else {
+            personas.push((id, name));
+        }
+    }
+    personas
+}
+
+/// Event handler for persona `index`, handing events to the worker thread
+fn forwarder(
+    events: Sender<(usize, MediaControlEvent)>,
+    index: usize,
+) -> impl Fn(MediaControlEvent) + Send + 'static {
+    move |event| {
+        let _ = events.send((index, event));
+    }
+}
+
+/// Apply a widget button press to a persona's playback state, returning
+/// whether it changed anything
+fn apply_event(persona: &Persona, event: &MediaControlEvent) -> bool {
+    let mut playback = persona.playback.locked();
+    let progress = match &*playback {
+        MediaPlayback::Playing { progress } | MediaPlayback::Paused { progress } => *progress,
+        MediaPlayback::Stopped => None,
+    };
+    let playing = matches!(*playback, MediaPlayback::Playing { .. });
+    *playback = match event {
+        MediaControlEvent::Play => MediaPlayback::Playing { progress },
+        MediaControlEvent::Pause => MediaPlayback::Paused { progress },
+        MediaControlEvent::Toggle if playing => MediaPlayback::Paused { progress },
+        MediaControlEvent::Toggle => MediaPlayback::Playing { progress },
+        MediaControlEvent::Stop => MediaPlayback::Stopped,
+        _ => return false,
+    };
+    true
+}
+
+/// JSON body of POST /personas/{id}; fields left out keep their value
+#[derive(Deserialize, ToSchema)]
+pub struct PersonaUpdate {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    // Shown as the cover, e.g. an https:// or file:// URL
+    art_url: Option<String>,
+    length_ms: Option<u64>,
+    // Playing, Paused or Stopped
+    playback: Option<String>,
+    position_ms: Option<u64>,
+}
+
+/// `update` applied to what a persona currently shows
+fn updated(
+    persona: &Persona,
+    update: PersonaUpdate,
+) -> Result<(PublishedMetadata, MediaPlayback), String> {
+    let mut meta = persona.meta.locked().clone();
+    meta.title = update.title.or(meta.title);
+    meta.artist = update.artist.or(meta.artist);
+    meta.album = update.album.or(meta.album);
+    meta.cover_url = update.art_url.or(meta.cover_url);
+    meta.duration = update
+        .length_ms
+        .map(Duration::from_millis)
+        .or(meta.duration);
+
+    let (current, progress) = match &*persona.playback.locked() {
+        MediaPlayback::Playing { progress } => ("playing", *progress),
+        MediaPlayback::Paused { progress } => ("paused", *progress),
+        MediaPlayback::Stopped => ("stopped", None),
+    };
+    let progress = update
+        .position_ms
+        .map(|ms| MediaPosition(Duration::from_millis(ms)))
+        .or(progress);
+    let status = update.playback.map(|s| s.to_lowercase());
+    let playback = match status.as_deref().unwrap_or(current) {
+        "playing" => MediaPlayback::Playing { progress },
+        "paused" => MediaPlayback::Paused { progress },
+        "stopped" => MediaPlayback::Stopped,
+        other => {
+            return Err(format!(
+                "unknown playback {other:?}, expected Playing, Paused or Stopped"
+            ))
+        }
+    };
+    Ok((meta, playback))
+}
+
+/// Look up `id`, or answer 404
+fn persona<'a>(personas: &'a Personas, id: &str) -> Result<&'a Arc<Persona>, AppError> {
+    personas
+        .find(id)
+        .ok_or_else(|| AppError::not_found(format!("no persona {id:?}")))
+}
+
+/// GET /personas — every extra publisher and what it shows
+#[utoipa::path(
+    get,
+    path = "/personas",
+    tag = "Extras",
+    responses((status = 200, description = "Every persona", body = [PersonaState])),
+)]
+pub async fn list_personas(state: web::Data<AppState>) -> impl Responder {
+    let views: Vec<_> = state.personas.personas.iter().map(|p| p.view()).collect();
+    HttpResponse::Ok().json(views)
+}
+
+/// GET /personas/{id} — what one persona shows
+#[utoipa::path(
+    get,
+    path = "/personas/{id}",
+    tag = "Extras",
+    params(("id" = String, Path, description = "Persona id from MEDIA_CONTROL_PERSONAS")),
+    responses(
+        (status = 200, description = "The persona", body = PersonaState),
+        (status = 404, description = "No such persona", body = ErrorBody),
+    ),
+)]
+pub async fn get_persona(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let persona = persona(&state.personas, &id)?;
+    Ok(HttpResponse::Ok().json(persona.view()))
+}
+
+/// POST /personas/{id} — change what a persona shows
+///
+/// Fields left out keep their current value, so a track change and a
+/// play/pause can be sent separately.
+#[utoipa::path(
+    post,
+    path = "/personas/{id}",
+    tag = "Extras",
+    params(("id" = String, Path, description = "Persona id from MEDIA_CONTROL_PERSONAS")),
+    request_body = PersonaUpdate,
+    responses(
+        (status = 200, description = "What the persona now shows", body = PersonaState),
+        (status = 400, description = "Unknown playback state", body = ErrorBody),
+        (status = 404, description = "No such persona", body = ErrorBody),
+    ),
+)]
+pub async fn update_persona(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+    update: web::Json<PersonaUpdate>,
+) -> Result<HttpResponse, AppError> {
+    let persona = persona(&state.personas, &id)?;
+    let (meta, playback) = updated(persona, update.into_inner()).map_err(AppError::bad_request)?;
+    if get_dry_run_mode() {
+        return Ok(HttpResponse::Ok().json(persona.view_of(&meta, &playback)));
+    }
+    *persona.meta.locked() = meta;
+    *persona.playback.locked() = playback;
+    if state.personas.registered {
+        persona.publish();
+    }
+    Ok(HttpResponse::Ok().json(persona.view()))
+}
+
+/// DELETE /personas/{id} — clear a persona's track and stop it
+#[utoipa::path(
+    delete,
+    path = "/personas/{id}",
+    tag = "Extras",
+    params(("id" = String, Path, description = "Persona id from MEDIA_CONTROL_PERSONAS")),
+    responses(
+        (status = 200, description = "The cleared persona", body = PersonaState),
+        (status = 404, description = "No such persona", body = ErrorBody),
+    ),
+)]
+pub async fn clear_persona(
+    state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let persona = persona(&state.personas, &id)?;
+    if !get_dry_run_mode() {
+        *persona.meta.locked() = PublishedMetadata::default();
+        *persona.playback.locked() = MediaPlayback::Stopped;
+        if state.personas.registered {
+            persona.publish();
+        }
+    }
+    Ok(HttpResponse::Ok().json(persona.view()))
+}
@@ -0,0 +1,453 @@
+//! Discovery and selection of the external MPRIS players we control.
+
+use crate::config;
+use crate::error::MutexExt;
+use crate::selection::{self, Choice, Discovered, PlayerFilter, PlayerView};
+use crate::{metrics, registry};
+pub use media_controller::models::Candidate;
+use mpris::{LoopStatus, MetadataValue, PlaybackStatus, Player};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Identity of our own publisher, never a control target
+pub const OWN_IDENTITY: &str = "My Player";
+
+/// Player names in order of preference, from `MEDIA_CONTROL_PLAYER_PRIORITY`
+/// (e.g. `spotify,chromium,firefox`). The older single-name
+/// `MEDIA_CONTROL_PREFERRED_PLAYER` is still read when it isn't set, and the
+/// default is `chromium`. Names may be aliases.
+pub fn get_player_priority() -> Vec<String> {
+    let list = config::var("MEDIA_CONTROL_PLAYER_PRIORITY")
+        .or_else(|_| config::var("MEDIA_CONTROL_PREFERRED_PLAYER"))
+        .unwrap_or_default();
+    let names: Vec<String> = list
+        .split(',')
+        .map(|name| resolve_player_alias(name.trim()).to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        vec!["chromium".to_string()]
+    } else {
+        names
+    }
+}
+
+/// Other identities a priority entry stands for, from
+/// `MEDIA_CONTROL_PLAYER_GROUPS` (e.g. `chromium=chrome|brave,mpv=celluloid`).
+/// `chromium` also matches Chrome unless the variable redefines it.
+pub fn get_player_groups() -> HashMap<String, Vec<String>> {
+    let mut groups = HashMap::from([("chromium".to_string(), vec!["chrome".to_string()])]);
+    let configured = config::var("MEDIA_CONTROL_PLAYER_GROUPS").unwrap_or_default();
+    for (name, members) in configured.split(',').filter_map(|e| e.split_once('=')) {
+        let members: Vec<String> = members
+            .split('|')
+            .map(|m| m.trim().to_lowercase())
+            .filter(|m| !m.is_empty())
+            .collect();
+        let name = resolve_player_alias(name.trim()).to_lowercase();
+        if !name.is_empty() && !members.is_empty() {
+            groups.insert(name, members);
+        }
+    }
+    groups
+}
+
+/// Read the players discovery should leave alone, e.g. `kdeconnect,playerctld`
+///
+/// Entries go through the alias table and match a case-insensitive substring
+/// of the identity or the bus name.
+fn get_ignored_players() -> Vec<String> {
+    config::var("MEDIA_CONTROL_PLAYER_IGNORE")
+        .unwrap_or_default()
+        .split(',')
+        .map(|entry| resolve_player_alias(entry.trim()).to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// What keeps a player out of discovery: being our own publisher ("My
+/// Player" or a persona) or on the ignore list
+fn discovery_filters() -> [Box<dyn PlayerFilter>; 2] {
+    [
+        Box::new(selection::OwnPublisher),
+        Box::new(selection::Ignored(get_ignored_players())),
+    ]
+}
+
+/// The application behind an MPRIS bus name, lowercased and without the
+/// instance suffix, e.g. `firefox` for
+/// `org.mpris.MediaPlayer2.firefox.instance_1_53`.
+///
+/// Sandboxed players (Flatpak, Snap) and multi-process browsers register one
+/// name per instance, and Flatpak apps often use their reverse-DNS app ID,
+/// so `org.mpris.MediaPlayer2.io.github.celluloid_player.Celluloid` becomes
+/// `celluloid`.
+pub fn app_name(bus_name: &str) -> String {
+    let name = bus_name.strip_prefix(MPRIS_PREFIX).unwrap_or(bus_name);
+    let mut parts: Vec<&str> = name.split('.').collect();
+    while parts.len() > 1 && parts.last().is_some_and(|part| is_instance_suffix(part)) {
+        parts.pop();
+    }
+    let app = if parts.len() >= 3 {
+        parts[parts.len() - 1]
+    } else {
+        parts[0]
+    };
+    app.to_lowercase()
+}
+
+/// Whether one dot-separated part of a bus name only tells instances apart:
+/// `instance_1_53`, `instance12345`, a bare number, or empty
+fn is_instance_suffix(part: &str) -> bool {
+    part.is_empty()
+        || part.chars().all(|c| c.is_ascii_digit())
+        || part.strip_prefix("instance").is_some_and(|rest| {
+            rest.chars()
+                .all(|c| c.is_ascii_digit() || c == '_' || c == '-')
+        })
+}
+
+/// Read player aliases from env var, e.g. `tv=mpv,music=Spotify`
+fn get_player_aliases() -> HashMap<String, String> {
+    config::var("MEDIA_CONTROL_PLAYER_ALIASES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(alias, target)| (alias.trim().to_lowercase(), target.trim().to_string()))
+        .filter(|(alias, target)| !alias.is_empty() && !target.is_empty())
+        .collect()
+}
+
+/// Turn a player selector into the identity string to match on.
+///
+/// Aliases are matched case-insensitively; anything that isn't an alias is
+/// returned unchanged so plain identities keep working.
+pub fn resolve_player_alias(selector: &str) -> String {
+    get_player_aliases()
+        .remove(&selector.trim().to_lowercase())
+        .unwrap_or_else(|| selector.to_string())
+}
+
+/// Bus name prefix every MPRIS player registers under
+pub const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+/// Object path every MPRIS player exports
+pub const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+/// Timeout for direct D-Bus calls to players
+const DBUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Default seek jump when no per-player override matches
+const DEFAULT_SEEK_STEP: Duration = Duration::from_secs(30);
+
+/// Parse a step like `15`, `15s`, `2m` into a duration. `None` for
+/// anything else, including steps too long to represent.
+fn parse_step(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, scale) = if let Some(n) = value.strip_suffix('m') {
+        (n, 60)
+    } else {
+        (value.strip_suffix('s').unwrap_or(value), 1)
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .map(Duration::from_secs)
+}
+
+/// Parse an MPRIS loop mode: `None`, `Track` or `Playlist`, in any case
+pub fn parse_loop_status(value: &str) -> Option<LoopStatus> {
+    match value.to_lowercase().as_str() {
+        "none" => Some(LoopStatus::None),
+        "track" => Some(LoopStatus::Track),
+        "playlist" => Some(LoopStatus::Playlist),
+        _ => None,
+    }
+}
+
+/// Parse a track position like `83`, `1:23`, `1:02:03` or `83.5`
+pub fn parse_position(value: &str) -> Option<Duration> {
+    let mut seconds: f64 = 0.0;
+    for (i, part) in value.trim().split(':').enumerate() {
+        // Only the last part may have a fraction, and at most hours:minutes:seconds
+        if i > 2 || (i > 0 && seconds != seconds.trunc()) {
+            return None;
+        }
+        let part: f64 = part.parse().ok()?;
+        if part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Seek step for a player, from `MEDIA_CONTROL_SEEK_STEPS` (e.g. `spotify=15s,mpv=60s`)
+///
+/// Keys may be aliases and match identities as case-insensitive substrings.
+pub fn seek_step_for(identity: &str) -> Duration {
+    let identity = identity.to_lowercase();
+    config::var("MEDIA_CONTROL_SEEK_STEPS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| {
+            let key = resolve_player_alias(key.trim()).to_lowercase();
+            !key.is_empty() && identity.contains(&key)
+        })
+        .and_then(|(key, step)| {
+            let parsed = parse_step(step);
+            if parsed.is_none() {
+                warn!("Ignoring seek step {step:?} for {key:?} in MEDIA_CONTROL_SEEK_STEPS");
+            }
+            parsed
+        })
+        .unwrap_or(DEFAULT_SEEK_STEP)
+}
+
+/// The discovery entry for `player`
+pub fn candidate(player: &Player) -> Candidate {
+    Candidate {
+        identity: player.identity().to_string(),
+        bus_name: player.bus_name().to_string(),
+    }
+}
+
+/// A player dropped by a discovery filter
+#[derive(Clone, Serialize)]
+pub struct Excluded {
+    #[serde(flatten)]
+    pub player: Candidate,
+    pub filter: String,
+}
+
+/// Why `find_player()` picked what it picked
+#[derive(Clone, Serialize)]
+pub struct SelectionTrace {
+    // Preferred identity substrings in order, after alias resolution
+    pub priority: Vec<String>,
+    // Every MPRIS player found on the bus
+    pub candidates: Vec<Candidate>,
+    // Players removed before choosing, with the filter that removed them
+    pub excluded: Vec<Excluded>,
+    // Which rule chose the winner ("pinned", "focused_window", "playing",
+    // "preferred", "player_group", "recent", "first_available")
+    pub rule: Option<&'static str>,
+    // Identity of the winner
+    pub selected: Option<String>,
+    // Human-readable summary
+    pub reason: String,
+}
+
+/// List every external player, leaving out our own publisher and ignored
+/// players
+pub fn external_players() -> Vec<Rc<Player>> {
+    let filters = discovery_filters();
+    registry::players()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| filters.iter().all(|f| f.rejects(&**p).is_none()))
+        .collect()
+}
+
+/// The external players that are playing, but not the one with bus name `except`
+pub fn playing_players(except: Option<&str>) -> Vec<Rc<Player>> {
+    external_players()
+        .into_iter()
+        .filter(|p| Some(p.bus_name()) != except)
+        .filter(|p| matches!(p.get_playback_status(), Ok(PlaybackStatus::Playing)))
+        .collect()
+}
+
+/// Choose the player to control, recording how the decision was made
+///
+/// `pinned` holds the bus name chosen with `/players/select`. It wins over every
+/// other rule while that player exists, and is cleared once it has gone away.
+pub fn select_player(pinned: &Mutex<Option<String>>) -> (Option<Rc<Player>>, SelectionTrace) {
+    let priority = get_player_priority();
+    let mut trace = SelectionTrace {
+        priority: priority.clone(),
+        candidates: Vec::new(),
+        excluded: Vec::new(),
+        rule: None,
+        selected: None,
+        reason: String::new(),
+    };
+
+    let all = match registry::players() {
+        Ok(all) => all,
+        Err(reason) => {
+            trace.reason = reason;
+            return (None, trace);
+        }
+    };
+    trace.candidates = all.iter().map(|p| candidate(p)).collect();
+
+    // Filter out our own publishers ("My Player" and personas) and ignored players
+    let filters = discovery_filters();
+    let mut external_players = Vec::new();
+    for player in all {
+        match filters.iter().find_map(|f| f.rejects(&*player)) {
+            Some(filter) => trace.excluded.push(Excluded {
+                player: candidate(&player),
+                filter,
+            }),
+            None => external_players.push(Discovered::new(player)),
+        }
+    }
+
+    if external_players.is_empty() {
+        trace.reason = "No external MPRIS players found".to_string();
+        return (None, trace);
+    }
+
+    // Top priority: a player pinned with /players/select, until it disappears
+    let mut chosen = None;
+    {
+        let mut pin = pinned.locked();
+        if let Some(bus_name) = pin.as_deref() {
+            chosen = external_players
+                .iter()
+                .position(|p| p.bus_name() == bus_name)
+                .map(|index| Choice {
+                    index,
+                    rule: "pinned",
+                    matched: None,
+                });
+            if chosen.is_none() {
+                info!("Pinned player {bus_name} has gone away, unpinning");
+                *pin = None;
+            }
+        }
+    }
+
+    // Then the configured strategies in order
+    if chosen.is_none() {
+        let views: Vec<&dyn PlayerView> = external_players
+            .iter()
+            .map(|p| p as &dyn PlayerView)
+            .collect();
+        chosen = selection::from_env()
+            .iter()
+            .find_map(|strategy| strategy.choose(&views));
+    }
+
+    // Final fallback: Use the first available player
+    let Choice {
+        index,
+        rule,
+        matched,
+    } = chosen.unwrap_or(Choice {
+        index: 0,
+        rule: "first_available",
+        matched: None,
+    });
+    let player = external_players.swap_remove(index).player;
+
+    trace.reason = match rule {
+        "pinned" => format!("Using pinned player: {}", player.identity()),
+        "focused_window" => format!("Following focused window: {}", player.identity()),
+        "playing" => format!("Using playing player: {}", player.identity()),
+        "recent" => format!("Using most recently active player: {}", player.identity()),
+        "preferred" => format!(
+            "Found preferred player '{}': {}",
+            matched.as_deref().unwrap_or_default(),
+            player.identity()
+        ),
+        "player_group" => format!(
+            "Found player from the '{}' group: {}",
+            matched.as_deref().unwrap_or_default(),
+            player.identity()
+        ),
+        _ => format!(
+            "Using fallback player (none of '{}' found): {}",
+            priority.join("', '"),
+            player.identity()
+        ),
+    };
+    trace.rule = Some(rule);
+    trace.selected = Some(player.identity().to_string());
+    (Some(player), trace)
+}
+
+/// Helper: find the best MPRIS player to control, prioritizing the preferred player.
+pub fn find_player(pinned: &Mutex<Option<String>>) -> Option<Rc<Player>> {
+    let (player, trace) = select_player(pinned);
+    info!(
+        rule = trace.rule,
+        player = trace.selected.as_deref(),
+        candidates = trace.candidates.len(),
+        "{}",
+        trace.reason
+    );
+    if let (Some(p), Some(rule)) = (&player, trace.rule) {
+        metrics::record_selection(p.identity(), rule);
+    }
+    player
+}
+
+/// Helper: find an external player by selector.
+///
+/// The selector is resolved through the alias table, then matched against the
+/// full bus name first and otherwise as a case-insensitive substring of the
+/// identity or of the bus name's application, preferring a playing instance.
+pub fn find_player_by_selector(selector: &str) -> Option<Rc<Player>> {
+    let wanted = resolve_player_alias(selector);
+    let wanted_lower = wanted.to_lowercase();
+    let mut external_players: Vec<Discovered> = external_players()
+        .into_iter()
+        .map(Discovered::new)
+        .collect();
+
+    if let Some(idx) = external_players.iter().position(|p| p.bus_name() == wanted) {
+        return Some(external_players.swap_remove(idx).player);
+    }
+    let views: Vec<&dyn PlayerView> = external_players
+        .iter()
+        .map(|p| p as &dyn PlayerView)
+        .collect();
+    let matches: Vec<usize> = (0..views.len())
+        .filter(|&idx| selection::names_player(views[idx], &wanted_lower))
+        .collect();
+    let idx = selection::most_active(&views, &matches)?;
+    Some(external_players.swap_remove(idx).player)
+}
+
+/// Ask a player to open and play a URI (MPRIS `OpenUri`).
+///
+/// The `mpris` crate doesn't expose this method, so it's called directly.
+pub fn open_uri(bus_name: &str, uri: &str) -> Result<(), String> {
+    let conn = dbus::blocking::Connection::new_session().map_err(|e| e.to_string())?;
+    let proxy = conn.with_proxy(bus_name, MPRIS_PATH, DBUS_TIMEOUT);
+    proxy
+        .method_call::<(), _, _, _>("org.mpris.MediaPlayer2.Player", "OpenUri", (uri,))
+        .map_err(|e| e.to_string())
+}
+
+/// Convert an MPRIS metadata value into plain JSON
+pub fn metadata_value_to_json(value: &MetadataValue) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        MetadataValue::String(s) => Json::from(s.as_str()),
+        MetadataValue::I16(n) => Json::from(*n),
+        MetadataValue::I32(n) => Json::from(*n),
+        MetadataValue::I64(n) => Json::from(*n),
+        MetadataValue::U8(n) => Json::from(*n),
+        MetadataValue::U16(n) => Json::from(*n),
+        MetadataValue::U32(n) => Json::from(*n),
+        MetadataValue::U64(n) => Json::from(*n),
+        MetadataValue::F64(n) => Json::from(*n),
+        MetadataValue::Bool(b) => Json::from(*b),
+        MetadataValue::Array(items) => items.iter().map(metadata_value_to_json).collect(),
+        MetadataValue::Map(map) => Json::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), metadata_value_to_json(v)))
+                .collect(),
+        ),
+        MetadataValue::Unsupported => Json::Null,
+    }
+}
@@ -0,0 +1,320 @@
+//! Position alarms ("wake me before the drop").
+//!
+//! `POST /position_alarm` with `{"at_seconds": 215, "action": "volume_up"}`
+//! runs the action once the current track reaches that position, or with
+//! `{"at_seconds": 215, "webhook": "https://..."}` POSTs a JSON event to the
+//! URL instead. An alarm belongs to the track playing when it was set and is
+//! dropped if the track changes or the player goes away first. Up to
+//! [`MAX_ALARMS`] can be set at once; `GET /position_alarm` lists them and
+//! `DELETE /position_alarm/{id}` cancels one.
+//!
+//! A scoped token can only set alarms whose action its scopes cover, so a
+//! `playback` token can't raise the volume this way. Webhook alarms have the
+//! server call any URL, so they need the main API token.
+
+use crate::actions::{self, Action, Target};
+use crate::auth::{self, Scope};
+use crate::diagnostics::redact_url;
+use crate::error::{AppError, MutexExt};
+use crate::player::{self, find_player};
+use crate::{get_dry_run_mode, template, webhooks, AppState};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use media_controller::models::{ErrorBody, PositionAlarm};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// How many alarms can be waiting at once
+pub const MAX_ALARMS: usize = 8;
+/// How often each alarm checks the position
+const TICK: Duration = Duration::from_millis(500);
+
+/// Body of POST /position_alarm
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PositionAlarmRequest {
+    // Position in the current track at which to fire
+    at_seconds: u64,
+    // Command to run once the position is reached
+    action: Option<Action>,
+    // URL to POST a `position_alarm` event to instead
+    webhook: Option<String>,
+}
+
+/// What an alarm does when it goes off
+#[derive(Clone)]
+enum Fire {
+    Action(Action),
+    Webhook(String),
+}
+
+#[derive(Clone)]
+struct Armed {
+    id: u64,
+    identity: String,
+    title: Option<String>,
+    // Identifies the track the alarm was set on
+    track: Option<String>,
+    at: Duration,
+    fire: Fire,
+}
+
+impl Armed {
+    fn view(&self) -> PositionAlarm {
+        let (action, webhook) = match &self.fire {
+            Fire::Action(action) => (Some(action.name()), None),
+            // The URL usually carries a secret; readers get the host only
+            Fire::Webhook(url) => (None, redact_url(url)),
+        };
+        PositionAlarm {
+            id: self.id,
+            player: self.identity.clone(),
+            title: self.title.clone(),
+            at_seconds: self.at.as_secs(),
+            action,
+            webhook,
+        }
+    }
+}
+
+/// The alarms waiting to go off
+#[derive(Default)]
+pub struct PositionAlarms {
+    // The last id handed out, and the alarms
+    inner: Mutex<(u64, Vec<Armed>)>,
+}
+
+impl PositionAlarms {
+    fn list(&self) -> Vec<PositionAlarm> {
+        self.inner.locked().1.iter().map(Armed::view).collect()
+    }
+
+    fn contains(&self, id: u64) -> bool {
+        self.inner.locked().1.iter().any(|armed| armed.id == id)
+    }
+
+    fn remove(&self, id: u64) -> Option<Armed> {
+        let mut inner = self.inner.locked();
+        let index = inner.1.iter().position(|armed| armed.id == id)?;
+        Some(inner.1.remove(index))
+    }
+}
+
+/// Something identifying the current track, to notice when it changes
+fn track_key(metadata: &mpris::Metadata) -> Option<String> {
+    metadata
+        .track_id()
+        .map(|id| id.to_string())
+        .or_else(|| metadata.url().map(str::to_string))
+        .or_else(|| metadata.title().map(str::to_string))
+}
+
+/// POST /position_alarm — run an action or call a webhook when the current track reaches a position
+///
+/// The alarm is tied to the track playing when it was set: it is dropped if
+/// the track changes or the player goes away before the position is reached.
+#[utoipa::path(
+    post,
+    path = "/position_alarm",
+    tag = "Playback",
+    request_body = PositionAlarmRequest,
+    responses(
+        (status = 200, description = "The alarm is set", body = PositionAlarm),
+        (status = 400, description = "Not exactly one of action and webhook, or the position is past the end of the track", body = ErrorBody),
+        (status = 403, description = "The token's scopes don't cover the action, or a webhook was asked for without the main token", body = ErrorBody),
+        (status = 404, description = "No external player", body = ErrorBody),
+        (status = 409, description = "Too many alarms are set", body = ErrorBody),
+    ),
+)]
+pub async fn set_position_alarm(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    alarms: web::Data<PositionAlarms>,
+    body: web::Json<PositionAlarmRequest>,
+) -> Result<HttpResponse, AppError> {
+    let PositionAlarmRequest {
+        at_seconds,
+        action,
+        webhook,
+    } = body.into_inner();
+    let fire = match (action, webhook) {
+        (Some(action), None) => Fire::Action(action),
+        (None, Some(url)) if url.starts_with("http://") || url.starts_with("https://") => {
+            Fire::Webhook(url)
+        }
+        (None, Some(_)) => return Err(AppError::bad_request("webhook must be an http(s) URL")),
+        _ => return Err(AppError::bad_request("expected either action or webhook")),
+    };
+    let action = match &fire {
+        Fire::Action(action) => Some(*action),
+        Fire::Webhook(_) => None,
+    };
+    if !Scope::of(&req).covers(auth::position_alarm_area(action)) {
+        let why = match action {
+            Some(action) => format!("This token can't set an alarm to {}", action.name()),
+            None => "Only the main API token can set webhook alarms".to_string(),
+        };
+        return Err(AppError::new(StatusCode::FORBIDDEN, why));
+    }
+    let at = Duration::from_secs(at_seconds);
+    let armed = {
+        let state = state.clone();
+        web::block(move || {
+            let player = find_player(&state.pinned)
+                .ok_or_else(|| AppError::not_found("no external player found"))?;
+            let metadata = player.get_metadata().ok();
+            if let Some(length) = metadata.as_ref().and_then(|m| m.length()) {
+                if at >= length {
+                    return Err(AppError::bad_request(format!(
+                        "the track is only {} long",
+                        template::format_duration(length)
+                    )));
+                }
+            }
+            Ok(Armed {
+                id: 0,
+                identity: player.identity().to_string(),
+                title: metadata
+                    .as_ref()
+                    .and_then(|m| m.title().map(str::to_string)),
+                track: metadata.as_ref().and_then(track_key),
+                at,
+                fire,
+            })
+        })
+        .await
+        .map_err(|e| AppError::internal(format!("alarm task failed: {e}")))??
+    };
+    let armed = {
+        let mut inner = alarms.inner.locked();
+        if inner.1.len() >= MAX_ALARMS {
+            return Err(AppError::conflict(format!(
+                "{MAX_ALARMS} alarms are already set, cancel one first"
+            )));
+        }
+        inner.0 += 1;
+        let armed = Armed {
+            id: inner.0,
+            ..armed
+        };
+        inner.1.push(armed.clone());
+        armed
+    };
+    info!(
+        "Position alarm {} set for {at_seconds}s on {}",
+        armed.id, armed.identity
+    );
+
+    let view = armed.view();
+    let (state, alarms) = (state.into_inner(), alarms.into_inner());
+    thread::spawn(move || watch(&state, &alarms, armed));
+    Ok(HttpResponse::Ok().json(view))
+}
+
+/// GET /position_alarm — the alarms waiting to go off
+#[utoipa::path(
+    get,
+    path = "/position_alarm",
+    tag = "Playback",
+    responses((status = 200, description = "The alarms", body = Vec<PositionAlarm>)),
+)]
+pub async fn get_position_alarms(alarms: web::Data<PositionAlarms>) -> HttpResponse {
+    HttpResponse::Ok().json(alarms.list())
+}
+
+/// DELETE /position_alarm/{id} — cancel an alarm
+#[utoipa::path(
+    delete,
+    path = "/position_alarm/{id}",
+    tag = "Playback",
+    params(("id" = u64, Path, description = "The id the alarm was given when set")),
+    responses(
+        (status = 200, description = "The cancelled alarm", body = PositionAlarm),
+        (status = 404, description = "No such alarm", body = ErrorBody),
+    ),
+)]
+pub async fn cancel_position_alarm(
+    alarms: web::Data<PositionAlarms>,
+    path: web::Path<u64>,
+) -> Result<HttpResponse, AppError> {
+    let id = path.into_inner();
+    let armed = alarms
+        .remove(id)
+        .ok_or_else(|| AppError::not_found(format!("no position alarm {id}")))?;
+    info!("Position alarm {id} cancelled");
+    Ok(HttpResponse::Ok().json(armed.view()))
+}
+
+/// Poll the player until it reaches the alarm's position, then fire it,
+/// unless it's cancelled, the track changes or the player goes away first
+fn watch(state: &AppState, alarms: &PositionAlarms, armed: Armed) {
+    let id = armed.id;
+    let identity = armed.identity.as_str();
+    let position = loop {
+        thread::sleep(TICK);
+        if !alarms.contains(id) {
+            return;
+        }
+        let (player, _) = player::select_player(&state.pinned);
+        let Some(player) = player.filter(|p| p.identity() == identity) else {
+            info!("Position alarm {id} dropped: {identity} is no longer the controlled player");
+            alarms.remove(id);
+            return;
+        };
+        if player.get_metadata().ok().and_then(|m| track_key(&m)) != armed.track {
+            info!("Position alarm {id} dropped: track changed on {identity}");
+            alarms.remove(id);
+            return;
+        }
+        match player.get_position() {
+            Ok(pos) if pos >= armed.at => break pos,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Position alarm {id} dropped, couldn't read position: {e}");
+                alarms.remove(id);
+                return;
+            }
+        }
+    };
+    // Cancelled at the last moment
+    if alarms.remove(id).is_none() {
+        return;
+    }
+
+    match armed.fire {
+        Fire::Action(action) => {
+            let result = if get_dry_run_mode() {
+                actions::plan(state, &Target::Auto, action)
+            } else {
+                actions::run(state, action)
+            };
+            match result {
+                Ok(msg) => info!("Position alarm {id} fired at {position:?}: {msg}"),
+                Err(e) => error!("Position alarm {id} fired at {position:?} but failed: {e}"),
+            }
+        }
+        Fire::Webhook(url) => {
+            let event = json!({
+                "type": "position_alarm",
+                "timestamp_ms": webhooks::timestamp_ms(),
+                "player": identity,
+                "title": armed.title,
+                "at_seconds": armed.at.as_secs(),
+                "position_ms": position.as_millis() as u64,
+            });
+            if get_dry_run_mode() {
+                info!("Dry run: position alarm {id} would call a webhook");
+                return;
+            }
+            match webhooks::post(&url, &event) {
+                Ok(()) => info!("Position alarm {id} fired at {position:?}, webhook called"),
+                Err(e) => error!("Position alarm {id} fired, but the webhook failed: {e}"),
+            }
+        }
+    }
+}
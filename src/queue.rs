@@ -0,0 +1,187 @@
+//! The controlled player's queue, from the MPRIS `TrackList` interface.
+//!
+//! `GET /queue` lists the tracks a player exposes, marking the current one,
+//! so a remote can show what's coming up. `POST /queue/goto/{trackid}` jumps
+//! to a track and `DELETE /queue/{trackid}` removes one. Track ids are the
+//! player's object paths, e.g. `/org/mpris/MediaPlayer2/Track/3`, and may be
+//! given with or without the leading slash.
+//!
+//! Only some players have a track list, many only list a window around the
+//! current track, and fewer still allow removing tracks.
+
+use crate::actions::Target;
+use crate::error::AppError;
+use crate::{get_dry_run_mode, AppState};
+use actix_web::{web, HttpResponse, ResponseError};
+use media_controller::models::{ErrorBody, Queue, QueueEntry};
+use mpris::{Player, TrackID};
+use serde::Deserialize;
+use std::rc::Rc;
+use utoipa::IntoParams;
+
+/// Query options of the queue endpoints
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueueOptions {
+    // Player selector as for /players/{id}/...; the auto-selected player if missing
+    player: Option<String>,
+}
+
+impl QueueOptions {
+    fn target(&self) -> Target {
+        self.player.clone().map_or(Target::Auto, Target::Player)
+    }
+}
+
+/// The player a queue request is about, if it has a track list
+fn track_list_player(state: &AppState, target: &Target) -> Result<Rc<Player>, AppError> {
+    let player = target
+        .find(state)
+        .ok_or_else(|| AppError::not_found("no matching player found"))?;
+    if !player.supports_track_lists() {
+        return Err(AppError::bad_request(format!(
+            "{} doesn't expose a track list",
+            player.identity()
+        )));
+    }
+    Ok(player)
+}
+
+/// Parse a track id from the URL, adding the slash the route swallows
+fn track_id(raw: &str) -> Result<TrackID, AppError> {
+    let path = format!("/{}", raw.trim_start_matches('/'));
+    TrackID::new(path).map_err(|e| AppError::bad_request(format!("bad track id: {e}")))
+}
+
+/// Read the whole queue of `player`
+fn read_queue(player: &Player) -> Result<Queue, String> {
+    let ids = player.get_track_list().map_err(|e| e.to_string())?;
+    let metadata = player
+        .get_tracks_metadata(ids.ids())
+        .map_err(|e| e.to_string())?;
+    let current = player.get_metadata().ok().and_then(|m| m.track_id());
+    let tracks = metadata
+        .iter()
+        .map(|m| QueueEntry {
+            id: m.track_id().map(|id| id.to_string()).unwrap_or_default(),
+            title: m.title().map(str::to_string),
+            artist: m.artists().map(|a| a.join(", ")),
+            album: m.album_name().map(str::to_string),
+            length_ms: m.length().map(|d| d.as_millis() as u64),
+            current: m.track_id().is_some() && m.track_id() == current,
+        })
+        .collect();
+    Ok(Queue {
+        player: player.identity().to_string(),
+        can_edit: player.checked_can_edit_tracks(),
+        tracks,
+    })
+}
+
+/// GET /queue — the tracks in the player's track list
+#[utoipa::path(
+    get,
+    path = "/queue",
+    tag = "Queue",
+    params(QueueOptions),
+    responses(
+        (status = 200, description = "The track list", body = Queue),
+        (status = 400, description = "The player has no track list", body = ErrorBody),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+pub async fn get_queue(
+    state: web::Data<AppState>,
+    opts: web::Query<QueueOptions>,
+) -> Result<HttpResponse, AppError> {
+    let target = opts.target();
+    if let Some(demo) = state.demo.as_ref().filter(|_| target.is_demo(&state)) {
+        return Ok(HttpResponse::Ok().json(Queue {
+            player: crate::demo::IDENTITY.to_string(),
+            can_edit: false,
+            tracks: demo.queue(),
+        }));
+    }
+    let player = track_list_player(&state, &target)?;
+    Ok(match read_queue(&player) {
+        Ok(queue) => HttpResponse::Ok().json(queue),
+        Err(e) => AppError::internal(format!("couldn't read the queue: {e}")).error_response(),
+    })
+}
+
+/// POST /queue/goto/{trackid} — start playing a track from the queue
+#[utoipa::path(
+    post,
+    path = "/queue/goto/{trackid}",
+    tag = "Queue",
+    params(("trackid" = String, Path, description = "Track id from GET /queue"), QueueOptions),
+    responses(
+        (status = 200, description = "Playing the track", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such player or track", body = ErrorBody),
+    ),
+)]
+pub async fn goto_track(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    opts: web::Query<QueueOptions>,
+) -> Result<HttpResponse, AppError> {
+    let id = track_id(&path)?;
+    let target = opts.target();
+    if let Some(demo) = state.demo.as_ref().filter(|_| target.is_demo(&state)) {
+        if !demo.has_track(id.as_str()) {
+            return Err(AppError::not_found(format!("no track {id} in the queue")));
+        }
+        if get_dry_run_mode() {
+            return Ok(HttpResponse::Ok().body(format!("would go to {id}")));
+        }
+        demo.go_to(&state, id.as_str());
+        return Ok(HttpResponse::Ok().body(format!("went to {id}")));
+    }
+    let player = track_list_player(&state, &target)?;
+    if get_dry_run_mode() {
+        return Ok(HttpResponse::Ok().body(format!("would go to {id} on {}", player.identity())));
+    }
+    Ok(match player.go_to(&id) {
+        Ok(()) => HttpResponse::Ok().body(format!("went to {id} on {}", player.identity())),
+        Err(e) => AppError::internal(format!("couldn't go to {id}: {e}")).error_response(),
+    })
+}
+
+/// DELETE /queue/{trackid} — remove a track from the queue
+#[utoipa::path(
+    delete,
+    path = "/queue/{trackid}",
+    tag = "Queue",
+    params(("trackid" = String, Path, description = "Track id from GET /queue"), QueueOptions),
+    responses(
+        (status = 200, description = "The track was removed", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such player or track", body = ErrorBody),
+    ),
+)]
+pub async fn remove_track(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    opts: web::Query<QueueOptions>,
+) -> Result<HttpResponse, AppError> {
+    let id = track_id(&path)?;
+    let target = opts.target();
+    if target.is_demo(&state) {
+        return Err(AppError::bad_request(
+            "the demo player's queue can't be edited",
+        ));
+    }
+    let player = track_list_player(&state, &target)?;
+    if !player.checked_can_edit_tracks() {
+        return Err(AppError::bad_request(format!(
+            "{} doesn't allow editing its track list",
+            player.identity()
+        )));
+    }
+    if get_dry_run_mode() {
+        return Ok(HttpResponse::Ok().body(format!("would remove {id} from {}", player.identity())));
+    }
+    Ok(match player.remove_track(&id) {
+        Ok(()) => HttpResponse::Ok().body(format!("removed {id} from {}", player.identity())),
+        Err(e) => AppError::internal(format!("couldn't remove {id}: {e}")).error_response(),
+    })
+}
@@ -0,0 +1,194 @@
+//! Per-client rate limiting and lockout after failed logins.
+//!
+//! `MEDIA_CONTROL_RATE_LIMIT` caps how many requests a client IP may make
+//! per minute, allowing bursts of `MEDIA_CONTROL_RATE_LIMIT_BURST` (default
+//! 10) on top. `MEDIA_CONTROL_AUTH_MAX_FAILURES` locks an IP out for
+//! `MEDIA_CONTROL_AUTH_LOCKOUT_MINUTES` (default 15) after that many wrong
+//! tokens in a row. Both answer 429 with a `Retry-After` header and are off
+//! unless set. Requests over the Unix socket have no IP and are never
+//! limited, and neither are the health probes.
+
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{Error, ResponseError};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Default for `MEDIA_CONTROL_RATE_LIMIT_BURST`
+const DEFAULT_BURST: f64 = 10.0;
+/// Default for `MEDIA_CONTROL_AUTH_LOCKOUT_MINUTES`
+const DEFAULT_LOCKOUT_MINUTES: f64 = 15.0;
+/// Idle clients are forgotten once this many are tracked
+const MAX_CLIENTS: usize = 1024;
+
+/// What we know about one client IP
+struct Client {
+    // Requests it may still make right away
+    tokens: f64,
+    refilled: Instant,
+    // Wrong tokens in a row, and when the last one came
+    failures: u32,
+    failed: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Client {
+    fn new(now: Instant, burst: f64) -> Self {
+        Client {
+            tokens: burst,
+            refilled: now,
+            failures: 0,
+            failed: now,
+            locked_until: None,
+        }
+    }
+}
+
+fn clients() -> &'static Mutex<HashMap<IpAddr, Client>> {
+    static CLIENTS: OnceLock<Mutex<HashMap<IpAddr, Client>>> = OnceLock::new();
+    CLIENTS.get_or_init(Mutex::default)
+}
+
+fn number(name: &str) -> Option<f64> {
+    config::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|n| *n > 0.0)
+}
+
+/// Requests per minute, if limited
+fn rate() -> Option<f64> {
+    number("MEDIA_CONTROL_RATE_LIMIT")
+}
+
+fn burst() -> f64 {
+    number("MEDIA_CONTROL_RATE_LIMIT_BURST").unwrap_or(DEFAULT_BURST)
+}
+
+/// Failed attempts allowed before a lockout, if enabled
+fn max_failures() -> Option<u32> {
+    config::var("MEDIA_CONTROL_AUTH_MAX_FAILURES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+}
+
+fn lockout() -> Duration {
+    let minutes = number("MEDIA_CONTROL_AUTH_LOCKOUT_MINUTES").unwrap_or(DEFAULT_LOCKOUT_MINUTES);
+    Duration::try_from_secs_f64(minutes * 60.0).unwrap_or_default()
+}
+
+/// Drop clients that have nothing left to remember
+fn forget_idle(clients: &mut HashMap<IpAddr, Client>, now: Instant, burst: f64) {
+    let lockout = lockout();
+    clients.retain(|_, c| {
+        c.locked_until.is_some_and(|until| until > now)
+            || (c.failures > 0 && now.duration_since(c.failed) < lockout)
+            || c.tokens < burst
+    });
+}
+
+/// How long `ip` must wait before its next request, if it must
+pub fn check(ip: IpAddr) -> Option<Duration> {
+    let rate = rate();
+    let lockouts = max_failures().is_some();
+    if rate.is_none() && !lockouts {
+        return None;
+    }
+    let now = Instant::now();
+    let burst = burst();
+    let mut clients = clients().locked();
+    if clients.len() >= MAX_CLIENTS {
+        forget_idle(&mut clients, now, burst);
+    }
+    let client = clients.entry(ip).or_insert_with(|| Client::new(now, burst));
+
+    if let Some(until) = client.locked_until {
+        if until > now && lockouts {
+            return Some(until - now);
+        }
+        client.locked_until = None;
+        client.failures = 0;
+    }
+    let per_second = rate? / 60.0;
+    let elapsed = now.duration_since(client.refilled).as_secs_f64();
+    client.tokens = (client.tokens + elapsed * per_second).min(burst);
+    client.refilled = now;
+    if client.tokens >= 1.0 {
+        client.tokens -= 1.0;
+        None
+    } else {
+        Some(Duration::from_secs_f64((1.0 - client.tokens) / per_second))
+    }
+}
+
+/// Count a wrong token from `ip`, locking it out once there are too many
+pub fn record_failure(ip: Option<IpAddr>) {
+    let (Some(ip), Some(max)) = (ip, max_failures()) else {
+        return;
+    };
+    let now = Instant::now();
+    let lockout = lockout();
+    let mut clients = clients().locked();
+    let client = clients
+        .entry(ip)
+        .or_insert_with(|| Client::new(now, burst()));
+    // Failures long ago don't add up to a lockout
+    if now.duration_since(client.failed) >= lockout {
+        client.failures = 0;
+    }
+    client.failures += 1;
+    client.failed = now;
+    if client.failures >= max {
+        client.locked_until = Some(now + lockout);
+        warn!(
+            "Locked out {ip} for {} seconds after {} failed logins",
+            lockout.as_secs(),
+            client.failures
+        );
+    }
+}
+
+/// Forget earlier failures once `ip` gets a token right
+pub fn record_success(ip: Option<IpAddr>) {
+    let Some(ip) = ip else {
+        return;
+    };
+    if let Some(client) = clients().locked().get_mut(&ip) {
+        client.failures = 0;
+    }
+}
+
+/// Middleware turning away clients that are over their limit or locked out
+pub async fn rate_limit_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limited = req
+        .peer_addr()
+        .filter(|_| !crate::auth::is_public_route(&req))
+        .and_then(|addr| check(addr.ip()));
+    match limited {
+        Some(wait) => {
+            let mut response = AppError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many requests, try again later",
+            )
+            .error_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(wait.as_secs().max(1)),
+            );
+            Ok(req.into_response(response))
+        }
+        None => next.call(req).await,
+    }
+}
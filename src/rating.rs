@@ -0,0 +1,123 @@
+//! Rating and loving the current track.
+//!
+//! `POST /rate` with `{"rating": 0.8}` writes the rating into the controlled
+//! player's `xesam:userRating`. MPRIS has no call for that, so it's set on
+//! the `Metadata` property, which most players refuse; the reply says
+//! whether the player took it. `{"love": true}` loves the track on Last.fm
+//! and ListenBrainz when scrobbling is configured (see [`crate::scrobble`]),
+//! and `false` unloves it. Both can be sent at once.
+
+use crate::actions::Target;
+use crate::error::AppError;
+use crate::scrobble::Scrobbler;
+use crate::{get_dry_run_mode, AppState};
+use actix_web::{web, HttpResponse};
+use dbus::arg::{PropMap, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::blocking::Connection;
+use media_controller::models::{ErrorBody, Rated};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::info;
+use utoipa::ToSchema;
+
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const PROPERTY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Body of POST /rate
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RateRequest {
+    // Rating from 0 to 1, written to the player's xesam:userRating
+    rating: Option<f64>,
+    // Love (or with false, unlove) the track on the scrobbling services
+    love: Option<bool>,
+}
+
+/// Write `rating` into the metadata of the player at `bus_name`
+fn write_rating(bus_name: &str, rating: f64) -> Result<(), dbus::Error> {
+    let conn = Connection::new_session()?;
+    let proxy = conn.with_proxy(bus_name, MPRIS_PATH, PROPERTY_TIMEOUT);
+    let mut metadata: PropMap = proxy.get(PLAYER_INTERFACE, "Metadata")?;
+    metadata.insert("xesam:userRating".into(), Variant(Box::new(rating)));
+    proxy.set(PLAYER_INTERFACE, "Metadata", metadata)
+}
+
+/// POST /rate — rate or love the current track
+#[utoipa::path(
+    post,
+    path = "/rate",
+    tag = "Playback",
+    request_body = RateRequest,
+    responses(
+        (status = 200, description = "What was rated, and where", body = Rated),
+        (status = 400, description = "Neither rating nor love, a rating outside 0 to 1, or nowhere to send it", body = ErrorBody),
+        (status = 409, description = "Nothing is playing", body = ErrorBody),
+    ),
+)]
+pub async fn rate(
+    state: web::Data<AppState>,
+    scrobbler: web::Data<Scrobbler>,
+    body: web::Json<RateRequest>,
+) -> Result<HttpResponse, AppError> {
+    let RateRequest { rating, love } = body.into_inner();
+    if rating.is_none() && love.is_none() {
+        return Err(AppError::bad_request("expected rating, love or both"));
+    }
+    if rating.is_some_and(|rating| !(0.0..=1.0).contains(&rating)) {
+        return Err(AppError::bad_request("rating must be between 0 and 1"));
+    }
+    let now = state.live.borrow().clone();
+    if now.title.is_none() {
+        return Err(AppError::conflict("Nothing is playing"));
+    }
+    let dry_run = get_dry_run_mode();
+
+    let mut problems = Vec::new();
+    let player_rated = match rating {
+        Some(rating) => {
+            let written = web::block(move || {
+                let player = Target::Auto
+                    .find(&state)
+                    .ok_or_else(|| "no player is being controlled".to_string())?;
+                let identity = player.identity().to_string();
+                if dry_run {
+                    info!("Dry run: would rate {identity}'s track {rating}");
+                    return Ok(());
+                }
+                write_rating(player.bus_name(), rating).map_err(|e| {
+                    format!(
+                        "{identity} doesn't take ratings: {}",
+                        e.message().unwrap_or("refused")
+                    )
+                })
+            })
+            .await
+            .map_err(|e| AppError::internal(format!("rating task failed: {e}")))?;
+            written.map_err(|e| problems.push(e)).is_ok()
+        }
+        None => false,
+    };
+    let loved = match love {
+        Some(loved) if dry_run => {
+            info!("Dry run: would send love={loved} to the scrobbling services");
+            scrobbler.is_on()
+        }
+        Some(loved) => scrobbler.love(&now, loved),
+        None => false,
+    };
+    if love.is_some() && !loved {
+        problems
+            .push("no scrobbling service is configured, or the track has no artist".to_string());
+    }
+    if !player_rated && !loved {
+        return Err(AppError::bad_request(problems.join(", and ")));
+    }
+    Ok(HttpResponse::Ok().json(Rated {
+        title: now.title,
+        artist: now.artist,
+        rating: rating.filter(|_| player_rated),
+        love: love.filter(|_| loved),
+    }))
+}
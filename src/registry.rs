@@ -0,0 +1,147 @@
+//! Cached view of the MPRIS players on the session bus.
+//!
+//! Enumerating the bus means a `ListNames` call plus a few property reads per
+//! player, which added noticeable latency when done on every request. Instead,
+//! a watcher thread subscribes to `NameOwnerChanged` and bumps a generation
+//! counter whenever an MPRIS name appears, disappears or changes owner.
+//! Callers keep a long-lived connection and the discovered players per thread
+//! (mpris `Player`s can't cross threads) and only enumerate again once the
+//! generation has moved on.
+//!
+//! So the bus is only scanned on a cache miss: the first call on a thread,
+//! after a name change, or after [`invalidate`], which the daemon calls on
+//! resume from sleep. [`scans`] counts the scans, which
+//! `/admin/diagnostics` reports.
+//!
+//! If the watcher can't subscribe, every call enumerates the bus as before.
+//! A player that fails to answer while enumerating, as sandboxed ones with
+//! restricted interfaces can, is left out instead of failing discovery.
+
+use crate::player::MPRIS_PREFIX;
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use mpris::{Player, PlayerFinder};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+/// Bumped on every MPRIS name change seen by the watcher, and by [`invalidate`]
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Whether the watcher is subscribed, i.e. whether cached lists can be trusted
+static WATCHING: AtomicBool = AtomicBool::new(false);
+/// Times the bus was enumerated, on any thread
+static SCANS: AtomicU64 = AtomicU64::new(0);
+
+struct Cache {
+    generation: u64,
+    finder: PlayerFinder,
+    players: Vec<Rc<Player>>,
+}
+
+thread_local! {
+    static CACHE: RefCell<Option<Cache>> = const { RefCell::new(None) };
+}
+
+/// Start the thread that tracks MPRIS name changes on the session bus
+pub fn spawn_watcher() {
+    thread::spawn(|| {
+        let conn = match Connection::new_session() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Player registry disabled, couldn't connect to session bus: {e}");
+                return;
+            }
+        };
+
+        let rule = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged");
+        let added = conn.add_match(rule, |(name, _, _): (String, String, String), _, _| {
+            if name.starts_with(MPRIS_PREFIX) {
+                GENERATION.fetch_add(1, Ordering::SeqCst);
+            }
+            true
+        });
+        if let Err(e) = added {
+            warn!("Player registry disabled, couldn't subscribe to NameOwnerChanged: {e}");
+            return;
+        }
+        WATCHING.store(true, Ordering::SeqCst);
+
+        loop {
+            if let Err(e) = conn.process(Duration::from_secs(60)) {
+                warn!("Player registry stopped watching the bus: {e}");
+                WATCHING.store(false, Ordering::SeqCst);
+                return;
+            }
+        }
+    });
+}
+
+/// Whether the watcher is subscribed and cached player lists are in use
+pub fn is_watching() -> bool {
+    WATCHING.load(Ordering::SeqCst)
+}
+
+/// How many MPRIS name changes the watcher has seen, counting invalidations
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// Make every thread enumerate the bus again on its next call, for when
+/// players may have changed without the watcher seeing it
+pub fn invalidate() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// How many times the bus has been enumerated since startup
+pub fn scans() -> u64 {
+    SCANS.load(Ordering::Relaxed)
+}
+
+/// Every MPRIS player on the bus, including our own publisher.
+///
+/// Served from this thread's cache unless the set of players has changed.
+pub fn players() -> Result<Vec<Rc<Player>>, String> {
+    // Read the generation before enumerating: a change that races with the
+    // enumeration then forces another one on the next call.
+    let generation = GENERATION.load(Ordering::SeqCst);
+    let watching = WATCHING.load(Ordering::SeqCst);
+
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(cached) = cache.as_ref() {
+            if watching && cached.generation == generation {
+                return Ok(cached.players.clone());
+            }
+        }
+
+        let finder = match cache.take() {
+            Some(cached) => cached.finder,
+            None => PlayerFinder::new()
+                .map_err(|e| format!("Couldn't connect to the session bus: {e}"))?,
+        };
+        SCANS.fetch_add(1, Ordering::Relaxed);
+        // On failure the finder is dropped so the next call reconnects
+        let found = finder
+            .iter_players()
+            .map_err(|e| format!("Player discovery failed: {e}"))?;
+        // A sandboxed player may not answer for its Identity; leave that one
+        // out rather than every player
+        let players: Vec<_> = found
+            .filter_map(|player| {
+                player
+                    .map_err(|e| warn!("Skipping a player that didn't answer discovery: {e}"))
+                    .ok()
+            })
+            .map(Rc::new)
+            .collect();
+        *cache = Some(Cache {
+            generation,
+            finder,
+            players: players.clone(),
+        });
+        Ok(players)
+    })
+}
@@ -0,0 +1,197 @@
+//! Players reached some other way than MPRIS, such as MPD or Google Cast
+//! devices.
+//!
+//! A backend implements [`RemotePlayer`] and [`add`]s its players as it
+//! finds them. They're listed in `GET /players` next to the MPRIS ones, and
+//! take the commands sent to them by id or name: `/players/{id}/...`,
+//! `?players=`, groups and batches all reach them through
+//! [`crate::actions::run_on`]. An MPRIS player matching the same selector
+//! wins. Automatic selection and the event streams only follow MPRIS players,
+//! so a remote player has to be named. `/status` falls back to the first
+//! player that asks for it with [`RemotePlayer::in_status`] while no MPRIS
+//! player is controlled.
+
+use crate::actions::{Action, ActionError};
+use crate::error::MutexExt;
+use crate::i18n::Message;
+use crate::player::{resolve_player_alias, seek_step_for};
+use crate::template::format_duration;
+use media_controller::models::PlayerInfo;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What a remote player reports about itself
+#[derive(Clone, Debug, Default)]
+pub struct RemoteStatus {
+    // `Playing`, `Paused` or `Stopped`, as MPRIS has it
+    pub playback: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    // The player's own volume in percent
+    pub volume: Option<u32>,
+    pub position: Option<Duration>,
+    pub length: Option<Duration>,
+    pub can_seek: bool,
+}
+
+/// A player outside MPRIS
+pub trait RemotePlayer: Send + Sync {
+    /// Unique id, used where MPRIS players have their bus name, e.g.
+    /// `cast:Chromecast-1f2e`
+    fn id(&self) -> &str;
+
+    /// Name to show, e.g. the device's friendly name
+    fn identity(&self) -> &str;
+
+    /// Ask the player what it's doing
+    fn status(&self) -> Result<RemoteStatus, String>;
+
+    /// Whether the player can carry out `action` at all
+    fn supports(&self, action: Action) -> bool;
+
+    /// Carry out a player command that [`RemotePlayer::supports`]
+    fn run(&self, action: Action) -> Result<Message, ActionError>;
+
+    /// Set the player's own volume, in percent
+    fn set_volume(&self, percent: u32) -> Result<(), String>;
+
+    /// Whether `/status` reports this player while no MPRIS player is
+    /// controlled, for players that play on this machine
+    fn in_status(&self) -> bool {
+        false
+    }
+}
+
+static PLAYERS: Mutex<Vec<Arc<dyn RemotePlayer>>> = Mutex::new(Vec::new());
+
+/// Make `player` available, replacing one with the same id
+pub fn add(player: Arc<dyn RemotePlayer>) {
+    let mut players = PLAYERS.locked();
+    players.retain(|p| p.id() != player.id());
+    players.push(player);
+}
+
+/// Forget the player with this id, if there is one
+#[cfg_attr(not(feature = "cast"), allow(dead_code))]
+pub fn remove(id: &str) {
+    PLAYERS.locked().retain(|p| p.id() != id);
+}
+
+/// Every remote player
+pub fn players() -> Vec<Arc<dyn RemotePlayer>> {
+    PLAYERS.locked().clone()
+}
+
+/// The remote player `selector` names: its id, or a case-insensitive
+/// substring of its id or name
+pub fn find(selector: &str) -> Option<Arc<dyn RemotePlayer>> {
+    let players = PLAYERS.locked();
+    if players.is_empty() {
+        return None;
+    }
+    let wanted = resolve_player_alias(selector);
+    if let Some(exact) = players.iter().find(|p| p.id() == wanted) {
+        return Some(exact.clone());
+    }
+    let wanted = wanted.to_lowercase();
+    players
+        .iter()
+        .find(|p| {
+            p.id().to_lowercase().contains(&wanted) || p.identity().to_lowercase().contains(&wanted)
+        })
+        .cloned()
+}
+
+/// Start the backends that were built in and are turned on
+pub fn start() {
+    crate::mpd::start();
+    #[cfg(feature = "cast")]
+    crate::cast::start();
+}
+
+/// Run `action` on `player`, refusing what it doesn't support
+pub fn run(player: &dyn RemotePlayer, action: Action) -> Result<Message, ActionError> {
+    check(player, action)?;
+    player.run(action)
+}
+
+/// What [`run`] would do, for dry runs
+pub fn plan(player: &dyn RemotePlayer, action: Action) -> Result<Message, ActionError> {
+    check(player, action)?;
+    let name = format!("{} ({})", player.identity(), player.id());
+    Ok(match action {
+        Action::Next => Message::new("plan-next").arg("player", name),
+        Action::Previous => Message::new("plan-previous").arg("player", name),
+        Action::SeekBy(ms) => Message::new("plan-seek")
+            .arg("player", name)
+            .arg("direction", if ms < 0 { "backward" } else { "forward" })
+            .arg("seconds", ms.unsigned_abs() as f64 / 1000.0),
+        Action::SeekForward | Action::SeekBackward => Message::new("plan-seek")
+            .arg("player", name)
+            .arg(
+                "direction",
+                if action == Action::SeekForward {
+                    "forward"
+                } else {
+                    "backward"
+                },
+            )
+            .arg("seconds", seek_step_for(player.identity()).as_secs()),
+        Action::SeekTo(ms) => Message::new("plan-seek-to")
+            .arg("player", name)
+            .arg("position", format_duration(Duration::from_millis(ms))),
+        // Everything else a remote player supports is play, pause, toggle or stop
+        _ => Message::new("plan-playback")
+            .arg("verb", action.name())
+            .arg("player", name),
+    })
+}
+
+fn check(player: &dyn RemotePlayer, action: Action) -> Result<(), ActionError> {
+    if player.supports(action) {
+        return Ok(());
+    }
+    let unsupported = match action {
+        Action::Next => "cannot-next",
+        Action::Previous => "cannot-previous",
+        Action::SeekForward | Action::SeekBackward | Action::SeekBy(_) | Action::SeekTo(_) => {
+            "cannot-seek"
+        }
+        Action::SetShuffle(_) => "cannot-shuffle",
+        Action::SetLoop(_) => "cannot-loop",
+        Action::Raise => "cannot-raise",
+        Action::Quit => "cannot-quit",
+        _ => "cannot-control",
+    };
+    Err(ActionError::Unsupported(Message::new(unsupported)))
+}
+
+/// The player `/status` reports while no MPRIS player is controlled, and
+/// what it says
+pub fn status_fallback() -> Option<(Arc<dyn RemotePlayer>, RemoteStatus)> {
+    players()
+        .into_iter()
+        .filter(|p| p.in_status())
+        .find_map(|p| p.status().ok().map(|status| (p, status)))
+}
+
+/// The remote players as GET /players lists them
+pub fn player_infos() -> Vec<PlayerInfo> {
+    players()
+        .iter()
+        .map(|p| {
+            let status = p.status().unwrap_or_default();
+            PlayerInfo {
+                identity: p.identity().to_string(),
+                bus_name: p.id().to_string(),
+                playback: status.playback,
+                can_seek: status.can_seek,
+                can_control: p.supports(Action::Play),
+                title: status.title,
+                artist: status.artist,
+                selected: false,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,219 @@
+//! Reporting this machine's state to a central server.
+//!
+//! Set `MEDIA_CONTROL_REPORT_URL` to push a [`Report`] (the `GET /status`
+//! body, the events that led to it, and which machine sent it) to another
+//! media-controller's `POST /reports`, or to any endpoint taking JSON, so one
+//! dashboard can show every machine in the house without talking to each of
+//! them. Reports go out whenever anything changes and every
+//! `MEDIA_CONTROL_REPORT_INTERVAL_SECS` otherwise, so the server can tell a
+//! quiet machine from one that's gone. A delivery that's still waiting when
+//! newer state comes along is replaced by it; failures are logged.
+//!
+//! The receiving side keeps the latest report of each machine in memory and
+//! lists them at `GET /reports`.
+
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use crate::events::changes;
+use crate::outbound::{self, Integration};
+use crate::AppState;
+use actix_web::{web, HttpResponse};
+use media_controller::models::{ErrorBody, Report, ReportedInstance};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Default for `MEDIA_CONTROL_REPORT_INTERVAL_SECS`
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+/// Give up on a delivery after this long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Machines the receiving side keeps; the one heard from longest ago goes
+const MAX_INSTANCES: usize = 64;
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn interval() -> Duration {
+    config::var("MEDIA_CONTROL_REPORT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .filter(|interval| *interval >= Duration::from_secs(1))
+        .unwrap_or(DEFAULT_INTERVAL)
+}
+
+/// `MEDIA_CONTROL_REPORT_NAME`, or else the hostname
+fn instance_name() -> String {
+    config::var("MEDIA_CONTROL_REPORT_NAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "media-controller".into())
+}
+
+/// Start reporting if `MEDIA_CONTROL_REPORT_URL` is set
+pub fn spawn(state: web::Data<AppState>) {
+    let Ok(url) = config::var("MEDIA_CONTROL_REPORT_URL") else {
+        return;
+    };
+    let token = config::var("MEDIA_CONTROL_REPORT_TOKEN").ok();
+    let instance = instance_name();
+    let every = interval();
+    info!(
+        "Reporting to {url} as {instance} every {}s",
+        every.as_secs()
+    );
+
+    let (reports, queue) = mpsc::channel::<Report>();
+    thread::spawn(move || {
+        // Only the first failure of a streak is logged
+        let mut failing = false;
+        while let Ok(mut report) = queue.recv() {
+            // Only the newest state is worth sending, with every event since
+            while let Ok(newer) = queue.try_recv() {
+                let mut events = std::mem::take(&mut report.events);
+                for event in &newer.events {
+                    if !events.contains(event) {
+                        events.push(event.clone());
+                    }
+                }
+                report = newer;
+                report.events = events;
+            }
+            let mut request = outbound::agent(Integration::Reporting)
+                .post(&url)
+                .config()
+                .timeout_global(Some(REQUEST_TIMEOUT))
+                .build();
+            if let Some(token) = &token {
+                request = request.header("Authorization", format!("Bearer {token}"));
+            }
+            match request.send_json(&report) {
+                Ok(_) if failing => {
+                    info!("Reporting to {url} works again");
+                    failing = false;
+                }
+                Ok(_) => {}
+                Err(e) if !failing => {
+                    warn!("Couldn't report to {url}: {e}");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+
+    let mut updates = state.live.subscribe();
+    let mut last = updates.borrow_and_update().clone();
+    actix_web::rt::spawn(async move {
+        let mut timer = tokio::time::interval(every);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            let events = tokio::select! {
+                changed = updates.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    let now = updates.borrow_and_update().clone();
+                    let events = changes(&last, &now);
+                    last = now;
+                    if events.is_empty() {
+                        continue;
+                    }
+                    // A report just went out, so the next periodic one can wait
+                    timer.reset();
+                    events
+                }
+                _ = timer.tick() => Vec::new(),
+            };
+            let report = Report {
+                instance: instance.clone(),
+                timestamp_ms: unix_ms(),
+                events: events.into_iter().map(String::from).collect(),
+                interval_secs: every.as_secs(),
+                status: crate::status_json(&state),
+            };
+            if reports.send(report).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// The latest report of every machine, on the receiving side
+#[derive(Default)]
+pub struct Reports {
+    instances: Mutex<HashMap<String, ReportedInstance>>,
+}
+
+/// POST /reports — take a report from a machine in reporting mode
+#[utoipa::path(
+    post,
+    path = "/reports",
+    tag = "Extras",
+    request_body = Report,
+    responses(
+        (status = 204, description = "Report kept"),
+        (status = 400, description = "Not a report", body = ErrorBody),
+    ),
+)]
+pub async fn receive_report(
+    reports: web::Data<Reports>,
+    report: web::Json<Report>,
+) -> Result<HttpResponse, AppError> {
+    let report = report.into_inner();
+    if report.instance.trim().is_empty() {
+        return Err(AppError::bad_request("instance must not be empty"));
+    }
+    let mut instances = reports.instances.locked();
+    if instances.len() >= MAX_INSTANCES && !instances.contains_key(&report.instance) {
+        let oldest = instances
+            .iter()
+            .min_by_key(|(_, i)| i.received_ms)
+            .map(|(name, _)| name.clone());
+        if let Some(oldest) = oldest {
+            instances.remove(&oldest);
+        }
+    }
+    let entry = ReportedInstance {
+        report,
+        received_ms: unix_ms(),
+        online: true,
+    };
+    instances.insert(entry.report.instance.clone(), entry);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// GET /reports — the latest report of every machine that sent one
+#[utoipa::path(
+    get,
+    path = "/reports",
+    tag = "Extras",
+    responses(
+        (status = 200, description = "Machines by name", body = [ReportedInstance]),
+    ),
+)]
+pub async fn list_reports(reports: web::Data<Reports>) -> HttpResponse {
+    let now = unix_ms();
+    let mut instances: Vec<ReportedInstance> = reports
+        .instances
+        .locked()
+        .values()
+        .cloned()
+        .map(|mut i| {
+            let allowance = i.report.interval_secs.max(1).saturating_mul(3 * 1000);
+            i.online = now.saturating_sub(i.received_ms) <= allowance;
+            i
+        })
+        .collect();
+    instances.sort_by(|a, b| a.report.instance.cmp(&b.report.instance));
+    HttpResponse::Ok().json(instances)
+}
@@ -0,0 +1,243 @@
+//! Resume points for long media.
+//!
+//! Enabled by setting `MEDIA_CONTROL_RESUME_MIN_MINUTES`. While any player
+//! plays a track at least that long (audiobooks, DJ sets, long podcasts),
+//! its position is saved every so often, keyed by the track's URL. Whenever
+//! that URL starts again, on the same player or another one, it's sent back
+//! to the saved position. Tracks played to the end are forgotten. The points
+//! are kept in `MEDIA_CONTROL_RESUME_FILE`, by default
+//! `$XDG_STATE_HOME/media-controller/resume.json` (usually under
+//! `~/.local/state`), so they survive restarts.
+
+use crate::actions::set_position;
+use crate::config;
+use crate::player::external_players;
+use crate::template::format_duration;
+use crate::{get_dry_run_mode, AppState};
+use actix_web::web;
+use mpris::{PlaybackStatus, Player};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+/// How often players are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often changed positions are written out
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Positions closer to the start aren't worth saving or resuming
+const MIN_POSITION: Duration = Duration::from_secs(10);
+/// A track seen this close to its start has just (re)started
+const START_WINDOW: Duration = Duration::from_secs(15);
+/// A track this close to its end counts as finished
+const END_MARGIN: Duration = Duration::from_secs(30);
+/// Oldest points are dropped beyond this many
+const MAX_POINTS: usize = 500;
+
+/// Read `MEDIA_CONTROL_RESUME_MIN_MINUTES` (fractions allowed)
+fn min_length_from_env() -> Option<Duration> {
+    let minutes: f64 = config::var("MEDIA_CONTROL_RESUME_MIN_MINUTES")
+        .ok()?
+        .parse()
+        .map_err(|_| warn!("Ignoring MEDIA_CONTROL_RESUME_MIN_MINUTES, expected a number"))
+        .ok()?;
+    Duration::try_from_secs_f64(minutes * 60.0)
+        .ok()
+        .filter(|d| !d.is_zero())
+}
+
+/// Where resume points are stored
+fn file_path() -> Option<PathBuf> {
+    if let Ok(path) = config::var("MEDIA_CONTROL_RESUME_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state_home.join("media-controller").join("resume.json"))
+}
+
+/// Where a track was left
+#[derive(Clone, Serialize, Deserialize)]
+struct ResumePoint {
+    position_ms: u64,
+    // Unix time of the last update, for dropping the oldest points
+    saved_at: u64,
+}
+
+/// The saved points, by track URL
+struct ResumePoints {
+    path: PathBuf,
+    points: HashMap<String, ResumePoint>,
+    dirty: bool,
+    saved: Instant,
+}
+
+impl ResumePoints {
+    fn load(path: PathBuf) -> Self {
+        let points = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                warn!("Ignoring resume points in {}: {e}", path.display());
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        ResumePoints {
+            path,
+            points,
+            dirty: false,
+            saved: Instant::now(),
+        }
+    }
+
+    fn get(&self, url: &str) -> Option<Duration> {
+        self.points
+            .get(url)
+            .map(|p| Duration::from_millis(p.position_ms))
+    }
+
+    fn set(&mut self, url: &str, position: Duration) {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.points.insert(
+            url.to_string(),
+            ResumePoint {
+                position_ms: position.as_millis() as u64,
+                saved_at,
+            },
+        );
+        self.dirty = true;
+    }
+
+    fn forget(&mut self, url: &str) {
+        if self.points.remove(url).is_some() {
+            self.dirty = true;
+            self.save();
+        }
+    }
+
+    /// Write the points out if they changed
+    fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        if self.points.len() > MAX_POINTS {
+            let mut by_age: Vec<_> = self
+                .points
+                .iter()
+                .map(|(k, p)| (p.saved_at, k.clone()))
+                .collect();
+            by_age.sort();
+            for (_, url) in &by_age[..self.points.len() - MAX_POINTS] {
+                self.points.remove(url);
+            }
+        }
+        let result = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                // Write a copy and move it over, so a crash can't leave half a file
+                let tmp = self.path.with_extension("json.tmp");
+                fs::write(&tmp, serde_json::to_vec(&self.points).unwrap_or_default())?;
+                fs::rename(&tmp, &self.path)
+            });
+        if let Err(e) = result {
+            error!(
+                "Couldn't save resume points to {}: {e}",
+                self.path.display()
+            );
+        }
+        self.dirty = false;
+        self.saved = Instant::now();
+    }
+}
+
+/// Start the watcher thread if resume points are enabled
+pub fn spawn_monitor(state: web::Data<AppState>) {
+    let Some(min_length) = min_length_from_env() else {
+        return;
+    };
+    if state.demo.is_some() {
+        return;
+    }
+    let Some(path) = file_path() else {
+        warn!("Resume points disabled, set MEDIA_CONTROL_RESUME_FILE or HOME");
+        return;
+    };
+    info!(
+        "Saving resume points for tracks over {} in {}",
+        format_duration(min_length),
+        path.display()
+    );
+    thread::spawn(move || monitor(ResumePoints::load(path), min_length));
+}
+
+fn monitor(mut points: ResumePoints, min_length: Duration) {
+    // The long track each player was playing at the last check
+    let mut last_url: HashMap<String, String> = HashMap::new();
+
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+        let mut seen = HashMap::new();
+        for player in external_players() {
+            if let Some(url) = check(&player, &mut points, min_length, &last_url) {
+                seen.insert(player.bus_name().to_string(), url);
+            }
+        }
+        last_url = seen;
+        if points.saved.elapsed() >= SAVE_INTERVAL {
+            points.save();
+        }
+    }
+}
+
+/// Save or resume one player's track, returning its URL if it's playing
+fn check(
+    player: &Player,
+    points: &mut ResumePoints,
+    min_length: Duration,
+    last_url: &HashMap<String, String>,
+) -> Option<String> {
+    let metadata = player.get_metadata().ok()?;
+    let url = metadata.url()?.to_string();
+    let length = metadata.length().filter(|l| *l >= min_length)?;
+    // Not counted as seen until it plays, so starting paused still resumes
+    if player.get_playback_status().ok() != Some(PlaybackStatus::Playing) {
+        return None;
+    }
+    let position = player.get_position().ok()?;
+    let started = last_url.get(player.bus_name()) != Some(&url) && position < START_WINDOW;
+
+    if started {
+        if let Some(saved) = points.get(&url).filter(|p| *p > position) {
+            resume(player, saved);
+            return Some(url);
+        }
+    }
+    if position + END_MARGIN >= length {
+        points.forget(&url);
+    } else if position >= MIN_POSITION {
+        points.set(&url, position);
+    }
+    Some(url)
+}
+
+/// Send a player back to where its track was left
+fn resume(player: &Player, position: Duration) {
+    let at = format_duration(position);
+    if get_dry_run_mode() {
+        info!("[dry run] would resume {} at {at}", player.identity());
+        return;
+    }
+    match set_position(player, position) {
+        Ok(()) => info!("Resumed {} at {at}", player.identity()),
+        Err(e) => error!("Couldn't resume {} at {at}: {e}", player.identity()),
+    }
+}
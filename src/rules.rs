@@ -0,0 +1,169 @@
+//! Automation rules reacting to player events.
+//!
+//! `MEDIA_CONTROL_RULES` holds rules separated by `;`, each a player, an
+//! event and, after a colon, what to do:
+//!
+//! ```text
+//! firefox playing: pause_others; * track: volume 60
+//! ```
+//!
+//! The player is matched like a selector against the controlled player's
+//! identity and bus name, or `*` for any. The events are `playing`, `paused`
+//! and `stopped` when the controlled player's play state becomes that (or
+//! a player already in it becomes the controlled one), `track` when it moves
+//! to another track, and `player` when a different player becomes the
+//! controlled one. The actions are those of [`crate::schedules`], so
+//! `pause_others` stops two browser tabs fighting over the speakers.
+//!
+//! A rule doesn't run again within a second of its last run, so a rule whose
+//! action sets off its own event can't spin.
+
+use crate::config;
+use crate::events::changes;
+use crate::live::NowPlaying;
+use crate::player::resolve_player_alias;
+use crate::schedules::Task;
+use crate::AppState;
+use actix_web::web;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Shortest time between two runs of one rule
+const COOLDOWN: Duration = Duration::from_secs(1);
+
+/// What a rule reacts to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Trigger {
+    Playing,
+    Paused,
+    Stopped,
+    Track,
+    Player,
+}
+
+impl Trigger {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "playing" => Trigger::Playing,
+            "paused" => Trigger::Paused,
+            "stopped" => Trigger::Stopped,
+            "track" => Trigger::Track,
+            "player" => Trigger::Player,
+            _ => return None,
+        })
+    }
+
+    /// The events between two snapshots of the controlled player
+    fn between(old: &NowPlaying, new: &NowPlaying) -> Vec<Trigger> {
+        let kinds = changes(old, new);
+        let switched = kinds.contains(&"player");
+        let mut triggers = Vec::new();
+        if new.bus_name.is_some() && switched {
+            triggers.push(Trigger::Player);
+        }
+        if switched || old.playback != new.playback {
+            triggers.extend(match new.playback.as_deref() {
+                Some("Playing") => Some(Trigger::Playing),
+                Some("Paused") => Some(Trigger::Paused),
+                Some("Stopped") => Some(Trigger::Stopped),
+                _ => None,
+            });
+        }
+        // Switching players isn't a track change
+        if kinds.contains(&"track") && !switched && new.title.is_some() {
+            triggers.push(Trigger::Track);
+        }
+        triggers
+    }
+}
+
+struct Rule {
+    // As written, for the log
+    text: String,
+    // Lowercase selector, None for `*`
+    player: Option<String>,
+    trigger: Trigger,
+    task: Task,
+    last_run: Option<Instant>,
+}
+
+impl Rule {
+    fn parse(text: &str) -> Result<Self, String> {
+        let (when, then) = text
+            .split_once(':')
+            .ok_or("expected a player and an event, a colon and an action")?;
+        let [player, event] = when.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(format!("{:?} isn't a player and an event", when.trim()));
+        };
+        let trigger = Trigger::parse(event).ok_or_else(|| {
+            format!("unknown event {event:?}, expected playing, paused, stopped, track or player")
+        })?;
+        let words: Vec<&str> = then.split_whitespace().collect();
+        Ok(Rule {
+            text: text.to_string(),
+            player: (player != "*").then(|| resolve_player_alias(player).to_lowercase()),
+            trigger,
+            task: Task::parse(&words)?,
+            last_run: None,
+        })
+    }
+
+    fn applies_to(&self, now: &NowPlaying) -> bool {
+        self.player.as_ref().map_or(true, |wanted| {
+            [&now.player, &now.bus_name]
+                .iter()
+                .filter_map(|name| name.as_deref())
+                .any(|name| name.to_lowercase().contains(wanted.as_str()))
+        })
+    }
+}
+
+/// Read `MEDIA_CONTROL_RULES`, leaving out rules that don't parse
+fn rules_from_env() -> Vec<Rule> {
+    let text = config::var("MEDIA_CONTROL_RULES").unwrap_or_default();
+    text.split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .filter_map(|text| match Rule::parse(text) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                warn!("Ignoring rule {text:?} in MEDIA_CONTROL_RULES: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Follow `state.live` and run the rules its events set off
+pub fn spawn(state: web::Data<AppState>) {
+    let mut rules = rules_from_env();
+    if rules.is_empty() {
+        return;
+    }
+    info!("{} automation rules configured", rules.len());
+
+    let mut updates = state.live.subscribe();
+    let mut last = updates.borrow_and_update().clone();
+    actix_web::rt::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let now = updates.borrow_and_update().clone();
+            let triggers = Trigger::between(&last, &now);
+            last = now.clone();
+            for rule in &mut rules {
+                if !triggers.contains(&rule.trigger) || !rule.applies_to(&now) {
+                    continue;
+                }
+                if rule.last_run.is_some_and(|at| at.elapsed() < COOLDOWN) {
+                    continue;
+                }
+                rule.last_run = Some(Instant::now());
+                let (state, task) = (state.clone(), rule.task.clone());
+                match web::block(move || task.run(&state)).await {
+                    Ok(Ok(msg)) => info!("Rule {:?} ran: {msg}", rule.text),
+                    Ok(Err(e)) => warn!("Rule {:?} failed: {e}", rule.text),
+                    Err(e) => error!("Rule task failed: {e}"),
+                }
+            }
+        }
+    });
+}
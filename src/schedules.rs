@@ -0,0 +1,678 @@
+//! Scheduled actions: alarms, bedtimes and the like.
+//!
+//! `MEDIA_CONTROL_SCHEDULES` holds crontab-style entries separated by `;`,
+//! each five time fields (minute, hour, day of month, month and weekday)
+//! followed by what to do:
+//!
+//! ```text
+//! 0 7 * * 1-5 volume 30; 0 7 * * 1-5 open https://radio.example/stream @vlc; 30 23 * * * pause
+//! ```
+//!
+//! `open` takes a URI, `volume` a percentage, and `pause_others` pauses every
+//! playing player but the controlled one. `select @selector` pins a player
+//! like `POST /players/select` and `unselect` drops the pin. Any other action is a
+//! `/do/{action}` name, such as `play` to resume playback. A trailing
+//! `@selector` picks the player as for `/players/{id}/...`. `POST /schedules`
+//! adds entries at run time, which last until the service restarts.
+//!
+//! Times are local. Runs missed while the machine was asleep aren't made up.
+//! In dry run the actions are only logged.
+//!
+//! The wind-down is a gentler kind of quiet hours: from
+//! `MEDIA_CONTROL_WIND_DOWN_AFTER` until `MEDIA_CONTROL_WIND_DOWN_UNTIL`
+//! every track change lowers the system volume a little, down to a floor, so
+//! the evening gets quieter without an abrupt cut. It never raises the
+//! volume, and leaves it alone while volume and seeking are locked.
+
+use crate::actions::{self, Action, Target};
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use crate::events::changes;
+use crate::{get_dry_run_mode, player, volume, AppState};
+use actix_web::{web, HttpResponse};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use media_controller::models::{ErrorBody, Schedule};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Longest the runner sleeps, so it notices a changed clock soon enough
+const TICK: Duration = Duration::from_secs(15);
+/// How far ahead to look for the next run; covers a 29th of February
+const LOOKAHEAD_DAYS: usize = 5 * 366;
+/// Default for `MEDIA_CONTROL_WIND_DOWN_UNTIL`
+const WIND_DOWN_UNTIL: &str = "06:00";
+/// Default for `MEDIA_CONTROL_WIND_DOWN_STEP`
+const WIND_DOWN_STEP: u32 = 2;
+/// Default for `MEDIA_CONTROL_WIND_DOWN_FLOOR`
+const WIND_DOWN_FLOOR: u32 = 20;
+
+fn has(bits: u64, n: u32) -> bool {
+    bits >> n & 1 == 1
+}
+
+/// Parse one crontab field into a bit per allowed value
+fn field(text: &str, min: u32, max: u32) -> Result<u64, String> {
+    let number = |n: &str| {
+        n.parse::<u32>()
+            .map_err(|_| format!("{text:?} isn't a list of numbers, ranges and steps"))
+    };
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, number(step)?),
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (number(from)?, number(to)?),
+            // `5/15` means from 5 on
+            None if part.contains('/') => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if from < min || to > max || from > to || step == 0 {
+            return Err(format!("{part:?} is outside {min}-{max}"));
+        }
+        for n in (from..=to).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+/// The five time fields of an entry
+#[derive(Clone, Copy)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    // Sunday is 0
+    weekdays: u64,
+    // Whether the day of month and weekday fields are `*`, which decides how
+    // they combine
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    fn parse(text: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "{text:?} isn't minute, hour, day of month, month and weekday"
+            ));
+        };
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        // 7 is Sunday too
+        if has(weekday_bits, 7) {
+            weekday_bits = weekday_bits & !(1 << 7) | 1;
+        }
+        Ok(Cron {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        // As in cron, a day of month and a weekday both given mean either
+        let either = !self.any_day && !self.any_weekday;
+        has(self.months, date.month())
+            && if either {
+                day || weekday
+            } else {
+                day && weekday
+            }
+    }
+
+    fn matches(&self, time: NaiveDateTime) -> bool {
+        self.day_matches(time.date())
+            && has(self.hours, time.hour())
+            && has(self.minutes, time.minute())
+    }
+
+    /// The first matching minute after `time`
+    fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let days = time.date().iter_days().take(LOOKAHEAD_DAYS);
+        for date in days.filter(|date| self.day_matches(*date)) {
+            for hour in (0..24).filter(|hour| has(self.hours, *hour)) {
+                for minute in (0..60).filter(|minute| has(self.minutes, *minute)) {
+                    let candidate = date.and_hms_opt(hour, minute, 0)?;
+                    if candidate > time {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// What an entry does
+#[derive(Clone)]
+pub(crate) enum Job {
+    Action(Action),
+    Open(String),
+    Volume(u32),
+    /// Pause every playing player but the controlled one
+    PauseOthers,
+    /// Pin the player, as POST /players/select does
+    Select,
+    /// Drop the pin
+    Unselect,
+}
+
+impl Job {
+    pub(crate) fn new(
+        action: &str,
+        uri: Option<String>,
+        level: Option<u32>,
+    ) -> Result<Self, String> {
+        if uri.is_some() && action != "open" {
+            return Err("a URI only applies to open".into());
+        }
+        if level.is_some() && action != "volume" {
+            return Err("a volume only applies to volume".into());
+        }
+        Ok(match action {
+            "open" => {
+                let uri = uri.ok_or("open needs a URI")?;
+                if !uri
+                    .split_once(':')
+                    .is_some_and(|(scheme, _)| !scheme.is_empty())
+                {
+                    return Err(format!("{uri:?} isn't a URI such as https://..."));
+                }
+                Job::Open(uri)
+            }
+            "volume" => {
+                let level = level.ok_or("volume needs a percentage")?;
+                if level > volume::MAX_VOLUME {
+                    return Err(format!(
+                        "volume must be between 0 and {}",
+                        volume::MAX_VOLUME
+                    ));
+                }
+                Job::Volume(level)
+            }
+            "pause_others" => Job::PauseOthers,
+            "select" => Job::Select,
+            "unselect" => Job::Unselect,
+            _ => Job::Action(
+                Action::from_name(action).ok_or_else(|| format!("unknown action {action:?}"))?,
+            ),
+        })
+    }
+
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Job::Action(action) => action.name(),
+            Job::Open(_) => "open".into(),
+            Job::Volume(_) => "volume".into(),
+            Job::PauseOthers => "pause_others".into(),
+            Job::Select => "select".into(),
+            Job::Unselect => "unselect".into(),
+        }
+    }
+
+    /// Whether the job is sent to one player, which can then be picked
+    fn takes_player(&self) -> bool {
+        match self {
+            Job::Action(action) => action.targets_player(),
+            Job::Open(_) | Job::Select => true,
+            Job::Volume(_) | Job::PauseOthers | Job::Unselect => false,
+        }
+    }
+}
+
+/// A job and the player it's for
+#[derive(Clone)]
+pub(crate) struct Task {
+    pub(crate) job: Job,
+    // Player selector; the auto-selected player if missing
+    pub(crate) player: Option<String>,
+}
+
+impl Task {
+    pub(crate) fn new(job: Job, player: Option<String>) -> Result<Self, String> {
+        if player.is_some() && !job.takes_player() {
+            return Err(format!("{} isn't sent to one player", job.name()));
+        }
+        if player.is_none() && matches!(job, Job::Select) {
+            return Err("select needs a player".into());
+        }
+        Ok(Task { job, player })
+    }
+
+    /// Parse the written form, e.g. `open https://radio.example/stream @vlc`
+    pub(crate) fn parse(words: &[&str]) -> Result<Self, String> {
+        let (player, words) = match words.split_last() {
+            Some((last, rest)) if last.starts_with('@') => (Some(last[1..].to_string()), rest),
+            _ => (None, words),
+        };
+        let job = match words {
+            ["open", uri] => Job::new("open", Some(uri.to_string()), None)?,
+            ["volume", level] => {
+                let level = level
+                    .trim_end_matches('%')
+                    .parse()
+                    .map_err(|_| format!("{level:?} isn't a percentage"))?;
+                Job::new("volume", None, Some(level))?
+            }
+            [action] => Job::new(action, None, None)?,
+            [] => return Err("expected an action".into()),
+            _ => return Err(format!("can't make sense of {:?}", words.join(" "))),
+        };
+        Task::new(job, player)
+    }
+
+    /// Carry the job out, returning what was done
+    pub(crate) fn run(&self, state: &AppState) -> Result<String, String> {
+        let target = self.player.clone().map_or(Target::Auto, Target::Player);
+        let dry_run = get_dry_run_mode();
+        match &self.job {
+            Job::Action(action) => if dry_run {
+                actions::plan(state, &target, *action)
+            } else {
+                actions::run_on(state, &target, *action)
+            }
+            .map(|msg| msg.to_string())
+            .map_err(|e| e.to_string()),
+            Job::Open(uri) => open(state, &target, uri, dry_run),
+            Job::Volume(_) if state.lock.is_locked() => Err("volume is locked".into()),
+            Job::Volume(level) if dry_run => Ok(format!("would set the volume to {level}%")),
+            Job::Volume(level) => volume::backend()
+                .set_volume(*level)
+                .map(|()| format!("volume set to {level}%")),
+            Job::PauseOthers => pause_others(state, dry_run),
+            Job::Select => {
+                let selector = self.player.as_deref().unwrap_or_default();
+                let player =
+                    player::find_player_by_selector(selector).ok_or("no matching player found")?;
+                *state.pinned.locked() = Some(player.bus_name().to_string());
+                Ok(format!("pinned {}", player.identity()))
+            }
+            Job::Unselect => Ok(match state.pinned.locked().take() {
+                Some(bus_name) => format!("unpinned {bus_name}"),
+                None => "no player was pinned".into(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    id: String,
+    cron_text: String,
+    cron: Cron,
+    task: Task,
+}
+
+impl Entry {
+    fn new(id: String, cron_text: &str, task: Task) -> Result<Self, String> {
+        Ok(Entry {
+            id,
+            cron_text: cron_text.split_whitespace().collect::<Vec<_>>().join(" "),
+            cron: Cron::parse(cron_text)?,
+            task,
+        })
+    }
+
+    /// Parse an entry of `MEDIA_CONTROL_SCHEDULES`
+    fn parse(id: String, text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() < 6 {
+            return Err("expected five time fields and an action".into());
+        }
+        let (cron_text, rest) = words.split_at(5);
+        Entry::new(id, &cron_text.join(" "), Task::parse(rest)?)
+    }
+
+    fn view(&self) -> Schedule {
+        let next_run = self
+            .cron
+            .next_after(Local::now().naive_local())
+            .and_then(|next| next.and_local_timezone(Local).earliest())
+            .map(|next| next.format("%Y-%m-%dT%H:%M:%S%:z").to_string());
+        Schedule {
+            id: self.id.clone(),
+            cron: self.cron_text.clone(),
+            action: self.task.job.name(),
+            uri: match &self.task.job {
+                Job::Open(uri) => Some(uri.clone()),
+                _ => None,
+            },
+            volume: match self.task.job {
+                Job::Volume(level) => Some(level),
+                _ => None,
+            },
+            player: self.task.player.clone(),
+            next_run,
+        }
+    }
+}
+
+/// Every schedule, configured or added at run time
+#[derive(Default)]
+pub struct Schedules {
+    entries: Mutex<Vec<Entry>>,
+    next_id: AtomicU64,
+}
+
+impl Schedules {
+    /// Read `MEDIA_CONTROL_SCHEDULES`, leaving out entries that don't parse
+    pub fn from_env() -> Self {
+        let text = config::var("MEDIA_CONTROL_SCHEDULES").unwrap_or_default();
+        let entries: Vec<Entry> = text
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .enumerate()
+            .filter_map(
+                |(i, text)| match Entry::parse(format!("config-{}", i + 1), text) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        warn!("Ignoring schedule {text:?} in MEDIA_CONTROL_SCHEDULES: {e}");
+                        None
+                    }
+                },
+            )
+            .collect();
+        if !entries.is_empty() {
+            info!("{} schedules configured", entries.len());
+        }
+        Schedules {
+            entries: Mutex::new(entries),
+            next_id: AtomicU64::new(1),
+        }
+    }
+}
+
+/// Open `uri` in the player `target` finds
+fn open(state: &AppState, target: &Target, uri: &str, dry_run: bool) -> Result<String, String> {
+    if target.is_demo(state) {
+        return Err("the demo player can't open URIs".into());
+    }
+    let player = target.find(state).ok_or("no matching player found")?;
+    if dry_run {
+        return Ok(format!("would open {uri} in {}", player.identity()));
+    }
+    player::open_uri(player.bus_name(), uri)
+        .map(|()| format!("opening {uri} in {}", player.identity()))
+}
+
+/// Pause every playing player but the controlled one
+fn pause_others(state: &AppState, dry_run: bool) -> Result<String, String> {
+    let controlled = state.live.borrow().bus_name.clone();
+    let others = player::playing_players(controlled.as_deref());
+    if others.is_empty() {
+        return Ok("nothing else was playing".into());
+    }
+    let names: Vec<&str> = others.iter().map(|p| p.identity()).collect();
+    if dry_run {
+        return Ok(format!("would pause {}", names.join(", ")));
+    }
+    for p in &others {
+        p.pause()
+            .map_err(|e| format!("couldn't pause {}: {e}", p.identity()))?;
+    }
+    Ok(format!("paused {}", names.join(", ")))
+}
+
+fn run_entry(state: &AppState, entry: &Entry) {
+    match entry.task.run(state) {
+        Ok(msg) => info!("Schedule {} ran: {msg}", entry.id),
+        Err(e) => warn!(
+            "Schedule {} ({}) failed: {e}",
+            entry.id,
+            entry.task.job.name()
+        ),
+    }
+}
+
+/// Run the schedules that are due, at the start of every minute
+pub fn spawn(state: web::Data<AppState>, schedules: web::Data<Schedules>) {
+    thread::spawn(move || {
+        // Minutes are counted in UTC so that changes of the local clock don't
+        // stop the runner, and matched in local time
+        let mut last = Local::now().timestamp() / 60;
+        loop {
+            let now = Local::now();
+            let minute = now.timestamp() / 60;
+            if minute != last {
+                last = minute;
+                let local = now.naive_local();
+                let due: Vec<Entry> = schedules
+                    .entries
+                    .locked()
+                    .iter()
+                    .filter(|entry| entry.cron.matches(local))
+                    .cloned()
+                    .collect();
+                for entry in &due {
+                    run_entry(&state, entry);
+                }
+            }
+            let next_minute = Duration::from_secs(u64::from(60 - now.second()));
+            thread::sleep(next_minute.min(TICK));
+        }
+    });
+}
+
+/// Body of POST /schedules
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRequest {
+    // Minute, hour, day of month, month and weekday, e.g. `0 7 * * 1-5`
+    cron: String,
+    // play, pause, open, volume or any other /do/{action} name
+    action: String,
+    // What `open` opens
+    uri: Option<String>,
+    // What `volume` sets the system volume to, in percent
+    volume: Option<u32>,
+    // Player selector as for /players/{id}/...; the auto-selected player if missing
+    player: Option<String>,
+}
+
+/// GET /schedules — every scheduled action, and when it runs next
+#[utoipa::path(
+    get,
+    path = "/schedules",
+    tag = "Playback",
+    responses((status = 200, description = "The schedules", body = [Schedule])),
+)]
+pub async fn list_schedules(schedules: web::Data<Schedules>) -> HttpResponse {
+    let entries: Vec<Schedule> = schedules.entries.locked().iter().map(Entry::view).collect();
+    HttpResponse::Ok().json(entries)
+}
+
+/// POST /schedules — run an action at set times until the service restarts
+#[utoipa::path(
+    post,
+    path = "/schedules",
+    tag = "Playback",
+    request_body = ScheduleRequest,
+    responses(
+        (status = 201, description = "The new schedule", body = Schedule),
+        (status = 400, description = "Invalid time fields or action", body = ErrorBody),
+    ),
+)]
+pub async fn add_schedule(
+    schedules: web::Data<Schedules>,
+    body: web::Json<ScheduleRequest>,
+) -> Result<HttpResponse, AppError> {
+    let ScheduleRequest {
+        cron,
+        action,
+        uri,
+        volume,
+        player,
+    } = body.into_inner();
+    let id = schedules
+        .next_id
+        .fetch_add(1, Ordering::Relaxed)
+        .to_string();
+    let entry = Job::new(&action, uri, volume)
+        .and_then(|job| Task::new(job, player))
+        .and_then(|task| Entry::new(id, &cron, task))
+        .map_err(AppError::bad_request)?;
+    let view = entry.view();
+    info!(
+        "Schedule {} added: {} at {}",
+        entry.id,
+        entry.task.job.name(),
+        entry.cron_text
+    );
+    schedules.entries.locked().push(entry);
+    Ok(HttpResponse::Created().json(view))
+}
+
+/// DELETE /schedules/{id} — remove a schedule
+#[utoipa::path(
+    delete,
+    path = "/schedules/{id}",
+    tag = "Playback",
+    params(("id" = String, Path, description = "Schedule id from GET /schedules")),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 404, description = "No such schedule", body = ErrorBody),
+    ),
+)]
+pub async fn remove_schedule(
+    schedules: web::Data<Schedules>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let mut entries = schedules.entries.locked();
+    let Some(index) = entries.iter().position(|entry| entry.id == *id) else {
+        return Err(AppError::not_found(format!("no schedule {id}")));
+    };
+    entries.remove(index);
+    info!("Schedule {id} removed");
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// When and how far the wind-down lowers the volume
+#[derive(Clone, Copy, Debug)]
+struct WindDown {
+    after: NaiveTime,
+    until: NaiveTime,
+    // Percentage points per track change
+    step: u32,
+    // Never lowered below this
+    floor: u32,
+}
+
+impl WindDown {
+    /// Read the `MEDIA_CONTROL_WIND_DOWN_*` settings, if the wind-down is on
+    fn from_env() -> Option<Self> {
+        let clock = |name: &str, text: &str| {
+            let time = NaiveTime::parse_from_str(text.trim(), "%H:%M").ok();
+            if time.is_none() {
+                warn!("Ignoring {name}, expected a time like 22:00");
+            }
+            time
+        };
+        let percent = |name: &str, default: u32| {
+            config::var(name)
+                .ok()
+                .and_then(|v| match v.trim().trim_end_matches('%').parse() {
+                    Ok(level) if level <= volume::MAX_VOLUME => Some(level),
+                    _ => {
+                        warn!("Ignoring {name}, expected a percentage");
+                        None
+                    }
+                })
+                .unwrap_or(default)
+        };
+        let after = config::var("MEDIA_CONTROL_WIND_DOWN_AFTER").ok()?;
+        let after = clock("MEDIA_CONTROL_WIND_DOWN_AFTER", &after)?;
+        let until = config::var("MEDIA_CONTROL_WIND_DOWN_UNTIL")
+            .ok()
+            .and_then(|until| clock("MEDIA_CONTROL_WIND_DOWN_UNTIL", &until))
+            .or_else(|| NaiveTime::parse_from_str(WIND_DOWN_UNTIL, "%H:%M").ok())?;
+        Some(WindDown {
+            after,
+            until,
+            step: percent("MEDIA_CONTROL_WIND_DOWN_STEP", WIND_DOWN_STEP),
+            floor: percent("MEDIA_CONTROL_WIND_DOWN_FLOOR", WIND_DOWN_FLOOR),
+        })
+    }
+
+    /// Whether `time` is after `after` and before `until`, which may be on
+    /// the next day
+    fn covers(&self, time: NaiveTime) -> bool {
+        if self.after <= self.until {
+            self.after <= time && time < self.until
+        } else {
+            self.after <= time || time < self.until
+        }
+    }
+
+    /// Lower the system volume by a step, as a track has just changed
+    fn step_down(&self, state: &AppState) -> Result<(), String> {
+        if state.lock.is_locked() {
+            return Ok(());
+        }
+        let backend = volume::backend();
+        let level = backend.get_volume()?;
+        if level <= self.floor {
+            return Ok(());
+        }
+        let lower = level.saturating_sub(self.step).max(self.floor);
+        if get_dry_run_mode() {
+            info!("[dry run] Winding down: would lower the volume from {level}% to {lower}%");
+            return Ok(());
+        }
+        backend.set_volume(lower)?;
+        info!("Winding down: volume lowered from {level}% to {lower}%");
+        Ok(())
+    }
+}
+
+/// Lower the volume on every track change during the wind-down hours, if
+/// `MEDIA_CONTROL_WIND_DOWN_AFTER` is set
+pub fn spawn_wind_down(state: web::Data<AppState>) {
+    let Some(wind_down) = WindDown::from_env() else {
+        return;
+    };
+    info!(
+        "Winding down by {}% per track from {} until {}, to no less than {}%",
+        wind_down.step,
+        wind_down.after.format("%H:%M"),
+        wind_down.until.format("%H:%M"),
+        wind_down.floor
+    );
+
+    let mut updates = state.live.subscribe();
+    actix_web::rt::spawn(async move {
+        let mut last = updates.borrow_and_update().clone();
+        while updates.changed().await.is_ok() {
+            let now = updates.borrow_and_update().clone();
+            let kinds = changes(&last, &now);
+            // Switching players isn't a track change
+            let new_track = kinds.contains(&"track") && !kinds.contains(&"player");
+            last = now;
+            if !new_track || !wind_down.covers(Local::now().time()) {
+                continue;
+            }
+            let state = state.clone();
+            match web::block(move || wind_down.step_down(&state)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Couldn't wind the volume down: {e}"),
+                Err(e) => error!("Wind-down task failed: {e}"),
+            }
+        }
+    });
+}
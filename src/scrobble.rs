@@ -0,0 +1,481 @@
+//! Scrobbling to Last.fm and ListenBrainz.
+//!
+//! Browser players don't scrobble, but the live snapshot already knows what
+//! they play. When credentials for either service are set, a task follows
+//! the snapshot: a track that starts playing is sent as "now playing", and
+//! once it has played for half its length or four minutes, whichever comes
+//! first, it's scrobbled with the time it started. Only time spent playing
+//! counts, so pausing doesn't scrobble early. Tracks shorter than 30
+//! seconds, or without an artist and title, are never scrobbled.
+//!
+//! `POST /rate` with `{"love": true}` loves the track on both services, and
+//! `false` takes that back. ListenBrainz needs the recording's MusicBrainz
+//! id for that, which it's asked for first.
+//!
+//! Submissions go out from a background thread, through the shared
+//! [`crate::outbound`] agent. A failed one is logged and not retried.
+
+use crate::config;
+use crate::error::MutexExt;
+use crate::live::NowPlaying;
+use crate::outbound::{self, Integration};
+use crate::AppState;
+use actix_web::web;
+use md5::{Digest, Md5};
+use serde_json::{json, Value};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const LASTFM_API: &str = "https://ws.audioscrobbler.com/2.0/";
+const LISTENBRAINZ_API: &str = "https://api.listenbrainz.org";
+/// Give up on a submission after this long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Shorter tracks are never scrobbled
+const MIN_LENGTH: Duration = Duration::from_secs(30);
+/// A track is scrobbled after half its length, or this much playing time
+const MAX_WAIT: Duration = Duration::from_secs(4 * 60);
+
+/// What gets submitted about a track
+#[derive(Clone, Debug, PartialEq)]
+struct Track {
+    artist: String,
+    title: String,
+    album: Option<String>,
+    length: Option<Duration>,
+    player: Option<String>,
+    // Tells replays of the same song on two players apart
+    bus_name: Option<String>,
+}
+
+impl Track {
+    fn of(now: &NowPlaying) -> Option<Self> {
+        let non_empty = |s: &Option<String>| s.clone().filter(|s| !s.trim().is_empty());
+        Some(Track {
+            artist: non_empty(&now.artist)?,
+            title: non_empty(&now.title)?,
+            album: non_empty(&now.album),
+            length: now.length,
+            player: now.player.clone(),
+            bus_name: now.bus_name.clone(),
+        })
+    }
+
+    /// Playing time after which the track counts as listened to
+    fn threshold(&self) -> Option<Duration> {
+        match self.length {
+            Some(length) if length < MIN_LENGTH => None,
+            Some(length) => Some((length / 2).min(MAX_WAIT)),
+            None => Some(MAX_WAIT),
+        }
+    }
+}
+
+enum Submission {
+    NowPlaying(Track),
+    // With the Unix time the track started
+    Scrobble(Track, u64),
+    // Loved, or no longer
+    Love(Track, bool),
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// `request` with our timeout, and error statuses left for the caller to
+/// read, since both services explain failures in the body
+fn configured<B>(request: ureq::RequestBuilder<B>) -> ureq::RequestBuilder<B> {
+    request
+        .config()
+        .timeout_global(Some(REQUEST_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+}
+
+/// A scrobbling service
+trait Service: Send {
+    fn name(&self) -> &'static str;
+    fn submit(&mut self, agent: &ureq::Agent, submission: &Submission) -> Result<(), String>;
+}
+
+struct ListenBrainz {
+    url: String,
+    token: String,
+}
+
+impl ListenBrainz {
+    fn from_env() -> Option<Self> {
+        let token = config::var("MEDIA_CONTROL_LISTENBRAINZ_TOKEN").ok()?;
+        let url = config::var("MEDIA_CONTROL_LISTENBRAINZ_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| LISTENBRAINZ_API.into());
+        Some(ListenBrainz { url, token })
+    }
+
+    /// An error from a reply that isn't a success
+    fn failure(response: &mut ureq::http::Response<ureq::Body>) -> String {
+        let status = response.status();
+        let reply: Value = response.body_mut().read_json().unwrap_or_default();
+        match reply["error"].as_str() {
+            Some(error) => format!("{status}: {error}"),
+            None => status.to_string(),
+        }
+    }
+
+    /// Love or unlove `track`, by the recording MusicBrainz matches it to
+    fn love(&self, agent: &ureq::Agent, track: &Track, loved: bool) -> Result<(), String> {
+        let mut response = configured(agent.get(format!("{}/1/metadata/lookup/", self.url)))
+            .query("artist_name", &track.artist)
+            .query("recording_name", &track.title)
+            .header("Authorization", format!("Token {}", self.token))
+            .call()
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(Self::failure(&mut response));
+        }
+        let found: Value = response.body_mut().read_json().map_err(|e| e.to_string())?;
+        let Some(mbid) = found["recording_mbid"].as_str() else {
+            return Err("MusicBrainz doesn't know the recording".into());
+        };
+        let body = json!({ "recording_mbid": mbid, "score": i32::from(loved) });
+        let mut response =
+            configured(agent.post(format!("{}/1/feedback/recording-feedback", self.url)))
+                .header("Authorization", format!("Token {}", self.token))
+                .send_json(&body)
+                .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        Err(Self::failure(&mut response))
+    }
+}
+
+impl Service for ListenBrainz {
+    fn name(&self) -> &'static str {
+        "ListenBrainz"
+    }
+
+    fn submit(&mut self, agent: &ureq::Agent, submission: &Submission) -> Result<(), String> {
+        let (listen_type, track, listened_at) = match submission {
+            Submission::NowPlaying(track) => ("playing_now", track, None),
+            Submission::Scrobble(track, started) => ("single", track, Some(*started)),
+            Submission::Love(track, loved) => return self.love(agent, track, *loved),
+        };
+        let mut listen = json!({
+            "track_metadata": {
+                "artist_name": track.artist,
+                "track_name": track.title,
+                "additional_info": {
+                    "media_player": track.player,
+                    "submission_client": "media-controller",
+                    "submission_client_version": env!("CARGO_PKG_VERSION"),
+                },
+            },
+        });
+        if let Some(album) = &track.album {
+            listen["track_metadata"]["release_name"] = json!(album);
+        }
+        if let Some(length) = track.length {
+            listen["track_metadata"]["additional_info"]["duration_ms"] =
+                json!(length.as_millis() as u64);
+        }
+        if let Some(at) = listened_at {
+            listen["listened_at"] = json!(at);
+        }
+        let body = json!({ "listen_type": listen_type, "payload": [listen] });
+        let mut response = configured(agent.post(format!("{}/1/submit-listens", self.url)))
+            .header("Authorization", format!("Token {}", self.token))
+            .send_json(&body)
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        Err(Self::failure(&mut response))
+    }
+}
+
+struct LastFm {
+    api_key: String,
+    secret: String,
+    // Asked for with the username and password when not configured
+    session_key: Option<String>,
+    login: Option<(String, String)>,
+}
+
+impl LastFm {
+    fn from_env() -> Option<Self> {
+        let api_key = config::var("MEDIA_CONTROL_LASTFM_API_KEY").ok()?;
+        let secret = config::var("MEDIA_CONTROL_LASTFM_API_SECRET").ok()?;
+        let session_key = config::var("MEDIA_CONTROL_LASTFM_SESSION_KEY").ok();
+        let login = config::var("MEDIA_CONTROL_LASTFM_USERNAME")
+            .ok()
+            .zip(config::var("MEDIA_CONTROL_LASTFM_PASSWORD").ok());
+        if session_key.is_none() && login.is_none() {
+            warn!("Not scrobbling to Last.fm, set MEDIA_CONTROL_LASTFM_SESSION_KEY or a username and password");
+            return None;
+        }
+        Some(LastFm {
+            api_key,
+            secret,
+            session_key,
+            login,
+        })
+    }
+
+    /// Call `method`, signing the parameters as the API wants: every
+    /// parameter sorted by name, names and values run together, the secret
+    /// appended, and the MD5 of that
+    fn call(
+        &self,
+        agent: &ureq::Agent,
+        method: &str,
+        mut params: Vec<(&str, String)>,
+    ) -> Result<Value, String> {
+        params.push(("method", method.into()));
+        params.push(("api_key", self.api_key.clone()));
+        params.sort();
+        let mut signature = Md5::new();
+        for (name, value) in &params {
+            signature.update(name.as_bytes());
+            signature.update(value.as_bytes());
+        }
+        signature.update(self.secret.as_bytes());
+        let api_sig: String = signature
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        params.push(("api_sig", api_sig));
+        params.push(("format", "json".into()));
+
+        let mut response = configured(agent.post(LASTFM_API))
+            .send_form(params.iter().map(|(name, value)| (*name, value.as_str())))
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let reply: Value = response
+            .body_mut()
+            .read_json()
+            .map_err(|e| format!("{status}: {e}"))?;
+        match reply["message"].as_str() {
+            Some(message) if reply.get("error").is_some() => Err(message.to_string()),
+            _ if !status.is_success() => Err(status.to_string()),
+            _ => Ok(reply),
+        }
+    }
+
+    /// The session key, logging in first if we don't have one yet
+    fn session_key(&mut self, agent: &ureq::Agent) -> Result<String, String> {
+        if let Some(key) = &self.session_key {
+            return Ok(key.clone());
+        }
+        let Some((username, password)) = self.login.clone() else {
+            return Err("no session key".into());
+        };
+        let reply = self
+            .call(
+                agent,
+                "auth.getMobileSession",
+                vec![("username", username), ("password", password)],
+            )
+            .map_err(|e| format!("couldn't log in: {e}"))?;
+        let key = reply["session"]["key"]
+            .as_str()
+            .ok_or("couldn't log in: no session key in the reply")?
+            .to_string();
+        info!("Logged in to Last.fm");
+        self.session_key = Some(key.clone());
+        Ok(key)
+    }
+}
+
+impl Service for LastFm {
+    fn name(&self) -> &'static str {
+        "Last.fm"
+    }
+
+    fn submit(&mut self, agent: &ureq::Agent, submission: &Submission) -> Result<(), String> {
+        let session_key = self.session_key(agent)?;
+        let (method, track, started) = match submission {
+            Submission::NowPlaying(track) => ("track.updateNowPlaying", track, None),
+            Submission::Scrobble(track, started) => ("track.scrobble", track, Some(*started)),
+            Submission::Love(track, loved) => {
+                let method = if *loved { "track.love" } else { "track.unlove" };
+                let params = vec![
+                    ("artist", track.artist.clone()),
+                    ("track", track.title.clone()),
+                    ("sk", session_key),
+                ];
+                return self.call(agent, method, params).map(|_| ());
+            }
+        };
+        let mut params = vec![
+            ("artist", track.artist.clone()),
+            ("track", track.title.clone()),
+            ("sk", session_key),
+        ];
+        if let Some(album) = &track.album {
+            params.push(("album", album.clone()));
+        }
+        if let Some(length) = track.length {
+            params.push(("duration", length.as_secs().to_string()));
+        }
+        if let Some(started) = started {
+            params.push(("timestamp", started.to_string()));
+        }
+        self.call(agent, method, params).map(|_| ())
+    }
+}
+
+/// The track being listened to, and how long it has played
+struct Listening {
+    track: Track,
+    started: u64,
+    played: Duration,
+    // Since when it has been playing, while it is
+    playing_since: Option<Instant>,
+    announced: bool,
+    scrobbled: bool,
+}
+
+/// Account for a new snapshot, queueing what's due, and return when the
+/// current track will have played long enough to scrobble
+fn follow(
+    listening: &mut Option<Listening>,
+    now: &NowPlaying,
+    queue: &mpsc::Sender<Submission>,
+) -> Option<Instant> {
+    let track = Track::of(now);
+    if listening.as_ref().map(|l| &l.track) != track.as_ref() {
+        *listening = track.map(|track| Listening {
+            track,
+            started: unix_now(),
+            played: Duration::ZERO,
+            playing_since: None,
+            announced: false,
+            scrobbled: false,
+        });
+    }
+    let l = listening.as_mut()?;
+
+    if let Some(since) = l.playing_since.take() {
+        l.played += since.elapsed();
+    }
+    if now.playback.as_deref() != Some("Playing") {
+        return None;
+    }
+    l.playing_since = Some(Instant::now());
+    if !l.announced {
+        l.announced = true;
+        let _ = queue.send(Submission::NowPlaying(l.track.clone()));
+    }
+    let threshold = l.track.threshold()?;
+    if l.scrobbled {
+        return None;
+    }
+    if l.played >= threshold {
+        l.scrobbled = true;
+        let _ = queue.send(Submission::Scrobble(l.track.clone(), l.started));
+        return None;
+    }
+    Some(Instant::now() + (threshold - l.played))
+}
+
+/// Sleep until `deadline`, or forever without one
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Where `POST /rate` sends loves, if scrobbling is on
+#[derive(Default)]
+pub struct Scrobbler {
+    queue: Option<Mutex<mpsc::Sender<Submission>>>,
+}
+
+impl Scrobbler {
+    /// Whether any scrobbling service is configured
+    pub fn is_on(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    /// Love or unlove what's playing on the scrobbling services. False when
+    /// none is configured or the track has no artist and title.
+    pub fn love(&self, now: &NowPlaying, loved: bool) -> bool {
+        let (Some(queue), Some(track)) = (&self.queue, Track::of(now)) else {
+            return false;
+        };
+        queue.locked().send(Submission::Love(track, loved)).is_ok()
+    }
+}
+
+/// Start scrobbling if credentials for any service are set
+pub fn spawn(state: web::Data<AppState>) -> Scrobbler {
+    let mut services: Vec<Box<dyn Service>> = Vec::new();
+    if let Some(lastfm) = LastFm::from_env() {
+        services.push(Box::new(lastfm));
+    }
+    if let Some(listenbrainz) = ListenBrainz::from_env() {
+        services.push(Box::new(listenbrainz));
+    }
+    if services.is_empty() || state.demo.is_some() {
+        return Scrobbler::default();
+    }
+    let names: Vec<_> = services.iter().map(|s| s.name()).collect();
+    info!("Scrobbling to {}", names.join(" and "));
+
+    let (queue, submissions) = mpsc::channel();
+    thread::spawn(move || {
+        for submission in submissions {
+            let agent = outbound::agent(Integration::Scrobbling);
+            for service in &mut services {
+                let result = service.submit(&agent, &submission);
+                match (&submission, result) {
+                    (Submission::Scrobble(track, _), Ok(())) => info!(
+                        "Scrobbled {} – {} to {}",
+                        track.artist,
+                        track.title,
+                        service.name()
+                    ),
+                    (Submission::Love(track, loved), Ok(())) => info!(
+                        "{} {} – {} on {}",
+                        if *loved { "Loved" } else { "Unloved" },
+                        track.artist,
+                        track.title,
+                        service.name()
+                    ),
+                    (_, Ok(())) => {}
+                    (_, Err(e)) => warn!("Couldn't submit to {}: {e}", service.name()),
+                }
+            }
+        }
+    });
+
+    let scrobbler = Scrobbler {
+        queue: Some(Mutex::new(queue.clone())),
+    };
+    let mut updates = state.live.subscribe();
+    actix_web::rt::spawn(async move {
+        let mut listening = None;
+        let mut deadline = None;
+        loop {
+            tokio::select! {
+                changed = updates.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+                _ = until(deadline) => {}
+            }
+            let now = updates.borrow_and_update().clone();
+            deadline = follow(&mut listening, &now, &queue);
+        }
+    });
+    scrobbler
+}
@@ -0,0 +1,662 @@
+//! Strategies for choosing the player to control.
+//!
+//! [`crate::player::select_player`] first drops the players no strategy may
+//! pick (our own publishers, the ignore list), then gives a player pinned
+//! with `/players/select` the win. Otherwise it asks a chain of
+//! [`SelectionStrategy`]s in turn and takes the first answer, falling back to
+//! the first player found.
+//!
+//! The chain is `MEDIA_CONTROL_SELECTION`, a comma-separated list of
+//! `focused_window`, `playing`, `preferred` and `recent`. Each can be
+//! narrowed with a [`PlayerFilter`] after a colon, e.g. `preferred:paused`
+//! only considers paused players. Without it the chain is what
+//! `MEDIA_CONTROL_FOLLOW_FOCUS` and `MEDIA_CONTROL_PREFER_PLAYING` ask for,
+//! then `preferred`.
+//!
+//! Strategies only see players through [`PlayerView`], so they can be tried
+//! on made-up players, and [`Discovered`] reads each property from the bus at
+//! most once per selection.
+
+use crate::error::MutexExt;
+use crate::player::{app_name, get_player_groups, get_player_priority, OWN_IDENTITY};
+use crate::{config, focus, personas};
+use mpris::{PlaybackStatus, Player};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::warn;
+
+/// Players `recent` remembers; the one seen longest ago goes
+const MAX_REMEMBERED: usize = 64;
+
+/// What strategies and filters can ask about a player
+pub trait PlayerView {
+    fn identity(&self) -> &str;
+    fn bus_name(&self) -> &str;
+    /// None when the player doesn't answer
+    fn playback(&self) -> Option<PlaybackStatus>;
+    /// The `.desktop` file name, if the player reports one
+    fn desktop_entry(&self) -> Option<String>;
+}
+
+impl PlayerView for Player {
+    fn identity(&self) -> &str {
+        Player::identity(self)
+    }
+
+    fn bus_name(&self) -> &str {
+        Player::bus_name(self)
+    }
+
+    fn playback(&self) -> Option<PlaybackStatus> {
+        self.get_playback_status().ok()
+    }
+
+    fn desktop_entry(&self) -> Option<String> {
+        self.get_desktop_entry().ok().flatten()
+    }
+}
+
+/// A player on the bus, with what selection asks of it read only once
+pub struct Discovered {
+    pub player: Rc<Player>,
+    playback: OnceCell<Option<PlaybackStatus>>,
+    desktop_entry: OnceCell<Option<String>>,
+}
+
+impl Discovered {
+    pub fn new(player: Rc<Player>) -> Self {
+        Discovered {
+            player,
+            playback: OnceCell::new(),
+            desktop_entry: OnceCell::new(),
+        }
+    }
+}
+
+impl PlayerView for Discovered {
+    fn identity(&self) -> &str {
+        self.player.identity()
+    }
+
+    fn bus_name(&self) -> &str {
+        self.player.bus_name()
+    }
+
+    fn playback(&self) -> Option<PlaybackStatus> {
+        *self
+            .playback
+            .get_or_init(|| PlayerView::playback(&*self.player))
+    }
+
+    fn desktop_entry(&self) -> Option<String> {
+        self.desktop_entry
+            .get_or_init(|| PlayerView::desktop_entry(&*self.player))
+            .clone()
+    }
+}
+
+/// Whether `name`, a lowercase entry from the settings, matches `player`
+/// as a substring of its identity or of the application in its bus name
+pub fn names_player(player: &dyn PlayerView, name: &str) -> bool {
+    player.identity().to_lowercase().contains(name) || app_name(player.bus_name()).contains(name)
+}
+
+/// Of several instances matching the same entry, the one playing, else the
+/// one paused, else the first
+pub fn most_active(players: &[&dyn PlayerView], matches: &[usize]) -> Option<usize> {
+    if matches.len() < 2 {
+        return matches.first().copied();
+    }
+    let rank = |idx: usize| match players[idx].playback() {
+        Some(PlaybackStatus::Playing) => 0,
+        Some(PlaybackStatus::Paused) => 1,
+        _ => 2,
+    };
+    matches.iter().copied().min_by_key(|&idx| rank(idx))
+}
+
+/// A strategy's pick
+#[derive(Clone, Debug, PartialEq)]
+pub struct Choice {
+    // Index into the players the strategy was given
+    pub index: usize,
+    // Reported as `rule` in selection traces and metrics
+    pub rule: &'static str,
+    // The priority entry that matched, for `preferred` and `player_group`
+    pub matched: Option<String>,
+}
+
+impl Choice {
+    fn new(index: usize, rule: &'static str) -> Self {
+        Choice {
+            index,
+            rule,
+            matched: None,
+        }
+    }
+}
+
+/// One way of choosing a player
+pub trait SelectionStrategy {
+    /// The player to control, or None to leave it to the next strategy
+    fn choose(&self, players: &[&dyn PlayerView]) -> Option<Choice>;
+}
+
+/// Something that rules players out of a selection
+pub trait PlayerFilter {
+    /// Why `player` is left out, or None to keep it
+    fn rejects(&self, player: &dyn PlayerView) -> Option<String>;
+}
+
+/// Our own publishers: "My Player" and personas
+pub struct OwnPublisher;
+
+impl PlayerFilter for OwnPublisher {
+    fn rejects(&self, player: &dyn PlayerView) -> Option<String> {
+        let own = player.identity() == OWN_IDENTITY
+            || player.bus_name().starts_with(personas::BUS_PREFIX);
+        own.then(|| "own publisher".to_string())
+    }
+}
+
+/// Players on `MEDIA_CONTROL_PLAYER_IGNORE`
+pub struct Ignored(pub Vec<String>);
+
+impl PlayerFilter for Ignored {
+    fn rejects(&self, player: &dyn PlayerView) -> Option<String> {
+        let identity = player.identity().to_lowercase();
+        let bus_name = player.bus_name().to_lowercase();
+        self.0
+            .iter()
+            .find(|entry| identity.contains(entry.as_str()) || bus_name.contains(entry.as_str()))
+            .map(|entry| format!("ignored ({entry})"))
+    }
+}
+
+/// Players in one play state
+pub struct InState(pub PlaybackStatus);
+
+impl InState {
+    fn parse(name: &str) -> Option<Self> {
+        Some(InState(match name {
+            "playing" => PlaybackStatus::Playing,
+            "paused" => PlaybackStatus::Paused,
+            "stopped" => PlaybackStatus::Stopped,
+            _ => return None,
+        }))
+    }
+}
+
+impl PlayerFilter for InState {
+    fn rejects(&self, player: &dyn PlayerView) -> Option<String> {
+        (player.playback() != Some(self.0)).then(|| format!("not {:?}", self.0).to_lowercase())
+    }
+}
+
+/// A strategy that only sees the players a filter keeps
+pub struct Filtered {
+    pub filter: Box<dyn PlayerFilter>,
+    pub inner: Box<dyn SelectionStrategy>,
+}
+
+impl SelectionStrategy for Filtered {
+    fn choose(&self, players: &[&dyn PlayerView]) -> Option<Choice> {
+        let kept: Vec<usize> = (0..players.len())
+            .filter(|&idx| self.filter.rejects(players[idx]).is_none())
+            .collect();
+        let views: Vec<&dyn PlayerView> = kept.iter().map(|&idx| players[idx]).collect();
+        let choice = self.inner.choose(&views)?;
+        Some(Choice {
+            index: kept[choice.index],
+            ..choice
+        })
+    }
+}
+
+/// `focused_window`: the player behind the focused window
+pub struct FocusedWindow {
+    // The focused window's classes, read when first needed
+    classes: OnceCell<Option<Vec<String>>>,
+}
+
+impl FocusedWindow {
+    /// Follow the window focused right now
+    pub fn current() -> Self {
+        FocusedWindow {
+            classes: OnceCell::new(),
+        }
+    }
+
+    /// As if a window with these classes had focus
+    #[cfg(test)]
+    fn with_classes(classes: Option<Vec<String>>) -> Self {
+        FocusedWindow {
+            classes: OnceCell::from(classes),
+        }
+    }
+}
+
+impl SelectionStrategy for FocusedWindow {
+    fn choose(&self, players: &[&dyn PlayerView]) -> Option<Choice> {
+        let classes = self
+            .classes
+            .get_or_init(focus::focused_window_classes)
+            .as_ref()?;
+        players
+            .iter()
+            .position(|p| {
+                focus::matches_player(classes, p.identity(), p.desktop_entry().as_deref())
+            })
+            .map(|idx| Choice::new(idx, "focused_window"))
+    }
+}
+
+/// `preferred`: the priority list in order, each entry matching its own name
+/// first and then the other members of its group
+#[derive(Clone)]
+pub struct Preferred {
+    pub priority: Vec<String>,
+    pub groups: HashMap<String, Vec<String>>,
+}
+
+impl Preferred {
+    pub fn from_env() -> Self {
+        Preferred {
+            priority: get_player_priority(),
+            groups: get_player_groups(),
+        }
+    }
+}
+
+impl SelectionStrategy for Preferred {
+    fn choose(&self, players: &[&dyn PlayerView]) -> Option<Choice> {
+        for name in &self.priority {
+            let members = self.groups.get(name).map(Vec::as_slice).unwrap_or_default();
+            for (i, member) in std::iter::once(name).chain(members).enumerate() {
+                let found: Vec<usize> = (0..players.len())
+                    .filter(|&idx| names_player(players[idx], member))
+                    .collect();
+                if let Some(idx) = most_active(players, &found) {
+                    return Some(Choice {
+                        index: idx,
+                        rule: if i == 0 { "preferred" } else { "player_group" },
+                        matched: Some(name.clone()),
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// `playing`: a playing player, the one the priority list names first if
+/// several are
+pub struct Playing(pub Preferred);
+
+impl SelectionStrategy for Playing {
+    fn choose(&self, players: &[&dyn PlayerView]) -> Option<Choice> {
+        let playing: Vec<usize> = (0..players.len())
+            .filter(|&idx| players[idx].playback() == Some(PlaybackStatus::Playing))
+            .collect();
+        let first = *playing.first()?;
+        let views: Vec<&dyn PlayerView> = playing.iter().map(|&idx| players[idx]).collect();
+        let index = self.0.choose(&views).map_or(first, |c| playing[c.index]);
+        Some(Choice::new(index, "playing"))
+    }
+}
+
+/// When each player was last seen playing, by bus name
+pub struct Activity {
+    seen: Mutex<Vec<(String, Instant)>>,
+}
+
+impl Activity {
+    pub const fn new() -> Self {
+        Activity {
+            seen: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn record(&self, bus_name: &str, at: Instant) {
+        let mut seen = self.seen.locked();
+        seen.retain(|(name, _)| name != bus_name);
+        if seen.len() >= MAX_REMEMBERED {
+            seen.remove(0);
+        }
+        seen.push((bus_name.to_string(), at));
+    }
+
+    fn last(&self, bus_name: &str) -> Option<Instant> {
+        self.seen
+            .locked()
+            .iter()
+            .find(|(name, _)| name == bus_name)
+            .map(|(_, at)| *at)
+    }
+}
+
+/// Players seen playing by `recent`, across selections
+static ACTIVITY: Activity = Activity::new();
+
+/// `recent`: the player that was playing most recently, as seen by earlier
+/// selections. Playing players count as playing now.
+pub struct MostRecentlyActive<'a>(pub &'a Activity);
+
+impl SelectionStrategy for MostRecentlyActive<'_> {
+    fn choose(&self, players: &[&dyn PlayerView]) -> Option<Choice> {
+        let now = Instant::now();
+        for player in players {
+            if player.playback() == Some(PlaybackStatus::Playing) {
+                self.0.record(player.bus_name(), now);
+            }
+        }
+        (0..players.len())
+            .filter_map(|idx| Some((self.0.last(players[idx].bus_name())?, idx)))
+            // The latest wins, the earliest listed on a tie
+            .max_by(|(a, a_idx), (b, b_idx)| a.cmp(b).then(b_idx.cmp(a_idx)))
+            .map(|(_, idx)| Choice::new(idx, "recent"))
+    }
+}
+
+/// Build one step of `MEDIA_CONTROL_SELECTION`, e.g. `preferred:playing`
+fn parse_step(step: &str, preferred: &Preferred) -> Result<Box<dyn SelectionStrategy>, String> {
+    let (name, filter) = match step.split_once(':') {
+        Some((name, filter)) => (name.trim(), Some(filter.trim())),
+        None => (step, None),
+    };
+    let strategy: Box<dyn SelectionStrategy> = match name {
+        "focused_window" => Box::new(FocusedWindow::current()),
+        "playing" => Box::new(Playing(preferred.clone())),
+        "preferred" => Box::new(preferred.clone()),
+        "recent" => Box::new(MostRecentlyActive(&ACTIVITY)),
+        _ => {
+            return Err(format!(
+                "unknown strategy {name:?}, expected focused_window, playing, preferred or recent"
+            ))
+        }
+    };
+    let Some(filter) = filter else {
+        return Ok(strategy);
+    };
+    let filter = InState::parse(filter)
+        .ok_or_else(|| format!("unknown filter {filter:?}, expected playing, paused or stopped"))?;
+    Ok(Box::new(Filtered {
+        filter: Box::new(filter),
+        inner: strategy,
+    }))
+}
+
+fn parse(list: &str, preferred: &Preferred) -> Vec<Result<Box<dyn SelectionStrategy>, String>> {
+    list.split(',')
+        .map(|step| step.trim().to_lowercase())
+        .filter(|step| !step.is_empty())
+        .map(|step| parse_step(&step, preferred))
+        .collect()
+}
+
+fn get_flag(name: &str) -> bool {
+    config::var(name)
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// The strategies to try, in order
+pub fn from_env() -> Vec<Box<dyn SelectionStrategy>> {
+    let preferred = Preferred::from_env();
+    if let Ok(list) = config::var("MEDIA_CONTROL_SELECTION") {
+        return parse(&list, &preferred)
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+    }
+    let mut strategies: Vec<Box<dyn SelectionStrategy>> = Vec::new();
+    if get_flag("MEDIA_CONTROL_FOLLOW_FOCUS") {
+        strategies.push(Box::new(FocusedWindow::current()));
+    }
+    if get_flag("MEDIA_CONTROL_PREFER_PLAYING") {
+        strategies.push(Box::new(Playing(preferred.clone())));
+    }
+    strategies.push(Box::new(preferred));
+    strategies
+}
+
+/// Warn about steps of `MEDIA_CONTROL_SELECTION` that are left out, since
+/// selection itself runs too often to say
+pub fn check() {
+    let Ok(list) = config::var("MEDIA_CONTROL_SELECTION") else {
+        return;
+    };
+    for result in parse(&list, &Preferred::from_env()) {
+        if let Err(e) = result {
+            warn!("Ignoring part of MEDIA_CONTROL_SELECTION: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct Fake {
+        identity: &'static str,
+        bus_name: &'static str,
+        playback: Option<PlaybackStatus>,
+        desktop_entry: Option<&'static str>,
+    }
+
+    impl PlayerView for Fake {
+        fn identity(&self) -> &str {
+            self.identity
+        }
+
+        fn bus_name(&self) -> &str {
+            self.bus_name
+        }
+
+        fn playback(&self) -> Option<PlaybackStatus> {
+            self.playback
+        }
+
+        fn desktop_entry(&self) -> Option<String> {
+            self.desktop_entry.map(String::from)
+        }
+    }
+
+    fn fake(identity: &'static str, bus_name: &'static str, playback: PlaybackStatus) -> Fake {
+        Fake {
+            identity,
+            bus_name,
+            playback: Some(playback),
+            desktop_entry: None,
+        }
+    }
+
+    fn views(players: &[Fake]) -> Vec<&dyn PlayerView> {
+        players.iter().map(|p| p as &dyn PlayerView).collect()
+    }
+
+    fn preferred(priority: &[&str], groups: &[(&str, &[&str])]) -> Preferred {
+        Preferred {
+            priority: priority.iter().map(|s| s.to_string()).collect(),
+            groups: groups
+                .iter()
+                .map(|(name, members)| {
+                    (
+                        name.to_string(),
+                        members.iter().map(|m| m.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn index(choice: Option<Choice>) -> Option<(usize, &'static str)> {
+        choice.map(|c| (c.index, c.rule))
+    }
+
+    const PLAYING: PlaybackStatus = PlaybackStatus::Playing;
+    const PAUSED: PlaybackStatus = PlaybackStatus::Paused;
+    const STOPPED: PlaybackStatus = PlaybackStatus::Stopped;
+
+    #[test]
+    fn preferred_walks_the_priority_list_in_order() {
+        let players = [
+            fake("VLC media player", "org.mpris.MediaPlayer2.vlc", PLAYING),
+            fake("Spotify", "org.mpris.MediaPlayer2.spotify", PAUSED),
+        ];
+        let strategy = preferred(&["spotify", "vlc"], &[]);
+        let choice = strategy.choose(&views(&players)).unwrap();
+        assert_eq!((choice.index, choice.rule), (1, "preferred"));
+        assert_eq!(choice.matched.as_deref(), Some("spotify"));
+        assert!(preferred(&["mpv"], &[]).choose(&views(&players)).is_none());
+    }
+
+    #[test]
+    fn preferred_falls_back_to_group_members_and_the_most_active_instance() {
+        let players = [
+            fake(
+                "Google Chrome",
+                "org.mpris.MediaPlayer2.chrome.instance1",
+                PAUSED,
+            ),
+            fake(
+                "Google Chrome",
+                "org.mpris.MediaPlayer2.chrome.instance2",
+                PLAYING,
+            ),
+        ];
+        let strategy = preferred(&["chromium"], &[("chromium", &["chrome"])]);
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((1, "player_group"))
+        );
+    }
+
+    #[test]
+    fn playing_prefers_the_priority_list_among_playing_players() {
+        let players = [
+            fake("Firefox", "org.mpris.MediaPlayer2.firefox", PLAYING),
+            fake("Spotify", "org.mpris.MediaPlayer2.spotify", PAUSED),
+            fake("VLC media player", "org.mpris.MediaPlayer2.vlc", PLAYING),
+        ];
+        let strategy = Playing(preferred(&["spotify", "vlc"], &[]));
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((2, "playing"))
+        );
+
+        let strategy = Playing(preferred(&["mpv"], &[]));
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((0, "playing"))
+        );
+
+        let idle = [fake("Spotify", "org.mpris.MediaPlayer2.spotify", PAUSED)];
+        assert!(strategy.choose(&views(&idle)).is_none());
+    }
+
+    #[test]
+    fn focused_window_matches_identity_or_desktop_entry() {
+        let mut players = [
+            fake("Spotify", "org.mpris.MediaPlayer2.spotify", PLAYING),
+            fake("Celluloid", "org.mpris.MediaPlayer2.celluloid", PAUSED),
+        ];
+        players[1].desktop_entry = Some("io.github.celluloid_player.celluloid");
+
+        let firefox = FocusedWindow::with_classes(Some(vec!["navigator".into(), "firefox".into()]));
+        assert!(firefox.choose(&views(&players)).is_none());
+
+        let celluloid =
+            FocusedWindow::with_classes(Some(vec!["io.github.celluloid_player.celluloid".into()]));
+        assert_eq!(
+            index(celluloid.choose(&views(&players))),
+            Some((1, "focused_window"))
+        );
+
+        let spotify = FocusedWindow::with_classes(Some(vec!["spotify".into()]));
+        assert_eq!(
+            index(spotify.choose(&views(&players))),
+            Some((0, "focused_window"))
+        );
+
+        assert!(FocusedWindow::with_classes(None)
+            .choose(&views(&players))
+            .is_none());
+    }
+
+    #[test]
+    fn recent_picks_the_player_that_played_last() {
+        let activity = Activity::new();
+        let players = [
+            fake("Spotify", "org.mpris.MediaPlayer2.spotify", PAUSED),
+            fake("VLC media player", "org.mpris.MediaPlayer2.vlc", STOPPED),
+            fake("Firefox", "org.mpris.MediaPlayer2.firefox", PAUSED),
+        ];
+        let strategy = MostRecentlyActive(&activity);
+        assert!(strategy.choose(&views(&players)).is_none());
+
+        let earlier = Instant::now() - Duration::from_secs(60);
+        activity.record("org.mpris.MediaPlayer2.spotify", earlier);
+        activity.record(
+            "org.mpris.MediaPlayer2.vlc",
+            earlier + Duration::from_secs(30),
+        );
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((1, "recent"))
+        );
+
+        // Playing means active now, and is remembered once it stops
+        let mut players = players;
+        players[2].playback = Some(PLAYING);
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((2, "recent"))
+        );
+        players[2].playback = Some(PAUSED);
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((2, "recent"))
+        );
+    }
+
+    #[test]
+    fn filters_narrow_a_strategy_and_keep_indexes() {
+        let players = [
+            fake("Spotify", "org.mpris.MediaPlayer2.spotify", PLAYING),
+            fake("VLC media player", "org.mpris.MediaPlayer2.vlc", PAUSED),
+            fake("mpv", "org.mpris.MediaPlayer2.mpv", PAUSED),
+        ];
+        let strategy = Filtered {
+            filter: Box::new(InState(PAUSED)),
+            inner: Box::new(preferred(&["spotify", "mpv"], &[])),
+        };
+        assert_eq!(
+            index(strategy.choose(&views(&players))),
+            Some((2, "preferred"))
+        );
+
+        assert_eq!(
+            Ignored(vec!["vlc".into()]).rejects(&players[1]).as_deref(),
+            Some("ignored (vlc)")
+        );
+        assert!(Ignored(vec!["vlc".into()]).rejects(&players[0]).is_none());
+        let own = fake(OWN_IDENTITY, "org.mpris.MediaPlayer2.x", PAUSED);
+        assert!(OwnPublisher.rejects(&own).is_some());
+    }
+
+    #[test]
+    fn selection_setting_names_strategies_and_filters() {
+        let preferred = preferred(&["spotify"], &[]);
+        let parsed = parse("focused_window, preferred:playing,, recent", &preferred);
+        assert_eq!(parsed.len(), 3);
+        assert!(parsed.iter().all(Result::is_ok));
+
+        let parsed = parse("loudest,preferred:muted", &preferred);
+        assert!(parsed.iter().all(Result::is_err));
+    }
+}
@@ -0,0 +1,108 @@
+//! Shutting down cleanly.
+//!
+//! On `SIGTERM` or `SIGINT` the server stops accepting connections and gives
+//! requests in flight `MEDIA_CONTROL_SHUTDOWN_TIMEOUT_SECS` to finish. Then
+//! [`finish`] takes "My Player" and the personas off the bus, so they don't
+//! linger in desktop widgets until the bus notices, writes out the playback
+//! history and saves the pinned player.
+//!
+//! The pinned player is kept in `MEDIA_CONTROL_SESSION_FILE`, by default
+//! `$XDG_STATE_HOME/media-controller/session.json`, and pinned again on the
+//! next start if it's still around by then.
+
+use crate::config;
+use crate::error::MutexExt;
+use crate::AppState;
+use actix_web::dev::ServerHandle;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+/// What is saved on exit and picked up on the next start
+#[derive(Default, Serialize, Deserialize)]
+struct Session {
+    // Bus name of the player pinned with /players/select
+    pinned: Option<String>,
+}
+
+/// Where the session is saved
+fn file_path() -> Option<PathBuf> {
+    if let Ok(path) = config::var("MEDIA_CONTROL_SESSION_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    Some(state_home.join("media-controller").join("session.json"))
+}
+
+/// The player pinned when the service last exited
+pub fn saved_pin() -> Option<String> {
+    let path = file_path()?;
+    let text = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<Session>(&text) {
+        Ok(session) => {
+            if let Some(pinned) = &session.pinned {
+                info!("Pinning {pinned} again, as before the restart");
+            }
+            session.pinned
+        }
+        Err(e) => {
+            warn!("Ignoring the saved session in {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+fn save(state: &AppState) {
+    let Some(path) = file_path() else {
+        return;
+    };
+    let session = Session {
+        pinned: state.pinned.locked().clone(),
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, serde_json::to_string(&session)?));
+    if let Err(e) = result {
+        warn!("Couldn't save the session to {}: {e}", path.display());
+    }
+}
+
+/// Stop `server` gracefully on `SIGTERM` or `SIGINT`. actix would drop
+/// requests in flight on `SIGINT`.
+pub fn stop_on_signals(server: ServerHandle) {
+    let signals = signal(SignalKind::terminate())
+        .and_then(|term| Ok((term, signal(SignalKind::interrupt())?)));
+    let (mut term, mut int) = match signals {
+        Ok(signals) => signals,
+        Err(e) => {
+            error!("Can't watch for SIGTERM and SIGINT, graceful shutdown disabled: {e}");
+            return;
+        }
+    };
+    actix_web::rt::spawn(async move {
+        let name = tokio::select! {
+            _ = term.recv() => "SIGTERM",
+            _ = int.recv() => "SIGINT",
+        };
+        info!("{name} received, finishing requests in flight");
+        server.stop(true).await;
+    });
+}
+
+/// Clean up once the server has stopped
+pub fn finish(state: &AppState) {
+    if state.demo.is_none() {
+        if let Err(e) = state.controls.locked().detach() {
+            error!("Failed to detach MPRIS publisher: {e:?}");
+        }
+    }
+    state.personas.unregister();
+    save(state);
+    info!("Shut down");
+}
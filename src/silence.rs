@@ -0,0 +1,139 @@
+//! Dead-air detection for the controlled player.
+//!
+//! Enabled by setting `MEDIA_CONTROL_SILENCE_SECS`. While the controlled
+//! player reports `Playing`, we periodically record a short sample of its
+//! stream with `parec --monitor-stream` and look at the peak level. If the
+//! stream stays below `MEDIA_CONTROL_SILENCE_THRESHOLD` (0.0–1.0 of full
+//! scale) for the configured time we log it and optionally run
+//! `MEDIA_CONTROL_SILENCE_ACTION` (`pause`, `next`, ...).
+
+use crate::actions::{self, Action};
+use crate::audio;
+use crate::config;
+use crate::player::select_player;
+use crate::AppState;
+use actix_web::web;
+use mpris::PlaybackStatus;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Sample rate used for level monitoring; plenty for a peak meter
+const SAMPLE_RATE: u32 = 8000;
+/// How long each level sample listens for
+const SAMPLE_WINDOW: Duration = Duration::from_millis(500);
+/// Pause between samples
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Silence detection settings
+struct SilenceConfig {
+    after: Duration,
+    threshold: f32,
+    action: Option<Action>,
+}
+
+impl SilenceConfig {
+    fn from_env() -> Option<Self> {
+        let after = config::var("MEDIA_CONTROL_SILENCE_SECS")
+            .ok()?
+            .parse()
+            .ok()?;
+        let threshold = config::var("MEDIA_CONTROL_SILENCE_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0.001);
+        let action = config::var("MEDIA_CONTROL_SILENCE_ACTION")
+            .ok()
+            .and_then(|v| {
+                serde_json::from_value(serde_json::Value::String(v.clone()))
+                    .map_err(|_| warn!("Ignoring unknown MEDIA_CONTROL_SILENCE_ACTION {v:?}"))
+                    .ok()
+            });
+        Some(SilenceConfig {
+            after: Duration::from_secs(after),
+            threshold,
+            action,
+        })
+    }
+}
+
+/// Start the monitor thread if silence detection is configured
+pub fn spawn_monitor(state: web::Data<AppState>) {
+    let Some(config) = SilenceConfig::from_env() else {
+        return;
+    };
+    info!(
+        "Silence detection enabled: {:?} below {} triggers {:?}",
+        config.after, config.threshold, config.action
+    );
+    thread::spawn(move || monitor(&state, &config));
+}
+
+fn monitor(state: &AppState, config: &SilenceConfig) {
+    let mut silent_since: Option<Instant> = None;
+
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+
+        let (player, _) = select_player(&state.pinned);
+        let peak = player
+            .filter(|p| matches!(p.get_playback_status(), Ok(PlaybackStatus::Playing)))
+            .and_then(|p| audio::sink_inputs_for_player(p.identity()).ok())
+            .and_then(|inputs| {
+                inputs
+                    .iter()
+                    .filter_map(|input| sample_peak(input.index))
+                    .reduce(f32::max)
+            });
+
+        match peak {
+            Some(peak) if peak < config.threshold => {
+                let since = *silent_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= config.after {
+                    info!(
+                        "Controlled player has been silent for {:?}",
+                        since.elapsed()
+                    );
+                    if let Some(action) = config.action {
+                        match actions::run(state, action) {
+                            Ok(msg) => info!("Silence action {action:?}: {msg}"),
+                            Err(e) => warn!("Silence action {action:?} failed: {e}"),
+                        }
+                    }
+                    silent_since = None;
+                }
+            }
+            // Not playing, no stream found, or audible: start counting afresh
+            _ => silent_since = None,
+        }
+    }
+}
+
+/// Record a short mono sample of one sink input and return its peak (0.0–1.0)
+fn sample_peak(sink_input: u32) -> Option<f32> {
+    let mut child = Command::new("parec")
+        .args([
+            &format!("--monitor-stream={sink_input}"),
+            "--format=s16le",
+            "--channels=1",
+            &format!("--rate={SAMPLE_RATE}"),
+            "--raw",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let bytes = (SAMPLE_RATE as u128 * SAMPLE_WINDOW.as_millis() / 1000) as usize * 2;
+    let mut buf = vec![0u8; bytes];
+    let read = child.stdout.take()?.read_exact(&mut buf);
+    let _ = child.kill();
+    let _ = child.wait();
+    read.ok()?;
+
+    buf.chunks_exact(2)
+        .map(|s| (i16::from_le_bytes([s[0], s[1]]) as f32).abs() / i16::MAX as f32)
+        .reduce(f32::max)
+}
@@ -0,0 +1,193 @@
+//! Skimming: play faster for a while instead of jumping blindly.
+//!
+//! `POST /skim` with `{"seconds": 60}` raises the controlled player's `Rate`
+//! to `MEDIA_CONTROL_SKIM_RATE` (default 2.5, capped at the player's
+//! `MaximumRate`) and puts the original rate back once the time is up. You
+//! still hear where you are, which beats 30-second jumps when hunting for
+//! where you left off in a podcast. Skimming again before that extends it
+//! and keeps the original rate to restore. If the rate was changed by
+//! someone else in the meantime, it's left alone.
+
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use crate::player::{find_player, find_player_by_selector};
+use crate::{get_dry_run_mode, lock, AppState};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use media_controller::models::ErrorBody;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// Default for `MEDIA_CONTROL_SKIM_RATE`
+const DEFAULT_RATE: f64 = 2.5;
+
+/// Read `MEDIA_CONTROL_SKIM_RATE`
+fn rate_from_env() -> f64 {
+    config::var("MEDIA_CONTROL_SKIM_RATE")
+        .ok()
+        .and_then(|v| match v.parse::<f64>() {
+            Ok(rate) if rate > 1.0 => Some(rate),
+            _ => {
+                warn!("Ignoring MEDIA_CONTROL_SKIM_RATE, expected a factor above 1");
+                None
+            }
+        })
+        .unwrap_or(DEFAULT_RATE)
+}
+
+/// A skim in progress
+struct Skimming {
+    id: u64,
+    bus_name: String,
+    // The rate we set
+    rate: f64,
+    // The rate to go back to
+    original: f64,
+}
+
+/// The running skim, if any
+#[derive(Default)]
+pub struct Skim {
+    current: Mutex<Option<Skimming>>,
+    next_id: AtomicU64,
+}
+
+/// Request body for POST /skim
+#[derive(Deserialize, ToSchema)]
+pub struct SkimRequest {
+    // How long to play faster
+    seconds: f64,
+    // Overrides MEDIA_CONTROL_SKIM_RATE for this skim
+    rate: Option<f64>,
+}
+
+/// Response of POST /skim
+#[derive(Serialize, ToSchema)]
+struct SkimView {
+    player: String,
+    rate: f64,
+    seconds: f64,
+    restores_to: f64,
+}
+
+/// POST /skim — play the controlled player faster for a while
+#[utoipa::path(
+    post,
+    path = "/skim",
+    tag = "Playback",
+    request_body = SkimRequest,
+    responses(
+        (status = 200, description = "The skim has started", body = SkimView),
+        (status = 400, description = "Invalid seconds or rate", body = ErrorBody),
+        (status = 404, description = "No external player", body = ErrorBody),
+    ),
+)]
+pub async fn skim(state: web::Data<AppState>, body: web::Json<SkimRequest>) -> impl Responder {
+    let SkimRequest { seconds, rate } = body.into_inner();
+    if !(seconds > 0.0 && seconds.is_finite()) {
+        return AppError::bad_request("\"seconds\" must be a positive number").error_response();
+    }
+    if rate.is_some_and(|r| !(r > 1.0 && r.is_finite())) {
+        return AppError::bad_request("\"rate\" must be above 1").error_response();
+    }
+    if state.demo.is_some() {
+        return AppError::bad_request("the demo player has no playback rate").error_response();
+    }
+    if state.lock.is_locked() {
+        return lock::refused();
+    }
+    let Some(player) = find_player(&state.pinned) else {
+        return AppError::not_found("no external player found").error_response();
+    };
+    let supported = player.can_control().unwrap_or(false)
+        && player.has_playback_rate().unwrap_or(false)
+        && player.can_set_playback_rate().unwrap_or(false);
+    if !supported {
+        return AppError::bad_request(format!(
+            "{} doesn't support changing the playback rate",
+            player.identity()
+        ))
+        .error_response();
+    }
+
+    let max = player.get_maximum_playback_rate().unwrap_or(1.0);
+    let rate = rate.unwrap_or_else(rate_from_env).min(max);
+    let bus_name = player.bus_name().to_string();
+    let mut current = state.skim.current.locked();
+    // Skimming again extends the skim, keeping the rate from before the first skim
+    let original = match current.as_ref() {
+        Some(s) if s.bus_name == bus_name => s.original,
+        _ => player.get_playback_rate().unwrap_or(1.0),
+    };
+    let view = SkimView {
+        player: player.identity().to_string(),
+        rate,
+        seconds,
+        restores_to: original,
+    };
+    if get_dry_run_mode() {
+        return HttpResponse::Ok().json(view);
+    }
+
+    if let Err(e) = player.set_playback_rate(rate) {
+        return AppError::internal(format!("couldn't set the playback rate: {e}")).error_response();
+    }
+    let id = state.skim.next_id.fetch_add(1, Ordering::Relaxed);
+    let previous = current.replace(Skimming {
+        id,
+        bus_name,
+        rate,
+        original,
+    });
+    drop(current);
+    // A skim of some other player is over now
+    if let Some(previous) = previous.filter(|p| p.bus_name != player.bus_name()) {
+        put_back(&previous);
+    }
+    info!("Skimming {} at {rate}x for {seconds}s", view.player);
+
+    let state = state.into_inner();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_secs_f64(seconds));
+        restore(&state, id);
+    });
+    HttpResponse::Ok().json(view)
+}
+
+/// Put the original rate back, unless a later skim took over
+fn restore(state: &AppState, id: u64) {
+    let mut current = state.skim.current.locked();
+    if current.as_ref().map(|s| s.id) != Some(id) {
+        return;
+    }
+    if let Some(skim) = current.take() {
+        drop(current);
+        put_back(&skim);
+    }
+}
+
+/// Give a skimmed player its original rate back
+fn put_back(skim: &Skimming) {
+    let Some(player) = find_player_by_selector(&skim.bus_name) else {
+        return;
+    };
+    // Someone picked another speed meanwhile; theirs wins
+    if player.get_playback_rate().ok() != Some(skim.rate) {
+        return;
+    }
+    match player.set_playback_rate(skim.original) {
+        Ok(()) => info!(
+            "Skim over, {} back at {}x",
+            player.identity(),
+            skim.original
+        ),
+        Err(e) => error!(
+            "Couldn't restore {}'s playback rate: {e}",
+            player.identity()
+        ),
+    }
+}
@@ -0,0 +1,256 @@
+//! Sleep timer.
+//!
+//! `POST /sleep_timer` with `{"minutes": 30, "action": "pause", "fade": true}`
+//! pauses (or stops) the controlled player once the time is up. With `fade`
+//! the system volume is lowered to nothing over the last minute first, and
+//! put back once the player has paused, so the next morning doesn't start
+//! silent. Setting a timer replaces the running one. `GET /sleep_timer`
+//! shows what's left and `DELETE /sleep_timer` cancels it, putting the
+//! volume back if it was fading.
+//!
+//! While volume and seeking are locked the timer still fires, without
+//! fading. In dry run it only logs what it would do.
+
+use crate::actions::{self, Action, Target};
+use crate::error::{AppError, MutexExt};
+use crate::{get_dry_run_mode, volume, AppState};
+use actix_web::{web, HttpResponse};
+use media_controller::models::{ErrorBody, SleepTimerState};
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How long the fade takes, or the whole timer if that's shorter
+const FADE: Duration = Duration::from_secs(60);
+/// How often the timer checks whether it was cancelled, and fades a step
+const TICK: Duration = Duration::from_millis(500);
+/// Longest timer `minutes` can ask for
+const MAX_MINUTES: f64 = 24.0 * 60.0;
+
+/// What to do when the time is up
+#[derive(Clone, Copy, Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SleepAction {
+    #[default]
+    Pause,
+    Stop,
+}
+
+impl SleepAction {
+    fn action(self) -> Action {
+        match self {
+            SleepAction::Pause => Action::Pause,
+            SleepAction::Stop => Action::Stop,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SleepAction::Pause => "pause",
+            SleepAction::Stop => "stop",
+        }
+    }
+}
+
+/// Body of POST /sleep_timer
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SleepTimerRequest {
+    // Minutes until the timer fires, fractions allowed
+    minutes: f64,
+    // `pause` or `stop` (default `pause`)
+    #[serde(default)]
+    action: SleepAction,
+    // Fade the system volume out over the last minute first
+    #[serde(default)]
+    fade: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Armed {
+    action: SleepAction,
+    fade: bool,
+    fires_at: Instant,
+    // How long before firing the fade starts
+    fade_for: Duration,
+}
+
+/// The running timer, if any
+#[derive(Default)]
+pub struct SleepTimer {
+    // Bumped by every new timer and cancel, so an old timer's thread can
+    // tell it has been replaced
+    inner: Mutex<(u64, Option<Armed>)>,
+}
+
+impl SleepTimer {
+    fn view(&self) -> SleepTimerState {
+        match self.inner.locked().1 {
+            Some(armed) => SleepTimerState {
+                active: true,
+                action: Some(armed.action.name().into()),
+                fade: Some(armed.fade),
+                remaining_secs: Some(
+                    armed
+                        .fires_at
+                        .saturating_duration_since(Instant::now())
+                        .as_secs(),
+                ),
+            },
+            None => SleepTimerState {
+                active: false,
+                action: None,
+                fade: None,
+                remaining_secs: None,
+            },
+        }
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.inner.locked().0 == generation
+    }
+}
+
+/// Wait for `armed` to fire, fading on the way, unless a newer timer or a
+/// cancel comes first
+fn run(state: &AppState, timers: &SleepTimer, generation: u64, armed: Armed) {
+    let dry_run = get_dry_run_mode();
+    // The volume before fading started, to put back
+    let mut faded_from: Option<f64> = None;
+    let restore = |from: Option<f64>| {
+        if let Some(level) = from {
+            if let Err(e) = volume::backend().set_volume_exact(level) {
+                warn!("Sleep timer couldn't put the volume back: {e}");
+            }
+        }
+    };
+
+    loop {
+        if !timers.is_current(generation) {
+            restore(faded_from);
+            return;
+        }
+        let left = armed.fires_at.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        if armed.fade && left <= armed.fade_for && !dry_run && !state.lock.is_locked() {
+            if faded_from.is_none() {
+                faded_from = volume::backend().get_volume_exact().ok();
+            }
+            if let Some(from) = faded_from {
+                let level = from * left.as_secs_f64() / armed.fade_for.as_secs_f64();
+                let _ = volume::backend().set_volume_exact(level);
+            }
+        }
+        thread::sleep(left.min(TICK));
+    }
+
+    {
+        let mut inner = timers.inner.locked();
+        if inner.0 != generation {
+            drop(inner);
+            restore(faded_from);
+            return;
+        }
+        inner.1 = None;
+    }
+    let action = armed.action.action();
+    let result = if dry_run {
+        actions::plan(state, &Target::Auto, action)
+    } else {
+        actions::run(state, action)
+    };
+    match result {
+        Ok(msg) => info!("Sleep timer is up: {msg}"),
+        Err(e) => warn!(
+            "Sleep timer is up, but couldn't {}: {e}",
+            armed.action.name()
+        ),
+    }
+    restore(faded_from);
+}
+
+/// POST /sleep_timer — pause or stop the controlled player after a while
+///
+/// Replaces any running timer.
+#[utoipa::path(
+    post,
+    path = "/sleep_timer",
+    tag = "Playback",
+    request_body = SleepTimerRequest,
+    responses(
+        (status = 200, description = "The timer is running", body = SleepTimerState),
+        (status = 400, description = "Minutes out of range", body = ErrorBody),
+    ),
+)]
+pub async fn set_sleep_timer(
+    state: web::Data<AppState>,
+    timers: web::Data<SleepTimer>,
+    body: web::Json<SleepTimerRequest>,
+) -> Result<HttpResponse, AppError> {
+    let SleepTimerRequest {
+        minutes,
+        action,
+        fade,
+    } = body.into_inner();
+    let duration = Some(minutes)
+        .filter(|minutes| *minutes > 0.0 && *minutes <= MAX_MINUTES)
+        .and_then(|minutes| Duration::try_from_secs_f64(minutes * 60.0).ok())
+        .ok_or_else(|| {
+            AppError::bad_request(format!("minutes must be between 0 and {MAX_MINUTES}"))
+        })?;
+    let armed = Armed {
+        action,
+        fade,
+        fires_at: Instant::now() + duration,
+        fade_for: FADE.min(duration),
+    };
+    let generation = {
+        let mut inner = timers.inner.locked();
+        inner.0 += 1;
+        inner.1 = Some(armed);
+        inner.0
+    };
+    info!(
+        "Sleep timer set: {} in {minutes} minutes{}",
+        action.name(),
+        if fade { ", fading out" } else { "" }
+    );
+
+    let (state, worker) = (state.into_inner(), timers.clone().into_inner());
+    thread::spawn(move || run(&state, &worker, generation, armed));
+    Ok(HttpResponse::Ok().json(timers.view()))
+}
+
+/// GET /sleep_timer — whether a sleep timer is running, and how long it has left
+#[utoipa::path(
+    get,
+    path = "/sleep_timer",
+    tag = "Playback",
+    responses((status = 200, description = "The timer", body = SleepTimerState)),
+)]
+pub async fn get_sleep_timer(timers: web::Data<SleepTimer>) -> HttpResponse {
+    HttpResponse::Ok().json(timers.view())
+}
+
+/// DELETE /sleep_timer — cancel the sleep timer, putting back a faded volume
+#[utoipa::path(
+    delete,
+    path = "/sleep_timer",
+    tag = "Playback",
+    responses((status = 200, description = "No timer is running", body = SleepTimerState)),
+)]
+pub async fn cancel_sleep_timer(timers: web::Data<SleepTimer>) -> HttpResponse {
+    {
+        let mut inner = timers.inner.locked();
+        if inner.1.take().is_some() {
+            info!("Sleep timer cancelled");
+        }
+        inner.0 += 1;
+    }
+    HttpResponse::Ok().json(timers.view())
+}
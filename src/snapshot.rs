@@ -0,0 +1,223 @@
+//! Capture and restore full playback state.
+//!
+//! A snapshot records the controlled player, its track and position, shuffle
+//! and loop state, playback status and the system volume, so something like a
+//! doorbell announcement can interrupt playback and put everything back
+//! afterwards.
+
+use crate::error::{AppError, MutexExt};
+use crate::player::{self, find_player, find_player_by_selector};
+use crate::volume;
+use crate::AppState;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use media_controller::models::ErrorBody;
+use mpris::TrackID;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// How many snapshots to keep before dropping the oldest
+const MAX_SNAPSHOTS: usize = 32;
+
+/// Everything needed to put playback back the way it was
+#[derive(Clone, Serialize, ToSchema)]
+pub struct Snapshot {
+    pub id: u64,
+    pub player: String,
+    pub bus_name: String,
+    pub playback: String,
+    pub track_id: Option<String>,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub position_ms: Option<u64>,
+    pub shuffle: Option<bool>,
+    pub loop_status: Option<String>,
+    pub volume: Option<u32>,
+}
+
+/// Snapshots taken so far, by id
+#[derive(Default)]
+pub struct Snapshots {
+    inner: Mutex<(u64, HashMap<u64, Snapshot>)>,
+}
+
+/// Read the current state of the controlled player and the mixer
+fn capture(id: u64, pinned: &Mutex<Option<String>>) -> Option<Snapshot> {
+    let p = find_player(pinned)?;
+    let metadata = p.get_metadata().ok();
+    Some(Snapshot {
+        id,
+        player: p.identity().to_string(),
+        bus_name: p.bus_name().to_string(),
+        playback: p
+            .get_playback_status()
+            .map(|s| format!("{s:?}"))
+            .unwrap_or_else(|_| "Stopped".to_string()),
+        track_id: metadata
+            .as_ref()
+            .and_then(|m| m.track_id())
+            .map(|id| id.to_string()),
+        url: metadata.as_ref().and_then(|m| m.url().map(str::to_string)),
+        title: metadata
+            .as_ref()
+            .and_then(|m| m.title().map(str::to_string)),
+        position_ms: p.get_position().ok().map(|d| d.as_millis() as u64),
+        shuffle: p.checked_get_shuffle().ok().flatten(),
+        loop_status: p
+            .checked_get_loop_status()
+            .ok()
+            .flatten()
+            .map(|l| format!("{l:?}")),
+        volume: volume::backend().get_volume().ok(),
+    })
+}
+
+/// Put playback back as recorded, returning a note for each step that failed.
+/// While volume and seeking are `locked`, the position and volume are kept.
+fn restore(snap: &Snapshot, locked: bool) -> Result<Vec<String>, String> {
+    let p = find_player_by_selector(&snap.bus_name)
+        .ok_or_else(|| format!("{} is no longer running", snap.player))?;
+    let mut problems = Vec::new();
+
+    // Get the right track back first; if it changed, reopen it by URL
+    let current = p
+        .get_metadata()
+        .ok()
+        .and_then(|m| m.track_id())
+        .map(|id| id.to_string());
+    if current != snap.track_id {
+        match &snap.url {
+            Some(url) => {
+                if let Err(e) = player::open_uri(&snap.bus_name, url) {
+                    problems.push(format!("couldn't reopen {url}: {e}"));
+                }
+            }
+            None => problems.push("track changed and has no URL to reopen".to_string()),
+        }
+    }
+
+    if locked && (snap.position_ms.is_some() || snap.volume.is_some()) {
+        problems.push("volume and seeking are locked, kept the current ones".to_string());
+    }
+    if let Some(ms) = snap.position_ms.filter(|_| !locked) {
+        // After OpenUri the player usually assigns a new track id
+        let track_id = p
+            .get_metadata()
+            .ok()
+            .and_then(|m| m.track_id())
+            .or_else(|| snap.track_id.as_deref().and_then(|t| TrackID::new(t).ok()));
+        match track_id {
+            Some(id) => {
+                if let Err(e) = p.set_position(id, &Duration::from_millis(ms)) {
+                    problems.push(format!("couldn't restore position: {e}"));
+                }
+            }
+            None => problems.push("no track id to restore position on".to_string()),
+        }
+    }
+
+    if let Some(shuffle) = snap.shuffle {
+        if let Err(e) = p.set_shuffle(shuffle) {
+            problems.push(format!("couldn't restore shuffle: {e}"));
+        }
+    }
+    if let Some(status) = snap
+        .loop_status
+        .as_deref()
+        .and_then(player::parse_loop_status)
+    {
+        if let Err(e) = p.set_loop_status(status) {
+            problems.push(format!("couldn't restore loop status: {e}"));
+        }
+    }
+    if let Some(volume) = snap.volume.filter(|_| !locked) {
+        if let Err(e) = volume::backend().set_volume(volume) {
+            problems.push(format!("couldn't restore volume: {e}"));
+        }
+    }
+
+    let playback = match snap.playback.as_str() {
+        "Playing" => p.play(),
+        "Paused" => p.pause(),
+        _ => p.stop(),
+    };
+    if let Err(e) = playback {
+        problems.push(format!("couldn't restore playback state: {e}"));
+    }
+    Ok(problems)
+}
+
+/// POST /snapshot — record the current playback state
+#[utoipa::path(
+    post,
+    path = "/snapshot",
+    tag = "Extras",
+    responses(
+        (status = 200, description = "The snapshot taken", body = Snapshot),
+        (status = 404, description = "No external player", body = ErrorBody),
+    ),
+)]
+pub async fn create_snapshot(
+    state: web::Data<AppState>,
+    snapshots: web::Data<Snapshots>,
+) -> impl Responder {
+    let id = {
+        let mut inner = snapshots.inner.locked();
+        inner.0 += 1;
+        inner.0
+    };
+    let Some(snap) = web::block(move || capture(id, &state.pinned))
+        .await
+        .ok()
+        .flatten()
+    else {
+        return AppError::not_found("no external player found").error_response();
+    };
+
+    let mut inner = snapshots.inner.locked();
+    if inner.1.len() >= MAX_SNAPSHOTS {
+        if let Some(oldest) = inner.1.keys().min().copied() {
+            inner.1.remove(&oldest);
+        }
+    }
+    inner.1.insert(id, snap.clone());
+    HttpResponse::Ok().json(snap)
+}
+
+/// POST /snapshot/{id}/restore — put playback back as it was in a snapshot
+#[utoipa::path(
+    post,
+    path = "/snapshot/{id}/restore",
+    tag = "Extras",
+    params(("id" = u64, Path, description = "Snapshot id")),
+    responses(
+        (status = 200, description = "Playback was restored", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such snapshot", body = ErrorBody),
+        (status = 409, description = "The snapshot's player is gone", body = ErrorBody),
+    ),
+)]
+pub async fn restore_snapshot(
+    state: web::Data<AppState>,
+    snapshots: web::Data<Snapshots>,
+    path: web::Path<u64>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let Some(snap) = snapshots.inner.locked().1.get(&id).cloned() else {
+        return AppError::not_found(format!("no snapshot {id}")).error_response();
+    };
+
+    let locked = state.lock.is_locked();
+    match web::block(move || restore(&snap, locked)).await {
+        Ok(Ok(problems)) if problems.is_empty() => {
+            HttpResponse::Ok().body(format!("restored snapshot {id}"))
+        }
+        Ok(Ok(problems)) => HttpResponse::Ok().json(serde_json::json!({
+            "restored": id,
+            "problems": problems,
+        })),
+        Ok(Err(e)) => AppError::conflict(e).error_response(),
+        Err(e) => AppError::internal(format!("restore task failed: {e}")).error_response(),
+    }
+}
@@ -0,0 +1,82 @@
+//! Text-to-speech through an external command.
+//!
+//! Uses speech-dispatcher's `spd-say` by default. Any other command that
+//! takes the text to speak as its last argument (`espeak-ng`, `piper-say`,
+//! ...) can be configured with `MEDIA_CONTROL_TTS_COMMAND`.
+
+use crate::config;
+use crate::i18n::Message;
+use crate::live::NowPlaying;
+use media_controller::models::Status;
+use std::process::Command;
+
+/// Command line used when `MEDIA_CONTROL_TTS_COMMAND` is unset. `--wait`
+/// keeps the request open until the announcement has been spoken.
+const DEFAULT_COMMAND: &str = "spd-say --wait";
+
+/// What to announce for a snapshot
+pub fn announcement(now: &NowPlaying) -> Message {
+    match (&now.title, &now.artist) {
+        (Some(title), Some(artist)) => Message::new("announce-track")
+            .arg("title", title)
+            .arg("artist", artist),
+        (Some(title), None) => Message::new("announce-title").arg("title", title),
+        (None, _) => Message::new("announce-nothing"),
+    }
+}
+
+/// `status` as one sentence to read out, such as "Spotify is playing
+/// Teardrop by Massive Attack, 2 minutes in"
+pub fn spoken_status(status: &Status, languages: &[String]) -> String {
+    let playback = status.other_playback.as_deref();
+    if status.controlled_player.is_none() && status.title.is_none() {
+        return Message::new("spoken-nothing").localize(languages);
+    }
+    let player = match &status.controlled_player {
+        Some(player) => player.clone(),
+        None => Message::new("spoken-some-player").localize(languages),
+    };
+    let track = match (&status.title, &status.artist) {
+        (Some(title), Some(artist)) => Message::new("announce-track")
+            .arg("title", title)
+            .arg("artist", artist),
+        (Some(title), None) => Message::new("announce-title").arg("title", title),
+        (None, _) => Message::new("spoken-some-track"),
+    };
+    let progress = match status.position_ms.map(|ms| ms / 1000) {
+        None | Some(0..=9) => Message::new("spoken-just-started"),
+        Some(seconds @ 10..=59) => Message::new("spoken-seconds-in").arg("seconds", seconds),
+        Some(60..=119) => Message::new("spoken-one-minute-in"),
+        Some(seconds) => Message::new("spoken-minutes-in").arg("minutes", seconds / 60),
+    };
+    let id = match playback {
+        Some("Playing") => "spoken-playing",
+        Some("Paused") => "spoken-paused",
+        _ => "spoken-stopped",
+    };
+    Message::new(id)
+        .arg("player", player)
+        .arg("track", track.localize(languages))
+        .arg("progress", progress.localize(languages))
+        .localize(languages)
+}
+
+/// Speak `text`, blocking until the TTS command exits
+pub fn say(text: &str) -> Result<(), Message> {
+    let command =
+        config::var("MEDIA_CONTROL_TTS_COMMAND").unwrap_or_else(|_| DEFAULT_COMMAND.into());
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return Err(Message::new("tts-launch-failed").arg("error", "empty command"));
+    };
+
+    let status = Command::new(program)
+        .args(words)
+        .arg(text)
+        .status()
+        .map_err(|e| Message::new("tts-launch-failed").arg("error", e))?;
+    if !status.success() {
+        return Err(Message::new("tts-exited").arg("status", status));
+    }
+    Ok(())
+}
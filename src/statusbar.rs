@@ -0,0 +1,146 @@
+//! `media-controller client statusbar`: a now-playing module for Waybar and
+//! polybar (the `client` feature).
+//!
+//! Runs as the bar's long-lived script and prints a line whenever `/events`
+//! reports a change, so nothing is polled. `--format waybar` (the default)
+//! prints Waybar's JSON with `class` set to `playing`, `paused`, `stopped`
+//! or `disconnected`, and `--format polybar` plain text for a `tail = true`
+//! script. The text is rendered from `MEDIA_CONTROL_STATUSBAR_FORMAT`
+//! (default `{{track}}`).
+
+use crate::config;
+use crate::template::{self, Vars};
+use media_controller::models::NowPlaying;
+use serde_json::json;
+use std::time::Duration;
+
+/// How long to wait before reconnecting a dropped event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+const USAGE: &str = "usage: media-controller client statusbar [--format waybar|polybar] [URL]";
+
+#[derive(Clone, Copy)]
+enum Format {
+    Waybar,
+    Polybar,
+}
+
+/// Follow the server and print the module's text until killed
+pub async fn run(mut args: impl Iterator<Item = String>) -> std::io::Result<()> {
+    let mut format = Format::Waybar;
+    let mut url = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("waybar") => Format::Waybar,
+                    Some("polybar") => Format::Polybar,
+                    _ => usage(),
+                }
+            }
+            _ if url.is_none() && !arg.starts_with('-') => url = Some(arg),
+            _ => usage(),
+        }
+    }
+    let client = crate::local_client(url);
+    let template =
+        config::var("MEDIA_CONTROL_STATUSBAR_FORMAT").unwrap_or_else(|_| "{{track}}".to_string());
+
+    let mut last = None;
+    let mut show = |now: Option<&NowPlaying>| {
+        let line = render(format, &template, now);
+        // Volume events often leave the text as it was
+        if last.as_ref() != Some(&line) {
+            println!("{line}");
+            last = Some(line);
+        }
+    };
+    loop {
+        match client.events().await {
+            Ok(mut events) => loop {
+                match events.next().await {
+                    Ok(Some(event)) => show(Some(&event.now_playing)),
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Lost the event stream: {e}");
+                        break;
+                    }
+                }
+            },
+            Err(e) => eprintln!("Couldn't connect to {}: {e}", client.base_url()),
+        }
+        show(None);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+/// One line of output, for the server's state or None while disconnected
+fn render(format: Format, template: &str, now: Option<&NowPlaying>) -> String {
+    let text = now.map_or_else(String::new, |n| template::render(template, &vars(n)));
+    let class = match now.map(|n| n.playback.as_deref()) {
+        None => "disconnected",
+        Some(Some("Playing")) => "playing",
+        Some(Some("Paused")) => "paused",
+        Some(_) => "stopped",
+    };
+    match format {
+        Format::Polybar => text,
+        Format::Waybar => {
+            let tooltip = now.map_or_else(|| "Not connected".to_string(), tooltip);
+            json!({
+                "text": escape_markup(&text),
+                "alt": class,
+                "class": class,
+                "tooltip": escape_markup(&tooltip),
+                "percentage": now.and_then(|n| n.volume),
+            })
+            .to_string()
+        }
+    }
+}
+
+/// Placeholders for `MEDIA_CONTROL_STATUSBAR_FORMAT`
+fn vars(now: &NowPlaying) -> Vars {
+    let field = |value: &Option<String>| value.clone().unwrap_or_default();
+    let track = match (&now.artist, &now.title) {
+        (Some(artist), Some(title)) => format!("{artist} – {title}"),
+        (None, Some(title)) => title.clone(),
+        _ => String::new(),
+    };
+    Vars::from([
+        ("track", track),
+        ("title", field(&now.title)),
+        ("artist", field(&now.artist)),
+        ("album", field(&now.album)),
+        ("player", field(&now.player)),
+        ("status", field(&now.playback)),
+        (
+            "volume",
+            now.volume.map(|v| v.to_string()).unwrap_or_default(),
+        ),
+    ])
+}
+
+/// Title, artist, album and player, one per line
+fn tooltip(now: &NowPlaying) -> String {
+    let player = now.player.as_deref().unwrap_or("no player");
+    let playback = now.playback.as_deref().unwrap_or("Stopped");
+    [now.title.clone(), now.artist.clone(), now.album.clone()]
+        .into_iter()
+        .flatten()
+        .chain([format!("{playback} on {player}")])
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Waybar renders text as Pango markup
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,340 @@
+//! Best-effort synchronized playback across several players.
+//!
+//! `POST /sync` with `{"players": ["vlc", "mpv"]}` links players that were
+//! pointed at the same track or stream, e.g. one per room: they are paused,
+//! lined up on the first player's (the leader's) position and started
+//! together. Given a `"uri"`, every player opens it first. A watcher thread
+//! then compares their `Position` every couple of seconds, nudges followers
+//! that drifted further than `MEDIA_CONTROL_SYNC_TOLERANCE_MS` (default
+//! 250) back with `SetPosition`, and has them follow the leader's play and
+//! pause. `DELETE /sync` unlinks them, and the sync ends by itself when the
+//! leader goes away.
+//!
+//! Position reports are only as accurate as the players make them, so this
+//! is good enough for music in neighbouring rooms, not for speakers sharing
+//! one room.
+
+use crate::actions::set_position;
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use crate::player::{self, find_player_by_selector};
+use crate::{get_dry_run_mode, lock, AppState};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use media_controller::models::ErrorBody;
+use mpris::{PlaybackStatus, Player};
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How often positions are compared
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Default for `MEDIA_CONTROL_SYNC_TOLERANCE_MS`
+const DEFAULT_TOLERANCE_MS: u64 = 250;
+
+/// Read `MEDIA_CONTROL_SYNC_TOLERANCE_MS`
+fn tolerance_from_env() -> Duration {
+    let ms = config::var("MEDIA_CONTROL_SYNC_TOLERANCE_MS")
+        .ok()
+        .and_then(|v| {
+            v.parse()
+                .map_err(|_| {
+                    warn!("Ignoring MEDIA_CONTROL_SYNC_TOLERANCE_MS, expected milliseconds")
+                })
+                .ok()
+        })
+        .unwrap_or(DEFAULT_TOLERANCE_MS);
+    Duration::from_millis(ms)
+}
+
+/// One player taking part in a sync
+struct Member {
+    bus_name: String,
+    identity: String,
+    // Follower position minus the leader's at the last check, in ms
+    drift_ms: Mutex<Option<i64>>,
+}
+
+struct Session {
+    // The leader first
+    members: Vec<Member>,
+    stopped: AtomicBool,
+}
+
+/// The players currently kept in sync, if any
+#[derive(Default)]
+pub struct SyncGroup {
+    session: Mutex<Option<Arc<Session>>>,
+}
+
+impl SyncGroup {
+    /// The running session, if it hasn't ended
+    fn current(&self) -> Option<Arc<Session>> {
+        self.session
+            .locked()
+            .clone()
+            .filter(|s| !s.stopped.load(Ordering::Relaxed))
+    }
+
+    /// Replace the running session, stopping the old one's watcher
+    fn replace(&self, session: Option<Arc<Session>>) {
+        let old = std::mem::replace(&mut *self.session.locked(), session);
+        if let Some(old) = old {
+            old.stopped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Request body for POST /sync
+#[derive(Deserialize, ToSchema)]
+pub struct SyncRequest {
+    // Player selectors as for /players/{id}/...; the first one leads
+    players: Vec<String>,
+    // Opened in every player before syncing
+    uri: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct MemberView {
+    player: String,
+    bus_name: String,
+    drift_ms: Option<i64>,
+}
+
+/// JSON view returned by the /sync endpoints
+#[derive(Serialize, ToSchema)]
+struct SyncView {
+    active: bool,
+    leader: Option<MemberView>,
+    followers: Vec<MemberView>,
+}
+
+impl SyncView {
+    fn of(session: Option<&Session>) -> Self {
+        let mut members = session
+            .map(|s| &s.members[..])
+            .unwrap_or_default()
+            .iter()
+            .map(|m| MemberView {
+                player: m.identity.clone(),
+                bus_name: m.bus_name.clone(),
+                drift_ms: *m.drift_ms.locked(),
+            });
+        let leader = members.next().map(|m| MemberView {
+            drift_ms: None,
+            ..m
+        });
+        SyncView {
+            active: session.is_some(),
+            leader,
+            followers: members.collect(),
+        }
+    }
+}
+
+/// Whether two players are on the same track, as far as they tell
+fn same_track(a: &Player, b: &Player) -> bool {
+    let url = |p: &Player| {
+        p.get_metadata()
+            .ok()
+            .and_then(|m| m.url().map(str::to_string))
+    };
+    match (url(a), url(b)) {
+        (Some(a), Some(b)) => a == b,
+        // Can't tell, so assume they are
+        _ => true,
+    }
+}
+
+/// Pause everyone, line the followers up on the leader and start them all
+fn start_together(players: &[Rc<Player>]) {
+    for p in players {
+        let _ = p.pause();
+    }
+    let leader = &players[0];
+    if let Ok(position) = leader.get_position() {
+        for p in &players[1..] {
+            if let Err(e) = set_position(p, position) {
+                warn!("Sync: couldn't line up {}: {e}", p.identity());
+            }
+        }
+    }
+    for p in players {
+        if let Err(e) = p.play() {
+            warn!("Sync: couldn't start {}: {e}", p.identity());
+        }
+    }
+}
+
+/// One round of the watcher; false once the leader is gone
+fn check(session: &Session, tolerance: Duration) -> bool {
+    let Some(leader) = find_player_by_selector(&session.members[0].bus_name) else {
+        info!(
+            "Sync: {} went away, ending sync",
+            session.members[0].identity
+        );
+        return false;
+    };
+    let leader_status = leader.get_playback_status().ok();
+
+    for member in &session.members[1..] {
+        let mut drift = None;
+        if let Some(follower) = find_player_by_selector(&member.bus_name) {
+            let status = follower.get_playback_status().ok();
+            match (leader_status, status) {
+                (Some(PlaybackStatus::Playing), Some(PlaybackStatus::Playing)) => {
+                    drift = nudge(&leader, &follower, tolerance);
+                }
+                (Some(PlaybackStatus::Playing), _) => {
+                    let _ = follower.play();
+                }
+                (Some(_), Some(PlaybackStatus::Playing)) => {
+                    let _ = follower.pause();
+                }
+                _ => {}
+            }
+        }
+        *member.drift_ms.locked() = drift;
+    }
+    true
+}
+
+/// Measure a follower's drift from the leader and pull it back if it's too
+/// far off, returning the drift measured
+fn nudge(leader: &Player, follower: &Player, tolerance: Duration) -> Option<i64> {
+    if !same_track(leader, follower) {
+        return None;
+    }
+    let ahead = follower.get_position().ok()?;
+    let target = leader.get_position().ok()?;
+    let drift = ahead.as_millis() as i64 - target.as_millis() as i64;
+    if drift.unsigned_abs() > tolerance.as_millis() as u64 {
+        // Read again, the leader has moved on while we were measuring
+        let target = leader.get_position().unwrap_or(target);
+        if let Err(e) = set_position(follower, target) {
+            warn!("Sync: couldn't nudge {}: {e}", follower.identity());
+        }
+    }
+    Some(drift)
+}
+
+fn watch(session: Arc<Session>, tolerance: Duration) {
+    while !session.stopped.load(Ordering::Relaxed) {
+        thread::sleep(CHECK_INTERVAL);
+        if session.stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        if !check(&session, tolerance) {
+            session.stopped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// GET /sync — the players kept in sync and how far each follower drifted
+#[utoipa::path(
+    get,
+    path = "/sync",
+    tag = "Extras",
+    responses((status = 200, description = "The players kept in sync", body = SyncView)),
+)]
+pub async fn sync_state(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(SyncView::of(state.sync.current().as_deref()))
+}
+
+/// POST /sync — start several players together and keep them in sync
+#[utoipa::path(
+    post,
+    path = "/sync",
+    tag = "Extras",
+    request_body = SyncRequest,
+    responses(
+        (status = 200, description = "The players now kept in sync", body = SyncView),
+        (status = 400, description = "Fewer than two players", body = ErrorBody),
+        (status = 404, description = "No matching player", body = ErrorBody),
+    ),
+)]
+pub async fn start_sync(
+    state: web::Data<AppState>,
+    body: web::Json<SyncRequest>,
+) -> impl Responder {
+    let SyncRequest { players, uri } = body.into_inner();
+    if state.demo.is_some() {
+        return AppError::bad_request("syncing needs MPRIS players, not the demo player")
+            .error_response();
+    }
+    if state.lock.is_locked() {
+        return lock::refused();
+    }
+
+    let mut found = Vec::new();
+    for selector in &players {
+        let Some(p) = find_player_by_selector(selector) else {
+            return AppError::not_found(format!("no player matching '{selector}'"))
+                .error_response();
+        };
+        if !found
+            .iter()
+            .any(|f: &Rc<Player>| f.bus_name() == p.bus_name())
+        {
+            found.push(p);
+        }
+    }
+    if found.len() < 2 {
+        return AppError::bad_request("\"players\" must name at least two players")
+            .error_response();
+    }
+    let names: Vec<_> = found.iter().map(|p| p.identity().to_string()).collect();
+    if get_dry_run_mode() {
+        return HttpResponse::Ok().body(format!("would sync {}", names.join(", ")));
+    }
+
+    if let Some(uri) = &uri {
+        for p in &found {
+            if let Err(e) = player::open_uri(p.bus_name(), uri) {
+                return AppError::internal(format!("couldn't open {uri} in {}: {e}", p.identity()))
+                    .error_response();
+            }
+        }
+    } else {
+        start_together(&found);
+    }
+
+    let session = Arc::new(Session {
+        members: found
+            .iter()
+            .map(|p| Member {
+                bus_name: p.bus_name().to_string(),
+                identity: p.identity().to_string(),
+                drift_ms: Mutex::new(None),
+            })
+            .collect(),
+        stopped: AtomicBool::new(false),
+    });
+    state.sync.replace(Some(session.clone()));
+    info!("Syncing {}", names.join(", "));
+    let tolerance = tolerance_from_env();
+    thread::spawn({
+        let session = session.clone();
+        move || watch(session, tolerance)
+    });
+    HttpResponse::Ok().json(SyncView::of(Some(&session)))
+}
+
+/// DELETE /sync — stop keeping the players in sync; they keep playing
+#[utoipa::path(
+    delete,
+    path = "/sync",
+    tag = "Extras",
+    responses((status = 200, description = "Nothing is kept in sync", body = SyncView)),
+)]
+pub async fn stop_sync(state: web::Data<AppState>) -> impl Responder {
+    if state.sync.current().is_some() {
+        info!("Sync ended");
+    }
+    state.sync.replace(None);
+    HttpResponse::Ok().json(SyncView::of(None))
+}
@@ -0,0 +1,121 @@
+//! systemd integration: socket activation and service notifications.
+//!
+//! Started from a `.socket` unit, the service takes the sockets systemd
+//! passes it (`LISTEN_FDS`) instead of binding `MEDIA_CONTROL_BIND`,
+//! `MEDIA_CONTROL_PORT` or `MEDIA_CONTROL_UNIX_SOCKET` itself. TCP sockets
+//! serve HTTPS when a certificate is configured, Unix sockets stay plain.
+//!
+//! Under `Type=notify` it reports `READY=1` once the publisher is on the bus
+//! and the server is listening, and `STOPPING=1` on shutdown. With
+//! `WatchdogSec=` it sends `WATCHDOG=1` at half that interval for as long as
+//! the session bus answers, so a service that lost the bus gets restarted.
+//! Outside systemd none of this happens.
+
+use crate::registry;
+use std::env;
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// The first file descriptor systemd passes
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed by systemd
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// The sockets systemd passed us, if it started us through a socket unit
+pub fn listeners() -> Option<Vec<Listener>> {
+    let pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    // The variables are inherited by children, which must not take the sockets
+    if pid != std::process::id() {
+        return None;
+    }
+    let count: RawFd = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    let listeners: Vec<Listener> = (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // Safety: systemd hands these descriptors to us alone, open
+            let tcp = unsafe { TcpListener::from_raw_fd(fd) };
+            match tcp.local_addr() {
+                Ok(addr) => {
+                    info!("Listening on {addr}, passed by systemd");
+                    Listener::Tcp(tcp)
+                }
+                // Not an IP socket
+                Err(_) => {
+                    let unix = unsafe { UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+                    info!("Listening on a Unix socket passed by systemd");
+                    Listener::Unix(unix)
+                }
+            }
+        })
+        .collect();
+    (!listeners.is_empty()).then_some(listeners)
+}
+
+/// Send `state` to systemd, if it's listening
+fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&path),
+    };
+    let sent = UnixDatagram::unbound()
+        .and_then(|socket| addr.and_then(|addr| socket.send_to_addr(state.as_bytes(), &addr)));
+    if let Err(e) = sent {
+        warn!("Couldn't notify systemd of {state:?}: {e}");
+    }
+}
+
+/// Tell systemd we're up, and keep its watchdog fed if it has one. The
+/// demo player needs no bus, so in demo mode the watchdog is always fed.
+pub fn ready(demo: bool) {
+    notify("READY=1");
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    info!(
+        "Feeding the systemd watchdog every {} ms",
+        interval.as_millis()
+    );
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if demo {
+            notify("WATCHDOG=1");
+            continue;
+        }
+        match registry::players() {
+            Ok(_) => notify("WATCHDOG=1"),
+            Err(e) => {
+                warn!("Not feeding the systemd watchdog, the session bus is unreachable: {e}")
+            }
+        }
+    });
+}
+
+/// Tell systemd we're shutting down
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Half the watchdog timeout systemd set for us
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
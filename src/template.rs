@@ -0,0 +1,49 @@
+//! Minimal handlebars-style templating for now-playing strings.
+//!
+//! Templates use `{{name}}` placeholders (whitespace inside the braces is
+//! ignored), e.g. `{{artist}} — {{title}} ({{position}}/{{duration}})`.
+//! Unknown placeholders render as an empty string and an unterminated `{{`
+//! is copied through verbatim.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Values available to a template, keyed by placeholder name
+pub type Vars = HashMap<&'static str, String>;
+
+/// Render `template`, substituting every `{{name}}` with its value from `vars`
+pub fn render(template: &str, vars: &Vars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                if let Some(value) = vars.get(name) {
+                    out.push_str(value);
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Format a duration as `m:ss`, or `h:mm:ss` once it passes an hour
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if h > 0 {
+        format!("{h}:{m:02}:{s:02}")
+    } else {
+        format!("{m}:{s:02}")
+    }
+}
@@ -0,0 +1,65 @@
+//! Where a command's time went, for `?timings=true`.
+//!
+//! Commands run synchronously on the worker thread handling the request, so
+//! the timeline is kept per thread: [`start`] begins one, [`time`] charges a
+//! closure to a phase while one is running, and [`finish`] hands it back.
+//! Phases nest, and a phase is only charged its own time, so the D-Bus call
+//! doesn't also count the publisher update made in the middle of it.
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Timeline {
+    // Time per phase, in the order phases first ran
+    phases: Vec<(&'static str, Duration)>,
+    // Time spent in nested phases, one entry per phase now running
+    nested: Vec<Duration>,
+}
+
+thread_local! {
+    static TIMELINE: RefCell<Option<Timeline>> = const { RefCell::new(None) };
+}
+
+/// Start timing on this thread
+pub fn start() {
+    TIMELINE.with(|t| *t.borrow_mut() = Some(Timeline::default()));
+}
+
+/// Stop timing and return the time spent per phase
+pub fn finish() -> Vec<(&'static str, Duration)> {
+    TIMELINE
+        .with(|t| t.borrow_mut().take())
+        .map(|t| t.phases)
+        .unwrap_or_default()
+}
+
+/// Run `f`, charging its time to `phase` if a timeline is running
+pub fn time<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    let running = TIMELINE.with(|t| {
+        let mut t = t.borrow_mut();
+        t.as_mut().map(|t| t.nested.push(Duration::ZERO)).is_some()
+    });
+    if !running {
+        return f();
+    }
+    let started = Instant::now();
+    let value = f();
+    let elapsed = started.elapsed();
+    TIMELINE.with(|t| {
+        let mut t = t.borrow_mut();
+        let Some(t) = t.as_mut() else {
+            return;
+        };
+        let nested = t.nested.pop().unwrap_or_default();
+        if let Some(parent) = t.nested.last_mut() {
+            *parent += elapsed;
+        }
+        let own = elapsed.saturating_sub(nested);
+        match t.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += own,
+            None => t.phases.push((phase, own)),
+        }
+    });
+    value
+}
@@ -0,0 +1,103 @@
+//! HTTPS with rustls (the `tls` feature).
+//!
+//! Setting `MEDIA_CONTROL_TLS_CERT` and `MEDIA_CONTROL_TLS_KEY` to PEM files
+//! serves HTTPS instead of plain HTTP on the TCP port, so the API token
+//! isn't sent in the clear. The certificate file may hold the whole chain.
+//! Both files are read again on every config reload (`SIGHUP` or
+//! `POST /admin/reload`), so renewed certificates, e.g. from Let's Encrypt,
+//! are picked up by new connections without a restart. If they can't be
+//! read the current certificate stays in use.
+
+use crate::config;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use tracing::info;
+
+/// The certificate being served, once HTTPS is set up
+static CERTIFICATE: OnceLock<Arc<Certificate>> = OnceLock::new();
+
+/// Hands out whichever certificate was loaded last
+#[derive(Debug)]
+struct Certificate {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for Certificate {
+    fn resolve(&self, _hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        )
+    }
+}
+
+/// `MEDIA_CONTROL_TLS_CERT` and `MEDIA_CONTROL_TLS_KEY`, if HTTPS is wanted
+fn paths() -> Result<Option<(String, String)>, String> {
+    let cert = config::var("MEDIA_CONTROL_TLS_CERT").ok();
+    let key = config::var("MEDIA_CONTROL_TLS_KEY").ok();
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some((cert, key))),
+        (None, None) => Ok(None),
+        _ => Err("set both MEDIA_CONTROL_TLS_CERT and MEDIA_CONTROL_TLS_KEY".to_string()),
+    }
+}
+
+/// Read a certificate chain and its private key
+fn load(cert_path: &str, key_path: &str) -> Result<CertifiedKey, String> {
+    let chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("couldn't read certificates from {cert_path}: {e}"))?;
+    if chain.is_empty() {
+        return Err(format!("no certificates in {cert_path}"));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("couldn't read the private key from {key_path}: {e}"))?;
+    let signing_key = ring::sign::any_supported_type(&key)
+        .map_err(|e| format!("unusable private key in {key_path}: {e}"))?;
+    let certified = CertifiedKey::new(chain, signing_key);
+    certified
+        .keys_match()
+        .map_err(|e| format!("{key_path} doesn't belong to {cert_path}: {e}"))?;
+    Ok(certified)
+}
+
+/// The TLS setup for the HTTP server, or None to serve plain HTTP
+pub fn server_config() -> Result<Option<ServerConfig>, String> {
+    let Some((cert_path, key_path)) = paths()? else {
+        return Ok(None);
+    };
+    let certificate = Arc::new(Certificate {
+        current: RwLock::new(Arc::new(load(&cert_path, &key_path)?)),
+    });
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_no_client_auth()
+        .with_cert_resolver(certificate.clone());
+    let _ = CERTIFICATE.set(certificate);
+    info!("Serving HTTPS with {cert_path}");
+    Ok(Some(config))
+}
+
+/// Read the certificate again after a config reload
+pub fn reload() -> Result<(), String> {
+    let Some(certificate) = CERTIFICATE.get() else {
+        return Ok(());
+    };
+    let (cert_path, key_path) =
+        paths()?.ok_or("HTTPS can't be turned off without a restart, keeping the certificate")?;
+    let key = load(&cert_path, &key_path)?;
+    *certificate
+        .current
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Arc::new(key);
+    info!("Reloaded certificate from {cert_path}");
+    Ok(())
+}
@@ -0,0 +1,351 @@
+//! `media-controller tui`: a terminal remote for a running server (the `tui`
+//! feature).
+//!
+//! Talks to `MEDIA_CONTROL_URL` (default `http://localhost:<port>`, or the
+//! URL given after `tui`) with `MEDIA_CONTROL_API_TOKEN`, so over SSH it
+//! picks up the same config file as the service. The screen is redrawn as
+//! soon as an event arrives on `/events`, and the position ticks along from
+//! `/status` once a second.
+
+use media_controller::client::Client;
+use media_controller::models::{Overview, PlayerInfo};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How often the position and volume are refreshed without an event
+const TICK: Duration = Duration::from_secs(1);
+/// How long to wait before reconnecting a dropped event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+const HELP: &str = "space play/pause · n/p next/previous · s stop · ←/→ seek · +/- volume · \
+                    m mute · ↑/↓ Enter pin player · u unpin · q quit";
+
+/// What the main loop reacts to
+enum Input {
+    Key(KeyEvent),
+    // Something changed on the server, by event type
+    ServerEvent(String),
+    // The event stream dropped, and why
+    StreamLost(String),
+}
+
+struct App {
+    client: Client,
+    overview: Option<Overview>,
+    // Cursor in the player list
+    players: ListState,
+    // Outcome of the last command, or what went wrong
+    message: String,
+    connected: bool,
+}
+
+/// Run the terminal UI until the user quits
+pub async fn run(url: Option<String>) -> std::io::Result<()> {
+    let client = crate::local_client(url);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    spawn_key_reader(tx.clone());
+    spawn_event_listener(client.clone(), tx);
+
+    let mut app = App {
+        client,
+        overview: None,
+        players: ListState::default().with_selected(Some(0)),
+        message: String::new(),
+        connected: false,
+    };
+    app.refresh().await;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal, rx).await;
+    ratatui::restore();
+    result
+}
+
+/// Read keys on a thread of their own, as crossterm blocks
+fn spawn_key_reader(tx: mpsc::UnboundedSender<Input>) {
+    thread::spawn(move || loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if tx.send(Input::Key(key)).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    });
+}
+
+/// Follow `/events`, reconnecting whenever the stream drops
+fn spawn_event_listener(client: Client, tx: mpsc::UnboundedSender<Input>) {
+    actix_web::rt::spawn(async move {
+        loop {
+            let error = match client.events().await {
+                Ok(mut events) => loop {
+                    match events.next().await {
+                        Ok(Some(event)) => {
+                            if tx.send(Input::ServerEvent(event.kind)).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => break "the server closed the event stream".to_string(),
+                        Err(e) => break e.to_string(),
+                    }
+                },
+                Err(e) => e.to_string(),
+            };
+            if tx.send(Input::StreamLost(error)).is_err() {
+                return;
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+impl App {
+    async fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        mut rx: mpsc::UnboundedReceiver<Input>,
+    ) -> std::io::Result<()> {
+        let mut tick = tokio::time::interval(TICK);
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            tokio::select! {
+                _ = tick.tick() => self.refresh_status().await,
+                input = rx.recv() => match input {
+                    None => return Ok(()),
+                    Some(Input::Key(key)) => {
+                        if !self.handle_key(key).await {
+                            return Ok(());
+                        }
+                    }
+                    // Players and devices only change with these
+                    Some(Input::ServerEvent(kind))
+                        if matches!(kind.as_str(), "snapshot" | "player" | "sink_added") =>
+                    {
+                        self.refresh().await
+                    }
+                    Some(Input::ServerEvent(_)) => self.refresh_status().await,
+                    Some(Input::StreamLost(error)) => {
+                        self.disconnected(error);
+                        self.refresh().await;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Fetch everything shown
+    async fn refresh(&mut self) {
+        match self.client.overview().await {
+            Ok(overview) => {
+                let len = overview.players.len();
+                if self.players.selected().is_some_and(|i| i >= len) {
+                    self.players.select(len.checked_sub(1));
+                }
+                self.overview = Some(overview);
+                self.connected = true;
+            }
+            Err(e) => self.disconnected(e.to_string()),
+        }
+    }
+
+    /// Fetch just the track, position and volume
+    async fn refresh_status(&mut self) {
+        if self.overview.is_none() {
+            return self.refresh().await;
+        }
+        match self.client.status().await {
+            Ok(status) => {
+                if let Some(overview) = &mut self.overview {
+                    overview.status = status;
+                }
+                self.connected = true;
+            }
+            Err(e) => self.disconnected(e.to_string()),
+        }
+    }
+
+    fn disconnected(&mut self, error: String) {
+        if self.connected || self.message.is_empty() {
+            self.message = error;
+        }
+        self.connected = false;
+    }
+
+    fn selected_player(&self) -> Option<&PlayerInfo> {
+        let players = &self.overview.as_ref()?.players;
+        players.get(self.players.selected()?)
+    }
+
+    /// Act on a key press; false to quit
+    async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let c = &self.client;
+        let result = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char(' ') => c.toggle().await,
+            KeyCode::Char('n') => c.next().await,
+            KeyCode::Char('p') => c.previous().await,
+            KeyCode::Char('s') => c.stop().await,
+            KeyCode::Right => c.seek_forward().await,
+            KeyCode::Left => c.seek_backward().await,
+            KeyCode::Char('+' | '=') => c.volume_up().await,
+            KeyCode::Char('-') => c.volume_down().await,
+            KeyCode::Char('m') => c.toggle_mute().await,
+            KeyCode::Char('u') => c.unselect_player().await,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.players.select_previous();
+                return true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.players.select_next();
+                return true;
+            }
+            KeyCode::Enter => match self.selected_player() {
+                Some(player) => {
+                    let bus_name = player.bus_name.clone();
+                    c.select_player(&bus_name)
+                        .await
+                        .map(|p| format!("pinned {}", p.identity))
+                }
+                None => return true,
+            },
+            _ => return true,
+        };
+        self.message = result.unwrap_or_else(|e| e.to_string());
+        self.refresh().await;
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [now_playing, volume, players, footer] = Layout::vertical([
+            Constraint::Length(6),
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        let title = if self.connected {
+            format!(" media-controller · {} ", self.client.base_url())
+        } else {
+            format!(
+                " media-controller · {} (disconnected) ",
+                self.client.base_url()
+            )
+        };
+        let status = self.overview.as_ref().map(|o| &o.status);
+
+        // Track and position
+        let block = Block::bordered().title(title);
+        let inner = block.inner(now_playing);
+        frame.render_widget(block, now_playing);
+        let [track, progress] =
+            Layout::vertical([Constraint::Length(3), Constraint::Length(1)]).areas(inner);
+        let text = match status {
+            Some(s) => {
+                let state = s.other_playback.as_deref().unwrap_or(&s.our_playback);
+                vec![
+                    Line::from(s.title.as_deref().unwrap_or("Nothing playing").bold()),
+                    Line::from(
+                        [s.artist.as_deref(), s.album.as_deref()]
+                            .into_iter()
+                            .flatten()
+                            .collect::<Vec<_>>()
+                            .join(" — "),
+                    ),
+                    Line::from(vec![
+                        Span::raw(state.to_string()).fg(Color::Cyan),
+                        Span::raw(format!(
+                            " on {}",
+                            s.controlled_player.as_deref().unwrap_or("no player")
+                        )),
+                    ]),
+                ]
+            }
+            None => vec![Line::from("Connecting…")],
+        };
+        frame.render_widget(Paragraph::new(text), track);
+        let (position, length) = status
+            .map(|s| (s.position_ms.unwrap_or(0), s.length_ms.unwrap_or(0)))
+            .unwrap_or_default();
+        let ratio = if length > 0 {
+            (position as f64 / length as f64).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        frame.render_widget(
+            Gauge::default()
+                .ratio(ratio)
+                .label(format!("{} / {}", clock(position), clock(length)))
+                .gauge_style(Style::new().fg(Color::Green)),
+            progress,
+        );
+
+        // System volume
+        let (level, muted) = status
+            .map(|s| (s.volume.unwrap_or(0), s.muted.unwrap_or(false)))
+            .unwrap_or_default();
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title(" Volume "))
+                .percent(level.min(100) as u16)
+                .label(if muted {
+                    format!("{level}% (muted)")
+                } else {
+                    format!("{level}%")
+                })
+                .gauge_style(Style::new().fg(if muted { Color::DarkGray } else { Color::Blue })),
+            volume,
+        );
+
+        // Players
+        let items: Vec<ListItem> = self
+            .overview
+            .iter()
+            .flat_map(|o| &o.players)
+            .map(|p| {
+                let marker = if p.selected { "▶ " } else { "  " };
+                let track = match (&p.artist, &p.title) {
+                    (Some(artist), Some(title)) => format!(" — {artist} – {title}"),
+                    (None, Some(title)) => format!(" — {title}"),
+                    _ => String::new(),
+                };
+                let playback = p.playback.as_deref().unwrap_or("?");
+                ListItem::new(format!("{marker}{} [{playback}]{track}", p.identity))
+            })
+            .collect();
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(" Players "))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            players,
+            &mut self.players,
+        );
+
+        // Last outcome and keys
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(self.message.as_str()),
+                Line::from(HELP).fg(Color::DarkGray),
+            ]),
+            footer,
+        );
+    }
+}
+
+/// `m:ss` for a position in milliseconds
+fn clock(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
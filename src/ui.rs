@@ -0,0 +1,90 @@
+//! `GET /ui`, a remote control page built into the binary.
+//!
+//! The page is a single self-contained HTML file using the same API as any
+//! other client: it asks for a token once (or takes it from a
+//! `/ui#token=...` bookmark), keeps it in the browser's local storage, and
+//! follows `/events` to stay current. The page itself holds nothing secret,
+//! so it's served without a token.
+//!
+//! Players often report artwork as a `file://` URL, which a phone's browser
+//! can't load, so `GET /ui/art` serves the current track's local artwork.
+
+use crate::error::AppError;
+use crate::AppState;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+
+const PAGE: &str = include_str!("../ui/index.html");
+
+/// GET /ui — the remote control page
+pub async fn page() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(PAGE)
+}
+
+/// GET /ui/art — the current track's artwork, when the player has it as a
+/// local file
+#[utoipa::path(
+    get,
+    path = "/ui/art",
+    tag = "Status",
+    responses(
+        (status = 200, description = "The image, as JPEG, PNG, GIF, WebP or BMP"),
+        (status = 404, description = "The track has no local artwork", body = media_controller::models::ErrorBody),
+    ),
+)]
+pub async fn art(state: web::Data<AppState>) -> impl Responder {
+    let art_url = state.live.borrow().art_url.clone();
+    let Some(path) = art_url.as_deref().and_then(local_path) else {
+        return AppError::not_found("the current track has no local artwork").error_response();
+    };
+    let content_type = content_type(&path);
+    match web::block(move || std::fs::read(path)).await {
+        Ok(Ok(image)) => HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(image),
+        Ok(Err(e)) => {
+            AppError::not_found(format!("couldn't read the artwork: {e}")).error_response()
+        }
+        Err(e) => AppError::internal(format!("artwork task failed: {e}")).error_response(),
+    }
+}
+
+/// The path of a `file://` URL, percent-decoded
+fn local_path(url: &str) -> Option<String> {
+    let encoded = url.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                path.push(byte);
+                i += 3;
+            }
+            None => {
+                path.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(path).ok()
+}
+
+/// Guess the image type from the file name; players write their covers
+/// without any other metadata
+fn content_type(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        _ => "image/jpeg",
+    }
+}
@@ -0,0 +1,1128 @@
+//! System volume backends.
+//!
+//! Output devices (sinks), the default sink's volume and mute flag, and those
+//! of each application's stream, are read and changed through a
+//! [`VolumeBackend`]:
+//!
+//! - `pulse` talks to PulseAudio (or PipeWire's PulseAudio server) natively
+//!   over libpulse. Only available when built with the `pulse` feature.
+//! - `pactl` runs the `pactl` command for every call, as this service always
+//!   did.
+//! - `command` runs user-configured commands for the default sink's volume
+//!   and mute flag, for setups neither of those reach. It has no devices or
+//!   application streams.
+//!
+//! `MEDIA_CONTROL_VOLUME_BACKEND` picks one. By default `pulse` is used when
+//! it is compiled in and can reach the sound server at startup, otherwise
+//! `pactl`.
+//!
+//! Sink volumes can be put through a [`Curve`], so that equal steps in the
+//! percentages clients see sound equally loud. `MEDIA_CONTROL_VOLUME_CURVE`
+//! sets the curve for every sink and `MEDIA_CONTROL_VOLUME_CURVES` overrides
+//! it per sink. Application stream volumes are never curved.
+
+use crate::audio;
+use crate::config;
+pub use media_controller::models::{AppStream, Sink, Source};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Highest level we set; PulseAudio allows some amplification past 100%
+pub const MAX_VOLUME: u32 = 150;
+
+/// Reads and changes the output devices and the volume and mute flag of the
+/// default sink and of application streams
+pub trait VolumeBackend: Send + Sync {
+    /// Name used in config and logs
+    fn name(&self) -> &'static str;
+
+    /// Default sink volume as a percentage (average of its channels)
+    fn get_volume(&self) -> Result<u32, String>;
+
+    /// Set every channel of the default sink to `percent`
+    fn set_volume(&self, percent: u32) -> Result<(), String>;
+
+    /// Default sink volume as an unrounded percentage. Curves need finer
+    /// steps than whole percent near silence.
+    fn get_volume_exact(&self) -> Result<f64, String> {
+        self.get_volume().map(f64::from)
+    }
+
+    /// Set every channel of the default sink to a fractional `percent`
+    fn set_volume_exact(&self, percent: f64) -> Result<(), String> {
+        self.set_volume(percent.round() as u32)
+    }
+
+    /// Change the volume by `delta` percentage points within
+    /// `0..=MAX_VOLUME`, returning the new level
+    fn adjust_volume(&self, delta: i32) -> Result<u32, String> {
+        let level = adjusted(self.get_volume()?, delta);
+        self.set_volume(level)?;
+        Ok(level)
+    }
+
+    /// Whether the default sink is muted
+    fn get_mute(&self) -> Result<bool, String>;
+
+    /// Mute or unmute the default sink
+    fn set_mute(&self, muted: bool) -> Result<(), String>;
+
+    /// Flip the mute flag, returning the new state
+    fn toggle_mute(&self) -> Result<bool, String> {
+        let muted = !self.get_mute()?;
+        self.set_mute(muted)?;
+        Ok(muted)
+    }
+
+    /// Every application stream currently playing
+    fn list_apps(&self) -> Result<Vec<AppStream>, String>;
+
+    /// Set every channel of one application stream to `percent`
+    fn set_app_volume(&self, id: u32, percent: u32) -> Result<(), String>;
+
+    /// Mute or unmute one application stream
+    fn set_app_mute(&self, id: u32, muted: bool) -> Result<(), String>;
+
+    /// Move one application stream to another sink
+    fn move_app(&self, id: u32, sink: &str) -> Result<(), String>;
+
+    /// Every output device
+    fn list_sinks(&self) -> Result<Vec<Sink>, String>;
+
+    /// Make `sink` (a sink name) the default output
+    fn set_default_sink(&self, sink: &str) -> Result<(), String>;
+
+    /// Every input device, including the monitors of output devices
+    fn list_sources(&self) -> Result<Vec<Source>, String>;
+}
+
+/// `current` changed by `delta` percentage points, kept within `0..=MAX_VOLUME`
+pub fn adjusted(current: u32, delta: i32) -> u32 {
+    (current as i64 + delta as i64).clamp(0, MAX_VOLUME as i64) as u32
+}
+
+/// Volume control through the `pactl` command
+pub struct Pactl;
+
+impl VolumeBackend for Pactl {
+    fn name(&self) -> &'static str {
+        "pactl"
+    }
+
+    fn get_volume(&self) -> Result<u32, String> {
+        audio::get_sink_volume()
+    }
+
+    fn set_volume(&self, percent: u32) -> Result<(), String> {
+        audio::set_sink_volume(percent)
+    }
+
+    fn get_volume_exact(&self) -> Result<f64, String> {
+        audio::get_sink_volume_exact()
+    }
+
+    fn set_volume_exact(&self, percent: f64) -> Result<(), String> {
+        audio::set_sink_volume_exact(percent)
+    }
+
+    fn get_mute(&self) -> Result<bool, String> {
+        audio::get_sink_mute()
+    }
+
+    fn set_mute(&self, muted: bool) -> Result<(), String> {
+        audio::set_sink_mute(muted)
+    }
+
+    fn list_apps(&self) -> Result<Vec<AppStream>, String> {
+        Ok(audio::list_sink_inputs()?
+            .into_iter()
+            .map(|input| AppStream {
+                id: input.index,
+                app: input.property("application.name").map(str::to_string),
+                binary: input
+                    .property("application.process.binary")
+                    .map(str::to_string),
+                volume: input.volume.unwrap_or_default(),
+                muted: input.muted.unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    fn set_app_volume(&self, id: u32, percent: u32) -> Result<(), String> {
+        audio::set_sink_input_volume(id, percent)
+    }
+
+    fn set_app_mute(&self, id: u32, muted: bool) -> Result<(), String> {
+        audio::set_sink_input_mute(id, muted)
+    }
+
+    fn move_app(&self, id: u32, sink: &str) -> Result<(), String> {
+        audio::move_sink_input(id, sink)
+    }
+
+    fn list_sinks(&self) -> Result<Vec<Sink>, String> {
+        let default = audio::default_sink_name().ok();
+        Ok(audio::list_sinks()?
+            .into_iter()
+            .map(|sink| Sink {
+                default: default.as_deref() == Some(sink.name.as_str()),
+                name: sink.name,
+                description: sink.description,
+                volume: sink.volume.unwrap_or_default(),
+                muted: sink.muted.unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    fn set_default_sink(&self, sink: &str) -> Result<(), String> {
+        audio::set_default_sink(sink)
+    }
+
+    fn list_sources(&self) -> Result<Vec<Source>, String> {
+        let default = audio::default_source_name().ok();
+        Ok(audio::list_sources()?
+            .into_iter()
+            .map(|source| Source {
+                default: default.as_deref() == Some(source.name.as_str()),
+                name: source.name,
+                description: source.description,
+                volume: source.volume.unwrap_or_default(),
+                muted: source.muted.unwrap_or_default(),
+                monitor: source.monitor_of.is_some(),
+            })
+            .collect())
+    }
+}
+
+/// Maps the volume clients see onto a sink's own volume, both in percent
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+    /// The sink volume is the client volume
+    Linear,
+    /// The sink volume follows the cube of the client volume up to 100%,
+    /// giving fine steps near silence and coarse ones near full volume
+    Cubic,
+    /// Straight lines through `(client, sink)` points, written `0:0 50:20 100:100`
+    Points(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    /// Parse `linear`, `cubic` or a list of points
+    pub fn parse(value: &str) -> Option<Curve> {
+        match value.trim().to_lowercase().as_str() {
+            "linear" => Some(Curve::Linear),
+            "cubic" => Some(Curve::Cubic),
+            points => {
+                let mut parsed = Vec::new();
+                for pair in points.split_whitespace() {
+                    let (client, sink) = pair.split_once(':')?;
+                    parsed.push((client.parse().ok()?, sink.parse().ok()?));
+                }
+                // Both sides must rise for the curve to be read back
+                let rising = parsed
+                    .windows(2)
+                    .all(|w: &[(f64, f64)]| w[0].0 < w[1].0 && w[0].1 < w[1].1);
+                (parsed.len() >= 2 && rising).then_some(Curve::Points(parsed))
+            }
+        }
+    }
+
+    /// Sink volume for a client volume
+    pub fn to_sink(&self, percent: f64) -> f64 {
+        match self {
+            Curve::Linear => percent,
+            Curve::Cubic if percent < 100.0 => 100.0 * (percent / 100.0).powi(3),
+            Curve::Cubic => percent,
+            Curve::Points(points) => interpolate(points, percent),
+        }
+    }
+
+    /// Client volume for a sink volume
+    pub fn to_client(&self, percent: f64) -> f64 {
+        match self {
+            Curve::Linear => percent,
+            Curve::Cubic if percent < 100.0 => 100.0 * (percent / 100.0).cbrt(),
+            Curve::Cubic => percent,
+            Curve::Points(points) => {
+                let swapped: Vec<_> = points.iter().map(|&(a, b)| (b, a)).collect();
+                interpolate(&swapped, percent)
+            }
+        }
+    }
+}
+
+/// Follow straight lines between rising points; past either end the line
+/// carries on one-to-one
+fn interpolate(points: &[(f64, f64)], x: f64) -> f64 {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if x <= first.0 {
+        return (first.1 - (first.0 - x)).max(0.0);
+    }
+    for w in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        if x <= x1 {
+            return y0 + (x - x0) * (y1 - y0) / (x1 - x0);
+        }
+    }
+    last.1 + (x - last.0)
+}
+
+/// Curves from `MEDIA_CONTROL_VOLUME_CURVE` and `MEDIA_CONTROL_VOLUME_CURVES`
+struct Curves {
+    default: Curve,
+    // Case-insensitive sink name substring and its curve
+    per_sink: Vec<(String, Curve)>,
+}
+
+impl Curves {
+    fn from_env() -> Curves {
+        let parse = |var: &str, value: &str| {
+            Curve::parse(value).or_else(|| {
+                warn!("Ignoring {var} curve {value:?}, expected linear, cubic or points like 0:0 50:20 100:100");
+                None
+            })
+        };
+        let default = config::var("MEDIA_CONTROL_VOLUME_CURVE")
+            .ok()
+            .and_then(|v| parse("MEDIA_CONTROL_VOLUME_CURVE", &v))
+            .unwrap_or(Curve::Linear);
+        // `sink=curve` entries separated by `;`, since points contain spaces
+        let per_sink = config::var("MEDIA_CONTROL_VOLUME_CURVES")
+            .unwrap_or_default()
+            .split(';')
+            .filter_map(|entry| entry.split_once('='))
+            .filter_map(|(sink, curve)| {
+                let curve = parse("MEDIA_CONTROL_VOLUME_CURVES", curve)?;
+                Some((sink.trim().to_lowercase(), curve))
+            })
+            .collect();
+        Curves { default, per_sink }
+    }
+
+    fn for_sink(&self, name: &str) -> &Curve {
+        let name = name.to_lowercase();
+        self.per_sink
+            .iter()
+            .find(|(sink, _)| !sink.is_empty() && name.contains(sink))
+            .map_or(&self.default, |(_, curve)| curve)
+    }
+}
+
+/// Puts another backend's sink volumes through the configured curves
+struct Curved {
+    inner: Box<dyn VolumeBackend>,
+    curves: Curves,
+}
+
+impl Curved {
+    /// Curve of the current default sink
+    fn default_curve(&self) -> Result<&Curve, String> {
+        if self.curves.per_sink.is_empty() {
+            return Ok(&self.curves.default);
+        }
+        let sinks = self.inner.list_sinks()?;
+        Ok(match sinks.iter().find(|s| s.default) {
+            Some(sink) => self.curves.for_sink(&sink.name),
+            None => &self.curves.default,
+        })
+    }
+}
+
+impl VolumeBackend for Curved {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn get_volume(&self) -> Result<u32, String> {
+        self.get_volume_exact().map(|v| v.round() as u32)
+    }
+
+    fn set_volume(&self, percent: u32) -> Result<(), String> {
+        self.set_volume_exact(f64::from(percent))
+    }
+
+    fn get_volume_exact(&self) -> Result<f64, String> {
+        let curve = self.default_curve()?;
+        Ok(curve.to_client(self.inner.get_volume_exact()?))
+    }
+
+    fn set_volume_exact(&self, percent: f64) -> Result<(), String> {
+        let level = self.default_curve()?.to_sink(percent);
+        self.inner
+            .set_volume_exact(level.min(f64::from(MAX_VOLUME)))
+    }
+
+    fn get_mute(&self) -> Result<bool, String> {
+        self.inner.get_mute()
+    }
+
+    fn set_mute(&self, muted: bool) -> Result<(), String> {
+        self.inner.set_mute(muted)
+    }
+
+    fn list_apps(&self) -> Result<Vec<AppStream>, String> {
+        self.inner.list_apps()
+    }
+
+    fn set_app_volume(&self, id: u32, percent: u32) -> Result<(), String> {
+        self.inner.set_app_volume(id, percent)
+    }
+
+    fn set_app_mute(&self, id: u32, muted: bool) -> Result<(), String> {
+        self.inner.set_app_mute(id, muted)
+    }
+
+    fn move_app(&self, id: u32, sink: &str) -> Result<(), String> {
+        self.inner.move_app(id, sink)
+    }
+
+    fn list_sinks(&self) -> Result<Vec<Sink>, String> {
+        let mut sinks = self.inner.list_sinks()?;
+        for sink in &mut sinks {
+            let curve = self.curves.for_sink(&sink.name);
+            sink.volume = curve.to_client(f64::from(sink.volume)).round() as u32;
+        }
+        Ok(sinks)
+    }
+
+    fn set_default_sink(&self, sink: &str) -> Result<(), String> {
+        self.inner.set_default_sink(sink)
+    }
+
+    fn list_sources(&self) -> Result<Vec<Source>, String> {
+        self.inner.list_sources()
+    }
+}
+
+/// The backend selected from `MEDIA_CONTROL_VOLUME_BACKEND`, with the
+/// configured curves applied
+pub fn backend() -> &'static dyn VolumeBackend {
+    static BACKEND: OnceLock<Box<dyn VolumeBackend>> = OnceLock::new();
+    BACKEND
+        .get_or_init(|| {
+            let backend = select(config::var("MEDIA_CONTROL_VOLUME_BACKEND").ok().as_deref());
+            info!("Using {} volume backend", backend.name());
+            let curves = Curves::from_env();
+            if curves.default == Curve::Linear && curves.per_sink.is_empty() {
+                return backend;
+            }
+            info!("Volume curve: {:?}", curves.default);
+            for (sink, curve) in &curves.per_sink {
+                info!("Volume curve for sinks matching {sink:?}: {curve:?}");
+            }
+            Box::new(Curved {
+                inner: backend,
+                curves,
+            })
+        })
+        .as_ref()
+}
+
+fn select(configured: Option<&str>) -> Box<dyn VolumeBackend> {
+    match configured {
+        Some("pactl") => Box::new(Pactl),
+        Some("command") => match command::Commands::from_env() {
+            Ok(commands) => Box::new(commands),
+            Err(e) => {
+                warn!("Can't use the command volume backend ({e}), falling back to pactl");
+                Box::new(Pactl)
+            }
+        },
+        #[cfg(feature = "pulse")]
+        Some("pulse") => Box::new(pulse::Pulse),
+        #[cfg(not(feature = "pulse"))]
+        Some("pulse") => {
+            warn!("This build has no pulse volume backend, falling back to pactl");
+            Box::new(Pactl)
+        }
+        Some(other) => {
+            warn!(
+                "Unknown MEDIA_CONTROL_VOLUME_BACKEND {other:?}, expected pulse, pactl or command"
+            );
+            select(None)
+        }
+        #[cfg(feature = "pulse")]
+        None => match pulse::Pulse.get_volume() {
+            Ok(_) => Box::new(pulse::Pulse),
+            Err(e) => {
+                warn!("Native volume control unavailable ({e}), falling back to pactl");
+                Box::new(Pactl)
+            }
+        },
+        #[cfg(not(feature = "pulse"))]
+        None => Box::new(Pactl),
+    }
+}
+
+#[cfg(feature = "pulse")]
+mod pulse {
+    //! Native PulseAudio client.
+    //!
+    //! libpulse objects can't leave the thread that created them, so every
+    //! call opens its own short-lived connection. That is still far cheaper
+    //! than starting a `pactl` process.
+
+    use super::{AppStream, Sink, Source, VolumeBackend};
+    use libpulse_binding::callbacks::ListResult;
+    use libpulse_binding::context::{Context, FlagSet, State};
+    use libpulse_binding::mainloop::standard::{IterateResult, Mainloop};
+    use libpulse_binding::operation::{Operation, State as OperationState};
+    use libpulse_binding::proplist::properties;
+    use libpulse_binding::volume::{ChannelVolumes, Volume};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+
+    pub struct Pulse;
+
+    struct Connection {
+        mainloop: Mainloop,
+        context: Context,
+    }
+
+    impl Connection {
+        fn open() -> Result<Self, String> {
+            let mainloop = Mainloop::new().ok_or("couldn't create a PulseAudio main loop")?;
+            let mut context = Context::new(&mainloop, env!("CARGO_PKG_NAME"))
+                .ok_or("couldn't create a PulseAudio context")?;
+            context
+                .connect(None, FlagSet::NOFLAGS, None)
+                .map_err(|e| format!("couldn't connect to PulseAudio: {e}"))?;
+            let mut conn = Connection { mainloop, context };
+            loop {
+                conn.iterate()?;
+                match conn.context.get_state() {
+                    State::Ready => return Ok(conn),
+                    State::Failed | State::Terminated => {
+                        return Err("couldn't connect to PulseAudio".to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn iterate(&mut self) -> Result<(), String> {
+            match self.mainloop.iterate(true) {
+                IterateResult::Success(_) => Ok(()),
+                IterateResult::Quit(_) => Err("PulseAudio main loop quit".to_string()),
+                IterateResult::Err(e) => Err(format!("PulseAudio main loop failed: {e}")),
+            }
+        }
+
+        /// Run the main loop until `op` has finished
+        fn wait<F: ?Sized>(&mut self, op: Operation<F>) -> Result<(), String> {
+            while op.get_state() == OperationState::Running {
+                self.iterate()?;
+            }
+            Ok(())
+        }
+
+        /// The default sink's channel volumes and mute flag
+        fn sink_state(&mut self) -> Result<(ChannelVolumes, bool), String> {
+            let found = Rc::new(Cell::new(None));
+            let op = self
+                .context
+                .introspect()
+                .get_sink_info_by_name(DEFAULT_SINK, {
+                    let found = found.clone();
+                    move |result| {
+                        if let ListResult::Item(sink) = result {
+                            found.set(Some((sink.volume, sink.mute)));
+                        }
+                    }
+                });
+            self.wait(op)?;
+            found.take().ok_or_else(|| "no default sink".to_string())
+        }
+
+        /// Every sink input with its channel volumes
+        fn sink_inputs(&mut self) -> Result<Vec<(AppStream, ChannelVolumes)>, String> {
+            let found = Rc::new(RefCell::new(Vec::new()));
+            let op = self.context.introspect().get_sink_input_info_list({
+                let found = found.clone();
+                move |result| {
+                    if let ListResult::Item(input) = result {
+                        let stream = AppStream {
+                            id: input.index,
+                            app: input.proplist.get_str(properties::APPLICATION_NAME),
+                            binary: input
+                                .proplist
+                                .get_str(properties::APPLICATION_PROCESS_BINARY),
+                            volume: to_percent(input.volume.avg()),
+                            muted: input.mute,
+                        };
+                        found.borrow_mut().push((stream, input.volume));
+                    }
+                }
+            });
+            self.wait(op)?;
+            Ok(found.take())
+        }
+
+        /// Name of the default sink
+        fn default_sink_name(&mut self) -> Result<Option<String>, String> {
+            let found = Rc::new(RefCell::new(None));
+            let op = self.context.introspect().get_server_info({
+                let found = found.clone();
+                move |info| {
+                    *found.borrow_mut() = info.default_sink_name.as_ref().map(|n| n.to_string());
+                }
+            });
+            self.wait(op)?;
+            Ok(found.take())
+        }
+
+        /// Name of the default source
+        fn default_source_name(&mut self) -> Result<Option<String>, String> {
+            let found = Rc::new(RefCell::new(None));
+            let op = self.context.introspect().get_server_info({
+                let found = found.clone();
+                move |info| {
+                    *found.borrow_mut() = info.default_source_name.as_ref().map(|n| n.to_string());
+                }
+            });
+            self.wait(op)?;
+            Ok(found.take())
+        }
+
+        /// Wait for an operation that reports success through a callback
+        fn confirm(
+            &mut self,
+            start: impl FnOnce(&mut Context, Box<dyn FnMut(bool)>) -> Operation<dyn FnMut(bool)>,
+        ) -> Result<(), String> {
+            let ok = Rc::new(Cell::new(false));
+            let op = start(&mut self.context, {
+                let ok = ok.clone();
+                Box::new(move |success| ok.set(success))
+            });
+            self.wait(op)?;
+            if ok.get() {
+                Ok(())
+            } else {
+                Err("PulseAudio refused the change".to_string())
+            }
+        }
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            self.context.disconnect();
+        }
+    }
+
+    fn to_percent(volume: Volume) -> u32 {
+        let normal = Volume::NORMAL.0 as u64;
+        ((volume.0 as u64 * 100 + normal / 2) / normal) as u32
+    }
+
+    fn from_percent(percent: u32) -> Volume {
+        Volume((percent as u64 * Volume::NORMAL.0 as u64 / 100) as u32)
+    }
+
+    fn to_percent_exact(volume: Volume) -> f64 {
+        f64::from(volume.0) * 100.0 / f64::from(Volume::NORMAL.0)
+    }
+
+    fn from_percent_exact(percent: f64) -> Volume {
+        Volume((percent.max(0.0) * f64::from(Volume::NORMAL.0) / 100.0).round() as u32)
+    }
+
+    impl VolumeBackend for Pulse {
+        fn name(&self) -> &'static str {
+            "pulse"
+        }
+
+        fn get_volume(&self) -> Result<u32, String> {
+            let (volume, _) = Connection::open()?.sink_state()?;
+            Ok(to_percent(volume.avg()))
+        }
+
+        fn set_volume(&self, percent: u32) -> Result<(), String> {
+            self.set_volume_exact(f64::from(percent))
+        }
+
+        fn get_volume_exact(&self) -> Result<f64, String> {
+            let (volume, _) = Connection::open()?.sink_state()?;
+            Ok(to_percent_exact(volume.avg()))
+        }
+
+        fn set_volume_exact(&self, percent: f64) -> Result<(), String> {
+            let mut conn = Connection::open()?;
+            let (mut volume, _) = conn.sink_state()?;
+            volume.set(volume.len(), from_percent_exact(percent));
+            conn.confirm(|context, done| {
+                context
+                    .introspect()
+                    .set_sink_volume_by_name(DEFAULT_SINK, &volume, Some(done))
+            })
+        }
+
+        fn get_mute(&self) -> Result<bool, String> {
+            let (_, muted) = Connection::open()?.sink_state()?;
+            Ok(muted)
+        }
+
+        fn set_mute(&self, muted: bool) -> Result<(), String> {
+            Connection::open()?.confirm(|context, done| {
+                context
+                    .introspect()
+                    .set_sink_mute_by_name(DEFAULT_SINK, muted, Some(done))
+            })
+        }
+
+        fn list_apps(&self) -> Result<Vec<AppStream>, String> {
+            let inputs = Connection::open()?.sink_inputs()?;
+            Ok(inputs.into_iter().map(|(stream, _)| stream).collect())
+        }
+
+        fn set_app_volume(&self, id: u32, percent: u32) -> Result<(), String> {
+            let mut conn = Connection::open()?;
+            let (_, mut volume) = conn
+                .sink_inputs()?
+                .into_iter()
+                .find(|(stream, _)| stream.id == id)
+                .ok_or_else(|| format!("no application stream {id}"))?;
+            volume.set(volume.len(), from_percent(percent));
+            conn.confirm(|context, done| {
+                context
+                    .introspect()
+                    .set_sink_input_volume(id, &volume, Some(done))
+            })
+        }
+
+        fn set_app_mute(&self, id: u32, muted: bool) -> Result<(), String> {
+            Connection::open()?.confirm(|context, done| {
+                context
+                    .introspect()
+                    .set_sink_input_mute(id, muted, Some(done))
+            })
+        }
+
+        fn move_app(&self, id: u32, sink: &str) -> Result<(), String> {
+            Connection::open()?.confirm(|context, done| {
+                context
+                    .introspect()
+                    .move_sink_input_by_name(id, sink, Some(done))
+            })
+        }
+
+        fn list_sinks(&self) -> Result<Vec<Sink>, String> {
+            let mut conn = Connection::open()?;
+            let default = conn.default_sink_name()?;
+            let found = Rc::new(RefCell::new(Vec::new()));
+            let op = conn.context.introspect().get_sink_info_list({
+                let found = found.clone();
+                move |result| {
+                    if let ListResult::Item(sink) = result {
+                        let name = sink.name.as_deref().unwrap_or_default().to_string();
+                        found.borrow_mut().push(Sink {
+                            default: default.as_deref() == Some(name.as_str()),
+                            name,
+                            description: sink.description.as_ref().map(|d| d.to_string()),
+                            volume: to_percent(sink.volume.avg()),
+                            muted: sink.mute,
+                        });
+                    }
+                }
+            });
+            conn.wait(op)?;
+            Ok(found.take())
+        }
+
+        fn set_default_sink(&self, sink: &str) -> Result<(), String> {
+            Connection::open()?.confirm(|context, done| context.set_default_sink(sink, done))
+        }
+
+        fn list_sources(&self) -> Result<Vec<Source>, String> {
+            let mut conn = Connection::open()?;
+            let default = conn.default_source_name()?;
+            let found = Rc::new(RefCell::new(Vec::new()));
+            let op = conn.context.introspect().get_source_info_list({
+                let found = found.clone();
+                move |result| {
+                    if let ListResult::Item(source) = result {
+                        let name = source.name.as_deref().unwrap_or_default().to_string();
+                        found.borrow_mut().push(Source {
+                            default: default.as_deref() == Some(name.as_str()),
+                            name,
+                            description: source.description.as_ref().map(|d| d.to_string()),
+                            volume: to_percent(source.volume.avg()),
+                            muted: source.mute,
+                            monitor: source.monitor_of_sink.is_some(),
+                        });
+                    }
+                }
+            });
+            conn.wait(op)?;
+            Ok(found.take())
+        }
+    }
+}
+
+mod command {
+    //! Volume control through user-configured commands, for setups no other
+    //! backend reaches (ALSA mixers, amplifiers with a CLI, scripts).
+    //!
+    //! Commands are split into arguments the way a shell would split quoted
+    //! words, but run directly rather than through one: placeholders such as
+    //! `{{percent}}` are filled in per argument, so a value can never become
+    //! another argument or command. Each command gets no stdin and is killed
+    //! when it outlives the timeout, and a failure reports its stderr.
+
+    use super::{AppStream, Sink, Source, VolumeBackend};
+    use crate::config;
+    use crate::template::{self, Vars};
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// How often a running command is checked for having exited
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// The `MEDIA_CONTROL_VOLUME_COMMAND_*` commands, split into arguments
+    pub struct Commands {
+        get_volume: Vec<String>,
+        set_volume: Vec<String>,
+        // Optional: without them a step is a get followed by a set
+        volume_up: Option<Vec<String>>,
+        volume_down: Option<Vec<String>>,
+        // Optional: without them mute isn't supported
+        get_mute: Option<Vec<String>>,
+        mute: Option<Vec<String>>,
+        unmute: Option<Vec<String>>,
+        timeout: Duration,
+    }
+
+    impl Commands {
+        /// Read the commands from the settings. Getting and setting the
+        /// volume are required.
+        pub fn from_env() -> Result<Self, String> {
+            let optional = |name: &str| -> Result<Option<Vec<String>>, String> {
+                let key = format!("MEDIA_CONTROL_VOLUME_COMMAND_{name}");
+                match config::var(&key) {
+                    Ok(command) if !command.trim().is_empty() => {
+                        split(&command).map(Some).map_err(|e| format!("{key}: {e}"))
+                    }
+                    _ => Ok(None),
+                }
+            };
+            let required = |name: &str| {
+                optional(name)?
+                    .ok_or_else(|| format!("MEDIA_CONTROL_VOLUME_COMMAND_{name} is not set"))
+            };
+            Ok(Commands {
+                get_volume: required("GET")?,
+                set_volume: required("SET")?,
+                volume_up: optional("UP")?,
+                volume_down: optional("DOWN")?,
+                get_mute: optional("GET_MUTE")?,
+                mute: optional("MUTE")?,
+                unmute: optional("UNMUTE")?,
+                timeout: Duration::from_millis(
+                    config::var("MEDIA_CONTROL_VOLUME_COMMAND_TIMEOUT_MS")
+                        .ok()
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(2000),
+                ),
+            })
+        }
+
+        /// Run `argv` with its placeholders filled in, returning its stdout
+        fn run(&self, argv: &[String], vars: &Vars) -> Result<String, String> {
+            let argv: Vec<String> = argv.iter().map(|arg| template::render(arg, vars)).collect();
+            let program = &argv[0];
+            let mut child = Command::new(program)
+                .args(&argv[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Couldn't run {program}: {e}"))?;
+
+            // Drain both pipes while waiting, so a chatty command can't block
+            let drain = |pipe: Option<Box<dyn Read + Send>>| {
+                thread::spawn(move || {
+                    let mut out = Vec::new();
+                    if let Some(mut pipe) = pipe {
+                        let _ = pipe.read_to_end(&mut out);
+                    }
+                    String::from_utf8_lossy(&out).into_owned()
+                })
+            };
+            let stdout = drain(child.stdout.take().map(|p| Box::new(p) as _));
+            let stderr = drain(child.stderr.take().map(|p| Box::new(p) as _));
+
+            let deadline = Instant::now() + self.timeout;
+            let status = loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => break status,
+                    Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+                    Ok(None) => {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return Err(format!("{program} timed out after {:?}", self.timeout));
+                    }
+                    Err(e) => return Err(format!("Couldn't wait for {program}: {e}")),
+                }
+            };
+            let stdout = stdout.join().unwrap_or_default();
+            let stderr = stderr.join().unwrap_or_default();
+            if !status.success() {
+                return Err(format!("{program} exited with {status}: {}", stderr.trim()));
+            }
+            Ok(stdout)
+        }
+
+        fn unsupported(&self, what: &str) -> String {
+            format!("{what} is not supported by the command volume backend")
+        }
+    }
+
+    impl VolumeBackend for Commands {
+        fn name(&self) -> &'static str {
+            "command"
+        }
+
+        fn get_volume(&self) -> Result<u32, String> {
+            let out = self.run(&self.get_volume, &Vars::new())?;
+            first_number(&out)
+                .map(|percent| percent.round() as u32)
+                .ok_or_else(|| format!("No volume in {:?}", out.trim()))
+        }
+
+        fn set_volume(&self, percent: u32) -> Result<(), String> {
+            self.run(
+                &self.set_volume,
+                &Vars::from([("percent", percent.to_string())]),
+            )
+            .map(drop)
+        }
+
+        fn adjust_volume(&self, delta: i32) -> Result<u32, String> {
+            let command = if delta < 0 {
+                &self.volume_down
+            } else {
+                &self.volume_up
+            };
+            let Some(command) = command else {
+                let level = super::adjusted(self.get_volume()?, delta);
+                self.set_volume(level)?;
+                return Ok(level);
+            };
+            self.run(
+                command,
+                &Vars::from([("step", delta.unsigned_abs().to_string())]),
+            )?;
+            self.get_volume()
+        }
+
+        fn get_mute(&self) -> Result<bool, String> {
+            let command = self
+                .get_mute
+                .as_ref()
+                .ok_or_else(|| self.unsupported("Reading the mute state"))?;
+            let out = self.run(command, &Vars::new())?;
+            match out.trim().to_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" | "muted" => Ok(true),
+                "0" | "false" | "no" | "off" | "unmuted" => Ok(false),
+                other => Err(format!("No mute state in {other:?}")),
+            }
+        }
+
+        fn set_mute(&self, muted: bool) -> Result<(), String> {
+            let command = if muted { &self.mute } else { &self.unmute };
+            let command = command.as_ref().ok_or_else(|| self.unsupported("Muting"))?;
+            self.run(command, &Vars::new()).map(drop)
+        }
+
+        fn list_apps(&self) -> Result<Vec<AppStream>, String> {
+            Err(self.unsupported("Listing application streams"))
+        }
+
+        fn set_app_volume(&self, _id: u32, _percent: u32) -> Result<(), String> {
+            Err(self.unsupported("Application volume"))
+        }
+
+        fn set_app_mute(&self, _id: u32, _muted: bool) -> Result<(), String> {
+            Err(self.unsupported("Application mute"))
+        }
+
+        fn move_app(&self, _id: u32, _sink: &str) -> Result<(), String> {
+            Err(self.unsupported("Moving application streams"))
+        }
+
+        fn list_sinks(&self) -> Result<Vec<Sink>, String> {
+            Err(self.unsupported("Listing output devices"))
+        }
+
+        fn set_default_sink(&self, _sink: &str) -> Result<(), String> {
+            Err(self.unsupported("Changing the default output"))
+        }
+
+        fn list_sources(&self) -> Result<Vec<Source>, String> {
+            Err(self.unsupported("Listing input devices"))
+        }
+    }
+
+    /// Split a command line into arguments, honouring single and double
+    /// quotes and backslash escapes
+    fn split(command: &str) -> Result<Vec<String>, String> {
+        let mut args = Vec::new();
+        let mut current = String::new();
+        // Whether `current` holds an argument, which may be an empty ""
+        let mut started = false;
+        let mut quote = None;
+        let mut chars = command.chars();
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some('"') | None, '\\') => {
+                    current.extend(chars.next());
+                    started = true;
+                }
+                (Some(_), c) => current.push(c),
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    started = true;
+                }
+                (None, c) if c.is_whitespace() => {
+                    if started {
+                        args.push(std::mem::take(&mut current));
+                        started = false;
+                    }
+                }
+                (None, c) => {
+                    current.push(c);
+                    started = true;
+                }
+            }
+        }
+        if quote.is_some() {
+            return Err("unterminated quote".to_string());
+        }
+        if started {
+            args.push(current);
+        }
+        if args.is_empty() {
+            return Err("empty command".to_string());
+        }
+        Ok(args)
+    }
+
+    /// The first number in `text`, such as `42` in `Volume: 42%`
+    fn first_number(text: &str) -> Option<f64> {
+        let start = text.find(|c: char| c.is_ascii_digit())?;
+        let rest = &text[start..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        rest[..end].trim_end_matches('.').parse().ok()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn args(command: &str) -> Vec<String> {
+            split(command).unwrap()
+        }
+
+        #[test]
+        fn split_honours_quotes_and_escapes() {
+            assert_eq!(
+                args("amixer  sset Master 40%"),
+                ["amixer", "sset", "Master", "40%"]
+            );
+            assert_eq!(
+                args(r#"sh -c 'echo "$1"' "two words" a\ b"#),
+                ["sh", "-c", r#"echo "$1""#, "two words", "a b"]
+            );
+            // Backslashes stay literal in single quotes
+            assert_eq!(args(r#"printf '\n' "\"""#), ["printf", r"\n", "\""]);
+            assert_eq!(args(r#"set "" x"#), ["set", "", "x"]);
+            assert_eq!(args("a''b"), ["ab"]);
+        }
+
+        #[test]
+        fn split_refuses_unterminated_quotes_and_empty_commands() {
+            assert!(split("echo 'oops").is_err());
+            assert!(split("echo \"oops").is_err());
+            assert!(split("").is_err());
+            assert!(split("   ").is_err());
+        }
+
+        #[test]
+        fn first_number_takes_the_first_number_in_the_text() {
+            assert_eq!(first_number("Volume: 42%"), Some(42.0));
+            assert_eq!(
+                first_number("Front Left: 65536 / 100% / 0.00 dB"),
+                Some(65536.0)
+            );
+            assert_eq!(first_number("[37.5%]"), Some(37.5));
+            // A full stop after the number is the end of a sentence
+            assert_eq!(first_number("Volume is 80."), Some(80.0));
+            assert_eq!(first_number("muted"), None);
+            assert_eq!(first_number(""), None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn parses_named_curves_and_rising_points() {
+        assert_eq!(Curve::parse(" Linear "), Some(Curve::Linear));
+        assert_eq!(Curve::parse("CUBIC"), Some(Curve::Cubic));
+        assert_eq!(
+            Curve::parse("0:0  50:20 100:100"),
+            Some(Curve::Points(vec![
+                (0.0, 0.0),
+                (50.0, 20.0),
+                (100.0, 100.0)
+            ]))
+        );
+        // One point, points that don't rise on either side, and junk
+        assert_eq!(Curve::parse("50:20"), None);
+        assert_eq!(Curve::parse("0:0 50:20 40:80"), None);
+        assert_eq!(Curve::parse("0:0 50:20 80:20"), None);
+        assert_eq!(Curve::parse("0:0 50-20"), None);
+        assert_eq!(Curve::parse("loud"), None);
+    }
+
+    #[test]
+    fn points_interpolate_and_carry_on_past_the_ends() {
+        let curve = Curve::parse("10:5 50:20 100:80").unwrap();
+        assert!(close(curve.to_sink(30.0), 12.5));
+        assert!(close(curve.to_sink(50.0), 20.0));
+        assert!(close(curve.to_sink(120.0), 100.0));
+        assert!(close(curve.to_sink(8.0), 3.0));
+        // Never below silence
+        assert!(close(curve.to_sink(0.0), 0.0));
+    }
+
+    #[test]
+    fn to_client_undoes_to_sink() {
+        let curves = [
+            Curve::Linear,
+            Curve::Cubic,
+            Curve::parse("0:0 50:20 100:100").unwrap(),
+            Curve::parse("0:0 25:40 100:90 150:150").unwrap(),
+        ];
+        for curve in &curves {
+            for percent in (0..=150).step_by(5).map(f64::from) {
+                let back = curve.to_client(curve.to_sink(percent));
+                assert!(close(back, percent), "{curve:?} at {percent}: {back}");
+            }
+        }
+        assert!(close(Curve::Cubic.to_sink(50.0), 12.5));
+        assert!(close(Curve::Cubic.to_client(12.5), 50.0));
+    }
+}
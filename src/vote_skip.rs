@@ -0,0 +1,242 @@
+//! Skip voting, for players everyone in a room shares.
+//!
+//! `POST /vote_skip` with `{"device": "desk-3"}` is one vote to skip the
+//! current track. Once `MEDIA_CONTROL_VOTE_SKIP_THRESHOLD` different devices
+//! have voted the controlled player skips to the next track and the votes
+//! start over. Votes only count for the track they were cast on, so they also
+//! start over whenever the track or player changes. With
+//! `MEDIA_CONTROL_VOTE_SKIP_DEVICES` only the devices listed there can vote.
+//!
+//! Every change to the tally goes out as a `votes` event on `/ws` and
+//! `/events`. In dry run the skip is only logged.
+
+use crate::actions::{self, Action, Target};
+use crate::config;
+use crate::error::{AppError, MutexExt};
+use crate::events::changes;
+use crate::live::NowPlaying;
+use crate::{get_dry_run_mode, AppState};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use media_controller::models::{ErrorBody, VoteTally};
+use serde::Deserialize;
+use std::sync::Mutex;
+use tokio::sync::watch;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Default for `MEDIA_CONTROL_VOTE_SKIP_THRESHOLD`
+const DEFAULT_THRESHOLD: usize = 2;
+/// Longest device name taken
+const MAX_DEVICE_LEN: usize = 64;
+
+fn threshold() -> usize {
+    config::var("MEDIA_CONTROL_VOTE_SKIP_THRESHOLD")
+        .ok()
+        .and_then(|v| match v.trim().parse::<usize>() {
+            Ok(votes) if votes >= 1 => Some(votes),
+            _ => {
+                warn!("Ignoring MEDIA_CONTROL_VOTE_SKIP_THRESHOLD, expected a number of votes");
+                None
+            }
+        })
+        .unwrap_or(DEFAULT_THRESHOLD)
+}
+
+/// `MEDIA_CONTROL_VOTE_SKIP_DEVICES`, if set
+fn registered_devices() -> Option<Vec<String>> {
+    let devices = config::var("MEDIA_CONTROL_VOTE_SKIP_DEVICES").ok()?;
+    Some(
+        devices
+            .split(',')
+            .map(str::trim)
+            .filter(|device| !device.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// What votes are tied to: the player and the track on it
+fn track_of(now: &NowPlaying) -> Option<String> {
+    let bus_name = now.bus_name.as_deref()?;
+    let title = now.title.as_deref()?;
+    Some(format!(
+        "{bus_name}\n{title}\n{}\n{}",
+        now.artist.as_deref().unwrap_or_default(),
+        now.album.as_deref().unwrap_or_default()
+    ))
+}
+
+#[derive(Default)]
+struct Ballot {
+    // The track the votes were cast on
+    track: Option<String>,
+    voters: Vec<String>,
+}
+
+/// The skip votes, shared with the event streams
+pub struct SkipVotes {
+    ballot: Mutex<Ballot>,
+    tally: watch::Sender<VoteTally>,
+}
+
+impl Default for SkipVotes {
+    fn default() -> Self {
+        SkipVotes {
+            ballot: Mutex::default(),
+            tally: watch::Sender::new(VoteTally {
+                threshold: threshold(),
+                ..VoteTally::default()
+            }),
+        }
+    }
+}
+
+impl SkipVotes {
+    /// Follow changes to the tally
+    pub fn subscribe(&self) -> watch::Receiver<VoteTally> {
+        self.tally.subscribe()
+    }
+
+    fn publish(&self, voters: &[String], skipped: bool) -> VoteTally {
+        let tally = VoteTally {
+            voters: voters.to_vec(),
+            votes: voters.len(),
+            threshold: threshold(),
+            skipped,
+        };
+        self.tally.send_if_modified(|current| {
+            let changed = *current != tally;
+            *current = tally.clone();
+            changed
+        });
+        tally
+    }
+
+    /// Forget the votes if they were cast on anything but `track`
+    fn reset_unless(&self, track: Option<&str>) {
+        let mut ballot = self.ballot.locked();
+        if ballot.track.as_deref() != track {
+            ballot.track = track.map(String::from);
+            if !ballot.voters.is_empty() {
+                ballot.voters.clear();
+                self.publish(&[], false);
+            }
+        }
+    }
+}
+
+/// Start the votes over whenever the track or player changes
+pub fn spawn(state: web::Data<AppState>) {
+    let mut updates = state.live.subscribe();
+    let mut last = updates.borrow_and_update().clone();
+    actix_web::rt::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let now = updates.borrow_and_update().clone();
+            let kinds = changes(&last, &now);
+            if kinds.contains(&"track") || kinds.contains(&"player") {
+                state.votes.reset_unless(track_of(&now).as_deref());
+            }
+            last = now;
+        }
+    });
+}
+
+/// Body of POST /vote_skip
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VoteRequest {
+    // Name of the voting device, one vote per device and track
+    device: String,
+}
+
+/// POST /vote_skip — vote to skip the current track
+///
+/// Skips once enough devices have voted. Voting twice changes nothing.
+#[utoipa::path(
+    post,
+    path = "/vote_skip",
+    tag = "Playback",
+    request_body = VoteRequest,
+    responses(
+        (status = 200, description = "The tally after this vote", body = VoteTally),
+        (status = 400, description = "No device name", body = ErrorBody),
+        (status = 403, description = "The device isn't registered to vote", body = ErrorBody),
+        (status = 409, description = "Nothing is playing", body = ErrorBody),
+    ),
+)]
+pub async fn vote_skip(
+    state: web::Data<AppState>,
+    body: web::Json<VoteRequest>,
+) -> Result<HttpResponse, AppError> {
+    let device = body.into_inner().device.trim().to_string();
+    if device.is_empty() || device.len() > MAX_DEVICE_LEN {
+        return Err(AppError::bad_request(format!(
+            "device must be a name of 1 to {MAX_DEVICE_LEN} bytes"
+        )));
+    }
+    if let Some(devices) = registered_devices() {
+        if !devices.contains(&device) {
+            return Err(AppError::new(
+                StatusCode::FORBIDDEN,
+                format!("{device} isn't registered to vote"),
+            ));
+        }
+    }
+    let Some(track) = track_of(&state.live.borrow()) else {
+        return Err(AppError::conflict("Nothing is playing"));
+    };
+
+    let votes = &state.votes;
+    let tally = {
+        let mut ballot = votes.ballot.locked();
+        if ballot.track.as_deref() != Some(track.as_str()) {
+            ballot.track = Some(track);
+            ballot.voters.clear();
+        }
+        if !ballot.voters.contains(&device) {
+            ballot.voters.push(device.clone());
+        }
+        if ballot.voters.len() < threshold() {
+            return Ok(HttpResponse::Ok().json(votes.publish(&ballot.voters, false)));
+        }
+        info!("Skip vote by {device} reached the threshold");
+        ballot.voters.clear();
+        votes.publish(&[], true)
+    };
+
+    // On the blocking pool, as a no-player policy can make it wait
+    let result = web::block(move || {
+        if get_dry_run_mode() {
+            actions::plan(&state, &Target::Auto, Action::Next)
+        } else {
+            actions::run(&state, Action::Next)
+        }
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("skip task failed: {e}")))?;
+    match result {
+        Ok(msg) => info!("Skipped by vote: {msg}"),
+        Err(e) => {
+            warn!("Skip vote passed, but couldn't skip: {e}");
+            return Err(AppError::new(e.status(), e.to_string()));
+        }
+    }
+    Ok(HttpResponse::Ok().json(tally))
+}
+
+/// GET /vote_skip — the skip votes on the current track
+#[utoipa::path(
+    get,
+    path = "/vote_skip",
+    tag = "Playback",
+    responses((status = 200, description = "The tally", body = VoteTally)),
+)]
+pub async fn get_votes(state: web::Data<AppState>) -> HttpResponse {
+    let tally = VoteTally {
+        threshold: threshold(),
+        skipped: false,
+        ..state.votes.tally.borrow().clone()
+    };
+    HttpResponse::Ok().json(tally)
+}
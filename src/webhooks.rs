@@ -0,0 +1,140 @@
+//! Webhooks: POST a JSON event to configured URLs when playback changes.
+//!
+//! Enabled by listing URLs in `MEDIA_CONTROL_WEBHOOKS`. A task follows the
+//! live snapshot like `/events` does, and every `track`, `playback` or
+//! `player` change is sent to each URL from a background thread, so a slow
+//! receiver never holds up the rest of the service. Deliveries aren't
+//! retried; failures are logged. The URLs, timeout and proxy are read for
+//! every event, so a config reload changes them straight away. Requests go
+//! through the shared [`crate::outbound`] agent.
+
+use crate::config;
+use crate::events::changes;
+use crate::live::NowPlaying;
+use crate::outbound::{self, Integration};
+use crate::AppState;
+use actix_web::web;
+use serde::Serialize;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Event types worth a webhook; volume and device changes are left to `/ws`
+const SENT_TYPES: &[&str] = &["track", "playback", "player"];
+/// Default for `MEDIA_CONTROL_WEBHOOK_TIMEOUT_SECS`
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The JSON body of every webhook
+#[derive(Clone, Debug, Serialize)]
+struct WebhookEvent {
+    // `track`, `playback` or `player`
+    r#type: &'static str,
+    // Milliseconds since the Unix epoch
+    timestamp_ms: u64,
+    player: Option<String>,
+    bus_name: Option<String>,
+    playback: Option<String>,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    art_url: Option<String>,
+    length_ms: Option<u64>,
+    position_ms: Option<u64>,
+}
+
+impl WebhookEvent {
+    fn new(kind: &'static str, now: &NowPlaying) -> Self {
+        WebhookEvent {
+            r#type: kind,
+            timestamp_ms: timestamp_ms(),
+            player: now.player.clone(),
+            bus_name: now.bus_name.clone(),
+            playback: now.playback.clone(),
+            title: now.title.clone(),
+            artist: now.artist.clone(),
+            album: now.album.clone(),
+            art_url: now.art_url.clone(),
+            length_ms: now.length.map(|d| d.as_millis() as u64),
+            position_ms: now.position().map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch, for `timestamp_ms`
+pub fn timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// URLs from `MEDIA_CONTROL_WEBHOOKS`
+fn urls() -> Vec<String> {
+    config::var("MEDIA_CONTROL_WEBHOOKS")
+        .map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn timeout() -> Duration {
+    config::var("MEDIA_CONTROL_WEBHOOK_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// POST `body` to one webhook `url`, such as a position alarm's
+pub fn post(url: &str, body: &impl Serialize) -> Result<(), String> {
+    outbound::agent(Integration::Webhooks)
+        .post(url)
+        .config()
+        .timeout_global(Some(timeout()))
+        .build()
+        .send_json(body)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Send webhooks to whatever URLs are configured at the time of each event
+pub fn spawn(state: web::Data<AppState>) {
+    let configured = urls().len();
+    if configured > 0 {
+        info!("Sending webhooks to {configured} URL(s)");
+    }
+
+    let (events, queue) = mpsc::channel::<WebhookEvent>();
+    thread::spawn(move || {
+        for event in queue {
+            for url in urls() {
+                if let Err(e) = post(&url, &event) {
+                    warn!("Webhook {} to {url} failed: {e}", event.r#type);
+                }
+            }
+        }
+    });
+
+    let mut updates = state.live.subscribe();
+    actix_web::rt::spawn(async move {
+        let mut last = updates.borrow_and_update().clone();
+        while updates.changed().await.is_ok() {
+            let now = updates.borrow_and_update().clone();
+            // Without URLs there's nobody to queue events for
+            if config::var("MEDIA_CONTROL_WEBHOOKS").is_ok() {
+                let kinds = changes(&last, &now).into_iter();
+                for kind in kinds.filter(|kind| SENT_TYPES.contains(kind)) {
+                    if events.send(WebhookEvent::new(kind, &now)).is_err() {
+                        return;
+                    }
+                }
+            }
+            last = now;
+        }
+    });
+}
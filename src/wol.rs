@@ -0,0 +1,87 @@
+//! Wake-on-LAN for the machines around the player.
+//!
+//! `POST /wol/{target}` sends a magic packet to a MAC address configured in
+//! `MEDIA_CONTROL_WOL_TARGETS`, e.g. `htpc=AA:BB:CC:DD:EE:FF,avr=11:22:33:44:55:66`.
+//! The packet is broadcast on UDP port 9 unless `MEDIA_CONTROL_WOL_BROADCAST`
+//! names another address.
+
+use crate::config;
+use crate::error::AppError;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use media_controller::models::ErrorBody;
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use tracing::info;
+
+/// Where magic packets go unless overridden
+const DEFAULT_BROADCAST: &str = "255.255.255.255:9";
+
+/// Read the configured targets, keyed by lowercased name
+fn get_wol_targets() -> HashMap<String, String> {
+    config::var("MEDIA_CONTROL_WOL_TARGETS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, mac)| (name.trim().to_lowercase(), mac.trim().to_string()))
+        .filter(|(name, mac)| !name.is_empty() && !mac.is_empty())
+        .collect()
+}
+
+/// Parse `AA:BB:CC:DD:EE:FF` (or with dashes) into bytes
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let parts: Vec<_> = mac.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let mut bytes = [0u8; 6];
+    for (byte, part) in bytes.iter_mut().zip(parts) {
+        if part.len() != 2 {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Broadcast a magic packet: six 0xFF bytes followed by the MAC sixteen times
+fn wake(mac: &str) -> Result<(), String> {
+    let bytes = parse_mac(mac).ok_or_else(|| format!("invalid MAC address: {mac}"))?;
+    let mut packet = vec![0xFF; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&bytes);
+    }
+
+    let addr =
+        config::var("MEDIA_CONTROL_WOL_BROADCAST").unwrap_or_else(|_| DEFAULT_BROADCAST.into());
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket.set_broadcast(true).map_err(|e| e.to_string())?;
+    socket
+        .send_to(&packet, addr.as_str())
+        .map_err(|e| format!("couldn't send to {addr}: {e}"))?;
+    Ok(())
+}
+
+/// POST /wol/{target} — wake a configured machine
+#[utoipa::path(
+    post,
+    path = "/wol/{target}",
+    tag = "Extras",
+    params(("target" = String, Path, description = "Name from MEDIA_CONTROL_WOL_TARGETS")),
+    responses(
+        (status = 200, description = "The magic packet was sent", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such target", body = ErrorBody),
+    ),
+)]
+pub async fn wake_target(path: web::Path<String>) -> impl Responder {
+    let name = path.into_inner();
+    let Some(mac) = get_wol_targets().remove(&name.to_lowercase()) else {
+        return AppError::not_found(format!("no Wake-on-LAN target named {name}")).error_response();
+    };
+    match wake(&mac) {
+        Ok(()) => {
+            info!("Sent Wake-on-LAN packet to {name} ({mac})");
+            HttpResponse::Ok().body(format!("sent magic packet to {name}"))
+        }
+        Err(e) => AppError::internal(e).error_response(),
+    }
+}
@@ -0,0 +1,119 @@
+//! Advertising the API on the local network with mDNS (the `zeroconf`
+//! feature).
+//!
+//! With `MEDIA_CONTROL_MDNS=true` the HTTP API is announced as a
+//! `_media-control._tcp` service, so apps on the same network can find it
+//! without being given an address. The TXT record carries the version, the
+//! scheme and what the server can do. It's off by default, since it tells
+//! everyone on the network that the service is there.
+
+use crate::config;
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+// Service names are limited to 15 characters, one short of "media-controller"
+const SERVICE: &str = "_media-control._tcp.local.";
+
+/// What a client can expect to find, for the `caps` TXT entry
+fn capabilities() -> Vec<&'static str> {
+    let mut caps = vec!["playback", "seek", "volume", "queue", "events", "ws"];
+    #[cfg(feature = "history")]
+    if crate::history::enabled() {
+        caps.push("history");
+    }
+    if cfg!(feature = "grpc") && config::var("MEDIA_CONTROL_GRPC_LISTEN").is_ok() {
+        caps.push("grpc");
+    }
+    caps
+}
+
+fn enabled() -> bool {
+    config::var("MEDIA_CONTROL_MDNS")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// `MEDIA_CONTROL_MDNS_NAME`, or else the hostname
+fn instance_name() -> String {
+    config::var("MEDIA_CONTROL_MDNS_NAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "media-controller".into())
+}
+
+/// The service on the network, withdrawn by [`Advertisement::withdraw`]
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+/// Announce the first of `sockets` that other machines can reach, if
+/// `MEDIA_CONTROL_MDNS` is on
+pub fn advertise(sockets: &[(SocketAddr, &str)]) -> Option<Advertisement> {
+    if !enabled() {
+        return None;
+    }
+    let Some(&(addr, scheme)) = sockets.iter().find(|(addr, _)| !addr.ip().is_loopback()) else {
+        warn!("Not advertising with mDNS, the server only listens on loopback or a Unix socket");
+        return None;
+    };
+    let name = instance_name();
+    let host = format!("{}.local.", name.replace([' ', '.'], "-"));
+    let version = env!("CARGO_PKG_VERSION");
+    let caps = capabilities().join(",");
+    let properties = [
+        ("version", version),
+        ("scheme", scheme),
+        ("path", "/"),
+        ("caps", caps.as_str()),
+    ];
+    // On a wildcard address every interface's addresses are announced
+    let ip = if addr.ip().is_unspecified() {
+        String::new()
+    } else {
+        addr.ip().to_string()
+    };
+    let service =
+        ServiceInfo::new(SERVICE, &name, &host, ip, addr.port(), &properties[..]).map(|service| {
+            if addr.ip().is_unspecified() {
+                service.enable_addr_auto()
+            } else {
+                service
+            }
+        });
+    let result = service.and_then(|service| {
+        let fullname = service.get_fullname().to_string();
+        let daemon = ServiceDaemon::new()?;
+        daemon.register(service)?;
+        Ok(Advertisement { daemon, fullname })
+    });
+    match result {
+        Ok(advertisement) => {
+            info!(
+                "Advertising {scheme} on port {} as {name:?} with mDNS",
+                addr.port()
+            );
+            Some(advertisement)
+        }
+        Err(e) => {
+            warn!("Couldn't advertise with mDNS: {e}");
+            None
+        }
+    }
+}
+
+impl Advertisement {
+    /// Say goodbye on the network, so browsers drop the service right away
+    pub fn withdraw(self) {
+        // Wait for the goodbye packets to go out before stopping the daemon
+        if let Ok(done) = self.daemon.unregister(&self.fullname) {
+            let _ = done.recv_timeout(Duration::from_secs(1));
+        }
+        let _ = self.daemon.shutdown();
+    }
+}